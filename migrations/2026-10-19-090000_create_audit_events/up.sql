@@ -0,0 +1,15 @@
+CREATE SEQUENCE audit_events_id_seq;
+CREATE TABLE audit_events (
+  id BIGINT PRIMARY KEY DEFAULT nextval('audit_events_id_seq'::regclass),
+  action CHARACTER VARYING NOT NULL,
+  subject TEXT,
+  actor TEXT,
+  request_id CHARACTER VARYING,
+  old_value JSONB,
+  new_value JSONB,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+);
+
+CREATE INDEX index_audit_events_on_subject ON audit_events(subject);
+CREATE INDEX index_audit_events_on_actor ON audit_events(actor);
+CREATE INDEX index_audit_events_on_created_at ON audit_events(created_at);
@@ -0,0 +1,2 @@
+DROP TABLE acl_rules;
+DROP SEQUENCE acl_rules_id_seq;
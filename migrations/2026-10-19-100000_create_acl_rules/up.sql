@@ -0,0 +1,12 @@
+CREATE SEQUENCE acl_rules_id_seq;
+CREATE TABLE acl_rules (
+  id BIGINT PRIMARY KEY DEFAULT nextval('acl_rules_id_seq'::regclass),
+  principal TEXT NOT NULL,
+  pattern TEXT NOT NULL,
+  pattern_type CHARACTER VARYING NOT NULL,
+  role CHARACTER VARYING NOT NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+);
+
+CREATE INDEX index_acl_rules_on_principal ON acl_rules(principal);
@@ -0,0 +1,2 @@
+DROP TABLE api_keys;
+DROP SEQUENCE api_keys_id_seq;
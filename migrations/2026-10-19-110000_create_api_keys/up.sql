@@ -0,0 +1,15 @@
+CREATE SEQUENCE api_keys_id_seq;
+CREATE TABLE api_keys (
+  id BIGINT PRIMARY KEY DEFAULT nextval('api_keys_id_seq'::regclass),
+  key_id CHARACTER VARYING NOT NULL,
+  owner TEXT NOT NULL,
+  role CHARACTER VARYING NOT NULL,
+  secret_hash TEXT NOT NULL,
+  expires_at TIMESTAMP WITHOUT TIME ZONE,
+  last_used_at TIMESTAMP WITHOUT TIME ZONE,
+  revoked_at TIMESTAMP WITHOUT TIME ZONE,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+);
+
+CREATE UNIQUE INDEX index_api_keys_on_key_id ON api_keys(key_id);
@@ -0,0 +1,2 @@
+ALTER TABLE schema_versions DROP COLUMN deleted;
+ALTER TABLE subjects DROP COLUMN deleted;
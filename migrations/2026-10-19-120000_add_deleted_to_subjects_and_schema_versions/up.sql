@@ -0,0 +1,2 @@
+ALTER TABLE subjects ADD COLUMN deleted BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE schema_versions ADD COLUMN deleted BOOLEAN NOT NULL DEFAULT FALSE;
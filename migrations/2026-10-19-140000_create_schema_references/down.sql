@@ -0,0 +1,2 @@
+DROP TABLE schema_references;
+DROP SEQUENCE schema_references_id_seq;
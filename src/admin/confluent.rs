@@ -0,0 +1,353 @@
+use std::io::BufRead;
+
+use chrono::Utc;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::db::models::{
+    AuditAction, AuditContext, AuditEvent, CompatibilityLevel, Config, Mode, NewSchema,
+    NewSchemaVersion, Reference, Schema, SchemaReference, SchemaVersion, Subject,
+};
+
+use super::AdminError;
+
+/// Key of a record of the `_schemas` topic, which tells what its value is about.
+#[derive(Debug, Deserialize)]
+struct TopicKey {
+    keytype: KeyType,
+    subject: Option<String>,
+    version: Option<i32>,
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum KeyType {
+    Schema,
+    Config,
+    Mode,
+    DeleteSubject,
+    ClearSubject,
+    Noop,
+}
+
+/// A version of a subject. Soft deleting it writes the same record again, as deleted.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SchemaValue {
+    subject: String,
+    version: i32,
+    id: i64,
+    schema: String,
+    schema_type: Option<String>,
+    #[serde(default)]
+    references: Vec<Reference>,
+    #[serde(default)]
+    deleted: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigValue {
+    compatibility_level: Option<CompatibilityLevel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModeValue {
+    mode: Mode,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteSubjectValue {
+    subject: String,
+    version: i32,
+}
+
+/// What a replay changed: records are all counted, but schemas and versions only when
+/// they weren't there yet, even if a later tombstone deletes them again.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReplaySummary {
+    pub records: usize,
+    pub schemas: usize,
+    pub versions: usize,
+}
+
+/// Replays a dump of the `_schemas` topic of a Confluent registry, as printed by
+/// `kafka-console-consumer` with `print.key=true`: a record per line, its JSON key and
+/// value separated by a tab, and `null` as the value of tombstones.
+///
+/// Schemas keep their IDs and versions their numbers, and deletions and configs are
+/// replayed along with them. The whole dump is replayed in one transaction, audit event
+/// included, so that a replay which fails changes nothing. Records only change what
+/// differs from them, so replaying a dump again, or a longer dump of the same topic, is
+/// harmless.
+pub fn replay_confluent(
+    conn: &mut PgConnection,
+    input: impl BufRead,
+    audit: &AuditContext,
+) -> Result<ReplaySummary, AdminError> {
+    conn.transaction::<_, AdminError, _>(|conn| {
+        let mut summary = ReplaySummary::default();
+        for (index, line) in input.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let replayed =
+                replay(conn, &line).map_err(|e| AdminError::Line(index + 1, Box::new(e)))?;
+            summary.records += 1;
+            summary.schemas += replayed.schemas;
+            summary.versions += replayed.versions;
+        }
+
+        AuditEvent::record(
+            conn,
+            audit,
+            AuditAction::ImportConfluent,
+            None,
+            None,
+            Some(json!({
+                "records": summary.records,
+                "schemas": summary.schemas,
+                "versions": summary.versions,
+            })),
+        )?;
+        Ok(summary)
+    })
+}
+
+fn replay(conn: &mut PgConnection, line: &str) -> Result<ReplaySummary, AdminError> {
+    let (key, value) = line.split_once('\t').ok_or_else(|| {
+        AdminError::Dump("records are a key and a value separated by a tab".to_string())
+    })?;
+    let key = serde_json::from_str::<TopicKey>(key).map_err(AdminError::Malformed)?;
+    let value = serde_json::from_str::<Option<JsonValue>>(value).map_err(AdminError::Malformed)?;
+
+    match (key.keytype, value) {
+        (KeyType::Schema, Some(value)) => return replay_schema(conn, parse(value)?),
+        // Versions deleted for good leave a tombstone
+        (KeyType::Schema, None) => {
+            let (Some(name), Some(number)) = (key.subject, key.version) else {
+                return Err(AdminError::Dump(
+                    "schema tombstones need a subject and a version".to_string(),
+                ));
+            };
+            if let Some(subject) = find_subject(conn, name)? {
+                if let Some(version) = SchemaVersion::with_number(conn, &subject, number)? {
+                    version.delete_permanently(conn)?;
+                }
+                settle(conn, &subject)?;
+            }
+        }
+        (KeyType::Config, value) => {
+            // Tombstones reset the level, while configs without one leave it as it is
+            let level = match value.map(parse::<ConfigValue>).transpose()? {
+                Some(ConfigValue {
+                    compatibility_level: None,
+                }) => return Ok(ReplaySummary::default()),
+                Some(ConfigValue {
+                    compatibility_level: Some(level),
+                }) => Some(level.valid()?),
+                None => None,
+            };
+            match key.subject {
+                Some(name) => configure(conn, name, |conn, subject| match level {
+                    Some(level) => {
+                        Config::set_with_subject_name(conn, subject.name.clone(), level.to_string())
+                            .map(drop)
+                    }
+                    None => Config::unset_subject_compatibility(conn, subject),
+                })?,
+                None => {
+                    let level = level.unwrap_or(Config::DEFAULT_COMPATIBILITY);
+                    Config::set_global_compatibility(conn, &level.to_string())?;
+                }
+            }
+        }
+        (KeyType::Mode, value) => {
+            let mode = match value.map(parse::<ModeValue>).transpose()? {
+                Some(value) => Some(value.mode.valid()?),
+                None => None,
+            };
+            match key.subject {
+                Some(name) => configure(conn, name, |conn, subject| match mode {
+                    Some(mode) => {
+                        Config::set_subject_mode(conn, subject.name.clone(), mode, true).map(drop)
+                    }
+                    None => Config::unset_subject_mode(conn, subject),
+                })?,
+                None => {
+                    Config::set_global_mode(conn, mode.unwrap_or(Config::DEFAULT_MODE), true)?;
+                }
+            }
+        }
+        (KeyType::DeleteSubject, Some(value)) => {
+            let value = parse::<DeleteSubjectValue>(value)?;
+            if let Some(subject) = find_subject(conn, value.subject)? {
+                SchemaVersion::soft_delete_up_to(conn, &subject, value.version)?;
+                settle(conn, &subject)?;
+            }
+        }
+        // Tombstones of deletions, and records the topic only uses internally
+        (KeyType::DeleteSubject, None) | (KeyType::ClearSubject, _) | (KeyType::Noop, _) => {}
+    }
+    Ok(ReplaySummary::default())
+}
+
+fn parse<T: DeserializeOwned>(value: JsonValue) -> Result<T, AdminError> {
+    serde_json::from_value(value).map_err(AdminError::Malformed)
+}
+
+fn replay_schema(conn: &mut PgConnection, value: SchemaValue) -> Result<ReplaySummary, AdminError> {
+    let mut replayed = ReplaySummary::default();
+    if let Some(schema_type) = value.schema_type.as_deref().filter(|t| *t != "AVRO") {
+        return Err(AdminError::Dump(format!(
+            "schema {} is a {} schema, only Avro ones are supported",
+            value.id, schema_type
+        )));
+    }
+
+    match Schema::get_by_id(conn, value.id) {
+        Ok(schema) if schema.json == value.schema => {}
+        Ok(_) => {
+            return Err(AdminError::Dump(format!(
+                "schema {} isn't the one already imported with that ID",
+                value.id
+            )))
+        }
+        Err(e) if e.response.error_code == ApiAvroErrorCode::SchemaNotFound => {
+            let resolved = SchemaReference::resolve(conn, &value.references)?;
+            let parsed = Schema::parse(&value.schema, &resolved)?;
+            let fingerprint = Schema::fingerprint_with_references(&parsed, &value.references);
+            if let Some(existing) = Schema::find_by_fingerprint(conn, fingerprint.to_owned())? {
+                return Err(AdminError::Dump(format!(
+                    "schema {} is the same as schema {}, which can't be registered twice",
+                    value.id, existing.id
+                )));
+            }
+            let now = Utc::now().naive_utc();
+            Schema::insert_with_id(
+                conn,
+                value.id,
+                NewSchema {
+                    fingerprint: Schema::salsify_fingerprint(&fingerprint),
+                    json: value.schema,
+                    created_at: now,
+                    updated_at: now,
+                    fingerprint2: Some(fingerprint),
+                },
+            )?;
+            SchemaReference::insert(conn, value.id, &value.references)?;
+            // Done as schemas are replayed, so that an interrupted replay doesn't make
+            // registrations reuse their IDs
+            Schema::reset_id_sequence(conn)?;
+            replayed.schemas += 1;
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    let subject = match find_subject(conn, value.subject.to_owned())? {
+        Some(subject) => subject,
+        None => Subject::insert(conn, value.subject)?,
+    };
+    let version = match SchemaVersion::with_number(conn, &subject, value.version)? {
+        Some(version) if version.schema_id == value.id => version,
+        Some(version) => {
+            return Err(AdminError::Dump(format!(
+                "version {} of {} is schema {}, not {}",
+                value.version, subject.name, version.schema_id, value.id
+            )))
+        }
+        None => {
+            replayed.versions += 1;
+            SchemaVersion::insert(
+                conn,
+                NewSchemaVersion {
+                    version: Some(value.version),
+                    subject_id: subject.id,
+                    schema_id: value.id,
+                },
+            )?
+        }
+    };
+    match (value.deleted, version.deleted) {
+        (true, false) => version.mark_deleted(conn)?,
+        (false, true) => {
+            version.restore(conn)?;
+        }
+        _ => {}
+    }
+    settle(conn, &subject)?;
+    Ok(replayed)
+}
+
+fn find_subject(conn: &mut PgConnection, name: String) -> Result<Option<Subject>, ApiError> {
+    match Subject::get_by_name_with_deleted(conn, name) {
+        Ok(subject) => Ok(Some(subject)),
+        Err(e) if e.response.error_code == ApiAvroErrorCode::SubjectNotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Configures a subject, created if needed: Confluent lets subjects without versions be
+/// configured, unlike this registry does with those it soft deleted.
+fn configure(
+    conn: &mut PgConnection,
+    name: String,
+    apply: impl FnOnce(&mut PgConnection, &Subject) -> Result<(), ApiError>,
+) -> Result<(), ApiError> {
+    // Also restores the subject if it was soft deleted
+    let subject = Subject::insert(conn, name)?;
+    apply(conn, &subject)?;
+    settle(conn, &subject)
+}
+
+/// Soft deletes a subject without active versions, which Confluent doesn't list, or
+/// restores one with some.
+fn settle(conn: &mut PgConnection, subject: &Subject) -> Result<(), ApiError> {
+    if SchemaVersion::has_active(conn, subject)? {
+        Subject::insert(conn, subject.name.to_owned()).map(drop)
+    } else {
+        subject.mark_deleted(conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_keys_are_parsed() {
+        let key = serde_json::from_str::<TopicKey>(
+            r#"{"keytype":"SCHEMA","subject":"payments","version":2,"magic":1}"#,
+        )
+        .unwrap();
+        assert_eq!(key.keytype, KeyType::Schema);
+        assert_eq!(key.subject.as_deref(), Some("payments"));
+        assert_eq!(key.version, Some(2));
+
+        let key = serde_json::from_str::<TopicKey>(r#"{"keytype":"CONFIG","magic":0}"#).unwrap();
+        assert_eq!(key.keytype, KeyType::Config);
+        assert_eq!(key.subject, None);
+
+        assert!(serde_json::from_str::<TopicKey>(r#"{"keytype":"OFFSET","magic":0}"#).is_err());
+    }
+
+    #[test]
+    fn schema_values_default_to_active_avro_without_references() {
+        let value = parse::<SchemaValue>(json!({
+            "subject": "payments",
+            "version": 1,
+            "id": 21,
+            "schema": "\"string\"",
+        }))
+        .unwrap();
+        assert_eq!(value.id, 21);
+        assert_eq!(value.schema_type, None);
+        assert!(value.references.is_empty());
+        assert!(!value.deleted);
+    }
+}
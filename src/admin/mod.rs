@@ -0,0 +1,689 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::db::models::{
+    AuditAction, AuditContext, AuditEvent, CompatibilityLevel, Config, Mode, NewSchema,
+    NewSchemaVersion, Reference, RegisterSchema, RegisteredSchema, Schema, SchemaReference,
+    SchemaVersion, Subject,
+};
+
+pub use self::confluent::*;
+pub use self::salsify::*;
+
+mod confluent;
+mod salsify;
+
+/// How many schemas are loaded at once, when going through all of them.
+const BATCH_SIZE: i64 = 1000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AdminError {
+    #[error("{}", describe(.0))]
+    Api(#[from] ApiError),
+    #[error("malformed record: {0}")]
+    Malformed(serde_json::Error),
+    #[error("invalid export: {0}")]
+    Invalid(String),
+    #[error("invalid dump: {0}")]
+    Dump(String),
+    #[error("line {0}: {1}")]
+    Line(usize, Box<AdminError>),
+    #[error("{} schemas don't match their exported fingerprints, such as: {}", .0.len(), .0[0])]
+    Integrity(Vec<Drift>),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl From<diesel::result::Error> for AdminError {
+    fn from(error: diesel::result::Error) -> Self {
+        Self::Api(error.into())
+    }
+}
+
+/// The message of an error, along with its cause for internal ones: unlike clients,
+/// operators get to see it.
+fn describe(error: &ApiError) -> String {
+    match &error.cause {
+        Some(cause) => format!("{}: {}", error.response.message, cause),
+        None => error.response.message.to_owned(),
+    }
+}
+
+/// Who mutations made by the admin subcommands are recorded as in the audit log.
+pub fn audit_context() -> AuditContext {
+    AuditContext {
+        actor: Some("cli".to_string()),
+        request_id: None,
+    }
+}
+
+/// Version of the export format, which imports only accept exports of.
+pub const EXPORT_FORMAT: u32 = 1;
+
+/// A line of an export, as a JSON object tagged by its `type`.
+///
+/// An export starts with its header, then the global config, the subjects, the schemas
+/// and the versions, each in the order they were created, and ends with the number of
+/// records before its end, so that truncated exports can be told apart.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportRecord {
+    Header {
+        format: u32,
+        registry_version: String,
+    },
+    /// Compatibility level and mode of the registry, unset until first changed
+    Config {
+        compatibility: Option<CompatibilityLevel>,
+        mode: Option<Mode>,
+    },
+    /// A subject, along with its own compatibility level and mode if it has any
+    Subject {
+        name: String,
+        deleted: bool,
+        compatibility: Option<CompatibilityLevel>,
+        mode: Option<Mode>,
+        /// Unset in exports made before timestamps were, restored as the time of import
+        #[serde(default)]
+        created_at: Option<NaiveDateTime>,
+        #[serde(default)]
+        updated_at: Option<NaiveDateTime>,
+    },
+    Schema {
+        id: i64,
+        fingerprint: String,
+        fingerprint2: Option<String>,
+        schema: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        references: Vec<Reference>,
+        #[serde(default)]
+        created_at: Option<NaiveDateTime>,
+        #[serde(default)]
+        updated_at: Option<NaiveDateTime>,
+    },
+    /// A version of a subject, `id` being that of its schema
+    Version {
+        subject: String,
+        version: i32,
+        id: i64,
+        deleted: bool,
+    },
+    End {
+        records: usize,
+    },
+}
+
+/// Writes every subject, schema, version, config and reference as newline delimited
+/// JSON, see [`ExportRecord`]. Everything is read from a single snapshot of the database,
+/// while registrations go on.
+pub fn export(conn: &mut PgConnection, output: &mut impl Write) -> Result<usize, AdminError> {
+    conn.build_transaction()
+        .read_only()
+        .repeatable_read()
+        .run::<_, AdminError, _>(|conn| {
+            let mut writer = ExportWriter { output, records: 0 };
+            writer.write(ExportRecord::Header {
+                format: EXPORT_FORMAT,
+                registry_version: env!("CARGO_PKG_VERSION").to_string(),
+            })?;
+
+            let global = Config::global(conn)?;
+            writer.write(ExportRecord::Config {
+                compatibility: parse_stored(
+                    global.as_ref().and_then(|c| c.compatibility.as_ref()),
+                )?,
+                mode: parse_stored(global.as_ref().and_then(|c| c.mode.as_ref()))?,
+            })?;
+
+            let mut after = 0;
+            loop {
+                let subjects = Subject::page(conn, after, BATCH_SIZE)?;
+                let Some(last) = subjects.last() else {
+                    break;
+                };
+                after = last.id;
+                let ids = subjects
+                    .iter()
+                    .map(|subject| subject.id)
+                    .collect::<Vec<_>>();
+                let mut configs = HashMap::new();
+                for config in Config::of_subjects(conn, &ids)? {
+                    configs.insert(config.subject_id, config);
+                }
+                for subject in subjects {
+                    let config = configs.remove(&Some(subject.id));
+                    writer.write(ExportRecord::Subject {
+                        compatibility: parse_stored(
+                            config.as_ref().and_then(|c| c.compatibility.as_ref()),
+                        )?,
+                        mode: parse_stored(config.as_ref().and_then(|c| c.mode.as_ref()))?,
+                        name: subject.name,
+                        deleted: subject.deleted,
+                        created_at: Some(subject.created_at),
+                        updated_at: Some(subject.updated_at),
+                    })?;
+                }
+            }
+
+            let mut after = 0;
+            loop {
+                let schemas = Schema::page(conn, after, BATCH_SIZE)?;
+                let Some(last) = schemas.last() else {
+                    break;
+                };
+                after = last.id;
+                let references = SchemaReference::of_schemas(conn, &schemas)?;
+                for (schema, references) in schemas.into_iter().zip(references) {
+                    writer.write(ExportRecord::Schema {
+                        id: schema.id,
+                        fingerprint: schema.fingerprint,
+                        fingerprint2: schema.fingerprint2,
+                        schema: schema.json,
+                        references,
+                        created_at: Some(schema.created_at),
+                        updated_at: Some(schema.updated_at),
+                    })?;
+                }
+            }
+
+            let mut after = 0;
+            loop {
+                let versions = SchemaVersion::page_with_subject(conn, after, BATCH_SIZE)?;
+                let Some((last, _)) = versions.last() else {
+                    break;
+                };
+                after = last.id;
+                for (version, subject) in versions {
+                    let (Some(subject), Some(number)) = (subject, version.version) else {
+                        warn!(
+                            "version {} has no subject or number, it isn't exported",
+                            version.id
+                        );
+                        continue;
+                    };
+                    writer.write(ExportRecord::Version {
+                        subject,
+                        version: number,
+                        id: version.schema_id,
+                        deleted: version.deleted,
+                    })?;
+                }
+            }
+
+            let records = writer.records;
+            writer.write(ExportRecord::End { records })?;
+            writer.output.flush()?;
+            Ok(writer.records)
+        })
+}
+
+/// Writes the records of an export, counting them.
+struct ExportWriter<'a, W> {
+    output: &'a mut W,
+    records: usize,
+}
+
+impl<W: Write> ExportWriter<'_, W> {
+    fn write(&mut self, record: ExportRecord) -> Result<(), AdminError> {
+        serde_json::to_writer(&mut *self.output, &record).map_err(io::Error::from)?;
+        self.output.write_all(b"\n")?;
+        self.records += 1;
+        Ok(())
+    }
+}
+
+/// Parses a compatibility level or mode as stored in the database.
+fn parse_stored<T: FromStr>(value: Option<&String>) -> Result<Option<T>, ApiError> {
+    value
+        .map(|value| {
+            value.parse().map_err(|_| {
+                ApiError::internal(
+                    ApiAvroErrorCode::BackendDatastoreError,
+                    format!("invalid config {}", value),
+                )
+            })
+        })
+        .transpose()
+}
+
+/// What an import restored.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub subjects: usize,
+    pub schemas: usize,
+    pub versions: usize,
+}
+
+/// Restores an export into an empty registry, in a single transaction. Schemas keep
+/// their IDs, and subjects and versions are restored in the same order, soft deleted or
+/// not, along with every config.
+///
+/// Once restored, the fingerprint of every schema is computed again, and nothing is kept
+/// unless each one matches the exported ones.
+pub fn import(
+    conn: &mut PgConnection,
+    input: impl BufRead,
+    audit: &AuditContext,
+) -> Result<ImportSummary, AdminError> {
+    conn.transaction::<_, AdminError, _>(|conn| {
+        if Schema::count(conn)? > 0 || Subject::count(conn)? > 0 {
+            return Err(ApiError::new(ApiAvroErrorCode::OperationNotPermitted)
+                .with_detail("exports can only be imported into an empty registry")
+                .into());
+        }
+
+        let mut import = Import::default();
+        let mut records = 0;
+        let mut ended = false;
+        for (index, line) in input.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let at_line = |e| AdminError::Line(index + 1, Box::new(e));
+            let record = serde_json::from_str::<ExportRecord>(&line)
+                .map_err(|e| at_line(AdminError::Malformed(e)))?;
+            let invalid = |message: String| Err(at_line(AdminError::Invalid(message)));
+            match (records, record) {
+                _ if ended => return invalid("nothing can follow the end".to_string()),
+                (0, ExportRecord::Header { format, .. }) if format != EXPORT_FORMAT => {
+                    return invalid(format!("format {} isn't supported", format))
+                }
+                (0, ExportRecord::Header { .. }) => {}
+                (0, _) => return invalid("the header is missing".to_string()),
+                (_, ExportRecord::Header { .. }) => {
+                    return invalid("there is a second header".to_string())
+                }
+                (_, ExportRecord::End { records: expected }) if expected != records => {
+                    return invalid(format!(
+                        "{} records were exported, but {} were read",
+                        expected, records
+                    ))
+                }
+                (_, ExportRecord::End { .. }) => ended = true,
+                (_, record) => import.record(conn, record).map_err(at_line)?,
+            }
+            records += 1;
+        }
+        if !ended {
+            return Err(AdminError::Invalid("the export is truncated".to_string()));
+        }
+
+        Schema::reset_id_sequence(conn)?;
+        let drifts = verify(conn)?;
+        if !drifts.is_empty() {
+            return Err(AdminError::Integrity(drifts));
+        }
+        AuditEvent::record(
+            conn,
+            audit,
+            AuditAction::ImportRegistry,
+            None,
+            None,
+            Some(json!({
+                "subjects": import.summary.subjects,
+                "schemas": import.summary.schemas,
+                "versions": import.summary.versions,
+            })),
+        )?;
+        Ok(import.summary)
+    })
+}
+
+/// State of an import, between its records.
+#[derive(Default)]
+struct Import {
+    /// Subjects restored so far, by name
+    subjects: HashMap<String, i64>,
+    summary: ImportSummary,
+}
+
+impl Import {
+    fn record(&mut self, conn: &mut PgConnection, record: ExportRecord) -> Result<(), AdminError> {
+        match record {
+            ExportRecord::Config {
+                compatibility,
+                mode,
+            } => {
+                if let Some(compatibility) = compatibility {
+                    Config::set_global_compatibility(conn, &compatibility.valid()?.to_string())?;
+                }
+                if let Some(mode) = mode {
+                    Config::set_global_mode(conn, mode.valid()?, true)?;
+                }
+            }
+            ExportRecord::Subject {
+                name,
+                deleted,
+                compatibility,
+                mode,
+                created_at,
+                updated_at,
+            } => {
+                if self.subjects.contains_key(&name) {
+                    return Err(AdminError::Invalid(format!("subject {} is repeated", name)));
+                }
+                let subject = Subject::insert(conn, name.to_owned())?;
+                if let Some(compatibility) = compatibility {
+                    let compatibility = compatibility.valid()?.to_string();
+                    Config::set_with_subject_name(conn, name.to_owned(), compatibility)?;
+                }
+                if let Some(mode) = mode {
+                    Config::set_subject_mode(conn, name.to_owned(), mode.valid()?, true)?;
+                }
+                // Only once configured, since soft deleted subjects can't be
+                if deleted {
+                    subject.mark_deleted(conn)?;
+                }
+                if let (Some(created_at), Some(updated_at)) = (created_at, updated_at) {
+                    subject.set_timestamps(conn, created_at, updated_at)?;
+                }
+                self.subjects.insert(name, subject.id);
+                self.summary.subjects += 1;
+            }
+            ExportRecord::Schema {
+                id,
+                fingerprint,
+                fingerprint2,
+                schema,
+                references,
+                created_at,
+                updated_at,
+            } => {
+                let now = Utc::now().naive_utc();
+                Schema::insert_with_id(
+                    conn,
+                    id,
+                    NewSchema {
+                        fingerprint,
+                        json: schema,
+                        created_at: created_at.unwrap_or(now),
+                        updated_at: updated_at.unwrap_or(now),
+                        fingerprint2,
+                    },
+                )?;
+                SchemaReference::insert(conn, id, &references)?;
+                self.summary.schemas += 1;
+            }
+            ExportRecord::Version {
+                subject,
+                version,
+                id,
+                deleted,
+            } => {
+                let subject_id = *self.subjects.get(&subject).ok_or_else(|| {
+                    AdminError::Invalid(format!("subject {} isn't exported", subject))
+                })?;
+                let version = SchemaVersion::insert(
+                    conn,
+                    NewSchemaVersion {
+                        version: Some(version),
+                        subject_id,
+                        schema_id: id,
+                    },
+                )?;
+                if deleted {
+                    version.mark_deleted(conn)?;
+                }
+                self.summary.versions += 1;
+            }
+            ExportRecord::Header { .. } | ExportRecord::End { .. } => {
+                unreachable!("the header and end are handled by the import")
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Registers a schema under a subject, as `POST /subjects/{subject}/versions` does.
+pub fn register(
+    conn: &mut PgConnection,
+    registration: RegisterSchema,
+    audit: &AuditContext,
+) -> Result<RegisteredSchema, ApiError> {
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let subject = registration.subject.clone();
+        let registered = Schema::register(conn, registration)?;
+        if let Some(version) = registered.created_version {
+            AuditEvent::record(
+                conn,
+                audit,
+                AuditAction::RegisterSchema,
+                Some(&subject),
+                None,
+                Some(json!({ "id": registered.schema.id, "version": version })),
+            )?;
+        }
+        Ok(registered)
+    })
+}
+
+/// Whether a valid schema could be registered under a subject, at the subject's
+/// compatibility level.
+pub fn check(
+    conn: &mut PgConnection,
+    subject: &str,
+    schema: &str,
+    references: &[Reference],
+) -> Result<bool, ApiError> {
+    let schema = Schema::parse(schema, &SchemaReference::resolve(conn, references)?)?;
+    match Schema::ensure_compatible(conn, subject, &schema) {
+        Ok(()) => Ok(true),
+        Err(e) if e.response.error_code == ApiAvroErrorCode::IncompatibleSchema => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Sets the compatibility level of a subject, or the global one without a subject, as
+/// `PUT /config` does.
+pub fn set_compatibility(
+    conn: &mut PgConnection,
+    subject: Option<&str>,
+    level: CompatibilityLevel,
+    audit: &AuditContext,
+) -> Result<String, ApiError> {
+    let compatibility = level.valid()?.to_string();
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let (action, old, new) = match subject {
+            None => (
+                AuditAction::SetGlobalConfig,
+                Some(Config::get_global_compatibility(conn)?),
+                Config::set_global_compatibility(conn, &compatibility)?,
+            ),
+            Some(subject) => (
+                AuditAction::SetSubjectConfig,
+                Config::get_with_subject_name(conn, subject.to_owned()).ok(),
+                Config::set_with_subject_name(conn, subject.to_owned(), compatibility)?,
+            ),
+        };
+        AuditEvent::record(
+            conn,
+            audit,
+            action,
+            subject,
+            old.map(|old| json!({ "compatibility": old })),
+            Some(json!({ "compatibility": new })),
+        )?;
+        Ok(new)
+    })
+}
+
+/// Deletes the schemas no version refers to anymore, and returns their IDs.
+pub fn collect_garbage(
+    conn: &mut PgConnection,
+    audit: &AuditContext,
+) -> Result<Vec<i64>, ApiError> {
+    conn.transaction::<_, ApiError, _>(|conn| {
+        let deleted = Schema::delete_orphans(conn)?;
+        if !deleted.is_empty() {
+            AuditEvent::record(
+                conn,
+                audit,
+                AuditAction::DeleteOrphanSchemas,
+                None,
+                Some(json!({ "ids": deleted })),
+                None,
+            )?;
+        }
+        Ok(deleted)
+    })
+}
+
+/// A schema whose stored fingerprints don't match its JSON anymore.
+#[derive(Debug)]
+pub enum Drift {
+    /// The schema, or one it references, can't be parsed anymore
+    Unparseable { id: i64, error: ApiError },
+    /// The fingerprints stored differ from the one computed from the JSON
+    Fingerprint {
+        id: i64,
+        fingerprint: String,
+        fingerprint2: Option<String>,
+        computed: String,
+    },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unparseable { id, error } => {
+                write!(f, "schema {} can't be parsed: {}", id, describe(error))
+            }
+            Self::Fingerprint {
+                id,
+                fingerprint,
+                fingerprint2,
+                computed,
+            } => write!(
+                f,
+                "schema {} has fingerprint {} and fingerprint2 {}, but its JSON has {}",
+                id,
+                fingerprint,
+                fingerprint2.as_deref().unwrap_or("NULL"),
+                computed
+            ),
+        }
+    }
+}
+
+/// Parses every schema again, with its references, and compares its fingerprint to the
+/// stored ones: `fingerprint2` holds it as is and `fingerprint` in salsify's format, or
+/// as is for schemas registered before this registry used it. Returns the schemas that
+/// differ, by ID.
+pub fn verify(conn: &mut PgConnection) -> Result<Vec<Drift>, ApiError> {
+    let mut drifts = vec![];
+    let mut after = 0;
+    loop {
+        let schemas = Schema::page(conn, after, BATCH_SIZE)?;
+        let Some(last) = schemas.last() else {
+            return Ok(drifts);
+        };
+        after = last.id;
+        for schema in schemas {
+            if let Some(drift) = verify_schema(conn, schema)? {
+                drifts.push(drift);
+            }
+        }
+    }
+}
+
+fn verify_schema(conn: &mut PgConnection, schema: Schema) -> Result<Option<Drift>, ApiError> {
+    let parsed = match schema.parse_registered(conn) {
+        Ok(parsed) => parsed,
+        Err(error) if error.cause.is_none() => {
+            return Ok(Some(Drift::Unparseable {
+                id: schema.id,
+                error,
+            }))
+        }
+        Err(e) => return Err(e),
+    };
+    let references = SchemaReference::of_schema(conn, schema.id)?;
+    let computed = Schema::fingerprint_with_references(&parsed, &references);
+    if schema.has_fingerprint(&computed) && schema.fingerprint2.as_ref() == Some(&computed) {
+        return Ok(None);
+    }
+    Ok(Some(Drift::Fingerprint {
+        id: schema.id,
+        fingerprint: schema.fingerprint,
+        fingerprint2: schema.fingerprint2,
+        computed,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_records_are_tagged() {
+        let subject = ExportRecord::Subject {
+            name: "a".to_string(),
+            deleted: false,
+            compatibility: Some(CompatibilityLevel::FullTransitive),
+            mode: None,
+            created_at: chrono::DateTime::from_timestamp(0, 0).map(|t| t.naive_utc()),
+            updated_at: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&subject).unwrap(),
+            json!({
+                "type": "subject",
+                "name": "a",
+                "deleted": false,
+                "compatibility": "FULL_TRANSITIVE",
+                "mode": null,
+                "created_at": "1970-01-01T00:00:00",
+                "updated_at": null,
+            })
+        );
+
+        let line =
+            r#"{"type":"schema","id":7,"fingerprint":"f","fingerprint2":"f","schema":"\"int\""}"#;
+        match serde_json::from_str::<ExportRecord>(line).unwrap() {
+            ExportRecord::Schema {
+                id,
+                schema,
+                references,
+                created_at,
+                ..
+            } => {
+                assert_eq!((id, schema.as_str()), (7, "\"int\""));
+                assert!(references.is_empty());
+                // exports made before timestamps were still import
+                assert!(created_at.is_none());
+            }
+            record => panic!("unexpected record {:?}", record),
+        }
+
+        assert!(serde_json::from_str::<ExportRecord>(r#"{"type":"schemas"}"#).is_err());
+    }
+
+    #[test]
+    fn internal_errors_are_described_with_their_cause() {
+        let error = AdminError::from(ApiError::internal(
+            ApiAvroErrorCode::BackendDatastoreError,
+            "connection reset",
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Error in the backend datastore: \"connection reset\""
+        );
+
+        let error = AdminError::Line(
+            3,
+            Box::new(ApiError::new(ApiAvroErrorCode::IncompatibleSchema).into()),
+        );
+        assert!(error.to_string().starts_with("line 3: "));
+    }
+}
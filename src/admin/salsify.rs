@@ -0,0 +1,256 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde_json::json;
+
+use crate::api::errors::ApiError;
+use crate::db::models::{
+    AuditAction, AuditContext, AuditEvent, CompatibilityLevel, Config, Mode, Schema,
+    SchemaReference, SchemaVersion, Subject,
+};
+use crate::db::{baseline_salsify, run_migrations};
+
+use super::{describe, AdminError, BATCH_SIZE};
+
+/// What adopting a database of salsify's registry did, or would do.
+#[derive(Debug, Default)]
+pub struct SalsifyAdoption {
+    /// Migrations recorded as run, since salsify's registry created their tables
+    pub baseline: Vec<String>,
+    /// Migrations run after those
+    pub migrations: Vec<String>,
+    pub schemas: usize,
+    /// Schemas whose fingerprints were rewritten
+    pub updated: usize,
+    pub problems: Vec<Problem>,
+}
+
+/// A row this registry can't use as it is, which is left untouched.
+#[derive(Debug)]
+pub enum Problem {
+    /// The schema, or one it references, can't be parsed
+    Unparseable {
+        id: i64,
+        error: ApiError,
+    },
+    /// The stored fingerprint is neither salsify's nor this registry's
+    Fingerprint {
+        id: i64,
+        stored: String,
+        expected: String,
+    },
+    /// The schema is the same as one with a lower ID
+    Duplicate {
+        id: i64,
+        of: i64,
+    },
+    Version {
+        id: i64,
+        reason: String,
+    },
+    Config {
+        id: i64,
+        reason: String,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unparseable { id, error } => {
+                write!(f, "schema {} can't be parsed: {}", id, describe(error))
+            }
+            Self::Fingerprint {
+                id,
+                stored,
+                expected,
+            } => write!(
+                f,
+                "schema {} has fingerprint {}, but its JSON has {}: registering it again \
+                 would give it another ID",
+                id, stored, expected
+            ),
+            Self::Duplicate { id, of } => write!(f, "schema {} is the same as schema {}", id, of),
+            Self::Version { id, reason } => write!(f, "schema version {} {}", id, reason),
+            Self::Config { id, reason } => write!(f, "config {} {}", id, reason),
+        }
+    }
+}
+
+/// Adopts a database of salsify's avro-schema-registry in place, in a single transaction,
+/// which a dry run rolls back once done.
+///
+/// The migrations creating salsify's tables are recorded as run and the others are run.
+/// Then the fingerprint of every schema is computed again, to store it in `fingerprint2`
+/// and salsify's format of it in `fingerprint`, so that both registries find the schemas
+/// either registers. Schema versions and configs are checked as well.
+pub fn adopt_salsify(
+    conn: &mut PgConnection,
+    dry_run: bool,
+    audit: &AuditContext,
+) -> Result<SalsifyAdoption, AdminError> {
+    let mut adoption = None;
+    let done = conn.transaction(|conn| {
+        let adopted = adopt(conn, audit);
+        let commit = adopted.is_ok() && !dry_run;
+        adoption = Some(adopted);
+        if commit {
+            Ok(())
+        } else {
+            Err(diesel::result::Error::RollbackTransaction)
+        }
+    });
+    match done {
+        Ok(()) | Err(diesel::result::Error::RollbackTransaction) => {
+            adoption.expect("the adoption ran in the transaction")
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn adopt(conn: &mut PgConnection, audit: &AuditContext) -> Result<SalsifyAdoption, AdminError> {
+    let mut adoption = SalsifyAdoption {
+        baseline: baseline_salsify(conn)?,
+        migrations: run_migrations(conn)?,
+        ..SalsifyAdoption::default()
+    };
+
+    let mut schemas = HashSet::new();
+    // IDs of the schemas checked so far, by the fingerprint this registry gives them
+    let mut fingerprints = HashMap::new();
+    let mut after = 0;
+    loop {
+        let page = Schema::page(conn, after, BATCH_SIZE)?;
+        let Some(last) = page.last() else {
+            break;
+        };
+        after = last.id;
+        for schema in page {
+            schemas.insert(schema.id);
+            adopt_schema(conn, schema, &mut fingerprints, &mut adoption)?;
+        }
+    }
+
+    let subjects = Subject::all(conn)?
+        .into_iter()
+        .map(|subject| subject.id)
+        .collect::<HashSet<_>>();
+    for version in SchemaVersion::all(conn)? {
+        let reason = if version.version.is_none() {
+            "has no number".to_string()
+        } else if !subjects.contains(&version.subject_id) {
+            format!(
+                "belongs to subject {}, which doesn't exist",
+                version.subject_id
+            )
+        } else if !schemas.contains(&version.schema_id) {
+            format!("is schema {}, which doesn't exist", version.schema_id)
+        } else {
+            continue;
+        };
+        adoption.problems.push(Problem::Version {
+            id: version.id,
+            reason,
+        });
+    }
+    for config in Config::all(conn)? {
+        let reason = match (&config.subject_id, &config.compatibility, &config.mode) {
+            (Some(subject), _, _) if !subjects.contains(subject) => {
+                format!("belongs to subject {}, which doesn't exist", subject)
+            }
+            (_, Some(level), _) if level.parse::<CompatibilityLevel>().is_err() => {
+                format!("has the unknown compatibility level {}", level)
+            }
+            (_, _, Some(mode)) if mode.parse::<Mode>().is_err() => {
+                format!("has the unknown mode {}", mode)
+            }
+            _ => continue,
+        };
+        adoption.problems.push(Problem::Config {
+            id: config.id,
+            reason,
+        });
+    }
+
+    AuditEvent::record(
+        conn,
+        audit,
+        AuditAction::AdoptSalsify,
+        None,
+        None,
+        Some(json!({
+            "schemas": adoption.schemas,
+            "updated": adoption.updated,
+            "problems": adoption.problems.len(),
+        })),
+    )?;
+    Ok(adoption)
+}
+
+fn adopt_schema(
+    conn: &mut PgConnection,
+    schema: Schema,
+    fingerprints: &mut HashMap<String, i64>,
+    adoption: &mut SalsifyAdoption,
+) -> Result<(), ApiError> {
+    adoption.schemas += 1;
+    let parsed = match schema.parse_registered(conn) {
+        Ok(parsed) => parsed,
+        Err(error) if error.cause.is_none() => {
+            adoption.problems.push(Problem::Unparseable {
+                id: schema.id,
+                error,
+            });
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+    let references = SchemaReference::of_schema(conn, schema.id)?;
+    let computed = Schema::fingerprint_with_references(&parsed, &references);
+    let expected = Schema::salsify_fingerprint(&computed);
+
+    // Schemas this registry registered before it used salsify's format have its own
+    if !schema.has_fingerprint(&computed) {
+        adoption.problems.push(Problem::Fingerprint {
+            id: schema.id,
+            stored: schema.fingerprint,
+            expected,
+        });
+        return Ok(());
+    }
+    if let Some(&of) = fingerprints.get(&computed) {
+        adoption
+            .problems
+            .push(Problem::Duplicate { id: schema.id, of });
+        return Ok(());
+    }
+    if schema.fingerprint != expected || schema.fingerprint2.as_ref() != Some(&computed) {
+        schema.update_fingerprints(conn, &computed)?;
+        adoption.updated += 1;
+    }
+    fingerprints.insert(computed, schema.id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn salsify_fingerprints_have_no_leading_zeros() {
+        assert_eq!(Schema::salsify_fingerprint("00a1f0"), "a1f0");
+        assert_eq!(Schema::salsify_fingerprint("a1f0"), "a1f0");
+        assert_eq!(Schema::salsify_fingerprint("0000"), "0");
+    }
+
+    #[test]
+    fn problems_name_the_row() {
+        let problem = Problem::Version {
+            id: 3,
+            reason: "has no number".to_string(),
+        };
+        assert_eq!(problem.to_string(), "schema version 3 has no number");
+    }
+}
@@ -0,0 +1,163 @@
+use actix_web::{
+    dev::Payload,
+    web::{Data, Json, Path},
+    FromRequest, HttpMessage, HttpRequest, HttpResponse, Responder,
+};
+use diesel::Connection;
+use futures::future::{ready, Ready};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::auth::{Acl, DenyByDefault, Principal, Role, UserStore};
+use crate::db::models::{AclRule, AclRuleBody, ApiKey, AuditAction, AuditContext, AuditEvent};
+use crate::db::{DbManage, DbPool};
+
+/// Principal identified by [`VerifyAuthorization`], which every registry endpoint goes
+/// through.
+///
+/// [`VerifyAuthorization`]: crate::middleware::VerifyAuthorization
+impl FromRequest for Principal {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Principal>()
+                .cloned()
+                .ok_or_else(|| ApiError::new(ApiAvroErrorCode::Unauthenticated)),
+        )
+    }
+}
+
+/// Operation on a subject to check a principal's access for, the calling one unless an
+/// admin names another.
+#[derive(Debug, Deserialize)]
+pub struct AclCheck {
+    pub principal: Option<String>,
+    pub subject: String,
+    /// Role the operation requires, see [`Role::required_for`]
+    pub role: Role,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AclCheckResponse {
+    pub allowed: bool,
+    /// Role the principal has on the subject, if any
+    pub effective_role: Option<Role>,
+}
+
+pub async fn get_acl_rules(db: Data<DbPool>) -> impl Responder {
+    match db.run(|conn| AclRule::all(conn)).await {
+        Ok(rules) => Ok(HttpResponse::Ok().json(rules)),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn post_acl_rule(
+    body: Json<AclRuleBody>,
+    audit: AuditContext,
+    db: Data<DbPool>,
+) -> impl Responder {
+    let rule = body.into_inner();
+    match db
+        .run(move |conn| {
+            conn.transaction::<_, ApiError, _>(|conn| {
+                let new = json!(rule);
+                let rule = AclRule::insert(conn, rule)?;
+                AuditEvent::record(
+                    conn,
+                    &audit,
+                    AuditAction::CreateAclRule,
+                    None,
+                    None,
+                    Some(new),
+                )?;
+                Ok(rule)
+            })
+        })
+        .await
+    {
+        Ok(rule) => Ok(HttpResponse::Ok().json(rule)),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn delete_acl_rule(
+    id: Path<i64>,
+    audit: AuditContext,
+    db: Data<DbPool>,
+) -> impl Responder {
+    let id = id.into_inner();
+    match db
+        .run(move |conn| {
+            conn.transaction::<_, ApiError, _>(|conn| {
+                let rule = AclRule::delete(conn, id)?;
+                AuditEvent::record(
+                    conn,
+                    &audit,
+                    AuditAction::DeleteAclRule,
+                    None,
+                    Some(json!(rule)),
+                    None,
+                )?;
+                Ok(rule)
+            })
+        })
+        .await
+    {
+        Ok(rule) => Ok(HttpResponse::Ok().json(rule)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Tells whether a principal is allowed an operation on a subject, without doing it.
+///
+/// Principals check their own access, while admins may check that of any principal. Its
+/// role is the one of the users file, or the best one of its active API keys. Principals
+/// known from neither, like those of bearer tokens, are assumed to have the role checked,
+/// so that the answer is what their ACL rules allow.
+pub async fn check_acl(
+    body: Json<AclCheck>,
+    principal: Principal,
+    deny_by_default: Option<Data<DenyByDefault>>,
+    users: Data<UserStore>,
+    db: Data<DbPool>,
+) -> impl Responder {
+    let check = body.into_inner();
+    let deny_by_default = deny_by_default.is_some();
+    let named = match check.principal {
+        Some(name) if name != principal.name => {
+            if !principal.can(Role::Admin) {
+                return Err(ApiError::new(ApiAvroErrorCode::UserDeniedOperation));
+            }
+            Some(name)
+        }
+        _ => None,
+    };
+    match db
+        .run(move |conn| {
+            let principal = match named {
+                Some(name) => match users.principal(&name) {
+                    Some(principal) => principal,
+                    None => Principal {
+                        role: ApiKey::role_of_owner(conn, &name)?.unwrap_or(check.role),
+                        name,
+                    },
+                },
+                None => principal,
+            };
+            let rules = AclRule::for_principal(conn, &principal.name)?;
+            let acl = Acl::new(&principal, &rules, deny_by_default);
+            Ok(AclCheckResponse {
+                allowed: acl.permits(&check.subject, check.role),
+                effective_role: acl.role_on(&check.subject),
+            })
+        })
+        .await
+    {
+        Ok(response) => Ok(HttpResponse::Ok().json(response)),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,103 @@
+use actix_web::{
+    web::{Data, Json, Path},
+    HttpResponse, Responder,
+};
+use diesel::Connection;
+use serde_json::json;
+
+use crate::api::errors::ApiError;
+use crate::db::models::{ApiKey, ApiKeyBody, AuditAction, AuditContext, AuditEvent};
+use crate::db::{DbManage, DbPool};
+
+pub async fn get_api_keys(db: Data<DbPool>) -> impl Responder {
+    match db.run(|conn| ApiKey::all(conn)).await {
+        Ok(keys) => Ok(HttpResponse::Ok().json(keys)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Create an API key. Its secret is only part of this response, it can't be retrieved
+/// afterwards.
+pub async fn post_api_key(
+    body: Json<ApiKeyBody>,
+    audit: AuditContext,
+    db: Data<DbPool>,
+) -> impl Responder {
+    let body = body.into_inner();
+    match db
+        .run(move |conn| {
+            conn.transaction::<_, ApiError, _>(|conn| {
+                let created = ApiKey::create(conn, body)?;
+                AuditEvent::record(
+                    conn,
+                    &audit,
+                    AuditAction::CreateApiKey,
+                    None,
+                    None,
+                    Some(json!(created.key)),
+                )?;
+                Ok(created)
+            })
+        })
+        .await
+    {
+        Ok(created) => Ok(HttpResponse::Ok().json(created)),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn rotate_api_key(
+    key_id: Path<String>,
+    audit: AuditContext,
+    db: Data<DbPool>,
+) -> impl Responder {
+    let key_id = key_id.into_inner();
+    match db
+        .run(move |conn| {
+            conn.transaction::<_, ApiError, _>(|conn| {
+                let rotated = ApiKey::rotate(conn, &key_id)?;
+                AuditEvent::record(
+                    conn,
+                    &audit,
+                    AuditAction::RotateApiKey,
+                    None,
+                    None,
+                    Some(json!(rotated.key)),
+                )?;
+                Ok(rotated)
+            })
+        })
+        .await
+    {
+        Ok(rotated) => Ok(HttpResponse::Ok().json(rotated)),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn revoke_api_key(
+    key_id: Path<String>,
+    audit: AuditContext,
+    db: Data<DbPool>,
+) -> impl Responder {
+    let key_id = key_id.into_inner();
+    match db
+        .run(move |conn| {
+            conn.transaction::<_, ApiError, _>(|conn| {
+                let revoked = ApiKey::revoke(conn, &key_id)?;
+                AuditEvent::record(
+                    conn,
+                    &audit,
+                    AuditAction::RevokeApiKey,
+                    None,
+                    None,
+                    Some(json!(revoked)),
+                )?;
+                Ok(revoked)
+            })
+        })
+        .await
+    {
+        Ok(revoked) => Ok(HttpResponse::Ok().json(revoked)),
+        Err(e) => Err(e),
+    }
+}
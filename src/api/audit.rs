@@ -0,0 +1,40 @@
+use actix_web::{
+    dev::Payload,
+    web::{Data, Query},
+    FromRequest, HttpMessage, HttpRequest, HttpResponse, Responder,
+};
+use futures::future::{ok, Ready};
+
+use crate::api::errors::ApiError;
+use crate::auth::Principal;
+use crate::db::models::{AuditContext, AuditEvent, AuditFilter};
+use crate::db::{DbManage, DbPool};
+use crate::middleware::RequestId;
+
+impl FromRequest for AuditContext {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ok(Self {
+            actor: req
+                .extensions()
+                .get::<Principal>()
+                .map(|principal| principal.name.to_owned())
+                .filter(|name| !name.is_empty()),
+            request_id: req
+                .extensions()
+                .get::<RequestId>()
+                .map(RequestId::to_string),
+        })
+    }
+}
+
+/// List audit events, newest first, filtered by subject, actor and time range.
+pub async fn get_audit(filter: Query<AuditFilter>, db: Data<DbPool>) -> impl Responder {
+    let filter = filter.into_inner();
+    match db.run(move |conn| AuditEvent::search(conn, &filter)).await {
+        Ok(events) => Ok(HttpResponse::Ok().json(events)),
+        Err(e) => Err(e),
+    }
+}
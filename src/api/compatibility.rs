@@ -1,65 +1,62 @@
-use std::str::FromStr;
-
 use actix_web::{
     web::{Data, Json, Path},
     HttpResponse, Responder,
 };
-use log::info;
 use serde::Serialize;
 
-use crate::api::errors::{ApiAvroErrorCode, ApiError};
-use crate::api::SchemaBody;
-use crate::db::models::{CompatibilityLevel, Config, Schema};
+use crate::api::errors::ApiError;
+use crate::api::SchemaBodyWithReferences;
+use crate::db::models::{Config, Schema, SchemaReference};
 use crate::db::{DbManage, DbPool};
+use crate::metrics::Metrics;
 
+/// Checks the schema against a version of the subject, at the compatibility level of the
+/// subject, or the global one if the subject has none.
 pub async fn check_compatibility(
     info: Path<(String, u32)>,
-    body: Json<SchemaBody>,
+    body: Json<SchemaBodyWithReferences>,
     db: Data<DbPool>,
+    metrics: Data<Metrics>,
 ) -> impl Responder {
     let (subject, version) = info.into_inner();
-    let schema = body.into_inner().schema;
-    info!("method=post,subject={},version={}", subject, version);
+    let body = body.into_inner();
+    let checked = db
+        .run(move |conn| {
+            let sv_response = crate::api::subjects::get_subject_version_from_db(
+                conn,
+                subject.clone(),
+                Some(version),
+            )?;
+            let compatibility = Config::get_compatibility_level(conn, &subject)?;
+            let mut check = || {
+                let old = Schema::parse(
+                    &sv_response.schema,
+                    &SchemaReference::resolve(conn, &sv_response.references)?,
+                )?;
+                let new = Schema::parse(
+                    &body.schema,
+                    &SchemaReference::resolve(conn, &body.references)?,
+                )?;
+                compatibility.allows(&old, &new)
+            };
+            Ok((compatibility, check()))
+        })
+        .await;
 
-    let mut conn = db.connection()?;
-    let sv_response = crate::api::subjects::get_subject_version_from_db(
-        &mut conn,
-        subject.clone(),
-        Some(version),
-    )?;
-    let compatibility = Config::get_with_subject_name(&mut conn, subject)?;
-    if let Ok(compat) = CompatibilityLevel::from_str(&compatibility) {
-        if let Ok(is_compatible) =
-            SchemaCompatibility::is_compatible(&sv_response.schema, &schema, compat)
-        {
-            Ok(HttpResponse::Ok().json(SchemaCompatibility { is_compatible }))
-        } else {
-            Err(ApiError::new(ApiAvroErrorCode::InvalidAvroSchema))
+    let is_compatible = match checked {
+        Ok((compatibility, is_compatible)) => {
+            metrics.compatibility_checked(Some(compatibility), is_compatible.as_ref().copied());
+            is_compatible?
+        }
+        Err(e) => {
+            metrics.compatibility_checked(None, Err(&e));
+            return Err(e);
         }
-    } else {
-        Err(ApiError::new(ApiAvroErrorCode::InvalidAvroSchema))
-    }
+    };
+    Ok::<_, ApiError>(HttpResponse::Ok().json(SchemaCompatibility { is_compatible }))
 }
 
 #[derive(Debug, Serialize)]
 struct SchemaCompatibility {
     is_compatible: bool,
 }
-
-impl SchemaCompatibility {
-    fn is_compatible(
-        old: &str,
-        new: &str,
-        compatibility: CompatibilityLevel,
-    ) -> Result<bool, ApiError> {
-        match compatibility {
-            CompatibilityLevel::CompatNone => Ok(true),
-            CompatibilityLevel::Backward => Schema::is_compatible(new, old),
-            CompatibilityLevel::Forward => Schema::is_compatible(old, new),
-            CompatibilityLevel::Full => {
-                Ok(Schema::is_compatible(old, new)? && Schema::is_compatible(new, old)?)
-            }
-            _ => unimplemented!(),
-        }
-    }
-}
@@ -1,28 +1,50 @@
 use actix_web::{
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpResponse, Responder,
 };
-use log::info;
+use diesel::Connection;
+use serde_json::json;
 
-use crate::db::models::{Config, ConfigCompatibility, SetConfig};
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::db::models::{
+    AuditAction, AuditContext, AuditEvent, Config, ConfigCompatibility, GetConfigOptions, SetConfig,
+};
 use crate::db::{DbManage, DbPool};
 
 pub async fn get_config(db: Data<DbPool>) -> impl Responder {
-    info!("path=/config,method=get");
-
-    let mut conn = db.connection()?;
-    match Config::get_global_compatibility(&mut conn).and_then(ConfigCompatibility::new) {
+    match db
+        .run(|conn| Config::get_global_compatibility(conn))
+        .await
+        .and_then(ConfigCompatibility::new)
+    {
         Ok(config) => Ok(HttpResponse::Ok().json(config)),
         Err(e) => Err(e),
     }
 }
 
-pub async fn put_config(body: Json<SetConfig>, db: Data<DbPool>) -> impl Responder {
-    let compatibility = body.compatibility;
-    info!("method=put,compatibility={}", compatibility);
-
-    let mut conn = db.connection()?;
-    match Config::set_global_compatibility(&mut conn, &compatibility.valid()?.to_string())
+pub async fn put_config(
+    body: Json<SetConfig>,
+    audit: AuditContext,
+    db: Data<DbPool>,
+) -> impl Responder {
+    let compatibility = body.compatibility.valid()?.to_string();
+    match db
+        .run(move |conn| {
+            conn.transaction::<_, ApiError, _>(|conn| {
+                let old = Config::get_global_compatibility(conn)?;
+                let new = Config::set_global_compatibility(conn, &compatibility)?;
+                AuditEvent::record(
+                    conn,
+                    &audit,
+                    AuditAction::SetGlobalConfig,
+                    None,
+                    Some(json!({ "compatibility": old })),
+                    Some(json!({ "compatibility": new })),
+                )?;
+                Ok(new)
+            })
+        })
+        .await
         .and_then(ConfigCompatibility::new)
     {
         Ok(config) => Ok(HttpResponse::Ok().json(config)),
@@ -30,13 +52,25 @@ pub async fn put_config(body: Json<SetConfig>, db: Data<DbPool>) -> impl Respond
     }
 }
 
-/// Get compatibility level for a subject.
-pub async fn get_subject_config(subject_path: Path<String>, db: Data<DbPool>) -> impl Responder {
+/// Get compatibility level for a subject, or the global one if the subject doesn't have
+/// its own and `defaultToGlobal` is set.
+pub async fn get_subject_config(
+    subject_path: Path<String>,
+    options: Query<GetConfigOptions>,
+    db: Data<DbPool>,
+) -> impl Responder {
     let subject = subject_path.into_inner();
-    info!("method=get,subject={}", subject);
-
-    let mut conn = db.connection()?;
-    match Config::get_with_subject_name(&mut conn, subject).and_then(ConfigCompatibility::new) {
+    match db
+        .run(move |conn| {
+            if options.default_to_global {
+                Config::get_compatibility_level(conn, &subject).map(|level| level.to_string())
+            } else {
+                Config::get_with_subject_name(conn, subject)
+            }
+        })
+        .await
+        .and_then(ConfigCompatibility::new)
+    {
         Ok(config) => Ok(HttpResponse::Ok().json(config)),
         Err(e) => Err(e),
     }
@@ -51,17 +85,39 @@ pub async fn get_subject_config(subject_path: Path<String>, db: Data<DbPool>) ->
 pub async fn put_subject_config(
     subject_path: Path<String>,
     body: Json<SetConfig>,
+    audit: AuditContext,
     db: Data<DbPool>,
 ) -> impl Responder {
     let subject = subject_path.into_inner();
-    let compatibility = body.compatibility;
-    info!(
-        "method=put,subject={},compatibility={}",
-        subject, compatibility
-    );
-
-    let mut conn = db.connection()?;
-    match Config::set_with_subject_name(&mut conn, subject, compatibility.valid()?.to_string())
+    let compatibility = body.compatibility.valid()?.to_string();
+    match db
+        .run(move |conn| {
+            conn.transaction::<_, ApiError, _>(|conn| {
+                // A subject without its own config yet has no previous value
+                let old = match Config::get_with_subject_name(conn, subject.clone()) {
+                    Ok(old) => Some(old),
+                    Err(e)
+                        if e.response.error_code == ApiAvroErrorCode::SubjectNotFound
+                            || e.response.error_code
+                                == ApiAvroErrorCode::SubjectCompatibilityNotConfigured =>
+                    {
+                        None
+                    }
+                    Err(e) => return Err(e),
+                };
+                let new = Config::set_with_subject_name(conn, subject.clone(), compatibility)?;
+                AuditEvent::record(
+                    conn,
+                    &audit,
+                    AuditAction::SetSubjectConfig,
+                    Some(&subject),
+                    old.map(|old| json!({ "compatibility": old })),
+                    Some(json!({ "compatibility": new })),
+                )?;
+                Ok(new)
+            })
+        })
+        .await
         .and_then(ConfigCompatibility::new)
     {
         Ok(config) => Ok(HttpResponse::Ok().json(config)),
@@ -1,12 +1,19 @@
-use actix_web::{http::StatusCode, HttpResponse};
+use std::fmt;
+
+use actix_web::{
+    error::{JsonPayloadError, PathError, QueryPayloadError},
+    http::StatusCode,
+    HttpRequest, HttpResponse,
+};
 use serde::Serialize;
+use tracing::error;
 
 // TODO: maybe replace this with serde_aux::serde_aux_enum_number_declare
 macro_rules! enum_number {
-    ($name:ident { $($variant:ident = $value:expr, )* }) => {
+    ($name:ident { $($(#[$attr:meta])* $variant:ident = $value:expr, )* }) => {
         #[derive(Clone, Copy, Debug, Eq, PartialEq)]
         pub enum $name {
-            $($variant = $value,)*
+            $($(#[$attr])* $variant = $value,)*
         }
 
         impl ::serde::Serialize for $name {
@@ -21,34 +28,127 @@ macro_rules! enum_number {
 }
 
 // We use the macro to ensure we serialize as numbers, not as the name.
+//
+// Codes are Confluent's. Like Confluent, errors it has no specific code for use their
+// HTTP status as their code. The only codes of the registry's own are those of the
+// resources Confluent doesn't have, ACL rules and API keys, in the 404xx range after
+// Confluent's.
 enum_number!(ApiAvroErrorCode {
+    MalformedRequest = 400,
+
+    Unauthenticated = 40101,
+
+    UserDeniedOperation = 40301,
+
+    NotFound = 404,
     SubjectNotFound = 40401,
     VersionNotFound = 40402,
     SchemaNotFound = 40403,
+    SubjectSoftDeleted = 40404,
+    SubjectNotSoftDeleted = 40405,
+    SchemaVersionSoftDeleted = 40406,
+    SchemaVersionNotSoftDeleted = 40407,
+    SubjectCompatibilityNotConfigured = 40408,
+    /// Registry specific, Confluent has no ACL rules resource
+    AclRuleNotFound = 40490,
+    /// Registry specific, Confluent has no API keys resource
+    ApiKeyNotFound = 40491,
+
+    MethodNotAllowed = 405,
+
+    NotAcceptable = 406,
+
+    IncompatibleSchema = 40901,
+
+    RequestTooLarge = 413,
+    UnsupportedMediaType = 415,
 
     InvalidAvroSchema = 42201,
     InvalidVersion = 42202,
     InvalidCompatibilityLevel = 42203,
+    InvalidMode = 42204,
+    OperationNotPermitted = 42205,
+    ReferenceExists = 42206,
+
+    RateLimitExceeded = 429,
 
+    InternalServerError = 500,
     BackendDatastoreError = 50001,
     OperationTimedOut = 50002,
-    MasterForwardingError = 50003,
+    LeaderForwardingError = 50003,
 });
 
 impl ApiAvroErrorCode {
     pub const fn message(&self) -> &str {
         match self {
+            Self::MalformedRequest => "Malformed request",
+
+            Self::Unauthenticated => "Missing or invalid credentials",
+
+            Self::UserDeniedOperation => "User is denied operation",
+
+            Self::NotFound => "Not found",
             Self::SubjectNotFound => "Subject not found",
             Self::VersionNotFound => "Version not found",
             Self::SchemaNotFound => "Schema not found",
+            Self::SubjectSoftDeleted => {
+                "Subject was soft deleted. Set permanent=true to delete permanently"
+            }
+            Self::SubjectNotSoftDeleted => {
+                "Subject was not deleted first before being permanently deleted"
+            }
+            Self::SchemaVersionSoftDeleted => {
+                "Version was soft deleted. Set permanent=true to delete permanently"
+            }
+            Self::SchemaVersionNotSoftDeleted => {
+                "Version was not deleted first before being permanently deleted"
+            }
+            Self::SubjectCompatibilityNotConfigured => {
+                "Subject does not have subject-level compatibility configured"
+            }
+            Self::AclRuleNotFound => "ACL rule not found",
+            Self::ApiKeyNotFound => "API key not found",
+
+            Self::MethodNotAllowed => "Method not allowed",
+
+            Self::NotAcceptable => "None of the accepted media types can be produced",
+
+            Self::IncompatibleSchema => {
+                "Schema being registered is incompatible with an earlier schema"
+            }
+
+            Self::RequestTooLarge => "Request body is too large",
+            Self::UnsupportedMediaType => "Unsupported media type",
 
             Self::InvalidAvroSchema => "Invalid Avro schema",
             Self::InvalidVersion => "Invalid version",
             Self::InvalidCompatibilityLevel => "Invalid compatibility level",
+            Self::InvalidMode => "Invalid mode",
+            Self::OperationNotPermitted => "Operation not permitted",
+            Self::ReferenceExists => "One or more references exist to the schema",
+
+            Self::RateLimitExceeded => "Rate limit exceeded",
 
+            Self::InternalServerError => "Internal server error",
             Self::BackendDatastoreError => "Error in the backend datastore",
             Self::OperationTimedOut => "Operation timed out",
-            Self::MasterForwardingError => "Error while forwarding the request to the master",
+            Self::LeaderForwardingError => "Error while forwarding the request to the leader",
+        }
+    }
+
+    /// The code of errors that only have a status, such as those of actix itself.
+    pub fn for_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => Self::Unauthenticated,
+            StatusCode::FORBIDDEN => Self::UserDeniedOperation,
+            StatusCode::NOT_FOUND => Self::NotFound,
+            StatusCode::METHOD_NOT_ALLOWED => Self::MethodNotAllowed,
+            StatusCode::NOT_ACCEPTABLE => Self::NotAcceptable,
+            StatusCode::PAYLOAD_TOO_LARGE => Self::RequestTooLarge,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => Self::UnsupportedMediaType,
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimitExceeded,
+            status if status.is_client_error() => Self::MalformedRequest,
+            _ => Self::InternalServerError,
         }
     }
 }
@@ -64,34 +164,141 @@ pub struct ApiErrorResponse {
 pub struct ApiError {
     pub status_code: StatusCode,
     pub response: ApiErrorResponse,
+    /// What is wrong with the request, when the code alone doesn't tell, also part of the
+    /// message.
+    pub detail: Option<String>,
+    /// What went wrong inside the registry, for internal errors. It is reported, but never
+    /// sent to clients.
+    pub cause: Option<String>,
 }
 
 impl ApiError {
     pub fn new(error_code: ApiAvroErrorCode) -> Self {
         let status_code = match error_code {
+            ApiAvroErrorCode::MalformedRequest => StatusCode::BAD_REQUEST,
+
+            ApiAvroErrorCode::Unauthenticated => StatusCode::UNAUTHORIZED,
+
+            ApiAvroErrorCode::UserDeniedOperation => StatusCode::FORBIDDEN,
+
+            ApiAvroErrorCode::NotFound => StatusCode::NOT_FOUND,
             ApiAvroErrorCode::SubjectNotFound => StatusCode::NOT_FOUND,
             ApiAvroErrorCode::VersionNotFound => StatusCode::NOT_FOUND,
             ApiAvroErrorCode::SchemaNotFound => StatusCode::NOT_FOUND,
+            ApiAvroErrorCode::SubjectSoftDeleted => StatusCode::NOT_FOUND,
+            ApiAvroErrorCode::SubjectNotSoftDeleted => StatusCode::NOT_FOUND,
+            ApiAvroErrorCode::SchemaVersionSoftDeleted => StatusCode::NOT_FOUND,
+            ApiAvroErrorCode::SchemaVersionNotSoftDeleted => StatusCode::NOT_FOUND,
+            ApiAvroErrorCode::SubjectCompatibilityNotConfigured => StatusCode::NOT_FOUND,
+            ApiAvroErrorCode::AclRuleNotFound => StatusCode::NOT_FOUND,
+            ApiAvroErrorCode::ApiKeyNotFound => StatusCode::NOT_FOUND,
+
+            ApiAvroErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+
+            ApiAvroErrorCode::NotAcceptable => StatusCode::NOT_ACCEPTABLE,
+
+            ApiAvroErrorCode::IncompatibleSchema => StatusCode::CONFLICT,
+
+            ApiAvroErrorCode::RequestTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiAvroErrorCode::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
 
             ApiAvroErrorCode::InvalidAvroSchema => StatusCode::UNPROCESSABLE_ENTITY,
             ApiAvroErrorCode::InvalidVersion => StatusCode::UNPROCESSABLE_ENTITY,
             ApiAvroErrorCode::InvalidCompatibilityLevel => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiAvroErrorCode::InvalidMode => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiAvroErrorCode::OperationNotPermitted => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiAvroErrorCode::ReferenceExists => StatusCode::UNPROCESSABLE_ENTITY,
 
+            ApiAvroErrorCode::RateLimitExceeded => StatusCode::TOO_MANY_REQUESTS,
+
+            ApiAvroErrorCode::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
             ApiAvroErrorCode::BackendDatastoreError => StatusCode::INTERNAL_SERVER_ERROR,
             ApiAvroErrorCode::OperationTimedOut => StatusCode::INTERNAL_SERVER_ERROR,
-            ApiAvroErrorCode::MasterForwardingError => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiAvroErrorCode::LeaderForwardingError => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
+        Self::with_status(status_code, error_code)
+    }
+
+    /// An error with `status_code`, which only differs from the code's own status for
+    /// statuses without a code.
+    pub fn with_status(status_code: StatusCode, error_code: ApiAvroErrorCode) -> Self {
         Self {
             status_code,
             response: ApiErrorResponse {
                 error_code,
                 message: error_code.message().to_string(),
             },
+            detail: None,
+            cause: None,
+        }
+    }
+
+    /// Adds `detail` to the message, such as the reason a schema can't be parsed. It must
+    /// be about the request, and never about the registry's internals.
+    pub fn with_detail(mut self, detail: impl fmt::Display) -> Self {
+        let detail = detail.to_string();
+        self.response.message = format!("{}: {}", self.response.error_code.message(), detail);
+        self.detail = Some(detail);
+        self
+    }
+
+    /// An error caused by the registry itself. `cause` is only logged, in the span of the
+    /// request, and reported to Sentry, so that the request ID of the response matches it
+    /// with what clients report without sending them SQL or other internals.
+    pub fn internal(error_code: ApiAvroErrorCode, cause: impl fmt::Debug) -> Self {
+        let cause = format!("{:?}", cause);
+        error!(error_code = error_code as u16, cause, "internal error");
+
+        let mut error = Self::new(error_code);
+        error.cause = Some(cause);
+        error
+    }
+}
+
+/// Errors of the `Json` extractor. Bodies over the limit are reported as such, anything
+/// else as a malformed request.
+pub fn json_error_handler(error: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match error {
+        JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+            ApiError::new(ApiAvroErrorCode::RequestTooLarge).into()
+        }
+        JsonPayloadError::ContentType => {
+            ApiError::new(ApiAvroErrorCode::UnsupportedMediaType).into()
         }
+        JsonPayloadError::Deserialize(e) => ApiError::new(ApiAvroErrorCode::MalformedRequest)
+            .with_detail(e)
+            .into(),
+        error => ApiError::new(ApiAvroErrorCode::MalformedRequest)
+            .with_detail(error)
+            .into(),
     }
 }
 
+/// Errors of the `Path` extractor: the only numbers routes take are versions and IDs.
+pub fn path_error_handler(error: PathError, req: &HttpRequest) -> actix_web::Error {
+    let error_code = match req.match_info().get("version") {
+        Some(_) => ApiAvroErrorCode::InvalidVersion,
+        None => ApiAvroErrorCode::MalformedRequest,
+    };
+    let detail = match error {
+        PathError::Deserialize(e) => e.to_string(),
+        error => error.to_string(),
+    };
+    ApiError::new(error_code).with_detail(detail).into()
+}
+
+/// Errors of the `Query` extractor.
+pub fn query_error_handler(error: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let detail = match error {
+        QueryPayloadError::Deserialize(e) => e.to_string(),
+        error => error.to_string(),
+    };
+    ApiError::new(ApiAvroErrorCode::MalformedRequest)
+        .with_detail(detail)
+        .into()
+}
+
 impl std::fmt::Display for ApiAvroErrorCode {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", &self.message())
@@ -105,17 +312,12 @@ impl std::fmt::Display for ApiErrorResponse {
 }
 
 impl actix_web::ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status_code
+    }
+
     fn error_response(&self) -> HttpResponse {
-        match self.status_code {
-            StatusCode::NOT_FOUND => HttpResponse::NotFound().json(&self.response),
-            StatusCode::INTERNAL_SERVER_ERROR => {
-                HttpResponse::InternalServerError().json(&self.response)
-            }
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                HttpResponse::build(StatusCode::UNPROCESSABLE_ENTITY).json(&self.response)
-            }
-            _ => HttpResponse::NotImplemented().finish(),
-        }
+        HttpResponse::build(self.status_code).json(&self.response)
     }
 }
 
@@ -130,30 +332,84 @@ impl std::error::Error for ApiAvroErrorCode {
 }
 
 impl std::convert::From<diesel::result::Error> for ApiAvroErrorCode {
-    fn from(_error: diesel::result::Error) -> Self {
-        Self::BackendDatastoreError
+    fn from(error: diesel::result::Error) -> Self {
+        match error {
+            diesel::result::Error::NotFound => Self::NotFound,
+            _ => Self::BackendDatastoreError,
+        }
     }
 }
 
 impl std::convert::From<diesel::result::Error> for ApiError {
-    fn from(_error: diesel::result::Error) -> Self {
-        Self::new(ApiAvroErrorCode::BackendDatastoreError)
+    fn from(error: diesel::result::Error) -> Self {
+        use diesel::result::Error;
+
+        // Diesel has no kind for statements cancelled by `statement_timeout` or
+        // `lock_timeout`, but Postgres always explains them the same way.
+        let error_code = match &error {
+            // Queries of a missing row are the client's doing, not the registry's. Those
+            // that know which resource it is answer with its own code instead.
+            Error::NotFound => return Self::new(ApiAvroErrorCode::from(error)),
+            Error::DatabaseError(_, info)
+                if info.message().starts_with("canceling statement due to") =>
+            {
+                ApiAvroErrorCode::OperationTimedOut
+            }
+            _ => ApiAvroErrorCode::BackendDatastoreError,
+        };
+        Self::internal(error_code, error)
     }
 }
 
 impl std::convert::From<actix::MailboxError> for ApiError {
-    fn from(_error: actix::MailboxError) -> Self {
-        Self::new(ApiAvroErrorCode::BackendDatastoreError)
+    fn from(error: actix::MailboxError) -> Self {
+        Self::internal(ApiAvroErrorCode::BackendDatastoreError, error)
     }
 }
 
 impl std::convert::From<actix_threadpool::BlockingError<ApiError>> for ApiError {
     fn from(error: actix_threadpool::BlockingError<Self>) -> Self {
         match error {
-            actix_threadpool::BlockingError::Canceled => {
-                Self::new(ApiAvroErrorCode::BackendDatastoreError)
-            }
+            actix_threadpool::BlockingError::Canceled => Self::internal(
+                ApiAvroErrorCode::BackendDatastoreError,
+                "blocking task canceled",
+            ),
             actix_threadpool::BlockingError::Error(e) => e,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiAvroErrorCode, ApiError};
+    use actix_web::http::StatusCode;
+
+    #[test]
+    fn error_with_detail() {
+        let error = ApiError::new(ApiAvroErrorCode::InvalidAvroSchema).with_detail("Unknown type");
+        assert_eq!(error.detail.as_deref(), Some("Unknown type"));
+        assert_eq!(error.response.message, "Invalid Avro schema: Unknown type");
+    }
+
+    #[test]
+    fn missing_row_is_not_found() {
+        let error = ApiError::from(diesel::result::Error::NotFound);
+        assert_eq!(error.status_code, StatusCode::NOT_FOUND);
+        assert_eq!(error.response.error_code, ApiAvroErrorCode::NotFound);
+        assert!(error.cause.is_none());
+    }
+
+    #[test]
+    fn internal_error_hides_cause() {
+        let error = ApiError::internal(
+            ApiAvroErrorCode::BackendDatastoreError,
+            "relation \"schemas\" does not exist",
+        );
+        assert!(error.detail.is_none());
+        assert_eq!(error.response.message, "Error in the backend datastore");
+        assert_eq!(
+            error.cause.as_deref(),
+            Some(r#""relation \"schemas\" does not exist""#)
+        );
+    }
+}
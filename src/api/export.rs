@@ -0,0 +1,150 @@
+use std::io::{self, Write};
+use std::pin::pin;
+use std::time::Duration;
+
+use actix_web::{
+    rt,
+    web::{Bytes, Data},
+    HttpResponse,
+};
+use futures::channel::{mpsc, oneshot};
+use futures::future::{self, Either};
+use futures::{executor, SinkExt};
+use log::{debug, warn};
+
+use crate::admin::{self, AdminError};
+use crate::api::errors::ApiError;
+use crate::db::{DbManage, DbPool};
+
+/// How many bytes of an export are sent at once.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How long an export may take, from `server.export_timeout_ms`. Past it, the export is
+/// cut short so that the connection it reads from goes back to the pool.
+#[derive(Clone, Copy, Debug)]
+pub struct ExportTimeout(pub Duration);
+
+/// Stream an export of the whole registry, as newline delimited JSON.
+///
+/// The export is written while the response is sent, so an error past its start can
+/// only cut the response short: imports tell such exports apart by their missing end.
+/// The same goes for exports that take longer than their [`ExportTimeout`].
+pub async fn get_export(db: Data<DbPool>, timeout: Data<ExportTimeout>) -> HttpResponse {
+    let (sender, chunks) = mpsc::channel(1);
+    let mut errors = sender.clone();
+    let (mut expire, expired) = oneshot::channel();
+    let timeout = timeout.0;
+    // Blocking threads have no timers, so the export is told once it took too long
+    rt::spawn(async move {
+        let expiring = pin!(rt::time::sleep(timeout));
+        let timed_out = matches!(
+            future::select(expiring, expire.cancellation()).await,
+            Either::Left(_)
+        );
+        if timed_out {
+            let _ = expire.send(());
+        }
+    });
+    rt::spawn(async move {
+        let exported = db
+            .run(move |conn| {
+                let mut writer = ResponseWriter {
+                    sender,
+                    buffer: Vec::with_capacity(CHUNK_SIZE),
+                    expired,
+                };
+                match admin::export(conn, &mut writer) {
+                    Ok(_) => Ok(()),
+                    Err(AdminError::Api(e)) => Err(e),
+                    Err(AdminError::Io(e)) if e.kind() == io::ErrorKind::TimedOut => {
+                        warn!("export stopped: {}", e);
+                        Ok(())
+                    }
+                    // Only writing to a response that was dropped fails otherwise
+                    Err(e) => {
+                        debug!("export stopped: {}", e);
+                        Ok(())
+                    }
+                }
+            })
+            .await;
+        if let Err(e) = exported {
+            let _ = errors.send(Err(e)).await;
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(chunks)
+}
+
+/// Sends what is written to it to the response, a chunk at a time. It is written from a
+/// blocking thread, which waits for the response to take each chunk until the export
+/// expired.
+struct ResponseWriter {
+    sender: mpsc::Sender<Result<Bytes, ApiError>>,
+    buffer: Vec<u8>,
+    expired: oneshot::Receiver<()>,
+}
+
+impl Write for ResponseWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = Ok(Bytes::from(std::mem::replace(
+            &mut self.buffer,
+            Vec::with_capacity(CHUNK_SIZE),
+        )));
+        let sent = executor::block_on(future::select(self.sender.feed(chunk), &mut self.expired));
+        match sent {
+            Either::Left((Ok(()), _)) => Ok(()),
+            Either::Left((Err(_), _)) => Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "the response was dropped",
+            )),
+            Either::Right(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the export took too long",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResponseWriter;
+    use futures::channel::{mpsc, oneshot};
+    use std::io::{ErrorKind, Write};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn export_stops_when_the_response_is_too_slow() {
+        let (sender, _chunks) = mpsc::channel(0);
+        let (expire, expired) = oneshot::channel();
+        let mut writer = ResponseWriter {
+            sender,
+            buffer: vec![],
+            expired,
+        };
+
+        // The channel holds a single chunk, which nothing takes
+        writer.write_all(b"header").unwrap();
+        writer.flush().unwrap();
+        writer.write_all(b"config").unwrap();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            expire.send(()).unwrap();
+        });
+        assert_eq!(writer.flush().unwrap_err().kind(), ErrorKind::TimedOut);
+    }
+}
@@ -1,11 +1,21 @@
+pub use self::acl::*;
+pub use self::api_keys::*;
+pub use self::audit::*;
 pub use self::compatibility::*;
 pub use self::configs::*;
+pub use self::export::*;
+pub use self::modes::*;
 pub use self::schemas::*;
 pub use self::subjects::*;
 
+mod acl;
+mod api_keys;
+mod audit;
 mod compatibility;
 mod configs;
 pub mod errors;
+mod export;
+mod modes;
 mod schemas;
 mod subjects;
 pub mod version;
@@ -0,0 +1,98 @@
+use actix_web::{
+    web::{Data, Json, Path, Query},
+    HttpResponse, Responder,
+};
+use diesel::Connection;
+use serde_json::json;
+
+use crate::api::errors::ApiError;
+use crate::db::models::{
+    AuditAction, AuditContext, AuditEvent, Config, ConfigMode, SetMode, SetModeOptions, Subject,
+};
+use crate::db::{DbManage, DbPool};
+
+pub async fn get_mode(db: Data<DbPool>) -> impl Responder {
+    match db.run(|conn| Config::get_global_mode(conn)).await {
+        Ok(mode) => Ok(HttpResponse::Ok().json(ConfigMode { mode })),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn put_mode(
+    body: Json<SetMode>,
+    options: Query<SetModeOptions>,
+    audit: AuditContext,
+    db: Data<DbPool>,
+) -> impl Responder {
+    let mode = body.mode.valid()?;
+    match db
+        .run(move |conn| {
+            conn.transaction::<_, ApiError, _>(|conn| {
+                let old = Config::get_global_mode(conn)?;
+                let new = Config::set_global_mode(conn, mode, options.force)?;
+                AuditEvent::record(
+                    conn,
+                    &audit,
+                    AuditAction::SetGlobalMode,
+                    None,
+                    Some(json!({ "mode": old.to_string() })),
+                    Some(json!({ "mode": new.to_string() })),
+                )?;
+                Ok(new)
+            })
+        })
+        .await
+    {
+        Ok(mode) => Ok(HttpResponse::Ok().json(ConfigMode { mode })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Get the mode of a subject, which is the global one unless the subject has its own.
+pub async fn get_subject_mode(subject_path: Path<String>, db: Data<DbPool>) -> impl Responder {
+    let subject = subject_path.into_inner();
+    match db
+        .run(move |conn| {
+            // Only the mode of a subject that exists is known
+            Subject::get_by_name(conn, subject.clone())?;
+            Config::get_mode(conn, &subject)
+        })
+        .await
+    {
+        Ok(mode) => Ok(HttpResponse::Ok().json(ConfigMode { mode })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Update the mode of a subject. As with compatibility levels, the subject has to exist.
+pub async fn put_subject_mode(
+    subject_path: Path<String>,
+    body: Json<SetMode>,
+    options: Query<SetModeOptions>,
+    audit: AuditContext,
+    db: Data<DbPool>,
+) -> impl Responder {
+    let subject = subject_path.into_inner();
+    let mode = body.mode.valid()?;
+    match db
+        .run(move |conn| {
+            conn.transaction::<_, ApiError, _>(|conn| {
+                let old = Config::get_mode(conn, &subject)?;
+                let new = Config::set_subject_mode(conn, subject.clone(), mode, options.force)?;
+                AuditEvent::record(
+                    conn,
+                    &audit,
+                    AuditAction::SetSubjectMode,
+                    Some(&subject),
+                    Some(json!({ "mode": old.to_string() })),
+                    Some(json!({ "mode": new.to_string() })),
+                )?;
+                Ok(new)
+            })
+        })
+        .await
+    {
+        Ok(mode) => Ok(HttpResponse::Ok().json(ConfigMode { mode })),
+        Err(e) => Err(e),
+    }
+}
@@ -1,35 +1,100 @@
 use actix_web::{
-    web::{Data, Json, Path},
+    web::{Data, Json, JsonConfig, Path, Query},
     HttpResponse, Responder,
 };
-use log::info;
+use diesel::Connection;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::api::errors::{json_error_handler, ApiAvroErrorCode, ApiError};
+use crate::auth::{Acl, DenyByDefault, Principal, Role};
 use crate::db::models::{
-    DeleteSchemaVersion, RegisterSchema, RegisterSchemaResponse, Schema, SchemaResponse,
-    SchemaVersion,
+    AclRule, AuditAction, AuditContext, AuditEvent, DeleteOptions, DeleteSchemaVersion, Reference,
+    RegisterSchema, RegisterSchemaResponse, Schema, SchemaReference, SchemaResponse, SchemaVersion,
+    Subject,
 };
-use crate::db::{DbManage, DbPool};
+use crate::db::{DbManage, DbPool, DbReadPool};
+use crate::metrics::Metrics;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SchemaBody {
     pub schema: String,
 }
 
-pub async fn get_schema(id: Path<i64>, db: Data<DbPool>) -> impl Responder {
-    info!("method=get,id={}", id);
+impl SchemaBody {
+    pub const DEFAULT_MAX_SIZE: usize = 2 * 1024 * 1024;
 
-    let mut conn = db.connection()?;
-    match Schema::get_by_id(&mut conn, id.into_inner()).map(|schema| SchemaResponse {
-        schema: schema.json,
-    }) {
+    /// Limits the size of JSON bodies to `limit` bytes. Schemas are by far the largest
+    /// bodies, and larger ones are rejected as soon as their `Content-Length` is known,
+    /// before being read or deserialized.
+    pub fn json_config(limit: usize) -> JsonConfig {
+        JsonConfig::default()
+            .limit(limit)
+            .error_handler(json_error_handler)
+    }
+}
+
+/// Body of registrations and compatibility checks, where schemas can use types defined
+/// by other registered schemas.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SchemaBodyWithReferences {
+    pub schema: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub references: Vec<Reference>,
+}
+
+/// Looks the schema up on the replica, or on the primary when the replica doesn't have it
+/// yet, as clients look it up right after registering it. Only principals allowed to read
+/// one of the subjects using the schema may look it up.
+pub async fn get_schema(
+    id: Path<i64>,
+    principal: Principal,
+    deny_by_default: Option<Data<DenyByDefault>>,
+    db: Data<DbReadPool>,
+    metrics: Data<Metrics>,
+) -> impl Responder {
+    let id = id.into_inner();
+    let deny_by_default = deny_by_default.is_some();
+    let schema = db
+        .run_or_primary(move |conn| {
+            let schema = Schema::get_by_id(conn, id)?;
+            let rules = AclRule::for_principal(conn, &principal.name)?;
+            let acl = Acl::new(&principal, &rules, deny_by_default);
+            if !Subject::names_with_schema(conn, schema.id)?
+                .iter()
+                .any(|subject| acl.permits(subject, Role::Reader))
+            {
+                return Err(ApiError::new(ApiAvroErrorCode::UserDeniedOperation));
+            }
+            Ok(SchemaResponse {
+                references: SchemaReference::of_schema(conn, schema.id)?,
+                schema: schema.json,
+            })
+        })
+        .await;
+    metrics.schema_looked_up(&schema);
+    match schema {
         Ok(response) => Ok(HttpResponse::Ok().json(response)),
         Err(e) => Err(e),
     }
 }
 
-pub async fn delete_schema_version(info: Path<(String, u32)>, db: Data<DbPool>) -> impl Responder {
+fn delete_schema_version_action(permanent: bool) -> AuditAction {
+    if permanent {
+        AuditAction::DeleteSchemaVersionPermanently
+    } else {
+        AuditAction::DeleteSchemaVersion
+    }
+}
+
+/// Soft deletes a version, or deletes a soft deleted one for good with `permanent=true`.
+pub async fn delete_schema_version(
+    info: Path<(String, u32)>,
+    options: Query<DeleteOptions>,
+    audit: AuditContext,
+    db: Data<DbPool>,
+    metrics: Data<Metrics>,
+) -> impl Responder {
     let q = info.into_inner();
 
     use crate::api::version::VersionLimit;
@@ -37,53 +102,125 @@ pub async fn delete_schema_version(info: Path<(String, u32)>, db: Data<DbPool>)
     let delete_schema_version = DeleteSchemaVersion {
         subject: q.0,
         version: q.1,
+        permanent: options.permanent,
     };
-    let mut conn = db.connection()?;
     if !delete_schema_version.version.within_limits() {
         return Err(ApiError::new(ApiAvroErrorCode::InvalidVersion));
     }
-    match SchemaVersion::delete_version_with_subject(&mut conn, delete_schema_version) {
-        Ok(r) => Ok(HttpResponse::Ok().body(format!("{}", r))),
+    let permanent = delete_schema_version.permanent;
+    match db
+        .run(move |conn| {
+            conn.transaction::<_, ApiError, _>(|conn| {
+                let subject = delete_schema_version.subject.clone();
+                let action = delete_schema_version_action(delete_schema_version.permanent);
+                let version =
+                    SchemaVersion::delete_version_with_subject(conn, delete_schema_version)?;
+                AuditEvent::record(
+                    conn,
+                    &audit,
+                    action,
+                    Some(&subject),
+                    Some(json!({ "version": version })),
+                    None,
+                )?;
+                Ok(version)
+            })
+        })
+        .await
+    {
+        Ok(r) => {
+            metrics.schema_version_deleted(permanent);
+            Ok(HttpResponse::Ok().body(format!("{}", r)))
+        }
         Err(e) => Err(e),
     }
 }
 
 pub async fn delete_schema_version_latest(
     subject: Path<String>,
+    options: Query<DeleteOptions>,
+    audit: AuditContext,
     db: Data<DbPool>,
+    metrics: Data<Metrics>,
 ) -> impl Responder {
     let subject = subject.into_inner();
+    let permanent = options.permanent;
 
     use crate::api::version::VersionLimit;
-    let mut conn = db.connection()?;
 
-    let sv_response =
-        crate::api::subjects::get_subject_version_from_db(&mut conn, subject.clone(), None)?;
+    match db
+        .run(move |conn| {
+            conn.transaction::<_, ApiError, _>(|conn| {
+                let sv_response =
+                    crate::api::subjects::get_subject_version_from_db(conn, subject.clone(), None)?;
 
-    let delete_schema_version = DeleteSchemaVersion {
-        subject,
-        version: sv_response.version as u32,
-    };
-    if !delete_schema_version.version.within_limits() {
-        return Err(ApiError::new(ApiAvroErrorCode::InvalidVersion));
-    }
-    match SchemaVersion::delete_version_with_subject(&mut conn, delete_schema_version) {
-        Ok(r) => Ok(HttpResponse::Ok().body(format!("{}", r))),
+                let delete_schema_version = DeleteSchemaVersion {
+                    subject: subject.clone(),
+                    version: sv_response.version as u32,
+                    permanent,
+                };
+                if !delete_schema_version.version.within_limits() {
+                    return Err(ApiError::new(ApiAvroErrorCode::InvalidVersion));
+                }
+                let version =
+                    SchemaVersion::delete_version_with_subject(conn, delete_schema_version)?;
+                AuditEvent::record(
+                    conn,
+                    &audit,
+                    delete_schema_version_action(permanent),
+                    Some(&subject),
+                    Some(json!({ "id": sv_response.id, "version": version })),
+                    None,
+                )?;
+                Ok(version)
+            })
+        })
+        .await
+    {
+        Ok(r) => {
+            metrics.schema_version_deleted(permanent);
+            Ok(HttpResponse::Ok().body(format!("{}", r)))
+        }
         Err(e) => Err(e),
     }
 }
 
 pub async fn register_schema(
     subject: Path<String>,
-    body: Json<SchemaBody>,
+    body: Json<SchemaBodyWithReferences>,
+    audit: AuditContext,
     db: Data<DbPool>,
+    metrics: Data<Metrics>,
 ) -> impl Responder {
-    let mut conn = db.connection()?;
+    let body = body.into_inner();
     let new_schema = RegisterSchema {
-        subject: subject.to_owned(),
-        schema: body.into_inner().schema,
+        subject: subject.into_inner(),
+        schema: body.schema,
+        references: body.references,
     };
-    match Schema::register_new_version(&mut conn, new_schema).map(|schema| RegisterSchemaResponse {
+    let subject = new_schema.subject.clone();
+    let registered = db
+        .run(move |conn| {
+            conn.transaction::<_, ApiError, _>(|conn| {
+                let subject = new_schema.subject.clone();
+                let registered = Schema::register(conn, new_schema)?;
+                // Registering a schema that is already under the subject changes nothing
+                if let Some(version) = registered.created_version {
+                    AuditEvent::record(
+                        conn,
+                        &audit,
+                        AuditAction::RegisterSchema,
+                        Some(&subject),
+                        None,
+                        Some(json!({ "id": registered.schema.id, "version": version })),
+                    )?;
+                }
+                Ok((registered.schema, registered.created_version.is_some()))
+            })
+        })
+        .await;
+    metrics.schema_registered(&subject, registered.as_ref().map(|(_, created)| *created));
+    match registered.map(|(schema, _)| RegisterSchemaResponse {
         id: format!("{}", schema.id),
     }) {
         Ok(response) => Ok(HttpResponse::Ok().json(response)),
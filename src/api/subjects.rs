@@ -1,30 +1,58 @@
 use actix_web::{
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpResponse, Responder,
 };
+use diesel::Connection;
+use serde_json::json;
 
 use crate::api::{
     errors::{ApiAvroErrorCode, ApiError},
     SchemaBody,
 };
+use crate::auth::{Acl, DenyByDefault, Principal, Role};
 use crate::db::models::{
-    DeleteSubjectResponse, GetSubjectVersionResponse, Schema, SchemaResponse, SchemaVersion,
-    Subject, SubjectList, SubjectVersionsResponse,
+    AclRule, AuditAction, AuditContext, AuditEvent, DeleteOptions, DeleteSubjectResponse,
+    GetSubjectVersionResponse, Schema, SchemaReference, SchemaResponse, SchemaVersion, Subject,
+    SubjectList, SubjectVersionsResponse,
 };
-use crate::db::{DbManage, DbPool};
+use crate::db::{DbManage, DbPool, DbReadPool};
+use crate::metrics::Metrics;
 
-pub async fn get_subjects(db: Data<DbPool>) -> impl Responder {
-    let mut conn = db.connection()?;
-    match Subject::distinct_names(&mut conn).map(|content| SubjectList { content }) {
+/// Lists the subjects the principal is allowed to read, see [`Acl`].
+pub async fn get_subjects(
+    principal: Principal,
+    deny_by_default: Option<Data<DenyByDefault>>,
+    db: Data<DbPool>,
+    read_db: Data<DbReadPool>,
+) -> impl Responder {
+    let name = principal.name.to_owned();
+    let rules = match db
+        .run(move |conn| AclRule::for_principal(conn, &name))
+        .await
+    {
+        Ok(rules) => rules,
+        Err(e) => return Err(e),
+    };
+    let acl = Acl::new(&principal, &rules, deny_by_default.is_some());
+    match read_db
+        .run(|conn| Subject::distinct_names(conn))
+        .await
+        .map(|names| SubjectList {
+            content: names
+                .into_iter()
+                .filter(|name| acl.permits(name, Role::Reader))
+                .collect(),
+        }) {
         Ok(subjects) => Ok(HttpResponse::Ok().json(subjects.content)),
         Err(e) => Err(e),
     }
 }
 
-pub async fn get_subject_versions(subject: Path<String>, db: Data<DbPool>) -> impl Responder {
-    //subject.into_inner()
-    let mut conn = db.connection()?;
-    match SchemaVersion::versions_with_subject_name(&mut conn, subject.into_inner())
+pub async fn get_subject_versions(subject: Path<String>, db: Data<DbReadPool>) -> impl Responder {
+    let subject = subject.into_inner();
+    match db
+        .run(move |conn| SchemaVersion::versions_with_subject_name(conn, subject))
+        .await
         .map(|versions| SubjectVersionsResponse { versions })
     {
         Ok(r) => Ok(HttpResponse::Ok().json(r.versions)),
@@ -32,12 +60,48 @@ pub async fn get_subject_versions(subject: Path<String>, db: Data<DbPool>) -> im
     }
 }
 
-pub async fn delete_subject(subject: Path<String>, db: Data<DbPool>) -> impl Responder {
-    let mut conn = db.connection()?;
-    match Subject::delete_by_name(&mut conn, subject.into_inner())
+/// Soft deletes a subject, or deletes a soft deleted one for good with `permanent=true`.
+pub async fn delete_subject(
+    subject: Path<String>,
+    options: Query<DeleteOptions>,
+    audit: AuditContext,
+    db: Data<DbPool>,
+    metrics: Data<Metrics>,
+) -> impl Responder {
+    let subject = subject.into_inner();
+    let permanent = options.permanent;
+    match db
+        .run(move |conn| {
+            conn.transaction::<_, ApiError, _>(|conn| {
+                let (versions, action) = if permanent {
+                    (
+                        Subject::delete_permanently_by_name(conn, subject.clone())?,
+                        AuditAction::DeleteSubjectPermanently,
+                    )
+                } else {
+                    (
+                        Subject::delete_by_name(conn, subject.clone())?,
+                        AuditAction::DeleteSubject,
+                    )
+                };
+                AuditEvent::record(
+                    conn,
+                    &audit,
+                    action,
+                    Some(&subject),
+                    Some(json!({ "versions": versions })),
+                    None,
+                )?;
+                Ok(versions)
+            })
+        })
+        .await
         .map(|versions| DeleteSubjectResponse { versions })
     {
-        Ok(r) => Ok(HttpResponse::Ok().json(r.versions)),
+        Ok(r) => {
+            metrics.subject_deleted(permanent);
+            Ok(HttpResponse::Ok().json(r.versions))
+        }
         Err(e) => Err(e),
     }
 }
@@ -63,33 +127,49 @@ pub fn get_subject_version_from_db(
         }
         None => SchemaVersion::get_schema_id_from_latest(conn, subject.to_string()),
     }
-    .map(|o| GetSubjectVersionResponse {
-        subject: subject.to_string(),
-        id: o.0,
-        version: o.1,
-        schema: o.2,
+    .and_then(|o| {
+        Ok(GetSubjectVersionResponse {
+            subject: subject.to_string(),
+            id: o.0,
+            version: o.1,
+            schema: o.2,
+            references: SchemaReference::of_schema(conn, o.0)?,
+        })
     })
 }
 
+// Versions are looked up like schemas are, see `DbReadPool::run_or_primary`.
+//
 // TODO(nlopes): maybe new type here
 //
 // According to
 // https://docs.confluent.io/3.1.0/schema-registry/docs/api.html#get--subjects-(string-%20subject)-versions-(versionId-%20version)
 // the Version ID should be in the range of 1 to 2^31-1, which isn't u32. We should create
 // a new type with the boundaries of this.
-pub async fn get_subject_version(info: Path<(String, u32)>, db: Data<DbPool>) -> impl Responder {
-    let q = info.into_inner();
+pub async fn get_subject_version(
+    info: Path<(String, u32)>,
+    db: Data<DbReadPool>,
+) -> impl Responder {
+    let (subject, version) = info.into_inner();
 
-    let mut conn = db.connection()?;
-    match get_subject_version_from_db(&mut conn, q.0, Some(q.1)) {
+    match db
+        .run_or_primary(move |conn| get_subject_version_from_db(conn, subject, Some(version)))
+        .await
+    {
         Ok(r) => Ok(HttpResponse::Ok().json(r)),
         Err(e) => Err(e),
     }
 }
 
-pub async fn get_subject_version_latest(subject: Path<String>, db: Data<DbPool>) -> impl Responder {
-    let mut conn = db.connection()?;
-    match get_subject_version_from_db(&mut conn, subject.into_inner(), None) {
+pub async fn get_subject_version_latest(
+    subject: Path<String>,
+    db: Data<DbReadPool>,
+) -> impl Responder {
+    let subject = subject.into_inner();
+    match db
+        .run_or_primary(move |conn| get_subject_version_from_db(conn, subject, None))
+        .await
+    {
         Ok(r) => Ok(HttpResponse::Ok().json(r)),
         Err(e) => Err(e),
     }
@@ -99,24 +179,35 @@ pub async fn get_subject_version_latest(subject: Path<String>, db: Data<DbPool>)
 // schema
 pub async fn get_subject_version_schema(
     info: Path<(String, u32)>,
-    db: Data<DbPool>,
+    db: Data<DbReadPool>,
 ) -> impl Responder {
-    let q = info.into_inner();
+    let (subject, version) = info.into_inner();
 
-    let mut conn = db.connection()?;
-    match get_subject_version_from_db(&mut conn, q.0, Some(q.1)) {
-        Ok(r) => Ok(HttpResponse::Ok().json(SchemaResponse { schema: r.schema })),
+    match db
+        .run_or_primary(move |conn| get_subject_version_from_db(conn, subject, Some(version)))
+        .await
+    {
+        Ok(r) => Ok(HttpResponse::Ok().json(SchemaResponse {
+            schema: r.schema,
+            references: r.references,
+        })),
         Err(e) => Err(e),
     }
 }
 
 pub async fn get_subject_version_latest_schema(
     subject: Path<String>,
-    db: Data<DbPool>,
+    db: Data<DbReadPool>,
 ) -> impl Responder {
-    let mut conn = db.connection()?;
-    match get_subject_version_from_db(&mut conn, subject.into_inner(), None) {
-        Ok(r) => Ok(HttpResponse::Ok().json(SchemaResponse { schema: r.schema })),
+    let subject = subject.into_inner();
+    match db
+        .run_or_primary(move |conn| get_subject_version_from_db(conn, subject, None))
+        .await
+    {
+        Ok(r) => Ok(HttpResponse::Ok().json(SchemaResponse {
+            schema: r.schema,
+            references: r.references,
+        })),
         Err(e) => Err(e),
     }
 }
@@ -126,8 +217,11 @@ pub async fn post_subject(
     body: Json<SchemaBody>,
     db: Data<DbPool>,
 ) -> impl Responder {
-    let mut conn = db.connection()?;
-    match Schema::verify_registration(&mut conn, subject.into_inner(), body.into_inner().schema) {
+    let (subject, schema) = (subject.into_inner(), body.into_inner().schema);
+    match db
+        .run(move |conn| Schema::verify_registration(conn, subject, schema))
+        .await
+    {
         Ok(response) => Ok(HttpResponse::Ok().json(response)),
         Err(e) => Err(e),
     }
@@ -1,71 +1,228 @@
-use std::env;
+use std::sync::Arc;
+use std::time::Duration;
 
-use actix_web::web;
+use actix_web::{guard, web};
 
-use crate::api;
+use crate::api::{self, errors};
+use crate::auth::{ForwardingKey, JwtVerifier, Role, UserStore, UserStoreError};
 use crate::health;
 use crate::middleware;
+use crate::settings::{Settings, SettingsError};
 
 pub fn monitoring_routing(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("_")
-            .service(web::resource("/health_check").route(web::get().to(health::status))),
+            .service(web::resource("/health_check").route(web::get().to(health::status)))
+            .service(web::resource("/live").route(web::get().to(health::live)))
+            .service(web::resource("/ready").route(web::get().to(health::ready))),
     );
 }
 
+/// What the registry endpoints are configured with, built once at startup so that invalid
+/// settings are reported before the server starts.
+#[derive(Clone)]
+pub struct ApiConfig {
+    users: Arc<UserStore>,
+    jwt: Option<Arc<JwtVerifier>>,
+    forwarding: Option<Arc<ForwardingKey>>,
+    max_schema_size: usize,
+    export_timeout: api::ExportTimeout,
+}
+
+impl ApiConfig {
+    pub fn from_settings(settings: &Settings) -> Result<Self, SettingsError> {
+        let jwt = JwtVerifier::from_settings(&settings.auth.jwt)?;
+        let users = match UserStore::from_settings(&settings.auth) {
+            // Bearer tokens are enough to authenticate clients
+            Err(UserStoreError::NoCredentials) if jwt.is_some() => UserStore::default(),
+            users => users?,
+        };
+        if settings.server.max_schema_size == 0 {
+            return Err(SettingsError::Invalid("server.max_schema_size", "positive"));
+        }
+        if settings.server.export_timeout_ms == 0 {
+            return Err(SettingsError::Invalid(
+                "server.export_timeout_ms",
+                "positive",
+            ));
+        }
+        Ok(Self {
+            users: Arc::new(users),
+            jwt: jwt.map(Arc::new),
+            forwarding: settings
+                .leader
+                .secret
+                .as_deref()
+                .map(|secret| Arc::new(ForwardingKey::new(secret.as_bytes()))),
+            max_schema_size: settings.server.max_schema_size,
+            export_timeout: api::ExportTimeout(Duration::from_millis(
+                settings.server.export_timeout_ms,
+            )),
+        })
+    }
+
+    pub fn configure(&self, cfg: &mut web::ServiceConfig) {
+        routes(cfg, self)
+    }
+}
+
+/// Configures the registry endpoints with the settings of the environment alone, see
+/// [`Settings::from_env`]. Panics when they are invalid.
 pub fn api_routing(cfg: &mut web::ServiceConfig) {
-    let password =
-        env::var("SCHEMA_REGISTRY_PASSWORD").expect("Must pass a schema registry password");
+    Settings::from_env()
+        .and_then(|settings| ApiConfig::from_settings(&settings))
+        .unwrap_or_else(|e| panic!("{}", e))
+        .configure(cfg)
+}
+
+fn routes(cfg: &mut web::ServiceConfig, config: &ApiConfig) {
+    let users = config.users.clone();
+    let mut verify_authorization = middleware::VerifyAuthorization::new(users.clone());
+    if let Some(jwt) = &config.jwt {
+        verify_authorization = verify_authorization.with_jwt(jwt.clone());
+    }
+    if let Some(key) = &config.forwarding {
+        verify_authorization = verify_authorization.with_forwarding(key.clone());
+    }
+
+    let scope = web::scope("")
+        .app_data(web::Data::from(users))
+        .app_data(api::SchemaBody::json_config(config.max_schema_size))
+        .app_data(web::PathConfig::default().error_handler(errors::path_error_handler))
+        .app_data(web::QueryConfig::default().error_handler(errors::query_error_handler))
+        .wrap(middleware::Authorize::by_method())
+        .wrap(middleware::RateLimit::by_principal())
+        .wrap(middleware::VerifyAcceptHeader)
+        .wrap(verify_authorization)
+        .wrap(middleware::RateLimit::by_address())
+        .wrap(middleware::Timeout)
+        .wrap(middleware::JsonErrors)
+        .wrap(middleware::Trace);
+    #[cfg(feature = "sentry")]
+    let scope = scope.wrap(middleware::ReportErrors);
 
+    // Writes are forwarded to the leader, if any, by each resource that has some, once
+    // its own ACL and role checks passed. Checks and lookups sent as POST change nothing,
+    // so they are served here
     cfg.service(
-        web::scope("")
-            .wrap(middleware::VerifyAcceptHeader)
-            .wrap(middleware::VerifyAuthorization::new(&password))
+        scope
+            .wrap(middleware::AssignRequestId)
             .service(
                 web::resource("/compatibility/subjects/{subject}/versions/{version}")
+                    .wrap(middleware::SubjectAcl)
                     .route(web::post().to(api::check_compatibility)),
             )
             .service(
                 web::resource("/config")
+                    .wrap(middleware::ForwardToLeader)
                     .route(web::get().to(api::get_config))
                     .route(web::put().to(api::put_config)),
             )
             .service(
                 web::resource("/config/{subject}")
+                    .wrap(middleware::ForwardToLeader)
+                    .wrap(middleware::SubjectAcl)
                     .route(web::get().to(api::get_subject_config))
                     .route(web::put().to(api::put_subject_config)),
             )
-            .service(web::resource("/schemas/ids/{id}").route(web::get().to(api::get_schema)))
             .service(
-                web::scope("/subjects")
-                    .service(web::resource("").to(api::get_subjects))
-                    .service(
-                        web::resource("/{subject}")
-                            .route(web::post().to(api::post_subject))
-                            .route(web::delete().to(api::delete_subject)),
-                    )
+                web::resource("/mode")
+                    .wrap(middleware::ForwardToLeader)
+                    .route(web::get().to(api::get_mode))
+                    .route(web::put().to(api::put_mode)),
+            )
+            .service(
+                web::resource("/mode/{subject}")
+                    .wrap(middleware::ForwardToLeader)
+                    .wrap(middleware::SubjectAcl)
+                    .route(web::get().to(api::get_subject_mode))
+                    .route(web::put().to(api::put_subject_mode)),
+            )
+            .service(
+                web::resource("/admin/export")
+                    .app_data(web::Data::new(config.export_timeout))
+                    .wrap(middleware::Authorize::require(Role::Admin))
+                    .route(web::get().to(api::get_export)),
+            )
+            .service(
+                web::resource("/audit")
+                    .wrap(middleware::Authorize::require(Role::Admin))
+                    .route(web::get().to(api::get_audit)),
+            )
+            // Any principal may check its own access, the rest is for admins
+            .service(web::resource("/acl/check").route(web::post().to(api::check_acl)))
+            .service(
+                web::scope("/acl")
+                    .wrap(middleware::ForwardToLeader)
+                    .wrap(middleware::Authorize::require(Role::Admin))
                     .service(
-                        web::resource("/{subject}/versions")
-                            .route(web::get().to(api::get_subject_versions))
-                            .route(web::post().to(api::register_schema)),
+                        web::resource("")
+                            .route(web::get().to(api::get_acl_rules))
+                            .route(web::post().to(api::post_acl_rule)),
                     )
+                    .service(web::resource("/{id}").route(web::delete().to(api::delete_acl_rule))),
+            )
+            .service(
+                web::scope("/keys")
+                    .wrap(middleware::ForwardToLeader)
+                    .wrap(middleware::Authorize::require(Role::Admin))
                     .service(
-                        web::resource("/{subject}/versions/latest")
-                            .route(web::get().to(api::get_subject_version_latest))
-                            .route(web::delete().to(api::delete_schema_version_latest)),
+                        web::resource("")
+                            .route(web::get().to(api::get_api_keys))
+                            .route(web::post().to(api::post_api_key)),
                     )
                     .service(
-                        web::resource("/{subject}/versions/{version}")
-                            .route(web::get().to(api::get_subject_version))
-                            .route(web::delete().to(api::delete_schema_version)),
+                        web::resource("/{key_id}").route(web::delete().to(api::revoke_api_key)),
                     )
                     .service(
-                        web::resource("/{subject}/versions/latest/schema")
-                            .to(api::get_subject_version_latest_schema),
-                    )
+                        web::resource("/{key_id}/rotate")
+                            .route(web::post().to(api::rotate_api_key)),
+                    ),
+            )
+            .service(web::resource("/schemas/ids/{id}").route(web::get().to(api::get_schema)))
+            .service(
+                web::scope("/subjects")
+                    .service(web::resource("").to(api::get_subjects))
                     .service(
-                        web::resource("/{subject}/versions/{version}/schema")
-                            .to(api::get_subject_version_schema),
+                        // Every route below is about one subject, and goes through its ACL
+                        web::scope("/{subject}")
+                            .wrap(middleware::SubjectAcl)
+                            .service(
+                                web::resource("")
+                                    .guard(guard::Post())
+                                    .route(web::post().to(api::post_subject)),
+                            )
+                            .service(
+                                web::resource("")
+                                    .wrap(middleware::ForwardToLeader)
+                                    .route(web::delete().to(api::delete_subject)),
+                            )
+                            .service(
+                                web::resource("/versions")
+                                    .wrap(middleware::ForwardToLeader)
+                                    .route(web::get().to(api::get_subject_versions))
+                                    .route(web::post().to(api::register_schema)),
+                            )
+                            .service(
+                                web::resource("/versions/latest")
+                                    .wrap(middleware::ForwardToLeader)
+                                    .route(web::get().to(api::get_subject_version_latest))
+                                    .route(web::delete().to(api::delete_schema_version_latest)),
+                            )
+                            .service(
+                                web::resource("/versions/{version}")
+                                    .wrap(middleware::ForwardToLeader)
+                                    .route(web::get().to(api::get_subject_version))
+                                    .route(web::delete().to(api::delete_schema_version)),
+                            )
+                            .service(
+                                web::resource("/versions/latest/schema")
+                                    .to(api::get_subject_version_latest_schema),
+                            )
+                            .service(
+                                web::resource("/versions/{version}/schema")
+                                    .to(api::get_subject_version_schema),
+                            ),
                     ),
             ),
     );
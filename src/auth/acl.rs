@@ -0,0 +1,261 @@
+use std::fmt;
+use std::str;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Principal, Role};
+use crate::db::models::AclRule;
+use crate::settings::AuthSettings;
+
+/// How the pattern of an ACL rule is matched against subject names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternType {
+    /// The subject is the pattern
+    Exact,
+    /// The subject starts with the pattern
+    Prefix,
+    /// The pattern is a glob, where `*` matches any sequence of characters and `?` any
+    /// single character
+    Glob,
+}
+
+impl PatternType {
+    pub fn matches(self, pattern: &str, subject: &str) -> bool {
+        match self {
+            Self::Exact => pattern == subject,
+            Self::Prefix => subject.starts_with(pattern),
+            Self::Glob => glob_matches(pattern, subject),
+        }
+    }
+}
+
+impl fmt::Display for PatternType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lowercase = match self {
+            Self::Exact => "exact",
+            Self::Prefix => "prefix",
+            Self::Glob => "glob",
+        };
+        write!(f, "{}", lowercase)
+    }
+}
+
+impl str::FromStr for PatternType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "exact" => Ok(Self::Exact),
+            "prefix" => Ok(Self::Prefix),
+            "glob" => Ok(Self::Glob),
+            _ => Err(()),
+        }
+    }
+}
+
+fn glob_matches(pattern: &str, subject: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let subject: Vec<char> = subject.chars().collect();
+    let (mut p, mut s) = (0, 0);
+    // Position of the last `*` seen, and of the subject character it was tried against
+    let mut backtrack = None;
+
+    while s < subject.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, s));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == subject[s] => {
+                p += 1;
+                s += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` swallow one more character and try again
+                Some((star, star_s)) => {
+                    backtrack = Some((star, star_s + 1));
+                    p = star + 1;
+                    s = star_s + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Takes away the access of principals to subjects none of their ACL rules match, when it
+/// is in the app data. Without it, they keep their own role on those subjects.
+#[derive(Debug, Clone, Copy)]
+pub struct DenyByDefault;
+
+impl DenyByDefault {
+    pub fn from_settings(settings: &AuthSettings) -> Option<Self> {
+        settings.acl_deny_by_default.then_some(Self)
+    }
+}
+
+/// Access a principal has on subjects, according to their ACL rules.
+///
+/// Principals other than admins have the best role granted by the rules matching the
+/// subject (capped by their own role). On subjects that no rule matches, they keep their
+/// own role, unless denying them by default. Admins manage the rules, so these can't
+/// restrict them anyway: they keep their role on every subject.
+pub struct Acl<'a> {
+    principal: &'a Principal,
+    rules: &'a [AclRule],
+    deny_by_default: bool,
+}
+
+impl<'a> Acl<'a> {
+    /// `rules` are the ones of `principal`, see [`AclRule::for_principal`].
+    pub fn new(principal: &'a Principal, rules: &'a [AclRule], deny_by_default: bool) -> Self {
+        Self {
+            principal,
+            rules,
+            deny_by_default,
+        }
+    }
+
+    pub fn role_on(&self, subject: &str) -> Option<Role> {
+        if self.principal.can(Role::Admin) {
+            return Some(Role::Admin);
+        }
+        let granted = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(subject))
+            .filter_map(AclRule::role)
+            .max();
+        match granted {
+            Some(role) => Some(role.min(self.principal.role)),
+            None if self.deny_by_default => None,
+            None => Some(self.principal.role),
+        }
+    }
+
+    pub fn permits(&self, subject: &str, required: Role) -> bool {
+        self.role_on(subject).is_some_and(|role| role >= required)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_matches, Acl, PatternType};
+    use crate::auth::{Principal, Role};
+    use crate::db::models::AclRule;
+
+    fn rule(pattern: &str, pattern_type: PatternType, role: Role) -> AclRule {
+        let now = chrono::Utc::now().naive_utc();
+        AclRule {
+            id: 1,
+            principal: String::from("team_a"),
+            pattern: pattern.to_string(),
+            pattern_type: pattern_type.to_string(),
+            role: role.to_string(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn team_a() -> Principal {
+        Principal {
+            name: String::from("team_a"),
+            role: Role::Writer,
+        }
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_matches("payments.*", "payments.refunds-value"));
+        assert!(glob_matches("payments.*", "payments."));
+        assert!(glob_matches("*-value", "orders-value"));
+        assert!(glob_matches("a*b*c", "aXbYbZc"));
+        assert!(glob_matches("orders.v?", "orders.v2"));
+        assert!(glob_matches("*", ""));
+        assert!(!glob_matches("payments.*", "orders.payments"));
+        assert!(!glob_matches("orders.v?", "orders.v10"));
+        assert!(!glob_matches("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn pattern_types() {
+        assert!(PatternType::Exact.matches("orders", "orders"));
+        assert!(!PatternType::Exact.matches("orders", "orders-value"));
+        assert!(PatternType::Prefix.matches("orders", "orders-value"));
+        assert!(!PatternType::Prefix.matches("orders", "all-orders"));
+        assert!(!PatternType::Exact.matches("orders.*", "orders.x"));
+    }
+
+    #[test]
+    fn principal_without_rules_keeps_role() {
+        let principal = team_a();
+        let acl = Acl::new(&principal, &[], false);
+        assert_eq!(acl.role_on("anything"), Some(Role::Writer));
+    }
+
+    #[test]
+    fn principal_without_rules_is_denied_by_default() {
+        let principal = team_a();
+        let acl = Acl::new(&principal, &[], true);
+        assert_eq!(acl.role_on("anything"), None);
+        assert!(!acl.permits("anything", Role::Reader));
+    }
+
+    #[test]
+    fn admins_are_not_restricted() {
+        let principal = Principal {
+            name: String::from("ops"),
+            role: Role::Admin,
+        };
+        let rules = [rule("payments", PatternType::Exact, Role::Reader)];
+
+        assert_eq!(
+            Acl::new(&principal, &[], true).role_on("anything"),
+            Some(Role::Admin)
+        );
+        assert!(Acl::new(&principal, &rules, true).permits("payments", Role::Admin));
+    }
+
+    #[test]
+    fn rules_narrow_down_role_by_subject() {
+        let principal = team_a();
+        let rules = [
+            rule("payments.*", PatternType::Glob, Role::Writer),
+            rule("orders.", PatternType::Prefix, Role::Reader),
+        ];
+        let acl = Acl::new(&principal, &rules, false);
+
+        assert!(acl.permits("payments.refunds", Role::Writer));
+        assert!(acl.permits("orders.created", Role::Reader));
+        assert!(!acl.permits("orders.created", Role::Writer));
+        assert_eq!(acl.role_on("users.created"), Some(Role::Writer));
+        let acl = Acl::new(&principal, &rules, true);
+        assert!(!acl.permits("orders.created", Role::Writer));
+        assert_eq!(acl.role_on("users.created"), None);
+    }
+
+    #[test]
+    fn rules_never_grant_more_than_role() {
+        let principal = team_a();
+        let rules = [rule("payments", PatternType::Exact, Role::Admin)];
+        let acl = Acl::new(&principal, &rules, false);
+
+        assert_eq!(acl.role_on("payments"), Some(Role::Writer));
+        assert!(!acl.permits("payments", Role::Admin));
+    }
+
+    #[test]
+    fn best_matching_rule_wins() {
+        let principal = team_a();
+        let rules = [
+            rule("*", PatternType::Glob, Role::Reader),
+            rule("payments.", PatternType::Prefix, Role::Writer),
+        ];
+        let acl = Acl::new(&principal, &rules, false);
+
+        assert_eq!(acl.role_on("payments.refunds"), Some(Role::Writer));
+        assert_eq!(acl.role_on("orders.created"), Some(Role::Reader));
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use sha2::{Digest, Sha256};
+
+use super::{constant_time_eq, verify_password};
+
+/// Prefix of every API key ID, which tells them apart from usernames in basic auth
+/// credentials.
+pub const API_KEY_PREFIX: &str = "ak_";
+
+/// Generates the public ID of a new API key, used as the basic auth username.
+pub fn generate_api_key_id() -> String {
+    let mut bytes = [0u8; 8];
+    OsRng.fill_bytes(&mut bytes);
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}{}", API_KEY_PREFIX, hex)
+}
+
+/// Generates the secret of an API key, used as the basic auth password.
+pub fn generate_api_key_secret() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Secrets recently verified against their hash, so that clients sending a request after
+/// another don't pay for hashing their secret each time. Used for API keys and for the
+/// users of the users file.
+///
+/// Entries are keyed by the stored hash, so a rotated key never matches its old secret,
+/// and only hold a SHA-256 digest of the secret. Whether the key is still active is up
+/// to the caller, which checks it on every request.
+pub struct VerifiedSecrets {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, ([u8; 32], Instant)>>,
+}
+
+impl VerifiedSecrets {
+    pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Same as [`verify_password`], skipping the hashing when `secret` was verified
+    /// against `hash` less than the TTL ago.
+    pub fn verify(&self, secret: &str, hash: &str) -> bool {
+        let digest: [u8; 32] = Sha256::digest(secret.as_bytes()).into();
+        let now = Instant::now();
+        let cached = self
+            .entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(hash)
+            .is_some_and(|(verified, at)| {
+                now.duration_since(*at) < self.ttl && constant_time_eq(verified, &digest)
+            });
+        if cached {
+            return true;
+        }
+
+        if !verify_password(secret, hash) {
+            return false;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, (_, at)| now.duration_since(*at) < self.ttl);
+        entries.insert(hash.to_string(), (digest, now));
+        true
+    }
+}
+
+impl fmt::Debug for VerifiedSecrets {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VerifiedSecrets")
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for VerifiedSecrets {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_TTL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{generate_api_key_id, generate_api_key_secret, VerifiedSecrets, API_KEY_PREFIX};
+    use crate::auth::hash_password;
+
+    #[test]
+    fn generated_ids_and_secrets_are_unique() {
+        let id = generate_api_key_id();
+        assert!(id.starts_with(API_KEY_PREFIX));
+        assert_eq!(id.len(), API_KEY_PREFIX.len() + 16);
+        assert_ne!(id, generate_api_key_id());
+        assert_ne!(generate_api_key_secret(), generate_api_key_secret());
+    }
+
+    #[test]
+    fn verified_secrets_are_cached() {
+        let hash = hash_password("some_secret").unwrap();
+        let secrets = VerifiedSecrets::default();
+
+        assert!(!secrets.verify("not_the_secret", &hash));
+        assert!(secrets.verify("some_secret", &hash));
+        assert!(secrets.entries.lock().unwrap().contains_key(&hash));
+        assert!(secrets.verify("some_secret", &hash));
+        // a cached secret doesn't let others in
+        assert!(!secrets.verify("not_the_secret", &hash));
+    }
+
+    #[test]
+    fn expired_verifications_are_checked_again() {
+        let hash = hash_password("some_secret").unwrap();
+        let secrets = VerifiedSecrets::new(Duration::ZERO);
+
+        assert!(secrets.verify("some_secret", &hash));
+        assert!(secrets.verify("some_secret", &hash));
+        assert!(secrets.entries.lock().unwrap().len() <= 1);
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use super::{Principal, Role};
+
+/// Header a follower passes the principal of a forwarded request in.
+pub const FORWARDED_PRINCIPAL_HEADER: &str = "x-forwarded-principal";
+
+/// Audience of the tokens, so that they can't be mistaken for bearer tokens.
+const AUDIENCE: &str = "schema-registry-leader";
+
+/// Seconds a token is valid for, long enough for the leader to answer.
+const LIFETIME: i64 = 60;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: String,
+    role: Role,
+    aud: String,
+    exp: i64,
+}
+
+/// Secret the leader and its followers share, so that the leader can trust the principal
+/// a follower authenticated, whatever credentials it used. Principals are passed as
+/// short-lived HS256 tokens.
+#[derive(Clone, PartialEq)]
+pub struct ForwardingKey {
+    secret: Vec<u8>,
+}
+
+impl ForwardingKey {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            secret: secret.to_vec(),
+        }
+    }
+
+    /// Token standing for `principal` in [`FORWARDED_PRINCIPAL_HEADER`].
+    pub fn sign(&self, principal: &Principal) -> Result<String, jsonwebtoken::errors::Error> {
+        let claims = Claims {
+            sub: principal.name.to_owned(),
+            role: principal.role,
+            aud: AUDIENCE.to_string(),
+            exp: chrono::Utc::now().timestamp() + LIFETIME,
+        };
+        encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(&self.secret),
+        )
+    }
+
+    /// Principal of a token signed with the same secret, unless it expired.
+    pub fn verify(&self, token: &str) -> Option<Principal> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_audience(&[AUDIENCE]);
+        validation.set_required_spec_claims(&["exp", "aud", "sub"]);
+        decode::<Claims>(token, &DecodingKey::from_secret(&self.secret), &validation)
+            .ok()
+            .map(|token| Principal {
+                name: token.claims.sub,
+                role: token.claims.role,
+            })
+    }
+}
+
+impl fmt::Debug for ForwardingKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ForwardingKey(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ForwardingKey;
+    use crate::auth::{Principal, Role};
+
+    fn principal() -> Principal {
+        Principal {
+            name: String::from("service.example.com"),
+            role: Role::Writer,
+        }
+    }
+
+    #[test]
+    fn verify_forwarded_principal() {
+        let key = ForwardingKey::new(b"shared");
+        let principal = key.verify(&key.sign(&principal()).unwrap()).unwrap();
+        assert_eq!(principal.name, "service.example.com");
+        assert_eq!(principal.role, Role::Writer);
+    }
+
+    #[test]
+    fn verify_forwarded_principal_with_another_secret() {
+        let token = ForwardingKey::new(b"shared").sign(&principal()).unwrap();
+        assert!(ForwardingKey::new(b"another").verify(&token).is_none());
+        assert!(ForwardingKey::new(b"shared")
+            .verify("not a token")
+            .is_none());
+    }
+}
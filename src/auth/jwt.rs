@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet, KeyAlgorithm};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde_json::Value;
+
+use super::{Principal, Role};
+use crate::settings::JwtSettings;
+
+#[derive(Debug, thiserror::Error)]
+pub enum JwtConfigError {
+    #[error("could not read JWKS file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid JWKS file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("invalid JWT key: {0}")]
+    Key(#[from] jsonwebtoken::errors::Error),
+    #[error("JWK {0:?} is neither an RS256 nor an ES256 key")]
+    UnsupportedKey(Option<String>),
+    #[error("{} must be set to accept JWTs", crate::settings::named(.0))]
+    Missing(&'static str),
+}
+
+struct VerificationKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+/// Verifies bearer tokens issued by a trusted identity provider, and maps their claims to
+/// a principal.
+///
+/// Tokens must be signed with one of the configured keys, and have a `sub`, an `exp`, an
+/// `aud` and an `iss` claim, the latter two matching the configured audience and issuer.
+/// When present, `nbf` is checked as well. The principal's role is the best one the
+/// values of the role claim map to, tokens without any are rejected.
+pub struct JwtVerifier {
+    keys: Vec<VerificationKey>,
+    issuer: String,
+    audience: String,
+    role_claim: String,
+    role_mapping: HashMap<String, Role>,
+}
+
+impl JwtVerifier {
+    pub const DEFAULT_ROLE_CLAIM: &'static str = "roles";
+
+    /// Creates a verifier without any key yet, with role claim values only mapped to
+    /// the role of the same name.
+    pub fn new(issuer: &str, audience: &str) -> Self {
+        Self {
+            keys: Vec::new(),
+            issuer: issuer.to_string(),
+            audience: audience.to_string(),
+            role_claim: Self::DEFAULT_ROLE_CLAIM.to_string(),
+            role_mapping: [Role::Reader, Role::Writer, Role::Admin]
+                .into_iter()
+                .map(|role| (role.to_string(), role))
+                .collect(),
+        }
+    }
+
+    pub fn with_hs256_secret(mut self, secret: &[u8]) -> Self {
+        self.keys.push(VerificationKey {
+            kid: None,
+            algorithm: Algorithm::HS256,
+            key: DecodingKey::from_secret(secret),
+        });
+        self
+    }
+
+    /// Adds the RS256 and ES256 public keys of `jwks`.
+    pub fn with_jwks(mut self, jwks: &JwkSet) -> Result<Self, JwtConfigError> {
+        for jwk in &jwks.keys {
+            let kid = jwk.common.key_id.to_owned();
+            let algorithm = match (jwk.common.key_algorithm, &jwk.algorithm) {
+                (Some(KeyAlgorithm::RS256), AlgorithmParameters::RSA(_))
+                | (None, AlgorithmParameters::RSA(_)) => Algorithm::RS256,
+                (Some(KeyAlgorithm::ES256), AlgorithmParameters::EllipticCurve(_))
+                | (None, AlgorithmParameters::EllipticCurve(_)) => Algorithm::ES256,
+                _ => return Err(JwtConfigError::UnsupportedKey(kid)),
+            };
+            self.keys.push(VerificationKey {
+                kid,
+                algorithm,
+                key: DecodingKey::from_jwk(jwk)?,
+            });
+        }
+        Ok(self)
+    }
+
+    pub fn with_jwks_file(self, path: impl AsRef<Path>) -> Result<Self, JwtConfigError> {
+        let jwks: JwkSet = serde_json::from_str(&fs::read_to_string(path)?)?;
+        self.with_jwks(&jwks)
+    }
+
+    /// Claim listing the roles of the token's subject, either as a string or an array of
+    /// strings.
+    pub fn with_role_claim(mut self, claim: &str) -> Self {
+        self.role_claim = claim.to_string();
+        self
+    }
+
+    /// Maps `value` of the role claim to `role`.
+    pub fn with_role_mapping(mut self, value: &str, role: Role) -> Self {
+        self.role_mapping.insert(value.to_string(), role);
+        self
+    }
+
+    /// Builds a verifier if an HS256 secret or a JWKS file is set, in which case the
+    /// issuer and audience are required.
+    pub fn from_settings(settings: &JwtSettings) -> Result<Option<Self>, JwtConfigError> {
+        if settings.hs256_secret.is_none() && settings.jwks_file.is_none() {
+            return Ok(None);
+        }
+
+        let issuer = settings
+            .issuer
+            .as_deref()
+            .ok_or(JwtConfigError::Missing("auth.jwt.issuer"))?;
+        let audience = settings
+            .audience
+            .as_deref()
+            .ok_or(JwtConfigError::Missing("auth.jwt.audience"))?;
+
+        let mut verifier = Self::new(issuer, audience);
+        if let Some(secret) = &settings.hs256_secret {
+            verifier = verifier.with_hs256_secret(secret.as_bytes());
+        }
+        if let Some(path) = &settings.jwks_file {
+            verifier = verifier.with_jwks_file(path)?;
+        }
+        if let Some(claim) = &settings.role_claim {
+            verifier = verifier.with_role_claim(claim);
+        }
+        for (value, role) in &settings.role_mapping {
+            verifier = verifier.with_role_mapping(value, *role);
+        }
+        Ok(Some(verifier))
+    }
+
+    pub fn verify(&self, token: &str) -> Option<Principal> {
+        let header = decode_header(token).ok()?;
+        self.keys
+            .iter()
+            .filter(|key| key.algorithm == header.alg)
+            .filter(|key| key.kid.is_none() || header.kid.is_none() || key.kid == header.kid)
+            .find_map(|key| decode::<Value>(token, &key.key, &self.validation(key.algorithm)).ok())
+            .and_then(|token| self.principal(&token.claims))
+    }
+
+    fn validation(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.validate_nbf = true;
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "aud", "iss", "sub"]);
+        validation
+    }
+
+    fn principal(&self, claims: &Value) -> Option<Principal> {
+        let name = claims.get("sub")?.as_str()?;
+        let values = match claims.get(&self.role_claim)? {
+            Value::String(value) => vec![value.as_str()],
+            Value::Array(values) => values.iter().filter_map(Value::as_str).collect(),
+            _ => return None,
+        };
+        let role = values
+            .into_iter()
+            .filter_map(|value| self.role_mapping.get(value))
+            .max()?;
+
+        Some(Principal {
+            name: name.to_string(),
+            role: *role,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JwtVerifier;
+    use crate::auth::Role;
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use serde_json::{json, Value};
+
+    const SECRET: &[u8] = b"some_secret";
+    const ISSUER: &str = "https://idp.example.com";
+    const AUDIENCE: &str = "schema-registry";
+
+    fn now() -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+
+    fn claims() -> Value {
+        json!({
+            "sub": "payments-service",
+            "iss": ISSUER,
+            "aud": AUDIENCE,
+            "exp": now() + 300,
+            "roles": ["writer"],
+        })
+    }
+
+    fn hs256_token(claims: &Value) -> String {
+        encode(
+            &Header::default(),
+            claims,
+            &EncodingKey::from_secret(SECRET),
+        )
+        .unwrap()
+    }
+
+    fn verifier() -> JwtVerifier {
+        JwtVerifier::new(ISSUER, AUDIENCE).with_hs256_secret(SECRET)
+    }
+
+    #[test]
+    fn verify_hs256_token() {
+        let principal = verifier().verify(&hs256_token(&claims())).unwrap();
+        assert_eq!(principal.name, "payments-service");
+        assert_eq!(principal.role, Role::Writer);
+    }
+
+    #[test]
+    fn verify_token_with_wrong_secret() {
+        let token = encode(
+            &Header::default(),
+            &claims(),
+            &EncodingKey::from_secret(b"another_secret"),
+        )
+        .unwrap();
+        assert!(verifier().verify(&token).is_none());
+    }
+
+    #[test]
+    fn verify_expired_token() {
+        let mut claims = claims();
+        claims["exp"] = json!(now() - 300);
+        assert!(verifier().verify(&hs256_token(&claims)).is_none());
+    }
+
+    #[test]
+    fn verify_token_not_yet_valid() {
+        let mut claims = claims();
+        claims["nbf"] = json!(now() + 300);
+        assert!(verifier().verify(&hs256_token(&claims)).is_none());
+    }
+
+    #[test]
+    fn verify_token_for_another_audience_or_issuer() {
+        let mut claims = claims();
+        claims["aud"] = json!("another-service");
+        assert!(verifier().verify(&hs256_token(&claims)).is_none());
+
+        let mut claims = self::claims();
+        claims["iss"] = json!("https://evil.example.com");
+        assert!(verifier().verify(&hs256_token(&claims)).is_none());
+
+        let mut claims = self::claims();
+        claims.as_object_mut().unwrap().remove("aud");
+        assert!(verifier().verify(&hs256_token(&claims)).is_none());
+    }
+
+    #[test]
+    fn verify_token_without_role() {
+        let mut claims = claims();
+        claims["roles"] = json!(["unknown"]);
+        assert!(verifier().verify(&hs256_token(&claims)).is_none());
+
+        claims.as_object_mut().unwrap().remove("roles");
+        assert!(verifier().verify(&hs256_token(&claims)).is_none());
+    }
+
+    #[test]
+    fn verify_token_with_mapped_role_claim() {
+        let verifier = verifier()
+            .with_role_claim("groups")
+            .with_role_mapping("registry-admins", Role::Admin);
+        let mut claims = claims();
+        claims["groups"] = json!(["engineering", "registry-admins"]);
+
+        let principal = verifier.verify(&hs256_token(&claims)).unwrap();
+        assert_eq!(principal.role, Role::Admin);
+    }
+
+    #[test]
+    fn verify_rs256_token_from_jwks() {
+        let verifier = JwtVerifier::new(ISSUER, AUDIENCE)
+            .with_jwks_file("tests/fixtures/jwt/jwks.json")
+            .unwrap();
+        let key = EncodingKey::from_rsa_pem(
+            &std::fs::read("tests/fixtures/jwt/rs256_private.pem").unwrap(),
+        )
+        .unwrap();
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(String::from("test-rs256"));
+
+        let token = encode(&header, &claims(), &key).unwrap();
+        assert_eq!(verifier.verify(&token).unwrap().role, Role::Writer);
+
+        // the key is only trusted for RS256, not as an HS256 secret
+        let token = encode(
+            &Header::default(),
+            &claims(),
+            &EncodingKey::from_secret(SECRET),
+        );
+        assert!(verifier.verify(&token.unwrap()).is_none());
+    }
+}
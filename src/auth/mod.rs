@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str;
+
+use actix_web::http::Method;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::AuthSettings;
+
+pub use self::acl::*;
+pub use self::api_keys::*;
+pub use self::forwarding::*;
+pub use self::jwt::*;
+pub use self::passwords::*;
+
+mod acl;
+mod api_keys;
+mod forwarding;
+mod jwt;
+mod passwords;
+
+/// Name of the principal authenticating with the shared password, unless
+/// `auth.password_principal` says otherwise.
+pub const DEFAULT_PASSWORD_PRINCIPAL: &str = "registry";
+
+/// What a principal is allowed to do. Each role can do everything the previous one can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Read schemas, subjects and configs
+    Reader,
+    /// Also register and look up schemas, and check their compatibility
+    Writer,
+    /// Also delete subjects and versions, and change configs
+    Admin,
+}
+
+impl Role {
+    /// Role required to call a registry endpoint with `method`.
+    pub fn required_for(method: &Method) -> Self {
+        match *method {
+            Method::GET | Method::HEAD => Self::Reader,
+            Method::POST => Self::Writer,
+            _ => Self::Admin,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lowercase = match self {
+            Self::Reader => "reader",
+            Self::Writer => "writer",
+            Self::Admin => "admin",
+        };
+        write!(f, "{}", lowercase)
+    }
+}
+
+impl str::FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "reader" => Ok(Self::Reader),
+            "writer" => Ok(Self::Writer),
+            "admin" => Ok(Self::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Identity of the client that authenticated the request, available to handlers through
+/// the request extensions.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub name: String,
+    pub role: Role,
+}
+
+impl Principal {
+    pub fn can(&self, role: Role) -> bool {
+        self.role >= role
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UserStoreError {
+    #[error("could not read users file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid users file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("either auth.users_file (SCHEMA_REGISTRY_USERS_FILE) or auth.password (SCHEMA_REGISTRY_PASSWORD) must be set")]
+    NoCredentials,
+}
+
+#[derive(Debug, Deserialize)]
+struct UsersFile {
+    users: Vec<User>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct User {
+    name: String,
+    /// Argon2 hash of the password as a PHC string, see [`hash_password`]. Users without
+    /// one can only authenticate with a client certificate
+    #[serde(default)]
+    password_hash: Option<String>,
+    role: Role,
+}
+
+/// Principals allowed to use the registry.
+///
+/// Users come from a JSON file listing their name, role and the argon2 hash of their
+/// password, as printed by `avro-schema-registry hash-password`:
+///
+/// ```json
+/// {"users": [{"name": "ci", "password_hash": "$argon2id$v=19$...", "role": "writer"}]}
+/// ```
+///
+/// For backwards compatibility, a single shared password can also be configured. It
+/// authenticates one admin principal, [`DEFAULT_PASSWORD_PRINCIPAL`] unless configured
+/// otherwise, with either its name or an empty username.
+#[derive(Debug, Default)]
+pub struct UserStore {
+    users: HashMap<String, User>,
+    password: Option<String>,
+    password_principal: String,
+    /// Passwords of users recently authenticated, so that they aren't hashed again on
+    /// every request
+    verified: VerifiedSecrets,
+}
+
+impl UserStore {
+    pub fn with_password(password: &str) -> Self {
+        Self {
+            users: HashMap::new(),
+            password: Some(password.to_string()),
+            password_principal: DEFAULT_PASSWORD_PRINCIPAL.to_string(),
+            verified: VerifiedSecrets::default(),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, UserStoreError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    fn from_json(users: &str) -> Result<Self, UserStoreError> {
+        let file: UsersFile = serde_json::from_str(users)?;
+        Ok(Self {
+            users: file
+                .users
+                .into_iter()
+                .map(|user| (user.name.to_owned(), user))
+                .collect(),
+            password: None,
+            password_principal: DEFAULT_PASSWORD_PRINCIPAL.to_string(),
+            verified: VerifiedSecrets::default(),
+        })
+    }
+
+    /// Loads the users from the users file, and sets the shared password and the
+    /// principal it authenticates. At least one of them must be set.
+    pub fn from_settings(settings: &AuthSettings) -> Result<Self, UserStoreError> {
+        let mut store = match &settings.users_file {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        store.password.clone_from(&settings.password);
+        store.password_principal = settings
+            .password_principal
+            .as_deref()
+            .unwrap_or(DEFAULT_PASSWORD_PRINCIPAL)
+            .to_string();
+
+        if store.users.is_empty() && store.password.is_none() {
+            return Err(UserStoreError::NoCredentials);
+        }
+        Ok(store)
+    }
+
+    /// Principal named `name`, without checking any password. That is a user of the users
+    /// file, or the principal of the shared password when one is configured.
+    pub fn principal(&self, name: &str) -> Option<Principal> {
+        self.user(name).or_else(|| {
+            self.password_principal()
+                .filter(|shared| shared.name == name)
+        })
+    }
+
+    /// Principal of the user named `name` in the users file, for clients that proved who
+    /// they are by other means than a password, like a client certificate.
+    pub fn user(&self, name: &str) -> Option<Principal> {
+        self.users.get(name).map(|user| Principal {
+            name: user.name.to_owned(),
+            role: user.role,
+        })
+    }
+
+    /// Checks the password of `username`. This hashes the password unless it was verified
+    /// recently, so it is slow on purpose and better run off the async workers.
+    pub fn authenticate(&self, username: &str, password: &str) -> Option<Principal> {
+        if let Some(user) = self.users.get(username) {
+            return match &user.password_hash {
+                Some(hash) if self.verified.verify(password, hash) => Some(Principal {
+                    name: user.name.to_owned(),
+                    role: user.role,
+                }),
+                Some(_) => None,
+                None => {
+                    verify_no_password(password);
+                    None
+                }
+            };
+        }
+        match (&self.password, self.password_principal()) {
+            (Some(shared), Some(principal))
+                if username.is_empty() || username == principal.name =>
+            {
+                constant_time_eq(shared.as_bytes(), password.as_bytes()).then_some(principal)
+            }
+            _ => {
+                verify_no_password(password);
+                None
+            }
+        }
+    }
+
+    /// Admin principal of the shared password, when one is configured.
+    fn password_principal(&self) -> Option<Principal> {
+        self.password.as_ref().map(|_| Principal {
+            name: self.password_principal.to_owned(),
+            role: Role::Admin,
+        })
+    }
+}
+
+/// Compares two secrets without returning early on the first difference, so that the
+/// time it takes doesn't tell how much of the secret was guessed right.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Role, UserStore, DEFAULT_PASSWORD_PRINCIPAL};
+    use crate::settings::AuthSettings;
+    use actix_web::http::Method;
+
+    // The passwords are "reader_password" and "password"
+    const USERS: &str = r#"{"users": [
+        {"name": "reader", "password_hash": "$argon2id$v=19$m=19456,t=2,p=1$vWUt3p+x+mlJxtbjc0aElA$F+u1z2ZqbZl2oVAonnFCJng81+McfTSYfYI4/DEQv9A", "role": "reader"},
+        {"name": "admin", "password_hash": "$argon2id$v=19$m=19456,t=2,p=1$v7jtTgu68A8fMk4Rh9cz2w$Kp1AdgKYsPNjZRqJKvDA+pA5CbjvwN7WFprVtrdDy/4", "role": "admin"},
+        {"name": "service.example.com", "role": "writer"}
+    ]}"#;
+
+    fn store() -> UserStore {
+        UserStore::from_json(USERS).unwrap()
+    }
+
+    #[test]
+    fn authenticate_user_from_file() {
+        let principal = store().authenticate("admin", "password").unwrap();
+        assert_eq!(principal.name, "admin");
+        assert_eq!(principal.role, Role::Admin);
+
+        let principal = store().authenticate("reader", "reader_password").unwrap();
+        assert_eq!(principal.role, Role::Reader);
+        assert!(!principal.can(Role::Writer));
+    }
+
+    #[test]
+    fn authenticated_users_are_cached() {
+        let store = store();
+        assert!(store.authenticate("admin", "password").is_some());
+        assert!(store.authenticate("admin", "password").is_some());
+        // a cached password doesn't let others in, nor another user with it
+        assert!(store.authenticate("admin", "not_the_password").is_none());
+        assert!(store.authenticate("reader", "password").is_none());
+    }
+
+    #[test]
+    fn authenticate_user_with_wrong_password() {
+        assert!(store().authenticate("admin", "not_the_password").is_none());
+        assert!(store().authenticate("reader", "password").is_none());
+    }
+
+    #[test]
+    fn users_file_with_sha256_digests() {
+        let users = r#"{"users": [{"name": "ci", "password_sha256": "5e88", "role": "writer"}]}"#;
+        assert!(UserStore::from_json(users).is_err());
+    }
+
+    #[test]
+    fn authenticate_unknown_user_without_shared_password() {
+        assert!(store().authenticate("someone", "password").is_none());
+        assert!(store().authenticate("", "password").is_none());
+    }
+
+    #[test]
+    fn authenticate_with_shared_password() {
+        let store = UserStore::with_password("shared");
+        for username in ["", DEFAULT_PASSWORD_PRINCIPAL] {
+            let principal = store.authenticate(username, "shared").unwrap();
+            assert_eq!(principal.name, DEFAULT_PASSWORD_PRINCIPAL);
+            assert_eq!(principal.role, Role::Admin);
+        }
+        assert!(store.authenticate("", "other").is_none());
+        // Other usernames don't get to pick the name audited for the shared password
+        assert!(store.authenticate("someone", "shared").is_none());
+    }
+
+    #[test]
+    fn authenticate_with_shared_password_of_configured_principal() {
+        let settings = AuthSettings {
+            password: Some(String::from("shared")),
+            password_principal: Some(String::from("deployer")),
+            ..Default::default()
+        };
+        let store = UserStore::from_settings(&settings).unwrap();
+        assert_eq!(
+            store.authenticate("deployer", "shared").unwrap().name,
+            "deployer"
+        );
+        assert!(store
+            .authenticate(DEFAULT_PASSWORD_PRINCIPAL, "shared")
+            .is_none());
+    }
+
+    #[test]
+    fn authenticate_user_without_password() {
+        assert!(store().authenticate("service.example.com", "").is_none());
+        assert_eq!(
+            store().user("service.example.com").unwrap().role,
+            Role::Writer
+        );
+        assert!(UserStore::with_password("shared").user("someone").is_none());
+    }
+
+    #[test]
+    fn principal_without_password() {
+        assert_eq!(store().principal("reader").unwrap().role, Role::Reader);
+        assert!(store().principal("someone").is_none());
+        let store = UserStore::with_password("shared");
+        assert!(store.principal("someone").is_none());
+        assert_eq!(
+            store.principal(DEFAULT_PASSWORD_PRINCIPAL).unwrap().role,
+            Role::Admin
+        );
+    }
+
+    #[test]
+    fn role_required_for_method() {
+        assert_eq!(Role::required_for(&Method::GET), Role::Reader);
+        assert_eq!(Role::required_for(&Method::POST), Role::Writer);
+        assert_eq!(Role::required_for(&Method::PUT), Role::Admin);
+        assert_eq!(Role::required_for(&Method::DELETE), Role::Admin);
+    }
+}
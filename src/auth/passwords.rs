@@ -0,0 +1,59 @@
+use std::sync::OnceLock;
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+
+/// Hashes `password` with argon2 and a random salt, into a PHC string. Passwords of the
+/// users file and secrets of API keys are only ever stored hashed this way.
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+}
+
+/// Checks `password` against a hash made by [`hash_password`]. The hashes are compared
+/// in constant time.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// Takes as long as [`verify_password`] without checking anything, so that credentials
+/// of unknown users or keys take as long to reject as wrong passwords, and don't tell
+/// which ones exist.
+pub fn verify_no_password(password: &str) {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    let hash = DUMMY_HASH.get_or_init(|| {
+        hash_password("not a password").expect("hashing a constant password can't fail")
+    });
+    let _ = verify_password(password, hash);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_password, verify_no_password, verify_password};
+
+    #[test]
+    fn verify_hashed_password() {
+        let hash = hash_password("some_password").unwrap();
+
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("some_password", &hash));
+        assert!(!verify_password("not_the_password", &hash));
+        assert!(!verify_password("some_password", "not_a_hash"));
+        verify_no_password("some_password");
+    }
+
+    #[test]
+    fn hashes_are_salted() {
+        assert_ne!(
+            hash_password("some_password").unwrap(),
+            hash_password("some_password").unwrap()
+        );
+    }
+}
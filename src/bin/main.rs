@@ -1,53 +1,441 @@
 use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-use actix_web::{
-    middleware::{Compat, Logger},
-    web::Data,
-    App, HttpServer,
-};
+use actix_web::middleware::Condition;
+use actix_web::{rt, web::Data, App, HttpServer};
 use actix_web_prom::PrometheusMetricsBuilder;
-use sentry::integrations::panic as sentry_panic;
-use sentry::IntoDsn;
+use clap::{Args, Parser, Subcommand};
+use prometheus::Registry;
 
-use avro_schema_registry::app;
-use avro_schema_registry::db::{DbManage, DbPool};
+use avro_schema_registry::admin::{self, AdminError};
+use avro_schema_registry::app::{self, ApiConfig};
+use avro_schema_registry::auth::{self, DenyByDefault};
+use avro_schema_registry::db::models::{CompatibilityLevel, Reference, RegisterSchema, Schema};
+use avro_schema_registry::db::{self, DbManage, DbPool, DbPoolConfig, DbReadPool};
+#[cfg(feature = "sentry")]
+use avro_schema_registry::error_reporting::ErrorReporting;
+use avro_schema_registry::metrics::Metrics;
+use avro_schema_registry::middleware::{Leader, RateLimiter, RequestTimeout};
+use avro_schema_registry::settings::{Settings, SettingsArgs, SettingsError};
+use avro_schema_registry::telemetry::{self, Telemetry};
+use avro_schema_registry::tls::{self, TlsConfig};
+
+/// Avro schema registry, speaking the Confluent Schema Registry API
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    settings: SettingsArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the API, which is what the registry does without a subcommand
+    Serve,
+    /// Run the database migrations that haven't been run yet
+    Migrate,
+    /// Write every subject, schema, version and config as newline delimited JSON
+    Export {
+        /// File to write to instead of the standard output
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Restore an export into an empty registry, schema IDs included
+    Import {
+        /// Export to read instead of the standard input
+        file: Option<PathBuf>,
+    },
+    /// Replay a dump of the `_schemas` topic of a Confluent registry, keeping its IDs
+    ImportConfluent {
+        /// Dump to read instead of the standard input
+        file: Option<PathBuf>,
+    },
+    /// Register a schema under a subject
+    Register(SchemaArgs),
+    /// Check whether a schema could be registered under a subject, exiting with 1 if not
+    Check(SchemaArgs),
+    /// Set the compatibility level of a subject, or the global one
+    SetCompat {
+        #[arg(value_parser = parse_level)]
+        level: CompatibilityLevel,
+        /// Subject to set it for, instead of the whole registry
+        #[arg(long)]
+        subject: Option<String>,
+    },
+    /// Delete the schemas that no version refers to anymore
+    Gc {
+        /// Only list them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Recompute the fingerprints of every schema and list those that differ from the
+    /// stored ones, exiting with 1 if any does
+    Verify,
+    /// Adopt a database of salsify's avro-schema-registry in place, listing the rows
+    /// that can't be used and exiting with 1 if there are any
+    AdoptSalsify {
+        /// Roll everything back once done
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Read a password from the standard input and print its hash, for the users file
+    HashPassword,
+}
+
+#[derive(Args)]
+struct SchemaArgs {
+    /// Subject the schema is registered under
+    subject: String,
+    /// Avro schema, in JSON
+    file: PathBuf,
+    /// Type the schema uses from a registered schema
+    #[arg(
+        long = "reference",
+        value_name = "NAME=SUBJECT:VERSION",
+        value_parser = parse_reference
+    )]
+    references: Vec<Reference>,
+}
+
+fn parse_level(level: &str) -> Result<CompatibilityLevel, String> {
+    level.parse().map_err(|_| {
+        "must be one of BACKWARD, BACKWARD_TRANSITIVE, FORWARD, FORWARD_TRANSITIVE, FULL, \
+         FULL_TRANSITIVE or NONE"
+            .to_string()
+    })
+}
+
+fn parse_reference(reference: &str) -> Result<Reference, String> {
+    let invalid = || "must be NAME=SUBJECT:VERSION".to_string();
+    let (name, versioned) = reference.split_once('=').ok_or_else(invalid)?;
+    let (subject, version) = versioned.rsplit_once(':').ok_or_else(invalid)?;
+    Ok(Reference {
+        name: name.to_string(),
+        subject: subject.to_string(),
+        version: version.parse().map_err(|_| invalid())?,
+    })
+}
+
+fn invalid(e: impl fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+}
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    env::set_var("RUST_LOG", "actix_web=debug,avro_schema_registry=debug");
+async fn main() -> ExitCode {
     env::set_var("RUST_BACKTRACE", "1");
-    env_logger::init();
-
-    let _sentry_client = sentry::init(sentry::ClientOptions {
-        dsn: env::var("SENTRY_URL")
-            .ok()
-            .into_dsn()
-            .expect("Invalid Sentry DSN"),
-        release: Some(std::borrow::Cow::Borrowed(env!("CARGO_PKG_VERSION"))),
-        ..Default::default()
-    });
-
-    let _integration = sentry_panic::PanicIntegration::default().add_extractor(|_info| None);
+
+    let cli = Cli::parse();
+    let result = match Settings::load(&cli.settings) {
+        Ok(settings) => match cli.command.unwrap_or(Command::Serve) {
+            Command::Serve => serve(settings).await.map(|()| ExitCode::SUCCESS),
+            Command::HashPassword => hash_password(),
+            command => run(command, &settings),
+        },
+        Err(e) => Err(invalid(e)),
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        ExitCode::FAILURE
+    })
+}
+
+async fn serve(settings: Settings) -> io::Result<()> {
+    let tracer_provider = Telemetry::from_settings(&settings.telemetry)
+        .map(|telemetry| telemetry.init())
+        .transpose()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    telemetry::init_subscriber(settings.logging.format, tracer_provider.as_ref())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    #[cfg(feature = "sentry")]
+    let _sentry = ErrorReporting::from_settings(&settings.sentry)
+        .map_err(invalid)?
+        .map(|reporting| reporting.init());
+    // Set up before any database connection, so that every query is instrumented
+    telemetry::instrument_queries().map_err(io::Error::other)?;
+
+    // Every setting is checked before anything is started
+    if settings.server.workers == Some(0) {
+        return Err(invalid(SettingsError::Invalid(
+            "server.workers",
+            "positive",
+        )));
+    }
+    let db_config = DbPoolConfig::from_settings(&settings.database).map_err(invalid)?;
+    let replica_config =
+        DbPoolConfig::replica_from_settings(&settings.database).map_err(invalid)?;
+    let api = ApiConfig::from_settings(&settings).map_err(invalid)?;
+    // Rate limits apply to the whole server, not to each worker
+    let rate_limiter =
+        Data::new(RateLimiter::from_settings(&settings.rate_limit).map_err(invalid)?);
+    let request_timeout = RequestTimeout::from_settings(&settings.server)
+        .map_err(invalid)?
+        .map(Data::new);
+    let deny_by_default = DenyByDefault::from_settings(&settings.auth).map(Data::new);
+    let leader = Leader::from_settings(&settings.leader)
+        .map_err(invalid)?
+        .map(Data::new);
+    let refresh_interval = Metrics::refresh_interval(&settings.server).map_err(invalid)?;
+    let tls_config = TlsConfig::from_settings(&settings.tls)
+        .map_err(invalid)?
+        .map(|config| config.server_config())
+        .transpose()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    // The registry's own metrics are served along the HTTP ones
+    let registry = Registry::new();
+    let metrics = Metrics::new(&registry).expect("Failed to instantiate registry metrics");
     let prometheus = PrometheusMetricsBuilder::new("avro_schema_registry")
         .endpoint("/_/metrics")
+        .registry(registry)
         .build()
         .expect("Failed to instantiate Prometheus metrics");
+    let serve_metrics = settings.server.metrics;
 
-    let host = env::var("DEFAULT_HOST").unwrap_or_else(|_| "127.0.0.1:8080".to_string());
-    log::info!("Starting server at {}", host);
-
-    HttpServer::new(move || {
-        let db_pool = DbPool::new_pool(None);
+    let db_pool = DbPool::from_config_with_events(&db_config, metrics.pool_events());
+    // Waiting sleeps between attempts, which mustn't block the runtime
+    let (pool, config) = (db_pool.clone(), db_config.clone());
+    rt::task::spawn_blocking(move || pool.wait_until_ready(&config))
+        .await
+        .map_err(io::Error::other)?
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "Database is not reachable",
+            )
+        })?;
+    let replica = replica_config.map(|config| DbPool::from_config(&config));
+    let db_read_pool = Data::new(DbReadPool::new(db_pool.clone(), replica));
+    let metrics = Data::new(metrics);
+    if serve_metrics {
+        Metrics::refresh_periodically(metrics.clone(), db_pool.clone(), refresh_interval);
+    }
+    // A single pool is shared by every worker
+    let db_pool = Data::new(db_pool);
 
+    let server = HttpServer::new(move || {
         App::new()
-            .wrap(Compat::new(Logger::default()))
-            .wrap(prometheus.clone())
+            .wrap(Condition::new(serve_metrics, prometheus.clone()))
             .configure(app::monitoring_routing)
-            .app_data(Data::new(db_pool))
-            .configure(app::api_routing)
+            .app_data(db_pool.clone())
+            .app_data(db_read_pool.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(metrics.clone())
+            .configure(|cfg| {
+                if let Some(request_timeout) = &request_timeout {
+                    cfg.app_data(request_timeout.clone());
+                }
+                if let Some(deny_by_default) = &deny_by_default {
+                    cfg.app_data(deny_by_default.clone());
+                }
+                if let Some(leader) = &leader {
+                    cfg.app_data(leader.clone());
+                }
+            })
+            .configure(|cfg| api.configure(cfg))
     })
-    .bind(host)?
-    .shutdown_timeout(2)
-    .run()
-    .await
+    .on_connect(tls::on_connect);
+    let server = match settings.server.workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+
+    let host = settings.server.listen;
+    let server = match tls_config {
+        Some(tls_config) => {
+            log::info!("Starting server at {} with TLS", host);
+            server.bind_rustls_0_23(host, tls_config)?
+        }
+        None => {
+            log::info!("Starting server at {}", host);
+            server.bind(host)?
+        }
+    };
+    let result = server.shutdown_timeout(2).run().await;
+    if let Some(tracer_provider) = tracer_provider {
+        if let Err(e) = tracer_provider.shutdown() {
+            log::warn!("failed to export the last spans: {}", e);
+        }
+    }
+    result
+}
+
+/// Prints the hash of the first line of the standard input, without its line ending.
+fn hash_password() -> io::Result<ExitCode> {
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(invalid("the password is empty"));
+    }
+    println!("{}", auth::hash_password(password).map_err(invalid)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Runs an admin subcommand directly against the database.
+fn run(command: Command, settings: &Settings) -> io::Result<ExitCode> {
+    telemetry::init_subscriber(settings.logging.format, None)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let db_config = DbPoolConfig::from_settings(&settings.database).map_err(invalid)?;
+    let db_pool = DbPool::from_config(&db_config);
+    db_pool.wait_until_ready(&db_config).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "Database is not reachable",
+        )
+    })?;
+    let mut conn = db_pool.connection().map_err(admin_error)?;
+    let audit = admin::audit_context();
+
+    match command {
+        Command::Serve | Command::HashPassword => {
+            unreachable!("neither the server nor hash-password are admin subcommands")
+        }
+        Command::Migrate => {
+            for version in db::run_migrations(&mut conn).map_err(admin_error)? {
+                println!("ran migration {}", version);
+            }
+        }
+        Command::Export { output } => {
+            let records = match output {
+                Some(path) => {
+                    let mut file = BufWriter::new(File::create(&path).map_err(at(&path))?);
+                    admin::export(&mut conn, &mut file).map_err(admin_error)?
+                }
+                None => {
+                    let mut stdout = io::stdout().lock();
+                    admin::export(&mut conn, &mut stdout).map_err(admin_error)?
+                }
+            };
+            eprintln!("exported {} records", records);
+        }
+        Command::Import { file } => {
+            let summary = match file {
+                Some(path) => {
+                    let file = BufReader::new(File::open(&path).map_err(at(&path))?);
+                    admin::import(&mut conn, file, &audit)
+                }
+                None => admin::import(&mut conn, io::stdin().lock(), &audit),
+            }
+            .map_err(admin_error)?;
+            eprintln!(
+                "imported {} subjects, {} schemas and {} versions",
+                summary.subjects, summary.schemas, summary.versions
+            );
+        }
+        Command::ImportConfluent { file } => {
+            let summary = match file {
+                Some(path) => {
+                    let file = BufReader::new(File::open(&path).map_err(at(&path))?);
+                    admin::replay_confluent(&mut conn, file, &audit)
+                }
+                None => admin::replay_confluent(&mut conn, io::stdin().lock(), &audit),
+            }
+            .map_err(admin_error)?;
+            eprintln!(
+                "replayed {} records, adding {} schemas and {} versions",
+                summary.records, summary.schemas, summary.versions
+            );
+        }
+        Command::Register(args) => {
+            let registration = RegisterSchema {
+                schema: read_schema(&args.file)?,
+                subject: args.subject,
+                references: args.references,
+            };
+            let subject = registration.subject.clone();
+            let registered =
+                admin::register(&mut conn, registration, &audit).map_err(admin_error)?;
+            match registered.created_version {
+                Some(version) => println!(
+                    "registered schema {} as version {} of {}",
+                    registered.schema.id, version, subject
+                ),
+                None => println!(
+                    "schema {} is already registered under {}",
+                    registered.schema.id, subject
+                ),
+            }
+        }
+        Command::Check(args) => {
+            let schema = read_schema(&args.file)?;
+            if !admin::check(&mut conn, &args.subject, &schema, &args.references)
+                .map_err(admin_error)?
+            {
+                println!("incompatible with {}", args.subject);
+                return Ok(ExitCode::FAILURE);
+            }
+            println!("compatible with {}", args.subject);
+        }
+        Command::SetCompat { level, subject } => {
+            let level = admin::set_compatibility(&mut conn, subject.as_deref(), level, &audit)
+                .map_err(admin_error)?;
+            match subject {
+                Some(subject) => println!("compatibility of {} is {}", subject, level),
+                None => println!("global compatibility is {}", level),
+            }
+        }
+        Command::Gc { dry_run: true } => {
+            for schema in Schema::orphans(&mut conn).map_err(admin_error)? {
+                println!("would delete schema {}", schema.id);
+            }
+        }
+        Command::Gc { dry_run: false } => {
+            for id in admin::collect_garbage(&mut conn, &audit).map_err(admin_error)? {
+                println!("deleted schema {}", id);
+            }
+        }
+        Command::Verify => {
+            let drifts = admin::verify(&mut conn).map_err(admin_error)?;
+            for drift in &drifts {
+                println!("{}", drift);
+            }
+            if !drifts.is_empty() {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::AdoptSalsify { dry_run } => {
+            let adoption = admin::adopt_salsify(&mut conn, dry_run, &audit).map_err(admin_error)?;
+            for version in &adoption.baseline {
+                println!("recorded migration {} as run", version);
+            }
+            for version in &adoption.migrations {
+                println!("ran migration {}", version);
+            }
+            for problem in &adoption.problems {
+                println!("{}", problem);
+            }
+            eprintln!(
+                "{} the fingerprints of {} of {} schemas{}",
+                if dry_run { "would update" } else { "updated" },
+                adoption.updated,
+                adoption.schemas,
+                if dry_run { ", nothing was changed" } else { "" }
+            );
+            if !adoption.problems.is_empty() {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn admin_error(e: impl Into<AdminError>) -> io::Error {
+    io::Error::other(e.into())
+}
+
+/// Names the file in errors about it.
+fn at(path: &Path) -> impl Fn(io::Error) -> io::Error + '_ {
+    move |e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+fn read_schema(path: &Path) -> io::Result<String> {
+    fs::read_to_string(path)
+        .map(|schema| schema.trim().to_string())
+        .map_err(at(path))
 }
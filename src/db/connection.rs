@@ -1,30 +1,245 @@
-use std::env;
+use std::future::Future;
+use std::thread;
+use std::time::Duration;
 
 use diesel::pg::PgConnection;
-use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
+use diesel::r2d2::{
+    Builder, ConnectionManager, CustomizeConnection, HandleEvent, Pool, PooledConnection,
+};
+use diesel::RunQueryDsl;
+use log::{info, warn};
+use tracing::Span;
 
 use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::settings::{DatabaseSettings, Settings, SettingsError};
 
 pub type DbPool = Pool<ConnectionManager<PgConnection>>;
 pub type DbConnection = PooledConnection<ConnectionManager<PgConnection>>;
 
+/// Longest pause between two attempts at reaching the database on startup.
+const MAX_STARTUP_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Settings for the connection pool shared by every worker.
+#[derive(Debug, Clone)]
+pub struct DbPoolConfig {
+    pub database_url: String,
+    pub min_idle: Option<u32>,
+    pub max_size: u32,
+    pub connection_timeout: Duration,
+    pub idle_timeout: Option<Duration>,
+    pub startup_attempts: u32,
+    pub startup_backoff: Duration,
+    pub statement_timeout: Option<Duration>,
+}
+
+impl DbPoolConfig {
+    /// Settings of the primary's pool, which requires its URL.
+    pub fn from_settings(settings: &DatabaseSettings) -> Result<Self, SettingsError> {
+        let database_url = settings
+            .url
+            .clone()
+            .ok_or(SettingsError::Missing("database.url"))?;
+        if settings.pool_max_size == 0 {
+            return Err(SettingsError::Invalid("database.pool_max_size", "positive"));
+        }
+        if settings.pool_min_idle > Some(settings.pool_max_size) {
+            return Err(SettingsError::Invalid(
+                "database.pool_min_idle",
+                "at most database.pool_max_size",
+            ));
+        }
+        if settings.startup_attempts == 0 {
+            return Err(SettingsError::Invalid(
+                "database.startup_attempts",
+                "positive",
+            ));
+        }
+        Ok(Self {
+            database_url,
+            min_idle: settings.pool_min_idle,
+            max_size: settings.pool_max_size,
+            connection_timeout: Duration::from_secs(settings.pool_connection_timeout),
+            idle_timeout: match settings.pool_idle_timeout {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            startup_attempts: settings.startup_attempts,
+            startup_backoff: Duration::from_millis(settings.startup_backoff_ms),
+            statement_timeout: match settings.statement_timeout_ms {
+                0 => None,
+                millis => Some(Duration::from_millis(millis)),
+            },
+        })
+    }
+
+    /// Settings of the optional read replica pool, enabled by setting its URL. It shares
+    /// the primary's settings, except for its connection timeout, kept short so that
+    /// reads quickly fall back to the primary when the replica is unreachable.
+    pub fn replica_from_settings(
+        settings: &DatabaseSettings,
+    ) -> Result<Option<Self>, SettingsError> {
+        let Some(database_url) = settings.read_url.clone() else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            database_url,
+            connection_timeout: Duration::from_secs(settings.read_connection_timeout),
+            ..Self::from_settings(settings)?
+        }))
+    }
+
+    /// Settings of the primary's pool from the environment alone, see
+    /// [`Settings::from_env`]. Panics when they are invalid.
+    pub fn from_env() -> Self {
+        Settings::from_env()
+            .and_then(|settings| Self::from_settings(&settings.database))
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+/// Sets `statement_timeout` on every new connection, so that no query holds a connection
+/// or its locks for longer.
+#[derive(Debug)]
+struct StatementTimeout(Duration);
+
+impl CustomizeConnection<PgConnection, diesel::r2d2::Error> for StatementTimeout {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), diesel::r2d2::Error> {
+        diesel::sql_query(format!("SET statement_timeout = {}", self.0.as_millis()))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+fn manager(config: &DbPoolConfig) -> ConnectionManager<PgConnection> {
+    ConnectionManager::new(config.database_url.to_owned())
+}
+
+fn pool_builder(config: &DbPoolConfig) -> Builder<ConnectionManager<PgConnection>> {
+    let builder = Pool::builder()
+        .min_idle(config.min_idle)
+        .max_size(config.max_size)
+        .connection_timeout(config.connection_timeout)
+        .idle_timeout(config.idle_timeout);
+    match config.statement_timeout {
+        Some(statement_timeout) => {
+            builder.connection_customizer(Box::new(StatementTimeout(statement_timeout)))
+        }
+        None => builder,
+    }
+}
+
 pub trait DbManage {
     fn new_pool(max_size: Option<u32>) -> Self;
+
+    /// Builds a pool from `config` without opening any connection yet. Use
+    /// [`DbManage::wait_until_ready`] to find out whether the database is reachable.
+    fn from_config(config: &DbPoolConfig) -> Self;
+
+    /// Same as [`DbManage::from_config`], reporting checkouts and timeouts to `events`.
+    fn from_config_with_events(config: &DbPoolConfig, events: Box<dyn HandleEvent>) -> Self;
+
+    /// Blocks until a connection can be checked out, retrying with exponential backoff
+    /// up to `config.startup_attempts` times.
+    fn wait_until_ready(&self, config: &DbPoolConfig) -> Result<(), ApiError>;
+
     fn connection(&self) -> Result<DbConnection, ApiError>;
+
+    /// Checks out a connection and runs `f` with it on the blocking thread pool, so that
+    /// slow queries never stall the actix worker that is serving the request.
+    fn run<F, T>(&self, f: F) -> impl Future<Output = Result<T, ApiError>>
+    where
+        F: FnOnce(&mut DbConnection) -> Result<T, ApiError> + Send + 'static,
+        T: Send + 'static;
 }
 
 impl DbManage for DbPool {
     fn new_pool(max_size: Option<u32>) -> Self {
-        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let manager = ConnectionManager::<PgConnection>::new(database_url);
-        Pool::builder()
-            .max_size(max_size.unwrap_or(10))
-            .build(manager)
-            .expect("Failed to create pool.")
+        let mut config = DbPoolConfig::from_env();
+        if let Some(max_size) = max_size {
+            config.max_size = max_size;
+            config.min_idle = config.min_idle.map(|min_idle| min_idle.min(max_size));
+        }
+        let pool = Self::from_config(&config);
+        pool.connection().expect("Failed to create pool.");
+        pool
+    }
+
+    fn from_config(config: &DbPoolConfig) -> Self {
+        pool_builder(config).build_unchecked(manager(config))
+    }
+
+    fn from_config_with_events(config: &DbPoolConfig, events: Box<dyn HandleEvent>) -> Self {
+        pool_builder(config)
+            .event_handler(events)
+            .build_unchecked(manager(config))
+    }
+
+    fn wait_until_ready(&self, config: &DbPoolConfig) -> Result<(), ApiError> {
+        let mut backoff = config.startup_backoff;
+        for attempt in 1..=config.startup_attempts {
+            match self.get() {
+                Ok(_) => {
+                    info!("database is ready after {} attempt(s)", attempt);
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!(
+                        "database is not ready (attempt {}/{}): {}",
+                        attempt, config.startup_attempts, e
+                    );
+                    if attempt < config.startup_attempts {
+                        thread::sleep(backoff);
+                        backoff = (backoff * 2).min(MAX_STARTUP_BACKOFF);
+                    }
+                }
+            }
+        }
+        Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError))
     }
 
     fn connection(&self) -> Result<DbConnection, ApiError> {
         self.get()
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::BackendDatastoreError))
+            .map_err(|e| ApiError::internal(ApiAvroErrorCode::BackendDatastoreError, e))
+    }
+
+    fn run<F, T>(&self, f: F) -> impl Future<Output = Result<T, ApiError>>
+    where
+        F: FnOnce(&mut DbConnection) -> Result<T, ApiError> + Send + 'static,
+        T: Send + 'static,
+    {
+        // The pool is reference counted, so cloning it only hands the blocking task its
+        // own handle.
+        let pool = self.clone();
+        run_blocking(move || f(&mut pool.connection()?))
+    }
+}
+
+/// Runs `f` on the blocking thread pool, where it checks connections out, see
+/// [`DbManage::run`].
+pub(super) async fn run_blocking<F, T>(f: F) -> Result<T, ApiError>
+where
+    F: FnOnce() -> Result<T, ApiError> + Send + 'static,
+    T: Send + 'static,
+{
+    actix_threadpool::run(in_caller_context(f))
+        .await
+        .map_err(ApiError::from)
+}
+
+/// Wraps `f` so that it runs in the span of the caller wherever it's called, for queries
+/// to be traced as part of whatever the caller is doing, and in its Sentry hub, for them
+/// to be breadcrumbs of the caller's errors.
+fn in_caller_context<T>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> impl FnOnce() -> T + Send + 'static {
+    let span = Span::current();
+    #[cfg(feature = "sentry")]
+    let hub = sentry::Hub::current();
+    move || {
+        let _entered = span.enter();
+        #[cfg(feature = "sentry")]
+        let f = move || sentry::Hub::run(hub, f);
+        f()
     }
 }
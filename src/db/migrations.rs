@@ -0,0 +1,71 @@
+use diesel::dsl::sql;
+use diesel::migration::MigrationSource;
+use diesel::pg::{Pg, PgConnection};
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Text};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+
+/// Migrations of `migrations/`, built into the binary so that it knows which ones the
+/// database is missing.
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// Migrations creating the tables of salsify's avro-schema-registry, which this one
+/// shares.
+const SALSIFY_MIGRATIONS: [&str; 4] = [
+    "2018-12-13-153527_create_subjects",
+    "2018-12-14-163507_create_schemas",
+    "2018-12-16-123727_create_schema_versions",
+    "2018-12-18-105635_create_configs",
+];
+
+/// Names of the migrations that haven't been run on the database yet, oldest first.
+pub fn pending_migrations(conn: &mut PgConnection) -> Result<Vec<String>, ApiError> {
+    conn.pending_migrations(MIGRATIONS)
+        .map(|migrations| {
+            migrations
+                .iter()
+                .map(|migration| migration.name().to_string())
+                .collect()
+        })
+        .map_err(|e| ApiError::internal(ApiAvroErrorCode::BackendDatastoreError, e))
+}
+
+/// Runs the migrations that haven't been run on the database yet, and returns their
+/// versions, oldest first.
+pub fn run_migrations(conn: &mut PgConnection) -> Result<Vec<String>, ApiError> {
+    conn.run_pending_migrations(MIGRATIONS)
+        .map(|versions| versions.iter().map(ToString::to_string).collect())
+        .map_err(|e| ApiError::internal(ApiAvroErrorCode::BackendDatastoreError, e))
+}
+
+/// Records the migrations creating the tables of salsify's registry as run, on a database
+/// of it that was never migrated by this registry, so that the following ones can be run
+/// on it. Returns their versions, none for any other database.
+pub fn baseline_salsify(conn: &mut PgConnection) -> Result<Vec<String>, ApiError> {
+    // Also creates the table recording the migrations that were run
+    let applied = conn
+        .applied_migrations()
+        .map_err(|e| ApiError::internal(ApiAvroErrorCode::BackendDatastoreError, e))?;
+    let tables_exist = diesel::select(sql::<Bool>("to_regclass('subjects') IS NOT NULL"))
+        .get_result::<bool>(conn)?;
+    if !applied.is_empty() || !tables_exist {
+        return Ok(vec![]);
+    }
+
+    let migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS)
+        .map_err(|e| ApiError::internal(ApiAvroErrorCode::BackendDatastoreError, e))?;
+    let mut versions = vec![];
+    for migration in migrations {
+        if !SALSIFY_MIGRATIONS.contains(&migration.name().to_string().as_str()) {
+            continue;
+        }
+        let version = migration.name().version().to_string();
+        diesel::sql_query("INSERT INTO __diesel_schema_migrations (version) VALUES ($1)")
+            .bind::<Text, _>(&version)
+            .execute(conn)?;
+        versions.push(version);
+    }
+    Ok(versions)
+}
@@ -1,4 +1,8 @@
-pub use self::connection::{DbConnection, DbManage, DbPool};
+pub use self::connection::{DbConnection, DbManage, DbPool, DbPoolConfig};
+pub use self::migrations::{baseline_salsify, pending_migrations, run_migrations, MIGRATIONS};
+pub use self::read_pool::DbReadPool;
 
 mod connection;
+mod migrations;
 pub mod models;
+mod read_pool;
@@ -0,0 +1,98 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::schema::*;
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::auth::{PatternType, Role};
+
+/// Grants `principal` the `role` on the subjects matching `pattern`, see
+/// [`crate::auth::Acl`] for how rules are evaluated.
+#[derive(Debug, Clone, Identifiable, Queryable, Serialize)]
+#[diesel(table_name = acl_rules)]
+pub struct AclRule {
+    pub id: i64,
+    pub principal: String,
+    pub pattern: String,
+    pub pattern_type: String,
+    pub role: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = acl_rules)]
+pub struct NewAclRule {
+    pub principal: String,
+    pub pattern: String,
+    pub pattern_type: String,
+    pub role: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AclRuleBody {
+    pub principal: String,
+    pub pattern: String,
+    pub pattern_type: PatternType,
+    pub role: Role,
+}
+
+impl AclRule {
+    pub fn matches(&self, subject: &str) -> bool {
+        self.pattern_type
+            .parse::<PatternType>()
+            .is_ok_and(|pattern_type| pattern_type.matches(&self.pattern, subject))
+    }
+
+    pub fn role(&self) -> Option<Role> {
+        self.role.parse().ok()
+    }
+
+    pub fn all(conn: &mut PgConnection) -> Result<Vec<Self>, ApiError> {
+        use super::schema::acl_rules::dsl::*;
+
+        acl_rules
+            .order(id.asc())
+            .load::<Self>(conn)
+            .map_err(ApiError::from)
+    }
+
+    pub fn for_principal(conn: &mut PgConnection, name: &str) -> Result<Vec<Self>, ApiError> {
+        use super::schema::acl_rules::dsl::*;
+
+        acl_rules
+            .filter(principal.eq(name))
+            .load::<Self>(conn)
+            .map_err(ApiError::from)
+    }
+
+    pub fn insert(conn: &mut PgConnection, rule: AclRuleBody) -> Result<Self, ApiError> {
+        use super::schema::acl_rules::dsl::acl_rules;
+
+        let now = Utc::now().naive_utc();
+        diesel::insert_into(acl_rules)
+            .values(&NewAclRule {
+                principal: rule.principal,
+                pattern: rule.pattern,
+                pattern_type: rule.pattern_type.to_string(),
+                role: rule.role.to_string(),
+                created_at: now,
+                updated_at: now,
+            })
+            .get_result::<Self>(conn)
+            .map_err(ApiError::from)
+    }
+
+    pub fn delete(conn: &mut PgConnection, rule_id: i64) -> Result<Self, ApiError> {
+        use super::schema::acl_rules::dsl::*;
+
+        diesel::delete(acl_rules.find(rule_id))
+            .get_result::<Self>(conn)
+            .optional()
+            .map_err(ApiError::from)?
+            .ok_or_else(|| ApiError::new(ApiAvroErrorCode::AclRuleNotFound))
+    }
+}
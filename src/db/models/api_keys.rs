@@ -0,0 +1,198 @@
+use std::sync::OnceLock;
+
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::schema::*;
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::auth::{
+    generate_api_key_id, generate_api_key_secret, hash_password, verify_no_password, Principal,
+    Role, VerifiedSecrets,
+};
+
+/// How stale `last_used_at` may get, so that busy keys aren't written to on every request.
+const LAST_USED_RESOLUTION: TimeDelta = TimeDelta::minutes(1);
+
+/// Secrets verified by [`ApiKey::authenticate`], shared by every worker.
+fn verified_secrets() -> &'static VerifiedSecrets {
+    static VERIFIED_SECRETS: OnceLock<VerifiedSecrets> = OnceLock::new();
+    VERIFIED_SECRETS.get_or_init(VerifiedSecrets::default)
+}
+
+/// Credentials of a client, used in basic auth as `key_id:secret`. Only the hash of the
+/// secret is stored.
+#[derive(Debug, Clone, Identifiable, Queryable, Serialize)]
+#[diesel(table_name = api_keys)]
+pub struct ApiKey {
+    pub id: i64,
+    pub key_id: String,
+    pub owner: String,
+    pub role: String,
+    #[serde(skip)]
+    pub secret_hash: String,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = api_keys)]
+pub struct NewApiKey {
+    pub key_id: String,
+    pub owner: String,
+    pub role: String,
+    pub secret_hash: String,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyBody {
+    pub owner: String,
+    pub role: Role,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// A key along with its secret, which is only ever returned when it's generated.
+#[derive(Debug, Serialize)]
+pub struct ApiKeyWithSecret {
+    #[serde(flatten)]
+    pub key: ApiKey,
+    pub secret: String,
+}
+
+fn hash_secret(secret: &str) -> Result<String, ApiError> {
+    hash_password(secret)
+        .map_err(|e| ApiError::internal(ApiAvroErrorCode::BackendDatastoreError, e))
+}
+
+impl ApiKey {
+    pub fn is_active(&self, now: NaiveDateTime) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+
+    pub fn role(&self) -> Option<Role> {
+        self.role.parse().ok()
+    }
+
+    pub fn create(conn: &mut PgConnection, body: ApiKeyBody) -> Result<ApiKeyWithSecret, ApiError> {
+        use super::schema::api_keys::dsl::api_keys;
+
+        let secret = generate_api_key_secret();
+        let now = Utc::now().naive_utc();
+        diesel::insert_into(api_keys)
+            .values(&NewApiKey {
+                key_id: generate_api_key_id(),
+                owner: body.owner,
+                role: body.role.to_string(),
+                secret_hash: hash_secret(&secret)?,
+                expires_at: body.expires_at.map(|expires_at| expires_at.naive_utc()),
+                created_at: now,
+                updated_at: now,
+            })
+            .get_result::<Self>(conn)
+            .map(|key| ApiKeyWithSecret { key, secret })
+            .map_err(ApiError::from)
+    }
+
+    pub fn all(conn: &mut PgConnection) -> Result<Vec<Self>, ApiError> {
+        use super::schema::api_keys::dsl::*;
+
+        api_keys
+            .order(id.asc())
+            .load::<Self>(conn)
+            .map_err(ApiError::from)
+    }
+
+    /// Best role of the active keys of `name`, which is the principal they authenticate.
+    pub fn role_of_owner(conn: &mut PgConnection, name: &str) -> Result<Option<Role>, ApiError> {
+        use super::schema::api_keys::dsl::*;
+
+        let now = Utc::now().naive_utc();
+        Ok(api_keys
+            .filter(owner.eq(name))
+            .load::<Self>(conn)?
+            .iter()
+            .filter(|key| key.is_active(now))
+            .filter_map(Self::role)
+            .max())
+    }
+
+    /// Replaces the secret of a key that isn't revoked. The previous secret stops working
+    /// right away.
+    pub fn rotate(conn: &mut PgConnection, key: &str) -> Result<ApiKeyWithSecret, ApiError> {
+        use super::schema::api_keys::dsl::*;
+
+        let secret = generate_api_key_secret();
+        diesel::update(api_keys.filter(key_id.eq(key)).filter(revoked_at.is_null()))
+            .set((
+                secret_hash.eq(hash_secret(&secret)?),
+                updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result::<Self>(conn)
+            .optional()
+            .map_err(ApiError::from)?
+            .map(|key| ApiKeyWithSecret { key, secret })
+            .ok_or_else(|| ApiError::new(ApiAvroErrorCode::ApiKeyNotFound))
+    }
+
+    pub fn revoke(conn: &mut PgConnection, key: &str) -> Result<Self, ApiError> {
+        use super::schema::api_keys::dsl::*;
+
+        let now = Utc::now().naive_utc();
+        diesel::update(api_keys.filter(key_id.eq(key)).filter(revoked_at.is_null()))
+            .set((revoked_at.eq(now), updated_at.eq(now)))
+            .get_result::<Self>(conn)
+            .optional()
+            .map_err(ApiError::from)?
+            .ok_or_else(|| ApiError::new(ApiAvroErrorCode::ApiKeyNotFound))
+    }
+
+    /// Returns the principal of an active key if `secret` is its secret, and records
+    /// that the key was used, at most once per [`LAST_USED_RESOLUTION`].
+    ///
+    /// Unknown and inactive keys take as long to reject as wrong secrets, so that the
+    /// time it takes doesn't tell which keys exist.
+    pub fn authenticate(
+        conn: &mut PgConnection,
+        key: &str,
+        secret: &str,
+    ) -> Result<Option<Principal>, ApiError> {
+        use super::schema::api_keys::dsl::*;
+
+        let now = Utc::now().naive_utc();
+        let api_key = match api_keys
+            .filter(key_id.eq(key))
+            .first::<Self>(conn)
+            .optional()?
+        {
+            Some(api_key) if api_key.is_active(now) => api_key,
+            _ => {
+                verify_no_password(secret);
+                return Ok(None);
+            }
+        };
+        if !verified_secrets().verify(secret, &api_key.secret_hash) {
+            return Ok(None);
+        }
+
+        if api_key
+            .last_used_at
+            .is_none_or(|used_at| now - used_at >= LAST_USED_RESOLUTION)
+        {
+            diesel::update(api_keys.find(api_key.id))
+                .set(last_used_at.eq(now))
+                .execute(conn)?;
+        }
+
+        Ok(api_key.role().map(|key_role| Principal {
+            name: api_key.owner,
+            role: key_role,
+        }))
+    }
+}
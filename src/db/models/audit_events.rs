@@ -0,0 +1,164 @@
+use std::fmt;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::schema::*;
+
+use crate::api::errors::ApiError;
+
+#[derive(Debug, Identifiable, Queryable, Serialize)]
+#[diesel(table_name = audit_events)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub action: String,
+    pub subject: Option<String>,
+    pub actor: Option<String>,
+    pub request_id: Option<String>,
+    pub old_value: Option<serde_json::Value>,
+    pub new_value: Option<serde_json::Value>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = audit_events)]
+pub struct NewAuditEvent {
+    pub action: String,
+    pub subject: Option<String>,
+    pub actor: Option<String>,
+    pub request_id: Option<String>,
+    pub old_value: Option<serde_json::Value>,
+    pub new_value: Option<serde_json::Value>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Registry mutations that are recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    RegisterSchema,
+    DeleteSchemaVersion,
+    DeleteSchemaVersionPermanently,
+    DeleteSubject,
+    DeleteSubjectPermanently,
+    SetGlobalConfig,
+    SetSubjectConfig,
+    SetGlobalMode,
+    SetSubjectMode,
+    CreateAclRule,
+    DeleteAclRule,
+    CreateApiKey,
+    RotateApiKey,
+    RevokeApiKey,
+    DeleteOrphanSchemas,
+    ImportRegistry,
+    ImportConfluent,
+    AdoptSalsify,
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let screaming_snake_case = match self {
+            Self::RegisterSchema => "REGISTER_SCHEMA",
+            Self::DeleteSchemaVersion => "DELETE_SCHEMA_VERSION",
+            Self::DeleteSchemaVersionPermanently => "DELETE_SCHEMA_VERSION_PERMANENTLY",
+            Self::DeleteSubject => "DELETE_SUBJECT",
+            Self::DeleteSubjectPermanently => "DELETE_SUBJECT_PERMANENTLY",
+            Self::SetGlobalConfig => "SET_GLOBAL_CONFIG",
+            Self::SetSubjectConfig => "SET_SUBJECT_CONFIG",
+            Self::SetGlobalMode => "SET_GLOBAL_MODE",
+            Self::SetSubjectMode => "SET_SUBJECT_MODE",
+            Self::CreateAclRule => "CREATE_ACL_RULE",
+            Self::DeleteAclRule => "DELETE_ACL_RULE",
+            Self::CreateApiKey => "CREATE_API_KEY",
+            Self::RotateApiKey => "ROTATE_API_KEY",
+            Self::RevokeApiKey => "REVOKE_API_KEY",
+            Self::DeleteOrphanSchemas => "DELETE_ORPHAN_SCHEMAS",
+            Self::ImportRegistry => "IMPORT_REGISTRY",
+            Self::ImportConfluent => "IMPORT_CONFLUENT",
+            Self::AdoptSalsify => "ADOPT_SALSIFY",
+        };
+        write!(f, "{}", screaming_snake_case)
+    }
+}
+
+/// Who is behind the request that triggers a mutation.
+#[derive(Debug, Clone, Default)]
+pub struct AuditContext {
+    pub actor: Option<String>,
+    pub request_id: Option<String>,
+}
+
+/// Filters for [`AuditEvent::search`]. Events are returned newest first.
+#[derive(Debug, Deserialize)]
+pub struct AuditFilter {
+    pub subject: Option<String>,
+    pub actor: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub offset: i64,
+    #[serde(default = "AuditFilter::default_limit")]
+    pub limit: i64,
+}
+
+impl AuditFilter {
+    pub const MAX_LIMIT: i64 = 1000;
+
+    fn default_limit() -> i64 {
+        100
+    }
+}
+
+impl AuditEvent {
+    /// Records a mutation. Call it with the connection the mutation was made with, inside
+    /// the same transaction, so that the event is only kept if the mutation is.
+    pub fn record(
+        conn: &mut PgConnection,
+        context: &AuditContext,
+        action: AuditAction,
+        subject: Option<&str>,
+        old_value: Option<serde_json::Value>,
+        new_value: Option<serde_json::Value>,
+    ) -> Result<Self, ApiError> {
+        use super::schema::audit_events::dsl::audit_events;
+
+        diesel::insert_into(audit_events)
+            .values(&NewAuditEvent {
+                action: action.to_string(),
+                subject: subject.map(str::to_string),
+                actor: context.actor.to_owned(),
+                request_id: context.request_id.to_owned(),
+                old_value,
+                new_value,
+                created_at: Utc::now().naive_utc(),
+            })
+            .get_result::<Self>(conn)
+            .map_err(ApiError::from)
+    }
+
+    pub fn search(conn: &mut PgConnection, filter: &AuditFilter) -> Result<Vec<Self>, ApiError> {
+        use super::schema::audit_events::dsl::*;
+
+        let mut query = audit_events.into_boxed();
+        if let Some(s) = &filter.subject {
+            query = query.filter(subject.eq(s));
+        }
+        if let Some(a) = &filter.actor {
+            query = query.filter(actor.eq(a));
+        }
+        if let Some(from) = filter.from {
+            query = query.filter(created_at.ge(from.naive_utc()));
+        }
+        if let Some(to) = filter.to {
+            query = query.filter(created_at.lt(to.naive_utc()));
+        }
+
+        query
+            .order(id.desc())
+            .offset(filter.offset.max(0))
+            .limit(filter.limit.clamp(1, AuditFilter::MAX_LIMIT))
+            .load::<Self>(conn)
+            .map_err(ApiError::from)
+    }
+}
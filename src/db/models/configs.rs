@@ -4,11 +4,12 @@ use std::str;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
+use tracing::{field, info_span};
 
 use crate::api::errors::{ApiAvroErrorCode, ApiError};
 
 use super::schema::*;
-use super::Subject;
+use super::{Schema, Subject};
 
 #[derive(Debug, Identifiable, Queryable, Associations, Serialize)]
 #[diesel(table_name = configs)]
@@ -19,6 +20,7 @@ pub struct Config {
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub subject_id: Option<i64>,
+    pub mode: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -79,6 +81,31 @@ impl CompatibilityLevel {
     pub fn valid(self) -> Result<Self, ApiError> {
         ConfigCompatibility::new(self.to_string()).and(Ok(self))
     }
+
+    /// Whether `new` can be used along `old` at this level. Transitive levels compare
+    /// schemas the same way as the others, only with every earlier schema.
+    pub fn allows(self, old: &avro_rs::Schema, new: &avro_rs::Schema) -> Result<bool, ApiError> {
+        let span = info_span!("compatibility check", level = %self, compatible = field::Empty);
+        let _entered = span.enter();
+        let allowed = match self {
+            Self::CompatNone => Ok(true),
+            Self::Backward | Self::BackwardTransitive => Ok(Schema::is_compatible(new, old)),
+            Self::Forward | Self::ForwardTransitive => Ok(Schema::is_compatible(old, new)),
+            Self::Full | Self::FullTransitive => {
+                Ok(Schema::is_compatible(old, new) && Schema::is_compatible(new, old))
+            }
+            Self::Unknown => Err(ApiError::new(ApiAvroErrorCode::InvalidCompatibilityLevel)),
+        }?;
+        span.record("compatible", allowed);
+        Ok(allowed)
+    }
+
+    pub fn is_transitive(self) -> bool {
+        matches!(
+            self,
+            Self::BackwardTransitive | Self::ForwardTransitive | Self::FullTransitive
+        )
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -98,6 +125,81 @@ impl ConfigCompatibility {
 // Just to be clearer when we're implementing the Handler
 pub type SetConfig = ConfigCompatibility;
 
+/// What can be done with a subject, or with the whole registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mode {
+    #[serde(rename = "READWRITE")]
+    ReadWrite,
+    /// Schemas can be read, but nothing can be registered or deleted.
+    #[serde(rename = "READONLY")]
+    ReadOnly,
+    /// Same as `ReadWrite`, only meant for filling an empty registry, or subject, with
+    /// the schemas of another one.
+    #[serde(rename = "IMPORT")]
+    Import,
+    #[serde(other)]
+    Unknown,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = match self {
+            Self::ReadWrite => "READWRITE",
+            Self::ReadOnly => "READONLY",
+            Self::Import => "IMPORT",
+            // This won't ever be parsed, so we're fine by leaving this empty
+            Self::Unknown => "",
+        };
+        write!(f, "{}", mode)
+    }
+}
+
+impl str::FromStr for Mode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "READWRITE" => Ok(Self::ReadWrite),
+            "READONLY" => Ok(Self::ReadOnly),
+            "IMPORT" => Ok(Self::Import),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Mode {
+    /// Returns `self` if it's a known mode, otherwise the error `InvalidMode`.
+    pub fn valid(self) -> Result<Self, ApiError> {
+        match self {
+            Self::Unknown => Err(ApiError::new(ApiAvroErrorCode::InvalidMode)),
+            mode => Ok(mode),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigMode {
+    pub mode: Mode,
+}
+
+pub type SetMode = ConfigMode;
+
+/// Query of the mode endpoints: a subject, or the registry, can only be switched to
+/// `IMPORT` while it's empty, unless `force` is set.
+#[derive(Debug, Default, Deserialize)]
+pub struct SetModeOptions {
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Query of `GET /config/{subject}`: the global compatibility level is returned for
+/// subjects without their own when `defaultToGlobal` is set.
+#[derive(Debug, Default, Deserialize)]
+pub struct GetConfigOptions {
+    #[serde(default, rename = "defaultToGlobal")]
+    pub default_to_global: bool,
+}
+
 pub struct GetSubjectConfig {
     pub subject: String,
 }
@@ -109,6 +211,14 @@ pub struct SetSubjectConfig {
 
 impl Config {
     pub const DEFAULT_COMPATIBILITY: CompatibilityLevel = CompatibilityLevel::Backward;
+    pub const DEFAULT_MODE: Mode = Mode::ReadWrite;
+
+    fn of_subject(conn: &mut PgConnection, subject: &Subject) -> Result<Option<Self>, ApiError> {
+        Self::belonging_to(subject)
+            .first::<Self>(conn)
+            .optional()
+            .map_err(ApiError::from)
+    }
 
     /// Retrieves the global compatibility level
     ///
@@ -132,27 +242,185 @@ impl Config {
                 Self::insert(&Self::DEFAULT_COMPATIBILITY.to_string(), conn)?;
                 Ok(Self::DEFAULT_COMPATIBILITY.to_string())
             }
-            _ => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
+            Err(e) => Err(e.into()),
         }
     }
 
+    /// Retrieves the compatibility level of a subject, which fails with
+    /// `SubjectCompatibilityNotConfigured` if it doesn't have its own.
     pub fn get_with_subject_name(
         conn: &mut PgConnection,
         subject_name: String,
     ) -> Result<String, ApiError> {
         let subject = Subject::get_by_name(conn, subject_name)?;
-        match Self::belonging_to(&subject).get_result::<Self>(conn) {
-            // This should always return ok. If it doesn't, that means someone manually
-            // edited the configs entry with id 0. Not only that, but they set the column
-            // compatibility to NULL. Because of that, we don't try to fix it (although we
-            // probably could) and instead return an internal server error.
-            Ok(config) => config
-                .compatibility
-                .ok_or_else(|| ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
-            _ => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
+        // Subjects with only a mode have a config without a compatibility level
+        Self::of_subject(conn, &subject)?
+            .and_then(|config| config.compatibility)
+            .ok_or_else(|| ApiError::new(ApiAvroErrorCode::SubjectCompatibilityNotConfigured))
+    }
+
+    /// The compatibility level schemas registered under a subject must comply with: the
+    /// subject's own, or the global one.
+    pub fn get_compatibility_level(
+        conn: &mut PgConnection,
+        subject_name: &str,
+    ) -> Result<CompatibilityLevel, ApiError> {
+        let level = match Self::get_with_subject_name(conn, subject_name.to_owned()) {
+            Ok(level) => level,
+            Err(e)
+                if e.response.error_code == ApiAvroErrorCode::SubjectNotFound
+                    || e.response.error_code
+                        == ApiAvroErrorCode::SubjectCompatibilityNotConfigured =>
+            {
+                Self::get_global_compatibility(conn)?
+            }
+            Err(e) => return Err(e),
+        };
+        level.parse().map_err(|_| {
+            ApiError::internal(
+                ApiAvroErrorCode::BackendDatastoreError,
+                format!("invalid compatibility level {}", level),
+            )
+        })
+    }
+
+    /// Every config: the global one has the ID 0, and those of subjects their ID.
+    pub fn all(conn: &mut PgConnection) -> Result<Vec<Self>, ApiError> {
+        use super::schema::configs::dsl::{configs, id};
+
+        configs
+            .order(id.asc())
+            .load::<Self>(conn)
+            .map_err(ApiError::from)
+    }
+
+    /// Configs of the subjects with the IDs `subject_ids`.
+    pub fn of_subjects(
+        conn: &mut PgConnection,
+        subject_ids: &[i64],
+    ) -> Result<Vec<Self>, ApiError> {
+        use super::schema::configs::dsl::{configs, id, subject_id};
+
+        configs
+            .filter(subject_id.eq_any(subject_ids))
+            .order(id.asc())
+            .load::<Self>(conn)
+            .map_err(ApiError::from)
+    }
+
+    /// The global config, unset until first changed.
+    pub fn global(conn: &mut PgConnection) -> Result<Option<Self>, ApiError> {
+        use super::schema::configs::dsl::{configs, subject_id};
+
+        configs
+            .filter(subject_id.is_null())
+            .first::<Self>(conn)
+            .optional()
+            .map_err(ApiError::from)
+    }
+
+    fn parse_mode(mode: Option<String>) -> Result<Mode, ApiError> {
+        match mode {
+            Some(mode) => mode.parse().map_err(|_| {
+                ApiError::internal(
+                    ApiAvroErrorCode::BackendDatastoreError,
+                    format!("invalid mode {}", mode),
+                )
+            }),
+            None => Ok(Self::DEFAULT_MODE),
+        }
+    }
+
+    /// Retrieves the mode of the registry
+    pub fn get_global_mode(conn: &mut PgConnection) -> Result<Mode, ApiError> {
+        use super::schema::configs::dsl::*;
+
+        let global_mode = configs
+            .find(0)
+            .select(mode)
+            .first::<Option<String>>(conn)
+            .optional()?
+            .flatten();
+        Self::parse_mode(global_mode)
+    }
+
+    /// Retrieves the mode of a subject: its own, or the registry's.
+    pub fn get_mode(conn: &mut PgConnection, subject_name: &str) -> Result<Mode, ApiError> {
+        let subject_mode = match Subject::get_by_name_with_deleted(conn, subject_name.to_owned()) {
+            Ok(subject) => Self::of_subject(conn, &subject)?.and_then(|config| config.mode),
+            Err(e) if e.response.error_code == ApiAvroErrorCode::SubjectNotFound => None,
+            Err(e) => return Err(e),
+        };
+        match subject_mode {
+            Some(_) => Self::parse_mode(subject_mode),
+            None => Self::get_global_mode(conn),
         }
     }
 
+    /// Fails with `OperationNotPermitted` if schemas can't be registered or deleted
+    /// under the subject.
+    pub fn ensure_writable(conn: &mut PgConnection, subject_name: &str) -> Result<(), ApiError> {
+        match Self::get_mode(conn, subject_name)? {
+            Mode::ReadOnly => Err(ApiError::new(ApiAvroErrorCode::OperationNotPermitted)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Updates the mode of the registry, which can only be switched to `IMPORT` while
+    /// there are no subjects, unless `force` is set.
+    pub fn set_global_mode(
+        conn: &mut PgConnection,
+        new_mode: Mode,
+        force: bool,
+    ) -> Result<Mode, ApiError> {
+        use super::schema::configs::dsl::*;
+
+        if new_mode == Mode::Import && !force && !Subject::distinct_names(conn)?.is_empty() {
+            return Err(ApiError::new(ApiAvroErrorCode::OperationNotPermitted));
+        }
+        // Makes sure there is a global config to update
+        Self::get_global_compatibility(conn)?;
+        diesel::update(configs.find(0))
+            .set(mode.eq(new_mode.to_string()))
+            .execute(conn)?;
+        Ok(new_mode)
+    }
+
+    /// Updates the mode of a subject, which can only be switched to `IMPORT` while it
+    /// has no versions, unless `force` is set.
+    pub fn set_subject_mode(
+        conn: &mut PgConnection,
+        subject_name: String,
+        new_mode: Mode,
+        force: bool,
+    ) -> Result<Mode, ApiError> {
+        use super::schema::configs::dsl::*;
+        use super::SchemaVersion;
+
+        let subject = Subject::get_by_name(conn, subject_name)?;
+        if new_mode == Mode::Import
+            && !force
+            && SchemaVersion::latest_version_with_subject_name(conn, subject.name.to_owned())?
+                .is_some()
+        {
+            return Err(ApiError::new(ApiAvroErrorCode::OperationNotPermitted));
+        }
+        match Self::of_subject(conn, &subject)? {
+            Some(config) => diesel::update(&config)
+                .set(mode.eq(new_mode.to_string()))
+                .execute(conn)?,
+            None => diesel::insert_into(configs)
+                .values((
+                    mode.eq(new_mode.to_string()),
+                    created_at.eq(diesel::dsl::now),
+                    updated_at.eq(diesel::dsl::now),
+                    subject_id.eq(subject.id),
+                ))
+                .execute(conn)?,
+        };
+        Ok(new_mode)
+    }
+
     pub fn set_with_subject_name(
         conn: &mut PgConnection,
         subject_name: String,
@@ -170,7 +438,7 @@ impl Config {
                     Ok(conf) => conf
                         .compatibility
                         .ok_or_else(|| ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
-                    _ => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
+                    Err(e) => Err(e.into()),
                 }
             }
             Err(diesel::result::Error::NotFound) => {
@@ -182,13 +450,38 @@ impl Config {
                         subject_id.eq(subject.id),
                     ))
                     .execute(conn)
-                    .map_err(|_| ApiError::new(ApiAvroErrorCode::BackendDatastoreError))?;
+                    .map_err(ApiError::from)?;
                 Ok(compat)
             }
-            _ => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
+            Err(e) => Err(e.into()),
         }
     }
 
+    /// Removes the compatibility level of a subject, which then complies with the global
+    /// one. Subjects may be soft deleted.
+    pub fn unset_subject_compatibility(
+        conn: &mut PgConnection,
+        subject: &Subject,
+    ) -> Result<(), ApiError> {
+        use super::schema::configs::dsl::compatibility;
+
+        diesel::update(Self::belonging_to(subject))
+            .set(compatibility.eq(None::<String>))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Removes the mode of a subject, which then follows the registry's. Subjects may be
+    /// soft deleted.
+    pub fn unset_subject_mode(conn: &mut PgConnection, subject: &Subject) -> Result<(), ApiError> {
+        use super::schema::configs::dsl::mode;
+
+        diesel::update(Self::belonging_to(subject))
+            .set(mode.eq(None::<String>))
+            .execute(conn)?;
+        Ok(())
+    }
+
     /// Updates the global compatibility level
     ///
     /// *NOTE*: if there is no global compatibility level, it sets it to the level passed
@@ -213,7 +506,7 @@ impl Config {
                 Self::insert(compat, conn)?;
                 Ok(compat.to_string())
             }
-            _ => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
+            Err(e) => Err(e.into()),
         }
     }
 
@@ -228,6 +521,6 @@ impl Config {
                 updated_at.eq(diesel::dsl::now),
             ))
             .execute(conn)
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::BackendDatastoreError))
+            .map_err(ApiError::from)
     }
 }
@@ -1,11 +1,19 @@
+pub use self::acl_rules::*;
+pub use self::api_keys::*;
+pub use self::audit_events::*;
 pub use self::configs::*;
+pub use self::schema_references::*;
 pub use self::schema_versions::*;
 pub use self::schemas::*;
 pub use self::subjects::*;
 
 pub mod schema;
 
+mod acl_rules;
+mod api_keys;
+mod audit_events;
 mod configs;
+mod schema_references;
 mod schema_versions;
 mod schemas;
 mod subjects;
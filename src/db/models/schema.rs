@@ -1,5 +1,45 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    acl_rules (id) {
+        id -> Int8,
+        principal -> Text,
+        pattern -> Text,
+        pattern_type -> Varchar,
+        role -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    api_keys (id) {
+        id -> Int8,
+        key_id -> Varchar,
+        owner -> Text,
+        role -> Varchar,
+        secret_hash -> Text,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    audit_events (id) {
+        id -> Int8,
+        action -> Varchar,
+        subject -> Nullable<Text>,
+        actor -> Nullable<Text>,
+        request_id -> Nullable<Varchar>,
+        old_value -> Nullable<Jsonb>,
+        new_value -> Nullable<Jsonb>,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     configs (id) {
         id -> Int8,
@@ -7,6 +47,17 @@ diesel::table! {
         created_at -> Timestamp,
         updated_at -> Timestamp,
         subject_id -> Nullable<Int8>,
+        mode -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    schema_references (id) {
+        id -> Int8,
+        schema_id -> Int8,
+        name -> Text,
+        subject -> Text,
+        version -> Int4,
     }
 }
 
@@ -16,6 +67,7 @@ diesel::table! {
         version -> Nullable<Int4>,
         subject_id -> Int8,
         schema_id -> Int8,
+        deleted -> Bool,
     }
 }
 
@@ -36,11 +88,13 @@ diesel::table! {
         name -> Text,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        deleted -> Bool,
     }
 }
 
 diesel::allow_tables_to_appear_in_same_query!(
     configs,
+    schema_references,
     schema_versions,
     schemas,
     subjects,
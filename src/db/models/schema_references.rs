@@ -0,0 +1,163 @@
+use std::collections::HashSet;
+
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::schema::*;
+use super::schemas::Schema;
+use super::SchemaVersion;
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+
+#[derive(Debug, Identifiable, Queryable, Associations)]
+#[diesel(table_name = schema_references)]
+#[diesel(belongs_to(Schema))]
+pub struct SchemaReference {
+    pub id: i64,
+    pub schema_id: i64,
+    pub name: String,
+    pub subject: String,
+    pub version: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = schema_references)]
+pub struct NewSchemaReference {
+    pub schema_id: i64,
+    pub name: String,
+    pub subject: String,
+    pub version: i32,
+}
+
+/// A type a schema uses without defining it: `name` is defined by `version` of
+/// `subject`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reference {
+    pub name: String,
+    pub subject: String,
+    pub version: i32,
+}
+
+impl From<SchemaReference> for Reference {
+    fn from(reference: SchemaReference) -> Self {
+        Self {
+            name: reference.name,
+            subject: reference.subject,
+            version: reference.version,
+        }
+    }
+}
+
+impl SchemaReference {
+    pub fn insert(
+        conn: &mut PgConnection,
+        schema_id: i64,
+        references: &[Reference],
+    ) -> QueryResult<usize> {
+        use super::schema::schema_references::dsl::schema_references;
+
+        let references = references
+            .iter()
+            .map(|reference| NewSchemaReference {
+                schema_id,
+                name: reference.name.to_owned(),
+                subject: reference.subject.to_owned(),
+                version: reference.version,
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(schema_references)
+            .values(&references)
+            .execute(conn)
+    }
+
+    /// References of a schema, in the order they were registered with.
+    pub fn of_schema(conn: &mut PgConnection, schema_id: i64) -> Result<Vec<Reference>, ApiError> {
+        use super::schema::schema_references::dsl::{id, schema_id as reference_schema_id};
+
+        super::schema::schema_references::table
+            .filter(reference_schema_id.eq(schema_id))
+            .order(id.asc())
+            .load::<Self>(conn)
+            .map(|references| references.into_iter().map(Reference::from).collect())
+            .map_err(ApiError::from)
+    }
+
+    /// References of each of `schemas`, in the same order.
+    pub fn of_schemas(
+        conn: &mut PgConnection,
+        schemas: &[Schema],
+    ) -> Result<Vec<Vec<Reference>>, ApiError> {
+        use super::schema::schema_references::dsl::id;
+
+        Ok(Self::belonging_to(schemas)
+            .order(id.asc())
+            .load::<Self>(conn)?
+            .grouped_by(schemas)
+            .into_iter()
+            .map(|references| references.into_iter().map(Reference::from).collect())
+            .collect())
+    }
+
+    /// JSON of the schemas `references` point to, and of the ones those reference in
+    /// turn, each after its own references, which is the order they're parsed in.
+    pub fn resolve(
+        conn: &mut PgConnection,
+        references: &[Reference],
+    ) -> Result<Vec<String>, ApiError> {
+        let mut resolved = Vec::new();
+        Self::resolve_into(conn, references, &mut HashSet::new(), &mut resolved)?;
+        Ok(resolved)
+    }
+
+    fn resolve_into(
+        conn: &mut PgConnection,
+        references: &[Reference],
+        seen: &mut HashSet<i64>,
+        resolved: &mut Vec<String>,
+    ) -> Result<(), ApiError> {
+        for reference in references {
+            let version = u32::try_from(reference.version)
+                .map_err(|_| ApiError::new(ApiAvroErrorCode::InvalidVersion))?;
+            let (schema_id, _, json) =
+                SchemaVersion::get_schema_id(conn, reference.subject.to_owned(), version)?;
+            if !seen.insert(schema_id) {
+                continue;
+            }
+            let nested = Self::of_schema(conn, schema_id)?;
+            Self::resolve_into(conn, &nested, seen, resolved)?;
+            resolved.push(json);
+        }
+        Ok(())
+    }
+
+    /// Fails with `ReferenceExists` if an active version references `version` of
+    /// `subject`, or any of its versions when `version` is `None`.
+    pub fn ensure_unreferenced(
+        conn: &mut PgConnection,
+        subject: &str,
+        version: Option<i32>,
+    ) -> Result<(), ApiError> {
+        use super::schema::schema_references::dsl::{
+            id, schema_id, schema_references, subject as referenced_subject,
+            version as referenced_version,
+        };
+        use super::schema::schema_versions::dsl::{
+            deleted, schema_id as versions_schema_id, schema_versions,
+        };
+
+        let mut query = schema_references
+            .inner_join(schema_versions.on(versions_schema_id.eq(schema_id)))
+            .filter(referenced_subject.eq(subject))
+            .filter(deleted.eq(false))
+            .select(id)
+            .into_boxed();
+        if let Some(version) = version {
+            query = query.filter(referenced_version.eq(version));
+        }
+
+        match query.first::<i64>(conn).optional()? {
+            Some(_) => Err(ApiError::new(ApiAvroErrorCode::ReferenceExists)),
+            None => Ok(()),
+        }
+    }
+}
@@ -16,6 +16,7 @@ pub struct SchemaVersion {
     pub version: Option<i32>,
     pub subject_id: i64,
     pub schema_id: i64,
+    pub deleted: bool,
 }
 
 #[derive(Debug, Insertable)]
@@ -30,11 +31,27 @@ pub type SchemaVersionFields = NewSchemaVersion;
 
 impl SchemaVersion {
     pub fn insert(conn: &mut PgConnection, sv: NewSchemaVersion) -> Result<Self, ApiError> {
+        Self::try_insert(conn, sv).map_err(ApiError::from)
+    }
+
+    /// Same as [`SchemaVersion::insert`] but keeps the database error, so that callers
+    /// can tell a conflicting version apart from any other failure.
+    pub(crate) fn try_insert(conn: &mut PgConnection, sv: NewSchemaVersion) -> QueryResult<Self> {
         use super::schema::schema_versions::dsl::schema_versions;
         diesel::insert_into(schema_versions)
             .values(&sv)
             .get_result::<Self>(conn)
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::BackendDatastoreError))
+    }
+
+    /// Counts the versions of every subject, leaving out soft deleted ones.
+    pub fn count(conn: &mut PgConnection) -> Result<i64, ApiError> {
+        use super::schema::schema_versions::dsl::{deleted, schema_versions};
+
+        schema_versions
+            .filter(deleted.eq(false))
+            .count()
+            .get_result(conn)
+            .map_err(ApiError::from)
     }
 
     pub fn find(
@@ -42,49 +59,110 @@ impl SchemaVersion {
         find_subject_id: i64,
         find_schema_id: i64,
     ) -> Result<Self, ApiError> {
-        use super::schema::schema_versions::dsl::{schema_id, schema_versions, subject_id};
+        use super::schema::schema_versions::dsl::{
+            deleted, schema_id, schema_versions, subject_id,
+        };
 
         schema_versions
             .filter(subject_id.eq(find_subject_id))
             .filter(schema_id.eq(find_schema_id))
+            .filter(deleted.eq(false))
             .get_result::<Self>(conn)
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::VersionNotFound))
+            .optional()?
+            .ok_or_else(|| ApiError::new(ApiAvroErrorCode::VersionNotFound))
     }
 
+    /// The version of a schema under a subject, preferring an active one to one that was
+    /// soft deleted.
     pub fn with_schema_and_subject(
         conn: &mut PgConnection,
         search_subject_name: String,
         search_schema_id: i64,
-    ) -> Result<usize, ApiError> {
-        use super::schema::schema_versions::dsl::{id, schema_id, schema_versions, subject_id};
-        use super::schema::schemas::dsl::{id as schemas_id, schemas};
+    ) -> Result<Option<Self>, ApiError> {
+        use super::schema::schema_versions::dsl::{
+            deleted, id, schema_id, schema_versions, subject_id, version,
+        };
         use super::schema::subjects::dsl::{id as subjects_id, name as subject_name, subjects};
 
         schema_versions
             .inner_join(subjects.on(subject_id.eq(subjects_id)))
-            .inner_join(schemas.on(schema_id.eq(schemas_id)))
             .filter(subject_name.eq(search_subject_name))
             .filter(schema_id.eq(search_schema_id))
-            .select(id)
-            .execute(conn)
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::BackendDatastoreError))
+            .order((deleted.asc(), version.desc()))
+            .select((id, version, subject_id, schema_id, deleted))
+            .first::<Self>(conn)
+            .optional()
+            .map_err(ApiError::from)
+    }
+
+    /// Active versions of a subject along with their schema, the latest first.
+    pub fn schemas_with_subject_name(
+        conn: &mut PgConnection,
+        subject_name: String,
+    ) -> Result<Vec<(Option<i32>, Schema)>, ApiError> {
+        use super::schema::schema_versions::dsl::{
+            deleted, schema_id, schema_versions, subject_id, version,
+        };
+        use super::schema::schemas::dsl::{id as schemas_id, schemas};
+        use super::schema::subjects::dsl::{id as subjects_id, name, subjects};
+
+        schema_versions
+            .inner_join(subjects.on(subject_id.eq(subjects_id)))
+            .inner_join(schemas.on(schema_id.eq(schemas_id)))
+            .filter(name.eq(&subject_name))
+            .filter(deleted.eq(false))
+            .order(version.desc())
+            .select((version, super::schema::schemas::all_columns))
+            .load::<(Option<i32>, Schema)>(conn)
+            .map_err(ApiError::from)
+    }
+
+    /// Every version, soft deleted or not, in the order they were registered.
+    pub fn all(conn: &mut PgConnection) -> Result<Vec<Self>, ApiError> {
+        use super::schema::schema_versions::dsl::{id, schema_versions};
+
+        schema_versions
+            .order(id.asc())
+            .load::<Self>(conn)
+            .map_err(ApiError::from)
+    }
+
+    /// Up to `limit` versions created after the one with the ID `after`, soft deleted or
+    /// not, in the order they were created, along with the name of their subject.
+    pub fn page_with_subject(
+        conn: &mut PgConnection,
+        after: i64,
+        limit: i64,
+    ) -> Result<Vec<(Self, Option<String>)>, ApiError> {
+        use super::schema::schema_versions::dsl::{id, schema_versions, subject_id};
+        use super::schema::subjects::dsl::{id as subjects_id, name, subjects};
+
+        schema_versions
+            .left_join(subjects.on(subjects_id.eq(subject_id)))
+            .filter(id.gt(after))
+            .order(id.asc())
+            .limit(limit)
+            .select((super::schema::schema_versions::all_columns, name.nullable()))
+            .load::<(Self, Option<String>)>(conn)
+            .map_err(ApiError::from)
     }
 
     pub fn versions_with_subject_name(
         conn: &mut PgConnection,
         subject_name: String,
     ) -> Result<Vec<Option<i32>>, ApiError> {
-        use super::schema::schema_versions::dsl::{schema_versions, subject_id, version};
+        use super::schema::schema_versions::dsl::{deleted, schema_versions, subject_id, version};
         use super::schema::subjects::dsl::{id as subjects_id, name, subjects};
 
         match schema_versions
             .inner_join(subjects.on(subject_id.eq(subjects_id)))
             .filter(name.eq(&subject_name))
+            .filter(deleted.eq(false))
             .select(version)
             .order(version.asc())
             .load::<Option<i32>>(conn)
         {
-            Err(_) => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
+            Err(e) => Err(e.into()),
             Ok(versions) => {
                 if versions.is_empty() {
                     Err(ApiError::new(ApiAvroErrorCode::SubjectNotFound))
@@ -95,6 +173,8 @@ impl SchemaVersion {
         }
     }
 
+    /// The latest version ever given under a subject, soft deleted or not, so that
+    /// numbers aren't reused.
     pub fn latest_version_with_subject_name(
         conn: &mut PgConnection,
         subject_name: String,
@@ -112,7 +192,7 @@ impl SchemaVersion {
         match res {
             Ok(v) => Ok(v),
             Err(diesel::NotFound) => Ok(None),
-            _ => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
+            Err(e) => Err(e.into()),
         }
     }
 
@@ -121,7 +201,7 @@ impl SchemaVersion {
         subject_name: String,
     ) -> Result<(i64, i32, String), ApiError> {
         use super::schema::schema_versions::dsl::{
-            schema_id, schema_versions, subject_id, version,
+            deleted, schema_id, schema_versions, subject_id, version,
         };
         use super::schema::schemas::dsl::{json, schemas};
 
@@ -130,6 +210,7 @@ impl SchemaVersion {
 
             let (schema_version, schema_id_result): (Option<i32>, i64) = match schema_versions
                 .filter(subject_id.eq(subject.id))
+                .filter(deleted.eq(false))
                 .order(version.desc())
                 .select((version, schema_id))
                 .first(conn)
@@ -137,14 +218,16 @@ impl SchemaVersion {
                 Err(diesel::result::Error::NotFound) => {
                     Err(ApiError::new(ApiAvroErrorCode::VersionNotFound))
                 }
-                Err(_) => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
+                Err(e) => Err(e.into()),
                 Ok(o) => Ok(o),
             }?;
 
-            let schema_json = match schemas.find(schema_id_result).select(json).first(conn) {
-                Err(_) => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
-                Ok(o) => Ok(o),
-            }?;
+            let schema_json = schemas
+                .find(schema_id_result)
+                .select(json)
+                .first(conn)
+                .optional()?
+                .ok_or_else(|| ApiError::new(ApiAvroErrorCode::SchemaNotFound))?;
 
             Ok((
                 schema_id_result,
@@ -162,7 +245,7 @@ impl SchemaVersion {
         schema_version: u32,
     ) -> Result<(i64, i32, String), ApiError> {
         use super::schema::schema_versions::dsl::{
-            schema_id, schema_versions, subject_id, version,
+            deleted, schema_id, schema_versions, subject_id, version,
         };
         use super::schema::schemas::dsl::{json, schemas};
 
@@ -172,20 +255,23 @@ impl SchemaVersion {
             let schema_id_result = match schema_versions
                 .filter(subject_id.eq(subject.id))
                 .filter(version.eq(Some(schema_version as i32)))
+                .filter(deleted.eq(false))
                 .select(schema_id)
                 .first(conn)
             {
                 Err(diesel::result::Error::NotFound) => {
                     Err(ApiError::new(ApiAvroErrorCode::VersionNotFound))
                 }
-                Err(_) => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
+                Err(e) => Err(e.into()),
                 Ok(o) => Ok(o),
             }?;
 
-            let schema_json = match schemas.find(schema_id_result).select(json).first(conn) {
-                Err(_) => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
-                Ok(o) => Ok(o),
-            }?;
+            let schema_json = schemas
+                .find(schema_id_result)
+                .select(json)
+                .first(conn)
+                .optional()?
+                .ok_or_else(|| ApiError::new(ApiAvroErrorCode::SchemaNotFound))?;
 
             Ok((schema_id_result, schema_version as i32, schema_json))
         })
@@ -196,7 +282,7 @@ impl SchemaVersion {
         subject: String,
     ) -> Result<Vec<Option<i32>>, diesel::result::Error> {
         use super::schema::schema_versions::dsl::{
-            id, schema_id, schema_versions, subject_id, version,
+            deleted, id, schema_id, schema_versions, subject_id, version,
         };
         use super::schema::schemas::dsl::{id as schemas_id, schemas};
         use super::schema::subjects::dsl::{id as subjects_id, name, subjects};
@@ -206,7 +292,7 @@ impl SchemaVersion {
                 .inner_join(subjects.on(subject_id.eq(subjects_id)))
                 .inner_join(schemas.on(schema_id.eq(schemas_id)))
                 .filter(name.eq(&subject))
-                .select((id, version, subject_id, schema_id))
+                .select((id, version, subject_id, schema_id, deleted))
                 .load::<Self>(conn)?
                 .into_iter()
                 .map(|entry| {
@@ -240,24 +326,129 @@ impl SchemaVersion {
         })
     }
 
+    /// Soft deletes the version alone, without any of the checks of
+    /// [`SchemaVersion::delete_version_with_subject`].
+    pub fn mark_deleted(&self, conn: &mut PgConnection) -> Result<(), ApiError> {
+        use super::schema::schema_versions::dsl::deleted;
+
+        diesel::update(self).set(deleted.eq(true)).execute(conn)?;
+        Ok(())
+    }
+
+    /// A version of a subject by its number, soft deleted or not.
+    pub fn with_number(
+        conn: &mut PgConnection,
+        subject: &Subject,
+        number: i32,
+    ) -> Result<Option<Self>, ApiError> {
+        use super::schema::schema_versions::dsl::version;
+
+        Self::belonging_to(subject)
+            .filter(version.eq(number))
+            .first::<Self>(conn)
+            .optional()
+            .map_err(ApiError::from)
+    }
+
+    /// Whether a subject has a version that isn't soft deleted.
+    pub fn has_active(conn: &mut PgConnection, subject: &Subject) -> Result<bool, ApiError> {
+        use super::schema::schema_versions::dsl::deleted;
+
+        diesel::select(diesel::dsl::exists(
+            Self::belonging_to(subject).filter(deleted.eq(false)),
+        ))
+        .get_result(conn)
+        .map_err(ApiError::from)
+    }
+
+    /// Deletes the version alone for good, without any of the checks of
+    /// [`SchemaVersion::delete_version_with_subject`].
+    pub fn delete_permanently(&self, conn: &mut PgConnection) -> Result<(), ApiError> {
+        diesel::delete(self).execute(conn)?;
+        Ok(())
+    }
+
+    /// Restores a soft deleted version, returning its number.
+    pub(crate) fn restore(&self, conn: &mut PgConnection) -> Result<Option<i32>, ApiError> {
+        use super::schema::schema_versions::dsl::deleted;
+
+        diesel::update(self).set(deleted.eq(false)).execute(conn)?;
+        Ok(self.version)
+    }
+
+    /// Soft deletes the active versions of a subject, returning their numbers.
+    pub(crate) fn soft_delete_subject(
+        conn: &mut PgConnection,
+        subject: &Subject,
+    ) -> Result<Vec<Option<i32>>, ApiError> {
+        use super::schema::schema_versions::dsl::{deleted, version};
+
+        let mut versions = diesel::update(Self::belonging_to(subject).filter(deleted.eq(false)))
+            .set(deleted.eq(true))
+            .returning(version)
+            .get_results::<Option<i32>>(conn)?;
+        versions.sort_unstable();
+        Ok(versions)
+    }
+
+    /// Soft deletes the active versions of a subject up to `number`, returning how many
+    /// there were.
+    pub fn soft_delete_up_to(
+        conn: &mut PgConnection,
+        subject: &Subject,
+        number: i32,
+    ) -> Result<usize, ApiError> {
+        use super::schema::schema_versions::dsl::{deleted, version};
+
+        diesel::update(
+            Self::belonging_to(subject)
+                .filter(deleted.eq(false))
+                .filter(version.le(number)),
+        )
+        .set(deleted.eq(true))
+        .execute(conn)
+        .map_err(ApiError::from)
+    }
+
+    /// Soft deletes a version, or deletes a soft deleted one for good when the request
+    /// is permanent.
     pub fn delete_version_with_subject(
         conn: &mut PgConnection,
         request: DeleteSchemaVersion,
     ) -> Result<u32, ApiError> {
-        use super::schema::schema_versions::dsl::version;
+        use super::schema::schema_versions::dsl::{deleted, version};
+        use super::{Config, SchemaReference};
 
         let (subject, v) = (request.subject, request.version);
 
         conn.transaction::<_, ApiError, _>(|conn| {
-            Subject::get_by_name(conn, subject.to_owned()).and_then(|subject| {
-                diesel::delete(Self::belonging_to(&subject).filter(version.eq(v as i32)))
-                    .execute(conn)
-                    .map_err(|_| ApiError::new(ApiAvroErrorCode::BackendDatastoreError))
-                    .and_then(|o| match o {
-                        0 => Err(ApiError::new(ApiAvroErrorCode::VersionNotFound)),
-                        _ => Ok(v),
-                    })
-            })
+            // Versions of a soft deleted subject can only be deleted permanently
+            let subject = if request.permanent {
+                Subject::get_by_name_with_deleted(conn, subject)?
+            } else {
+                Subject::get_by_name(conn, subject)?
+            };
+            let schema_version = Self::belonging_to(&subject)
+                .filter(version.eq(v as i32))
+                .first::<Self>(conn)
+                .optional()?
+                .ok_or_else(|| ApiError::new(ApiAvroErrorCode::VersionNotFound))?;
+            match (request.permanent, schema_version.deleted) {
+                (false, true) => Err(ApiError::new(ApiAvroErrorCode::SchemaVersionSoftDeleted)),
+                (true, false) => Err(ApiError::new(ApiAvroErrorCode::SchemaVersionNotSoftDeleted)),
+                _ => Ok(()),
+            }?;
+            Config::ensure_writable(conn, &subject.name)?;
+            SchemaReference::ensure_unreferenced(conn, &subject.name, Some(v as i32))?;
+
+            if request.permanent {
+                diesel::delete(&schema_version).execute(conn)?;
+            } else {
+                diesel::update(&schema_version)
+                    .set(deleted.eq(true))
+                    .execute(conn)?;
+            }
+            Ok(v)
         })
     }
 }
@@ -265,4 +456,5 @@ impl SchemaVersion {
 pub struct DeleteSchemaVersion {
     pub subject: String,
     pub version: u32,
+    pub permanent: bool,
 }
@@ -1,12 +1,23 @@
 use avro_rs::schema_compatibility::SchemaCompatibility;
 use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
+use log::warn;
 use serde::Serialize;
 
 use crate::api::errors::{ApiAvroErrorCode, ApiError};
 
 use super::schema::*;
-use super::{GetSubjectVersionResponse, NewSchemaVersion, SchemaVersion, Subject};
+use super::{
+    CompatibilityLevel, Config, GetSubjectVersionResponse, NewSchemaVersion, Reference,
+    SchemaReference, SchemaVersion, Subject,
+};
+
+/// How many times a registration is attempted when it conflicts with a concurrent one.
+const REGISTRATION_ATTEMPTS: u32 = 3;
+
+/// First key of the advisory locks serializing registrations, one per kind of resource.
+const SUBJECT_LOCK_NAMESPACE: i32 = 1;
+const FINGERPRINT_LOCK_NAMESPACE: i32 = 2;
 
 #[derive(Debug, Identifiable, Queryable)]
 #[diesel(table_name = schemas)]
@@ -32,6 +43,8 @@ pub struct NewSchema {
 #[derive(Debug, Serialize)]
 pub struct SchemaResponse {
     pub schema: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub references: Vec<Reference>,
 }
 
 pub struct GetSchema {
@@ -39,97 +52,244 @@ pub struct GetSchema {
 }
 
 impl Schema {
-    fn parse(data: String) -> Result<avro_rs::Schema, ApiError> {
-        avro_rs::Schema::parse_str(&data)
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::InvalidAvroSchema))
+    /// Parses a schema along with the JSON of the schemas it references, as given by
+    /// [`SchemaReference::resolve`].
+    pub fn parse(data: &str, references: &[String]) -> Result<avro_rs::Schema, ApiError> {
+        let invalid = |e| ApiError::new(ApiAvroErrorCode::InvalidAvroSchema).with_detail(e);
+        if references.is_empty() {
+            return avro_rs::Schema::parse_str(data).map_err(invalid);
+        }
+
+        let mut schemata = references.iter().map(String::as_str).collect::<Vec<_>>();
+        schemata.push(data);
+        avro_rs::Schema::parse_list(&schemata)
+            .map_err(invalid)?
+            .pop()
+            .ok_or_else(|| ApiError::new(ApiAvroErrorCode::InvalidAvroSchema))
     }
 
-    fn generate_fingerprint(data: String) -> Result<String, ApiError> {
-        Ok(format!(
-            "{}",
-            Self::parse(data)?.fingerprint::<sha2::Sha256>()
-        ))
+    /// Parses a registered schema, resolving its references.
+    pub fn parse_registered(&self, conn: &mut PgConnection) -> Result<avro_rs::Schema, ApiError> {
+        let references = SchemaReference::of_schema(conn, self.id)?;
+        Self::parse(&self.json, &SchemaReference::resolve(conn, &references)?)
+    }
+
+    pub(crate) fn generate_fingerprint(schema: &avro_rs::Schema) -> String {
+        format!("{}", schema.fingerprint::<sha2::Sha256>())
+    }
+
+    /// The fingerprint schemas are stored, and so deduplicated, with. It is the one of
+    /// [`Schema::generate_fingerprint`] for schemas without references, like salsify's and
+    /// Confluent's. Otherwise the references are hashed along with it: the same schema
+    /// referencing other versions of its types is another schema, even when they look
+    /// the same.
+    pub(crate) fn fingerprint_with_references(
+        schema: &avro_rs::Schema,
+        references: &[Reference],
+    ) -> String {
+        use sha2::{Digest, Sha256};
+
+        let fingerprint = Self::generate_fingerprint(schema);
+        if references.is_empty() {
+            return fingerprint;
+        }
+        let references = serde_json::json!(references).to_string();
+        format!(
+            "{:x}",
+            Sha256::digest(format!("{}{}", fingerprint, references))
+        )
+    }
+
+    /// The fingerprint salsify's registry stores in `fingerprint`, from the one given by
+    /// [`Schema::generate_fingerprint`]: both are the SHA-256 of the parsing canonical
+    /// form, but salsify's is printed as a number, without leading zeros.
+    pub fn salsify_fingerprint(fingerprint: &str) -> String {
+        match fingerprint.trim_start_matches('0') {
+            "" => "0".to_string(),
+            trimmed => trimmed.to_string(),
+        }
+    }
+
+    /// Whether `fingerprint` holds `computed`, in salsify's format or in full, as this
+    /// registry stored it before using salsify's.
+    pub fn has_fingerprint(&self, computed: &str) -> bool {
+        self.fingerprint == Self::salsify_fingerprint(computed) || self.fingerprint == computed
     }
 
     pub(crate) fn is_compatible(
-        readers_schema: &str,
-        writers_schema: &str,
-    ) -> Result<bool, ApiError> {
-        let writers_schema = avro_rs::Schema::parse_str(writers_schema)
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::InvalidAvroSchema))?;
-        let readers_schema = avro_rs::Schema::parse_str(readers_schema)
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::InvalidAvroSchema))?;
-        Ok(SchemaCompatibility::can_read(&writers_schema, &readers_schema).is_ok())
+        readers_schema: &avro_rs::Schema,
+        writers_schema: &avro_rs::Schema,
+    ) -> bool {
+        SchemaCompatibility::can_read(writers_schema, readers_schema).is_ok()
+    }
+
+    /// Fails with `IncompatibleSchema` unless `schema` complies with the compatibility
+    /// level of the subject, compared to its latest version or, for transitive levels,
+    /// to all of them.
+    pub(crate) fn ensure_compatible(
+        conn: &mut PgConnection,
+        subject: &str,
+        schema: &avro_rs::Schema,
+    ) -> Result<(), ApiError> {
+        let level = Config::get_compatibility_level(conn, subject)?;
+        if let CompatibilityLevel::CompatNone = level {
+            return Ok(());
+        }
+
+        let mut previous = SchemaVersion::schemas_with_subject_name(conn, subject.to_owned())?;
+        if !level.is_transitive() {
+            previous.truncate(1);
+        }
+        for (_, old) in previous {
+            if !level.allows(&old.parse_registered(conn)?, schema)? {
+                return Err(ApiError::new(ApiAvroErrorCode::IncompatibleSchema));
+            }
+        }
+        Ok(())
     }
 
+    /// Finds a schema by the fingerprint [`Schema::fingerprint_with_references`] gives,
+    /// also matching schemas salsify's registry registered without it.
     pub fn find_by_fingerprint(
         conn: &mut PgConnection,
         fingerprint: String,
     ) -> Result<Option<Self>, ApiError> {
-        use super::schema::schemas::dsl::{fingerprint2, schemas};
+        use super::schema::schemas::dsl::{
+            fingerprint as salsify_fingerprint, fingerprint2, id, schemas,
+        };
         Ok(schemas
-            .filter(fingerprint2.eq(fingerprint))
-            .load::<Self>(conn)
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::BackendDatastoreError))?
-            .pop())
+            .filter(
+                salsify_fingerprint
+                    .eq(Self::salsify_fingerprint(&fingerprint))
+                    .or(fingerprint2.eq(fingerprint)),
+            )
+            .order(id.asc())
+            .first::<Self>(conn)
+            .optional()?)
     }
 
     pub fn register_new_version(
         conn: &mut PgConnection,
         registration: RegisterSchema,
     ) -> Result<Self, ApiError> {
-        let (subject, json) = (registration.subject, registration.schema);
-        let fingerprint = Self::generate_fingerprint(json.to_owned())?;
+        Self::register(conn, registration).map(|registered| registered.schema)
+    }
 
-        conn.transaction::<_, ApiError, _>(|conn| {
-            let db_schema = Self::find_by_fingerprint(conn, fingerprint.to_owned())?;
-            match db_schema {
-                Some(s) => {
-                    match SchemaVersion::with_schema_and_subject(conn, subject.to_owned(), s.id)? {
-                        1 => Ok(s),
-                        _ => Self::create_new_version(conn, None, fingerprint, subject, Some(s)),
-                    }
+    /// Same as [`Schema::register_new_version`], but also tells whether a new version was
+    /// created or the schema was already registered under the subject.
+    pub fn register(
+        conn: &mut PgConnection,
+        registration: RegisterSchema,
+    ) -> Result<RegisteredSchema, ApiError> {
+        let RegisterSchema {
+            subject,
+            schema: json,
+            references,
+        } = registration;
+        let resolved = SchemaReference::resolve(conn, &references)?;
+        let schema = Self::parse(&json, &resolved)?;
+        let new_schema = NewRegistration {
+            json: &json,
+            fingerprint: &Self::fingerprint_with_references(&schema, &references),
+            parsed: &schema,
+            references: &references,
+        };
+
+        let mut attempt = 1;
+        loop {
+            match Self::try_register_new_version(conn, &subject, &new_schema) {
+                Ok(registered) => return Ok(registered),
+                Err(RegistrationError::Conflict(e)) if attempt < REGISTRATION_ATTEMPTS => {
+                    warn!(
+                        "retrying registration under subject {} (attempt {}): {}",
+                        subject, attempt, e
+                    );
+                    attempt += 1;
                 }
-                None => Self::create_new_version(conn, Some(json), fingerprint, subject, None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn try_register_new_version(
+        conn: &mut PgConnection,
+        subject: &str,
+        new_schema: &NewRegistration,
+    ) -> Result<RegisteredSchema, RegistrationError> {
+        conn.transaction::<_, RegistrationError, _>(|conn| {
+            // Registrations under the same subject, or of the same schema, wait for each
+            // other so that versions are numbered without duplicates and each schema is
+            // only inserted once. Locks are always taken in this order, so two
+            // registrations can never deadlock.
+            Self::advisory_lock(conn, SUBJECT_LOCK_NAMESPACE, subject)?;
+            Self::advisory_lock(conn, FINGERPRINT_LOCK_NAMESPACE, new_schema.fingerprint)?;
+
+            let db_schema = Self::find_by_fingerprint(conn, new_schema.fingerprint.to_owned())?;
+            let registered = match &db_schema {
+                Some(s) => SchemaVersion::with_schema_and_subject(conn, subject.to_owned(), s.id)?,
+                None => None,
+            };
+            if let Some(SchemaVersion { deleted: false, .. }) = registered {
+                return Ok(RegisteredSchema {
+                    schema: db_schema
+                        .ok_or_else(|| ApiError::new(ApiAvroErrorCode::BackendDatastoreError))?,
+                    created_version: None,
+                });
+            }
+
+            Config::ensure_writable(conn, subject)?;
+            Self::ensure_compatible(conn, subject, new_schema.parsed)?;
+            let schema = match db_schema {
+                Some(s) => s,
+                None => {
+                    let s = Self::try_new(
+                        conn,
+                        new_schema.json.to_owned(),
+                        new_schema.fingerprint.to_owned(),
+                    )?;
+                    SchemaReference::insert(conn, s.id, new_schema.references)?;
+                    s
+                }
+            };
+            match registered {
+                // Registering a soft deleted version again restores it
+                Some(schema_version) => {
+                    Subject::insert(conn, subject.to_owned())?;
+                    let version = schema_version.restore(conn)?;
+                    Ok(RegisteredSchema {
+                        schema,
+                        created_version: version,
+                    })
+                }
+                None => Self::create_new_version(conn, subject.to_owned(), schema),
             }
         })
     }
 
+    /// Takes a transaction level advisory lock on `key`, released on commit or rollback.
+    fn advisory_lock(conn: &mut PgConnection, namespace: i32, key: &str) -> QueryResult<()> {
+        use diesel::sql_types::{Integer, Text};
+
+        diesel::sql_query("SELECT pg_advisory_xact_lock($1, hashtext($2))")
+            .bind::<Integer, _>(namespace)
+            .bind::<Text, _>(key)
+            .execute(conn)
+            .map(|_| ())
+    }
+
     fn create_new_version(
         conn: &mut PgConnection,
-        json: Option<String>,
-        fingerprint: String,
         subject_name: String,
-        db_schema: Option<Self>,
-    ) -> Result<Self, ApiError> {
+        schema: Self,
+    ) -> Result<RegisteredSchema, RegistrationError> {
         let latest =
             SchemaVersion::latest_version_with_subject_name(conn, subject_name.to_owned())?;
 
         // If it already exists, we don't care, we just update and get the subject.
         let subject = Subject::insert(conn, subject_name)?;
-        let (schema, new_version) = match latest {
-            Some(latest_version) => {
-                // TODO: Check compatibility first - implementation should be mostly in
-                // https://github.com/flavray/avro-rs
-                let sch = match json {
-                    Some(j) => Self::new(conn, j, fingerprint)?,
-                    None => db_schema
-                        .ok_or_else(|| ApiError::new(ApiAvroErrorCode::BackendDatastoreError))?,
-                };
-                (sch, latest_version + 1)
-            }
-            None => {
-                // Create schema version for subject
-                let sch = match json {
-                    Some(j) => Self::new(conn, j, fingerprint)?,
-                    None => db_schema
-                        .ok_or_else(|| ApiError::new(ApiAvroErrorCode::BackendDatastoreError))?,
-                };
-                (sch, 1)
-            }
-        };
+        let new_version = latest.map_or(1, |latest_version| latest_version + 1);
 
-        SchemaVersion::insert(
+        SchemaVersion::try_insert(
             conn,
             NewSchemaVersion {
                 version: Some(new_version),
@@ -138,7 +298,10 @@ impl Schema {
             },
         )?;
         // TODO: set compatibility
-        Ok(schema)
+        Ok(RegisteredSchema {
+            schema,
+            created_version: Some(new_version),
+        })
     }
 
     pub fn new(
@@ -146,25 +309,136 @@ impl Schema {
         json: String,
         fingerprint: String,
     ) -> Result<Self, ApiError> {
-        // TODO: we use the same in both fields. This means we don't do the same as
-        // salsify
+        Self::try_new(conn, json, fingerprint).map_err(ApiError::from)
+    }
+
+    fn try_new(conn: &mut PgConnection, json: String, fingerprint: String) -> QueryResult<Self> {
+        // Salsify's registry looks schemas up by `fingerprint`, which is given its format
+        // so that both registries can share the same tables
         let new_schema = NewSchema {
             json,
-            fingerprint: fingerprint.to_owned(),
+            fingerprint: Self::salsify_fingerprint(&fingerprint),
             fingerprint2: Some(fingerprint),
             created_at: Utc::now().naive_utc(),
             updated_at: Utc::now().naive_utc(),
         };
 
-        Self::insert(conn, new_schema)
+        Self::try_insert(conn, new_schema)
     }
 
     pub fn insert(conn: &mut PgConnection, schema: NewSchema) -> Result<Self, ApiError> {
+        Self::try_insert(conn, schema).map_err(ApiError::from)
+    }
+
+    /// Same as [`Schema::insert`], keeping the ID the schema has elsewhere. Call
+    /// [`Schema::reset_id_sequence`] once done, so that registrations don't reuse it.
+    pub fn insert_with_id(
+        conn: &mut PgConnection,
+        schema_id: i64,
+        schema: NewSchema,
+    ) -> Result<Self, ApiError> {
+        use super::schema::schemas::dsl::*;
+        diesel::insert_into(schemas)
+            .values((id.eq(schema_id), &schema))
+            .get_result::<Self>(conn)
+            .map_err(ApiError::from)
+    }
+
+    /// Stores both formats of the fingerprint [`Schema::generate_fingerprint`] gives, as
+    /// [`Schema::new`] does.
+    pub fn update_fingerprints(
+        &self,
+        conn: &mut PgConnection,
+        computed: &str,
+    ) -> Result<(), ApiError> {
+        use super::schema::schemas::dsl::{fingerprint, fingerprint2};
+
+        diesel::update(self)
+            .set((
+                fingerprint.eq(Self::salsify_fingerprint(computed)),
+                fingerprint2.eq(computed),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Makes the next registered schema get an ID greater than any schema has.
+    pub fn reset_id_sequence(conn: &mut PgConnection) -> Result<(), ApiError> {
+        diesel::sql_query(
+            "SELECT setval('schemas_id_seq', COALESCE((SELECT MAX(id) FROM schemas), 0) + 1, false)",
+        )
+        .execute(conn)?;
+        Ok(())
+    }
+
+    fn try_insert(conn: &mut PgConnection, schema: NewSchema) -> QueryResult<Self> {
         use super::schema::schemas::dsl::*;
         diesel::insert_into(schemas)
             .values(&schema)
             .get_result::<Self>(conn)
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::BackendDatastoreError))
+    }
+
+    pub fn count(conn: &mut PgConnection) -> Result<i64, ApiError> {
+        use super::schema::schemas::dsl::schemas;
+        schemas.count().get_result(conn).map_err(ApiError::from)
+    }
+
+    /// Up to `limit` schemas with an ID greater than `after`, by ID, to go through every
+    /// schema without loading them all at once.
+    pub fn page(conn: &mut PgConnection, after: i64, limit: i64) -> Result<Vec<Self>, ApiError> {
+        use super::schema::schemas::dsl::*;
+        schemas
+            .filter(id.gt(after))
+            .order(id.asc())
+            .limit(limit)
+            .load::<Self>(conn)
+            .map_err(ApiError::from)
+    }
+
+    /// Schemas that no version refers to, not even a soft deleted one, such as those left
+    /// behind by permanent deletions.
+    pub fn orphans(conn: &mut PgConnection) -> Result<Vec<Self>, ApiError> {
+        use super::schema::schema_versions::dsl::{schema_id, schema_versions};
+        use super::schema::schemas::dsl::*;
+        use diesel::dsl::{exists, not};
+
+        schemas
+            .filter(not(exists(schema_versions.filter(schema_id.eq(id)))))
+            .order(id.asc())
+            .load::<Self>(conn)
+            .map_err(ApiError::from)
+    }
+
+    /// Deletes the orphan schemas, along with their references, and returns their IDs.
+    /// Each one is locked the way registrations lock the schema they register, so that a
+    /// schema being registered again is never deleted.
+    pub fn delete_orphans(conn: &mut PgConnection) -> Result<Vec<i64>, ApiError> {
+        use super::schema::schema_references::dsl::{
+            schema_id as references_schema_id, schema_references,
+        };
+        use super::schema::schema_versions::dsl::{schema_id, schema_versions};
+        use super::schema::schemas::dsl::schemas;
+
+        conn.transaction::<_, ApiError, _>(|conn| {
+            let mut deleted = vec![];
+            for schema in Self::orphans(conn)? {
+                let fingerprint = schema.fingerprint2.as_ref().unwrap_or(&schema.fingerprint);
+                Self::advisory_lock(conn, FINGERPRINT_LOCK_NAMESPACE, fingerprint)?;
+                // A registration may have used it again before it was locked
+                let used = diesel::select(diesel::dsl::exists(
+                    schema_versions.filter(schema_id.eq(schema.id)),
+                ))
+                .get_result::<bool>(conn)?;
+                if used {
+                    continue;
+                }
+                diesel::delete(schema_references.filter(references_schema_id.eq(schema.id)))
+                    .execute(conn)?;
+                diesel::delete(schemas.find(schema.id)).execute(conn)?;
+                deleted.push(schema.id);
+            }
+            Ok(deleted)
+        })
     }
 
     pub fn get_by_json(conn: &mut PgConnection, data: String) -> Result<Self, ApiError> {
@@ -172,7 +446,8 @@ impl Schema {
         schemas
             .filter(json.eq(data))
             .get_result::<Self>(conn)
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::SchemaNotFound))
+            .optional()?
+            .ok_or_else(|| ApiError::new(ApiAvroErrorCode::SchemaNotFound))
     }
 
     pub fn get_by_id(conn: &mut PgConnection, schema_id: i64) -> Result<Self, ApiError> {
@@ -180,7 +455,8 @@ impl Schema {
         schemas
             .find(schema_id)
             .get_result::<Self>(conn)
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::SchemaNotFound))
+            .optional()?
+            .ok_or_else(|| ApiError::new(ApiAvroErrorCode::SchemaNotFound))
     }
 
     pub fn verify_registration(
@@ -198,6 +474,7 @@ impl Schema {
                             version: schema_version
                                 .version
                                 .ok_or_else(|| ApiError::new(ApiAvroErrorCode::VersionNotFound))?,
+                            references: SchemaReference::of_schema(conn, schema.id)?,
                             schema: schema.json,
                         })
                     })
@@ -215,6 +492,23 @@ pub struct RegisterSchemaResponse {
 pub struct RegisterSchema {
     pub subject: String,
     pub schema: String,
+    pub references: Vec<Reference>,
+}
+
+/// A schema being registered, parsed and fingerprinted once before any attempt.
+struct NewRegistration<'a> {
+    json: &'a str,
+    fingerprint: &'a str,
+    parsed: &'a avro_rs::Schema,
+    references: &'a [Reference],
+}
+
+#[derive(Debug)]
+pub struct RegisteredSchema {
+    pub schema: Schema,
+    /// Version created by the registration, `None` if the schema was already registered
+    /// under the subject
+    pub created_version: Option<i32>,
 }
 
 pub struct VerifySchemaRegistration {
@@ -223,3 +517,40 @@ pub struct VerifySchemaRegistration {
 }
 
 pub type VerifyRegistrationResponse = GetSubjectVersionResponse;
+
+/// Failure while registering a schema. Conflicts with a concurrent registration are kept
+/// apart from any other error so that the registration can be retried.
+#[derive(Debug)]
+enum RegistrationError {
+    Conflict(diesel::result::Error),
+    Api(ApiError),
+}
+
+impl From<diesel::result::Error> for RegistrationError {
+    fn from(error: diesel::result::Error) -> Self {
+        use diesel::result::{DatabaseErrorKind, Error};
+
+        match error {
+            Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)
+            | Error::DatabaseError(DatabaseErrorKind::SerializationFailure, _) => {
+                Self::Conflict(error)
+            }
+            _ => Self::Api(error.into()),
+        }
+    }
+}
+
+impl From<ApiError> for RegistrationError {
+    fn from(error: ApiError) -> Self {
+        Self::Api(error)
+    }
+}
+
+impl From<RegistrationError> for ApiError {
+    fn from(error: RegistrationError) -> Self {
+        match error {
+            RegistrationError::Conflict(_) => Self::new(ApiAvroErrorCode::BackendDatastoreError),
+            RegistrationError::Api(e) => e,
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use super::schema::*;
+use super::Reference;
 
 use crate::api::errors::{ApiAvroErrorCode, ApiError};
 
@@ -13,13 +14,15 @@ pub struct Subject {
     pub name: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub deleted: bool,
 }
 
 impl Subject {
     /// Insert a new subject but ignore if it already exists.
     ///
     /// *Note:* 'ignore' in the case above means we will update the name if it already
-    /// exists. This spares us complicated code to fetch, verify and then insert.
+    /// exists. This spares us complicated code to fetch, verify and then insert. A soft
+    /// deleted subject is restored.
     pub fn insert(conn: &mut PgConnection, subject: String) -> Result<Self, ApiError> {
         use super::schema::subjects::dsl::*;
 
@@ -31,47 +34,165 @@ impl Subject {
             ))
             .on_conflict(name)
             .do_update()
-            .set(name.eq(&subject))
+            .set((name.eq(&subject), deleted.eq(false)))
             .get_result::<Self>(conn)
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::BackendDatastoreError))
+            .map_err(ApiError::from)
     }
 
     pub fn distinct_names(conn: &mut PgConnection) -> Result<Vec<String>, ApiError> {
-        use super::schema::subjects::dsl::{name, subjects};
+        use super::schema::subjects::dsl::{deleted, name, subjects};
 
         subjects
+            .filter(deleted.eq(false))
             .select(name)
             .load::<String>(conn)
-            .map_err(|_| ApiError::new(ApiAvroErrorCode::BackendDatastoreError))
+            .map_err(ApiError::from)
+    }
+
+    /// Names of the subjects with a version of the schema, soft deleted or not.
+    pub fn names_with_schema(
+        conn: &mut PgConnection,
+        search_schema_id: i64,
+    ) -> Result<Vec<String>, ApiError> {
+        use super::schema::schema_versions::dsl::{schema_id, schema_versions, subject_id};
+        use super::schema::subjects::dsl::{id, name, subjects};
+
+        subjects
+            .inner_join(schema_versions.on(subject_id.eq(id)))
+            .filter(schema_id.eq(search_schema_id))
+            .select(name)
+            .distinct()
+            .load::<String>(conn)
+            .map_err(ApiError::from)
+    }
+
+    /// Counts subjects, leaving out soft deleted ones.
+    pub fn count(conn: &mut PgConnection) -> Result<i64, ApiError> {
+        use super::schema::subjects::dsl::{deleted, subjects};
+
+        subjects
+            .filter(deleted.eq(false))
+            .count()
+            .get_result(conn)
+            .map_err(ApiError::from)
+    }
+
+    /// Every subject, soft deleted or not, in the order they were created.
+    pub fn all(conn: &mut PgConnection) -> Result<Vec<Self>, ApiError> {
+        use super::schema::subjects::dsl::{id, subjects};
+
+        subjects
+            .order(id.asc())
+            .load::<Self>(conn)
+            .map_err(ApiError::from)
     }
 
+    /// Up to `limit` subjects created after the one with the ID `after`, soft deleted or
+    /// not, in the order they were created.
+    pub fn page(conn: &mut PgConnection, after: i64, limit: i64) -> Result<Vec<Self>, ApiError> {
+        use super::schema::subjects::dsl::{id, subjects};
+
+        subjects
+            .filter(id.gt(after))
+            .order(id.asc())
+            .limit(limit)
+            .load::<Self>(conn)
+            .map_err(ApiError::from)
+    }
+
+    /// Sets when the subject was created and last updated, as restored from an export.
+    pub fn set_timestamps(
+        &self,
+        conn: &mut PgConnection,
+        created: NaiveDateTime,
+        updated: NaiveDateTime,
+    ) -> Result<(), ApiError> {
+        use super::schema::subjects::dsl::{created_at, updated_at};
+
+        diesel::update(self)
+            .set((created_at.eq(created), updated_at.eq(updated)))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Soft deletes the subject alone, leaving its versions as they are.
+    pub fn mark_deleted(&self, conn: &mut PgConnection) -> Result<(), ApiError> {
+        use super::schema::subjects::dsl::deleted;
+
+        diesel::update(self).set(deleted.eq(true)).execute(conn)?;
+        Ok(())
+    }
+
+    /// Gets a subject unless it was soft deleted.
     pub fn get_by_name(conn: &mut PgConnection, subject: String) -> Result<Self, ApiError> {
+        match Self::get_by_name_with_deleted(conn, subject)? {
+            s if !s.deleted => Ok(s),
+            _ => Err(ApiError::new(ApiAvroErrorCode::SubjectNotFound)),
+        }
+    }
+
+    /// Same as [`Subject::get_by_name`], including soft deleted subjects.
+    pub fn get_by_name_with_deleted(
+        conn: &mut PgConnection,
+        subject: String,
+    ) -> Result<Self, ApiError> {
         use super::schema::subjects::dsl::{name, subjects};
         match subjects.filter(name.eq(subject)).first::<Self>(conn) {
             Ok(s) => Ok(s),
             Err(diesel::result::Error::NotFound) => {
                 Err(ApiError::new(ApiAvroErrorCode::SubjectNotFound))
             }
-            _ => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
+            Err(e) => Err(e.into()),
         }
     }
 
+    /// Soft deletes a subject along with its versions, which are hidden from then on but
+    /// kept until the subject is deleted permanently. Registering a schema under the
+    /// subject again restores it.
     pub fn delete_by_name(
         conn: &mut PgConnection,
         subject_name: String,
     ) -> Result<Vec<Option<i32>>, ApiError> {
-        use super::SchemaVersion;
-
-        match SchemaVersion::delete_subject_with_name(conn, subject_name) {
-            Err(_) => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
-            Ok(res) => {
-                if !res.is_empty() {
-                    Ok(res)
-                } else {
-                    Err(ApiError::new(ApiAvroErrorCode::SubjectNotFound))
-                }
-            }
+        use super::schema::subjects::dsl::deleted;
+        use super::{Config, SchemaReference, SchemaVersion};
+
+        let subject = Self::get_by_name_with_deleted(conn, subject_name)?;
+        if subject.deleted {
+            return Err(ApiError::new(ApiAvroErrorCode::SubjectSoftDeleted));
         }
+        Config::ensure_writable(conn, &subject.name)?;
+        SchemaReference::ensure_unreferenced(conn, &subject.name, None)?;
+
+        let versions = SchemaVersion::soft_delete_subject(conn, &subject)?;
+        if versions.is_empty() {
+            return Err(ApiError::new(ApiAvroErrorCode::SubjectNotFound));
+        }
+        diesel::update(&subject)
+            .set(deleted.eq(true))
+            .execute(conn)?;
+        Ok(versions)
+    }
+
+    /// Deletes a soft deleted subject for good, along with its versions and config.
+    pub fn delete_permanently_by_name(
+        conn: &mut PgConnection,
+        subject_name: String,
+    ) -> Result<Vec<Option<i32>>, ApiError> {
+        use super::schema::configs::dsl::{configs, subject_id};
+        use super::{Config, SchemaReference, SchemaVersion};
+
+        let subject = Self::get_by_name_with_deleted(conn, subject_name)?;
+        if !subject.deleted {
+            return Err(ApiError::new(ApiAvroErrorCode::SubjectNotSoftDeleted));
+        }
+        Config::ensure_writable(conn, &subject.name)?;
+        SchemaReference::ensure_unreferenced(conn, &subject.name, None)?;
+
+        let versions = SchemaVersion::delete_subject_with_name(conn, subject.name.to_owned())?;
+        // The subject is left behind when its versions were all deleted one by one
+        diesel::delete(configs.filter(subject_id.eq(subject.id))).execute(conn)?;
+        diesel::delete(&subject).execute(conn)?;
+        Ok(versions)
     }
 }
 
@@ -101,6 +222,13 @@ pub struct DeleteSubject {
     pub subject: String,
 }
 
+/// Query of the delete endpoints: deletes are soft unless `permanent` is set.
+#[derive(Debug, Default, Deserialize)]
+pub struct DeleteOptions {
+    #[serde(default)]
+    pub permanent: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct GetSubjectVersion {
     pub subject: String,
@@ -114,4 +242,6 @@ pub struct GetSubjectVersionResponse {
     pub id: i64,
     pub version: i32,
     pub schema: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub references: Vec<Reference>,
 }
@@ -0,0 +1,101 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::http::StatusCode;
+use log::warn;
+
+use super::connection::run_blocking;
+use super::{DbConnection, DbManage, DbPool};
+use crate::api::errors::ApiError;
+
+/// How long reads stay on the primary after the replica failed to hand out a connection.
+const REPLICA_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Pool used by read-only requests.
+///
+/// Connections come from the read replica when one is configured, and from the primary
+/// when it isn't or whenever the replica is unhealthy. Requests that write, or that read
+/// what they are about to write, must use the primary [`DbPool`] instead. Lookups of
+/// single schemas and versions use [`DbReadPool::run_or_primary`]: clients make them
+/// right after registering, and a lagging replica may not have what they registered yet.
+#[derive(Clone)]
+pub struct DbReadPool {
+    primary: DbPool,
+    replica: Option<DbPool>,
+    /// Milliseconds since the epoch until which the replica is skipped
+    replica_skipped_until: Arc<AtomicU64>,
+}
+
+impl DbReadPool {
+    pub fn new(primary: DbPool, replica: Option<DbPool>) -> Self {
+        Self {
+            primary,
+            replica,
+            replica_skipped_until: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn connection(&self) -> Result<DbConnection, ApiError> {
+        match self.replica_connection() {
+            Some(conn) => Ok(conn),
+            None => self.primary.connection(),
+        }
+    }
+
+    /// Connection from the replica, unless there is none or it's unhealthy.
+    fn replica_connection(&self) -> Option<DbConnection> {
+        let replica = self.replica.as_ref()?;
+        if now_millis() < self.replica_skipped_until.load(Ordering::Relaxed) {
+            return None;
+        }
+        match replica.get() {
+            Ok(conn) => Some(conn),
+            Err(e) => {
+                warn!("read replica is unhealthy, reading from primary: {}", e);
+                self.replica_skipped_until.store(
+                    now_millis() + REPLICA_RETRY_INTERVAL.as_millis() as u64,
+                    Ordering::Relaxed,
+                );
+                None
+            }
+        }
+    }
+
+    /// Same as [`DbManage::run`], with a connection from the replica when it's healthy.
+    pub fn run<F, T>(&self, f: F) -> impl Future<Output = Result<T, ApiError>>
+    where
+        F: FnOnce(&mut DbConnection) -> Result<T, ApiError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.clone();
+        run_blocking(move || f(&mut pool.connection()?))
+    }
+
+    /// Same as [`DbReadPool::run`], but runs `f` again on the primary when the replica
+    /// answers that what it looks up doesn't exist, which it may not have replicated yet.
+    pub fn run_or_primary<F, T>(&self, f: F) -> impl Future<Output = Result<T, ApiError>>
+    where
+        F: FnOnce(&mut DbConnection) -> Result<T, ApiError> + Clone + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.clone();
+        run_blocking(move || {
+            if let Some(mut conn) = pool.replica_connection() {
+                match f.clone()(&mut conn) {
+                    Err(e) if e.status_code == StatusCode::NOT_FOUND => {}
+                    done => return done,
+                }
+            }
+            f(&mut pool.primary.connection()?)
+        })
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
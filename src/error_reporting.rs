@@ -0,0 +1,65 @@
+use std::borrow::Cow;
+
+use log::warn;
+use sentry::types::Dsn;
+use sentry::{ClientInitGuard, ClientOptions};
+
+use crate::settings::{SentrySettings, SettingsError};
+
+/// Where panics and server errors are reported to, with Sentry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorReporting {
+    pub dsn: Dsn,
+    /// Share of the errors that are reported, from 0 to 1.
+    pub sample_rate: f32,
+    /// Share of the requests that are reported as transactions, from 0 to 1.
+    pub traces_sample_rate: f32,
+}
+
+impl ErrorReporting {
+    /// Where to report errors to, if anywhere. Nothing is reported when the URL isn't set.
+    ///
+    /// A URL that isn't a valid DSN is logged and ignored, so that error reporting never
+    /// keeps the registry from starting.
+    pub fn from_settings(settings: &SentrySettings) -> Result<Option<Self>, SettingsError> {
+        for (key, rate) in [
+            ("sentry.sample_rate", settings.sample_rate),
+            ("sentry.traces_sample_rate", settings.traces_sample_rate),
+        ] {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(SettingsError::Invalid(key, "between 0 and 1"));
+            }
+        }
+
+        let Some(url) = &settings.url else {
+            return Ok(None);
+        };
+        let dsn = match url.parse::<Dsn>() {
+            Ok(dsn) => dsn,
+            Err(e) => {
+                warn!(
+                    "sentry.url is not a valid DSN, errors won't be reported: {}",
+                    e
+                );
+                return Ok(None);
+            }
+        };
+        Ok(Some(Self {
+            dsn,
+            sample_rate: settings.sample_rate,
+            traces_sample_rate: settings.traces_sample_rate,
+        }))
+    }
+
+    /// Reports to Sentry until the returned guard is dropped, which waits for the events
+    /// left to send.
+    pub fn init(&self) -> ClientInitGuard {
+        sentry::init(ClientOptions {
+            dsn: Some(self.dsn.clone()),
+            release: Some(Cow::Borrowed(env!("CARGO_PKG_VERSION"))),
+            sample_rate: self.sample_rate,
+            traces_sample_rate: self.traces_sample_rate,
+            ..Default::default()
+        })
+    }
+}
@@ -1,7 +1,213 @@
-use actix_web::{HttpResponse, Responder};
+use std::time::{Duration, Instant};
+
+use actix_web::{web::Data, HttpResponse, Responder};
+use diesel::{sql_query, RunQueryDsl};
+use serde::Serialize;
+
+use crate::api::errors::ApiError;
+use crate::db::models::Config;
+use crate::db::{pending_migrations, DbConnection, DbPool};
+
+/// Longest wait for a connection when checking readiness, much shorter than the pool's
+/// own timeout so that probes answer before they time out themselves.
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(2);
 
 pub async fn status() -> impl Responder {
     HttpResponse::Ok()
         .content_type("application/json")
         .body("{\"status\": \"healthy\"}")
 }
+
+/// Whether the process is up. It never touches the database, so that a database outage
+/// doesn't get every instance restarted.
+pub async fn live() -> impl Responder {
+    HttpResponse::Ok().json(Liveness { status: "alive" })
+}
+
+/// Whether the registry can serve requests, with the status of each of its components.
+/// Answers with a `503` unless they are all up.
+pub async fn ready(db: Data<DbPool>) -> impl Responder {
+    let pool = db.get_ref().clone();
+    let readiness = actix_threadpool::run(move || Ok::<_, ApiError>(Readiness::check(&pool)))
+        .await
+        .map_err(ApiError::from)?;
+
+    let mut response = if readiness.is_ready() {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    Ok::<_, ApiError>(response.json(readiness))
+}
+
+#[derive(Debug, Serialize)]
+struct Liveness {
+    status: &'static str,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentStatus {
+    Up,
+    Down,
+}
+
+/// Outcome of checking one component, and how long it took.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub status: ComponentStatus,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Check {
+    fn run<T>(check: impl FnOnce() -> Result<T, String>) -> (Self, Option<T>) {
+        let started = Instant::now();
+        let outcome = check();
+        let latency_ms = started.elapsed().as_millis() as u64;
+        match outcome {
+            Ok(value) => (
+                Self {
+                    status: ComponentStatus::Up,
+                    latency_ms,
+                    detail: None,
+                },
+                Some(value),
+            ),
+            Err(detail) => (
+                Self {
+                    status: ComponentStatus::Down,
+                    latency_ms,
+                    detail: Some(detail),
+                },
+                None,
+            ),
+        }
+    }
+
+    fn with_detail(mut self, detail: String) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+
+    fn skipped() -> Self {
+        Self {
+            status: ComponentStatus::Down,
+            latency_ms: 0,
+            detail: Some(String::from("no database connection")),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Checks {
+    pub pool: Check,
+    pub database: Check,
+    pub migrations: Check,
+    pub mode: Check,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub status: &'static str,
+    pub checks: Checks,
+}
+
+impl Readiness {
+    /// Checks out a connection, runs a `SELECT 1`, looks for migrations that haven't been
+    /// run, and makes sure the registry isn't importing schemas. Every check but the
+    /// first needs the connection, and is reported down without one.
+    pub fn check(pool: &DbPool) -> Self {
+        let (pool_check, conn) = Check::run(|| {
+            pool.get_timeout(CHECKOUT_TIMEOUT)
+                .map_err(|e| e.to_string())
+        });
+        let checks = match conn {
+            Some(mut conn) => Checks {
+                pool: pool_check,
+                database: Self::check_database(&mut conn),
+                migrations: Self::check_migrations(&mut conn),
+                mode: Self::check_mode(&mut conn),
+            },
+            None => Checks {
+                pool: pool_check,
+                database: Check::skipped(),
+                migrations: Check::skipped(),
+                mode: Check::skipped(),
+            },
+        };
+
+        let ready = [
+            &checks.pool,
+            &checks.database,
+            &checks.migrations,
+            &checks.mode,
+        ]
+        .iter()
+        .all(|check| check.status == ComponentStatus::Up);
+        Self {
+            status: if ready { "ready" } else { "unavailable" },
+            checks,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.status == "ready"
+    }
+
+    fn check_database(conn: &mut DbConnection) -> Check {
+        Check::run(|| {
+            sql_query("SELECT 1")
+                .execute(conn)
+                .map_err(|e| e.to_string())
+        })
+        .0
+    }
+
+    fn check_migrations(conn: &mut DbConnection) -> Check {
+        Check::run(|| match pending_migrations(conn) {
+            Ok(pending) if pending.is_empty() => Ok(()),
+            Ok(pending) => Err(format!("pending migrations: {}", pending.join(", "))),
+            Err(e) => Err(e.response.message),
+        })
+        .0
+    }
+
+    /// Reports the mode the registry is in. Being in `IMPORT` mode doesn't make it
+    /// unready: imports go through the registry itself, which must keep receiving them.
+    fn check_mode(conn: &mut DbConnection) -> Check {
+        let (check, mode) =
+            Check::run(|| Config::get_global_mode(conn).map_err(|e| e.response.message));
+        match mode {
+            Some(mode) => check.with_detail(mode.to_string()),
+            None => check,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Check, ComponentStatus};
+
+    #[test]
+    fn check_reports_failures() {
+        let (check, value) = Check::run(|| Ok::<_, String>(1));
+        assert_eq!(check.status, ComponentStatus::Up);
+        assert_eq!(check.detail, None);
+        assert_eq!(value, Some(1));
+
+        let (check, value) = Check::run(|| Err::<(), _>(String::from("unreachable")));
+        assert_eq!(check.status, ComponentStatus::Down);
+        assert_eq!(check.detail.as_deref(), Some("unreachable"));
+        assert_eq!(value, None);
+    }
+
+    #[test]
+    fn check_serializes_without_empty_detail() {
+        let (check, _) = Check::run(|| Ok::<_, String>(()));
+        let json = serde_json::to_value(&check).unwrap();
+        assert_eq!(json["status"], "up");
+        assert!(json.get("detail").is_none());
+    }
+}
@@ -1,5 +1,13 @@
+pub mod admin;
 pub mod api;
 pub mod app;
+pub mod auth;
 pub mod db;
+#[cfg(feature = "sentry")]
+pub mod error_reporting;
 pub mod health;
+pub mod metrics;
 pub mod middleware;
+pub mod settings;
+pub mod telemetry;
+pub mod tls;
@@ -0,0 +1,299 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+use diesel::r2d2::event::{CheckoutEvent, HandleEvent, TimeoutEvent};
+use log::warn;
+use prometheus::{
+    Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+};
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::db::models::{CompatibilityLevel, Schema, SchemaVersion, Subject};
+use crate::db::{DbManage, DbPool};
+use crate::settings::{ServerSettings, SettingsError};
+
+const NAMESPACE: &str = "avro_schema_registry";
+
+/// Registry specific metrics, served along the HTTP ones on `/_/metrics`.
+///
+/// Shared by every worker through the app data. Handlers record what they did, while
+/// the gauges of the pool and of what the registry holds are refreshed periodically.
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    schemas_registered: IntCounterVec,
+    compatibility_checks: IntCounterVec,
+    schema_lookups: IntCounterVec,
+    deletes: IntCounterVec,
+    pool_connections: IntGaugeVec,
+    pool_max_size: IntGauge,
+    pool_wait: Histogram,
+    pool_timeouts: IntCounter,
+    subjects: IntGauge,
+    schemas: IntGauge,
+    schema_versions: IntGauge,
+}
+
+impl Metrics {
+    /// How often gauges are refreshed.
+    pub fn refresh_interval(settings: &ServerSettings) -> Result<Duration, SettingsError> {
+        match settings.metrics_refresh_seconds {
+            0 => Err(SettingsError::Invalid(
+                "server.metrics_refresh_seconds",
+                "positive",
+            )),
+            seconds => Ok(Duration::from_secs(seconds)),
+        }
+    }
+
+    pub fn new(registry: &Registry) -> prometheus::Result<Self> {
+        let opts = |name: &str, help: &str| Opts::new(name, help).namespace(NAMESPACE);
+        let metrics = Self {
+            schemas_registered: IntCounterVec::new(
+                opts(
+                    "schemas_registered_total",
+                    "Schema registrations, by subject and result",
+                ),
+                &["subject", "result"],
+            )?,
+            compatibility_checks: IntCounterVec::new(
+                opts(
+                    "compatibility_checks_total",
+                    "Compatibility checks, by level and outcome",
+                ),
+                &["level", "outcome"],
+            )?,
+            schema_lookups: IntCounterVec::new(
+                opts(
+                    "schema_lookups_total",
+                    "Lookups of schemas by ID, by result",
+                ),
+                &["result"],
+            )?,
+            deletes: IntCounterVec::new(
+                opts(
+                    "deletes_total",
+                    "Deleted subjects and versions, by kind and permanence",
+                ),
+                &["kind", "permanent"],
+            )?,
+            pool_connections: IntGaugeVec::new(
+                opts("db_pool_connections", "Connections of the pool, by state"),
+                &["state"],
+            )?,
+            pool_max_size: IntGauge::with_opts(opts(
+                "db_pool_max_size",
+                "Most connections the pool opens",
+            ))?,
+            pool_wait: Histogram::with_opts(
+                HistogramOpts::new(
+                    "db_pool_wait_seconds",
+                    "Time spent waiting for a connection of the pool",
+                )
+                .namespace(NAMESPACE)
+                .buckets(vec![0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0]),
+            )?,
+            pool_timeouts: IntCounter::with_opts(opts(
+                "db_pool_timeouts_total",
+                "Times no connection of the pool was available in time",
+            ))?,
+            subjects: IntGauge::with_opts(opts("subjects", "Subjects in the registry"))?,
+            schemas: IntGauge::with_opts(opts("schemas", "Schemas in the registry"))?,
+            schema_versions: IntGauge::with_opts(opts(
+                "schema_versions",
+                "Versions of all subjects in the registry",
+            ))?,
+        };
+
+        registry.register(Box::new(metrics.schemas_registered.clone()))?;
+        registry.register(Box::new(metrics.compatibility_checks.clone()))?;
+        registry.register(Box::new(metrics.schema_lookups.clone()))?;
+        registry.register(Box::new(metrics.deletes.clone()))?;
+        registry.register(Box::new(metrics.pool_connections.clone()))?;
+        registry.register(Box::new(metrics.pool_max_size.clone()))?;
+        registry.register(Box::new(metrics.pool_wait.clone()))?;
+        registry.register(Box::new(metrics.pool_timeouts.clone()))?;
+        registry.register(Box::new(metrics.subjects.clone()))?;
+        registry.register(Box::new(metrics.schemas.clone()))?;
+        registry.register(Box::new(metrics.schema_versions.clone()))?;
+        Ok(metrics)
+    }
+
+    /// Records the outcome of registering a schema under `subject`: whether it created a
+    /// new version, or the error it failed with. Failures are recorded without their
+    /// subject, which clients pick freely, so that only stored subjects are labels.
+    pub fn schema_registered(&self, subject: &str, result: Result<bool, &ApiError>) {
+        let (subject, result) = match result {
+            Ok(true) => (subject, "created"),
+            Ok(false) => (subject, "existing"),
+            Err(e) => ("", error_result(e)),
+        };
+        self.schemas_registered
+            .with_label_values(&[subject, result])
+            .inc();
+    }
+
+    /// Records a compatibility check at `level`, unknown when the check failed before
+    /// the level was.
+    pub fn compatibility_checked(
+        &self,
+        level: Option<CompatibilityLevel>,
+        result: Result<bool, &ApiError>,
+    ) {
+        let level = level.map_or_else(|| String::from("unknown"), |level| level.to_string());
+        let outcome = match result {
+            Ok(true) => "compatible",
+            Ok(false) => "incompatible",
+            Err(e) => error_result(e),
+        };
+        self.compatibility_checks
+            .with_label_values(&[level.as_str(), outcome])
+            .inc();
+    }
+
+    pub fn schema_looked_up<T>(&self, result: &Result<T, ApiError>) {
+        let result = match result {
+            Ok(_) => "hit",
+            Err(e) if e.response.error_code == ApiAvroErrorCode::SchemaNotFound => "miss",
+            Err(_) => "error",
+        };
+        self.schema_lookups.with_label_values(&[result]).inc();
+    }
+
+    pub fn subject_deleted(&self, permanent: bool) {
+        self.deleted("subject", permanent);
+    }
+
+    pub fn schema_version_deleted(&self, permanent: bool) {
+        self.deleted("version", permanent);
+    }
+
+    fn deleted(&self, kind: &str, permanent: bool) {
+        self.deletes
+            .with_label_values(&[kind, if permanent { "true" } else { "false" }])
+            .inc();
+    }
+
+    /// Handler of the pool's events, to be given to
+    /// [`DbManage::from_config_with_events`].
+    pub fn pool_events(&self) -> Box<dyn HandleEvent> {
+        Box::new(PoolEvents {
+            wait: self.pool_wait.clone(),
+            timeouts: self.pool_timeouts.clone(),
+        })
+    }
+
+    /// Samples the pool and counts what the registry holds.
+    pub async fn refresh(&self, pool: &DbPool) -> Result<(), ApiError> {
+        let state = pool.state();
+        let idle = i64::from(state.idle_connections);
+        self.pool_connections.with_label_values(&["idle"]).set(idle);
+        self.pool_connections
+            .with_label_values(&["active"])
+            .set(i64::from(state.connections) - idle);
+        self.pool_max_size.set(i64::from(pool.max_size()));
+
+        let (subjects, schemas, schema_versions) = pool
+            .run(|conn| {
+                Ok((
+                    Subject::count(conn)?,
+                    Schema::count(conn)?,
+                    SchemaVersion::count(conn)?,
+                ))
+            })
+            .await?;
+        self.subjects.set(subjects);
+        self.schemas.set(schemas);
+        self.schema_versions.set(schema_versions);
+        Ok(())
+    }
+
+    /// Refreshes the gauges right away, then every `interval`, for as long as the
+    /// runtime it's spawned on is running.
+    pub fn refresh_periodically(metrics: Data<Self>, pool: DbPool, interval: Duration) {
+        actix_web::rt::spawn(async move {
+            let mut ticks = actix_web::rt::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                if let Err(e) = metrics.refresh(&pool).await {
+                    warn!("failed to refresh metrics: {}", e.response.message);
+                }
+            }
+        });
+    }
+}
+
+/// Label of a failure, after the most common errors.
+fn error_result(error: &ApiError) -> &'static str {
+    match error.response.error_code {
+        ApiAvroErrorCode::IncompatibleSchema => "incompatible",
+        ApiAvroErrorCode::InvalidAvroSchema => "invalid",
+        ApiAvroErrorCode::SubjectNotFound | ApiAvroErrorCode::VersionNotFound => "not_found",
+        ApiAvroErrorCode::OperationNotPermitted => "not_permitted",
+        _ => "error",
+    }
+}
+
+#[derive(Debug)]
+struct PoolEvents {
+    wait: Histogram,
+    timeouts: IntCounter,
+}
+
+impl HandleEvent for PoolEvents {
+    fn handle_checkout(&self, event: CheckoutEvent) {
+        self.wait.observe(event.duration().as_secs_f64());
+    }
+
+    fn handle_timeout(&self, event: TimeoutEvent) {
+        self.wait.observe(event.timeout().as_secs_f64());
+        self.timeouts.inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+    use crate::api::errors::{ApiAvroErrorCode, ApiError};
+    use prometheus::{Encoder, Registry, TextEncoder};
+
+    fn scrape(registry: &Registry) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn metrics_label_registrations() {
+        let registry = Registry::new();
+        let metrics = Metrics::new(&registry).unwrap();
+
+        metrics.schema_registered("test.subject", Ok(true));
+        metrics.schema_registered(
+            "test.subject",
+            Err(&ApiError::new(ApiAvroErrorCode::IncompatibleSchema)),
+        );
+
+        let scraped = scrape(&registry);
+        assert!(scraped.contains(
+            r#"avro_schema_registry_schemas_registered_total{result="created",subject="test.subject"} 1"#
+        ));
+        assert!(scraped.contains(
+            r#"avro_schema_registry_schemas_registered_total{result="incompatible",subject=""} 1"#
+        ));
+    }
+
+    #[test]
+    fn metrics_label_lookups() {
+        let registry = Registry::new();
+        let metrics = Metrics::new(&registry).unwrap();
+
+        metrics.schema_looked_up(&Ok(()));
+        metrics.schema_looked_up::<()>(&Err(ApiError::new(ApiAvroErrorCode::SchemaNotFound)));
+
+        let scraped = scrape(&registry);
+        assert!(scraped.contains(r#"avro_schema_registry_schema_lookups_total{result="hit"} 1"#));
+        assert!(scraped.contains(r#"avro_schema_registry_schema_lookups_total{result="miss"} 1"#));
+    }
+}
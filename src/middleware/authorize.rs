@@ -0,0 +1,122 @@
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{HttpMessage, ResponseError};
+use futures::future::{ok, Either, Ready};
+use futures::task::{Context, Poll};
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::auth::{Principal, Role};
+
+/// Rejects requests from principals without the role a route requires.
+///
+/// It relies on the principal identified by [`VerifyAuthorization`], so it has to be
+/// wrapped *before* it (actix runs the last registered middleware first).
+///
+/// [`VerifyAuthorization`]: super::VerifyAuthorization
+pub struct Authorize {
+    role: Option<Role>,
+}
+
+impl Authorize {
+    /// Requires the role matching the request method, see [`Role::required_for`].
+    pub fn by_method() -> Self {
+        Self { role: None }
+    }
+
+    /// Requires `role` whatever the request method.
+    pub fn require(role: Role) -> Self {
+        Self { role: Some(role) }
+    }
+
+    fn is_allowed(principal: Option<&Principal>, required: Role) -> bool {
+        principal.is_some_and(|principal| principal.can(required))
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for Authorize
+where
+    S: Service<ServiceRequest, Response = ServiceResponse>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = S::Error;
+    type InitError = ();
+    type Transform = AuthorizeMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AuthorizeMiddleware {
+            service,
+            role: self.role,
+        })
+    }
+}
+
+pub struct AuthorizeMiddleware<S> {
+    service: S,
+    role: Option<Role>,
+}
+
+impl<S> Service<ServiceRequest> for AuthorizeMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = S::Error;
+    type Future = Either<Ready<Result<Self::Response, Self::Error>>, S::Future>;
+
+    fn poll_ready(&self, ct: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ct)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let required = self
+            .role
+            .unwrap_or_else(|| Role::required_for(req.method()));
+        let allowed = Authorize::is_allowed(req.extensions().get::<Principal>(), required);
+
+        if allowed {
+            return Either::Right(self.service.call(req));
+        }
+        let error = ApiError::new(ApiAvroErrorCode::UserDeniedOperation);
+        Either::Left(ok(req.into_response(error.error_response())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Authorize;
+    use crate::auth::{Principal, Role};
+
+    fn principal(role: Role) -> Principal {
+        Principal {
+            name: String::from("someone"),
+            role,
+        }
+    }
+
+    #[test]
+    fn middleware_allows_sufficient_role() {
+        assert!(Authorize::is_allowed(
+            Some(&principal(Role::Writer)),
+            Role::Reader
+        ));
+        assert!(Authorize::is_allowed(
+            Some(&principal(Role::Writer)),
+            Role::Writer
+        ));
+    }
+
+    #[test]
+    fn middleware_denies_insufficient_role() {
+        assert!(!Authorize::is_allowed(
+            Some(&principal(Role::Writer)),
+            Role::Admin
+        ));
+    }
+
+    #[test]
+    fn middleware_denies_missing_principal() {
+        assert!(!Authorize::is_allowed(None, Role::Reader));
+    }
+}
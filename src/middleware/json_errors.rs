@@ -0,0 +1,185 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::InternalError;
+use actix_web::http::header;
+use actix_web::{Error, HttpMessage, HttpResponse};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::task::{Context, Poll};
+use serde_json::Value;
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::middleware::RequestId;
+
+/// Turns error responses without a JSON body, such as actix's own for unmatched routes
+/// or methods, into registry errors with a code matching their status, and adds the
+/// request ID to every error body as `request_id`.
+///
+/// It has to be wrapped after every other middleware but [`super::Trace`] and
+/// [`super::AssignRequestId`], so that it sees all of their responses.
+pub struct JsonErrors;
+
+impl JsonErrors {
+    fn needs_body(response: &HttpResponse) -> bool {
+        let is_json = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| content_type.contains("json"));
+        (response.status().is_client_error() || response.status().is_server_error()) && !is_json
+    }
+
+    fn to_json(response: &HttpResponse) -> HttpResponse {
+        let status = response.status();
+        let error = ApiError::with_status(status, ApiAvroErrorCode::for_status(status));
+        HttpResponse::from_error(error)
+    }
+
+    /// Adds `request_id` to the JSON object in the body, other bodies are left as is.
+    fn with_request_id(response: HttpResponse, request_id: &RequestId) -> HttpResponse {
+        let (response, body) = response.into_parts();
+        let body = match body.try_into_bytes() {
+            Ok(bytes) => match serde_json::from_slice::<Value>(&bytes) {
+                Ok(Value::Object(mut error)) => {
+                    error.insert(
+                        String::from("request_id"),
+                        Value::String(request_id.to_string()),
+                    );
+                    BoxBody::new(Value::Object(error).to_string())
+                }
+                _ => BoxBody::new(bytes),
+            },
+            Err(body) => body,
+        };
+        response.set_body(body)
+    }
+
+    fn rewrite(response: HttpResponse, request_id: Option<&RequestId>) -> HttpResponse {
+        if !response.status().is_client_error() && !response.status().is_server_error() {
+            return response;
+        }
+        let response = if Self::needs_body(&response) {
+            Self::to_json(&response)
+        } else {
+            response
+        };
+        match request_id {
+            Some(request_id) => Self::with_request_id(response, request_id),
+            None => response,
+        }
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for JsonErrors
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = JsonErrorsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(JsonErrorsMiddleware { service })
+    }
+}
+
+pub struct JsonErrorsMiddleware<S> {
+    service: S,
+}
+
+impl<S> Service<ServiceRequest> for JsonErrorsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ct: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ct)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // Holding on to the request while the scope routes it would make actix panic, so
+        // errors of the services below are answered without it
+        let request_id = req.extensions().get::<RequestId>().cloned();
+        let response = self.service.call(req);
+
+        Box::pin(async move {
+            match response.await {
+                Ok(response) => {
+                    let (request, response) = response.into_parts();
+                    let response = JsonErrors::rewrite(response, request_id.as_ref());
+                    Ok(ServiceResponse::new(request, response))
+                }
+                Err(e) => {
+                    let response = JsonErrors::rewrite(e.error_response(), request_id.as_ref());
+                    Err(InternalError::from_response(e, response).into())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonErrors;
+    use crate::api::errors::{ApiAvroErrorCode, ApiError};
+    use crate::middleware::RequestId;
+    use actix_web::body::MessageBody;
+    use actix_web::http::header::HeaderValue;
+    use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+
+    #[test]
+    fn json_errors_replace_empty_error() {
+        let response = HttpResponse::MethodNotAllowed().finish();
+        assert!(JsonErrors::needs_body(&response));
+
+        let response = JsonErrors::to_json(&response);
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/json"
+        );
+    }
+
+    #[test]
+    fn json_errors_keep_registry_errors_and_successes() {
+        let error = ApiError::new(ApiAvroErrorCode::SubjectNotFound);
+        assert!(!JsonErrors::needs_body(&error.error_response()));
+        assert!(!JsonErrors::needs_body(&HttpResponse::Ok().finish()));
+    }
+
+    #[test]
+    fn json_errors_add_request_id() {
+        let request_id = RequestId::from_header(&HeaderValue::from_static("ticket-42")).unwrap();
+        let error = ApiError::new(ApiAvroErrorCode::SubjectNotFound);
+
+        let response = JsonErrors::with_request_id(error.error_response(), &request_id);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = response.into_body().try_into_bytes().unwrap();
+        assert_eq!(
+            body,
+            r#"{"error_code":40401,"message":"Subject not found","request_id":"ticket-42"}"#
+        );
+    }
+
+    #[test]
+    fn json_errors_code_for_status() {
+        assert_eq!(
+            ApiAvroErrorCode::for_status(StatusCode::NOT_FOUND),
+            ApiAvroErrorCode::NotFound
+        );
+        assert_eq!(
+            ApiAvroErrorCode::for_status(StatusCode::URI_TOO_LONG),
+            ApiAvroErrorCode::MalformedRequest
+        );
+        assert_eq!(
+            ApiAvroErrorCode::for_status(StatusCode::BAD_GATEWAY),
+            ApiAvroErrorCode::InternalServerError
+        );
+    }
+}
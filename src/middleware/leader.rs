@@ -0,0 +1,240 @@
+use std::time::Duration;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, Method};
+use actix_web::web::Data;
+use actix_web::{Error, HttpMessage, HttpResponse};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::task::{Context, Poll};
+use tracing::info;
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::auth::{ForwardingKey, Principal, FORWARDED_PRINCIPAL_HEADER};
+use crate::settings::{LeaderSettings, SettingsError};
+
+/// Largest response of the leader relayed back to clients.
+const MAX_RESPONSE_SIZE: usize = 8 * 1024 * 1024;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// The instance every write goes through, when this one is a follower.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Leader {
+    pub url: String,
+    pub timeout: Duration,
+    /// Signs the principals requests are forwarded on behalf of
+    pub key: ForwardingKey,
+}
+
+impl Leader {
+    /// The leader of this instance, if its URL, such as `http://registry-0:8080`, is set.
+    /// The leader itself, like a registry running on its own, leaves it unset. Followers
+    /// must share a secret with the leader.
+    pub fn from_settings(settings: &LeaderSettings) -> Result<Option<Self>, SettingsError> {
+        let Some(url) = settings.url.as_deref() else {
+            return Ok(None);
+        };
+        let secret = settings
+            .secret
+            .as_deref()
+            .ok_or(SettingsError::Missing("leader.secret"))?;
+        Ok(Some(Self {
+            url: url.trim_end_matches('/').to_string(),
+            timeout: Duration::from_millis(settings.timeout_ms),
+            key: ForwardingKey::new(secret.as_bytes()),
+        }))
+    }
+
+    fn forwards(method: &Method) -> bool {
+        !matches!(*method, Method::GET | Method::HEAD)
+    }
+
+    async fn forward(
+        &self,
+        client: &awc::Client,
+        req: ServiceRequest,
+    ) -> Result<HttpResponse, ApiError> {
+        let failed = |e: String| ApiError::internal(ApiAvroErrorCode::LeaderForwardingError, e);
+
+        // Requests only get here once authorized, so the principal is always there
+        let principal = req
+            .extensions()
+            .get::<Principal>()
+            .cloned()
+            .ok_or_else(|| ApiError::new(ApiAvroErrorCode::Unauthenticated))?;
+        let token = self
+            .key
+            .sign(&principal)
+            .map_err(|e| failed(e.to_string()))?;
+
+        let path = req
+            .uri()
+            .path_and_query()
+            .map_or("/", |path_and_query| path_and_query.as_str());
+        let mut forwarded = client
+            .request(req.method().clone(), format!("{}{}", self.url, path))
+            .timeout(self.timeout);
+        for (name, value) in req.headers() {
+            if !is_hop_by_hop(name) && !is_replaced(name) {
+                forwarded = forwarded.append_header((name.clone(), value.clone()));
+            }
+        }
+        forwarded = forwarded
+            .insert_header((FORWARDED_PRINCIPAL_HEADER, token))
+            .insert_header((X_FORWARDED_FOR, forwarded_for(&req)));
+        // The request's span already has its method and path
+        info!(leader = %self.url, "forwarding to leader");
+
+        let (_, payload) = req.into_parts();
+        let mut response = forwarded
+            .send_stream(payload)
+            .await
+            .map_err(|e| failed(e.to_string()))?;
+        let body = response
+            .body()
+            .limit(MAX_RESPONSE_SIZE)
+            .await
+            .map_err(|e| failed(e.to_string()))?;
+
+        let mut relayed = HttpResponse::build(response.status());
+        for (name, value) in response.headers() {
+            if !is_hop_by_hop(name) {
+                relayed.append_header((name.clone(), value.clone()));
+            }
+        }
+        Ok(relayed.body(body))
+    }
+}
+
+/// Headers describing a single connection, which the client that forwards the request
+/// sets on its own.
+fn is_hop_by_hop(name: &header::HeaderName) -> bool {
+    matches!(
+        *name,
+        header::CONNECTION
+            | header::CONTENT_LENGTH
+            | header::HOST
+            | header::TE
+            | header::TRAILER
+            | header::TRANSFER_ENCODING
+            | header::UPGRADE
+    )
+}
+
+/// Headers the leader is given by the follower instead of the client: the leader trusts
+/// the principal the follower authenticated, not the client's credentials.
+fn is_replaced(name: &header::HeaderName) -> bool {
+    *name == header::AUTHORIZATION || name == FORWARDED_PRINCIPAL_HEADER || name == X_FORWARDED_FOR
+}
+
+/// Addresses the request went through, the client's `X-Forwarded-For` followed by its
+/// own address.
+fn forwarded_for(req: &ServiceRequest) -> String {
+    let mut addresses = req
+        .headers()
+        .get_all(X_FORWARDED_FOR)
+        .filter_map(|value| value.to_str().ok())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if let Some(peer) = req.peer_addr() {
+        addresses.push(peer.ip().to_string());
+    }
+    addresses.join(", ")
+}
+
+/// Forwards every request that changes the registry to the [`Leader`] in the app data,
+/// if any, so that schema ids and versions are only ever given out by a single instance.
+/// Clients get the leader's response as is, or `LeaderForwardingError` when it can't be
+/// reached.
+///
+/// It must wrap resources inside their ACL and role checks: requests are authenticated
+/// and authorized locally before being forwarded, on behalf of their principal, which the
+/// leader authorizes once more.
+pub struct ForwardToLeader;
+
+impl<S> Transform<S, ServiceRequest> for ForwardToLeader
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ForwardToLeaderMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ForwardToLeaderMiddleware {
+            service,
+            client: awc::Client::default(),
+        })
+    }
+}
+
+pub struct ForwardToLeaderMiddleware<S> {
+    service: S,
+    client: awc::Client,
+}
+
+impl<S> Service<ServiceRequest> for ForwardToLeaderMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ct: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ct)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let leader = match req.app_data::<Data<Leader>>() {
+            Some(leader) if Leader::forwards(req.method()) => leader.clone(),
+            _ => return Box::pin(self.service.call(req)),
+        };
+
+        let client = self.client.clone();
+        let request = req.request().clone();
+        Box::pin(async move {
+            let response = match leader.forward(&client, req).await {
+                Ok(response) => response,
+                Err(e) => HttpResponse::from_error(e),
+            };
+            Ok(ServiceResponse::new(request, response))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_hop_by_hop, is_replaced, Leader};
+    use actix_web::http::{header, Method};
+
+    #[test]
+    fn leader_forwards_writes_only() {
+        assert!(!Leader::forwards(&Method::GET));
+        assert!(!Leader::forwards(&Method::HEAD));
+        assert!(Leader::forwards(&Method::POST));
+        assert!(Leader::forwards(&Method::PUT));
+        assert!(Leader::forwards(&Method::DELETE));
+    }
+
+    #[test]
+    fn leader_keeps_end_to_end_headers() {
+        assert!(is_hop_by_hop(&header::HOST));
+        assert!(is_hop_by_hop(&header::CONTENT_LENGTH));
+        assert!(!is_hop_by_hop(&header::AUTHORIZATION));
+        assert!(!is_hop_by_hop(&header::CONTENT_TYPE));
+    }
+
+    #[test]
+    fn leader_replaces_credentials() {
+        assert!(is_replaced(&header::AUTHORIZATION));
+        assert!(is_replaced(&header::HeaderName::from_static(
+            "x-forwarded-principal"
+        )));
+        assert!(!is_replaced(&header::CONTENT_TYPE));
+    }
+}
@@ -1,5 +1,25 @@
+pub use self::authorize::Authorize;
+pub use self::json_errors::JsonErrors;
+pub use self::leader::{ForwardToLeader, Leader};
+pub use self::rate_limit::{RateBudget, RateLimit, RateLimitKey, RateLimiter, RequestKind};
+#[cfg(feature = "sentry")]
+pub use self::report_errors::ReportErrors;
+pub use self::request_id::{AssignRequestId, RequestId, REQUEST_ID_HEADER};
+pub use self::subject_acl::SubjectAcl;
+pub use self::timeout::{RequestTimeout, Timeout};
+pub use self::trace::Trace;
 pub use self::verify_auth::VerifyAuthorization;
 pub use self::verify_headers::VerifyAcceptHeader;
 
+mod authorize;
+mod json_errors;
+mod leader;
+mod rate_limit;
+#[cfg(feature = "sentry")]
+mod report_errors;
+mod request_id;
+mod subject_acl;
+mod timeout;
+mod trace;
 mod verify_auth;
 mod verify_headers;
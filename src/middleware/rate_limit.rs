@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::web::Data;
+use actix_web::{HttpMessage, HttpResponse, ResponseError};
+use futures::future::{ok, Either, LocalBoxFuture, Ready};
+use futures::task::{Context, Poll};
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::auth::{Principal, Role};
+use crate::settings::{RateLimitSettings, SettingsError};
+
+/// Requests a client can make: `burst` at once, then `per_second` on average.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateBudget {
+    pub per_second: f64,
+    pub burst: f64,
+}
+
+impl RateBudget {
+    /// The budget of `per_second` requests, if set, with a burst of `burst` or else of
+    /// the rate.
+    fn from_settings(
+        per_second: Option<f64>,
+        burst: Option<f64>,
+        (per_second_key, burst_key): (&'static str, &'static str),
+    ) -> Result<Option<Self>, SettingsError> {
+        let Some(per_second) = per_second else {
+            return Ok(None);
+        };
+        if per_second <= 0.0 {
+            return Err(SettingsError::Invalid(per_second_key, "positive"));
+        }
+        let burst = match burst {
+            Some(burst) if burst < 1.0 => {
+                return Err(SettingsError::Invalid(burst_key, "at least 1"));
+            }
+            Some(burst) => burst,
+            None => per_second.max(1.0),
+        };
+        Ok(Some(Self { per_second, burst }))
+    }
+}
+
+/// Whether a request spends the read or the write budget.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RequestKind {
+    Read,
+    Write,
+}
+
+impl RequestKind {
+    pub fn of(method: &Method) -> Self {
+        match Role::required_for(method) {
+            Role::Reader => Self::Read,
+            _ => Self::Write,
+        }
+    }
+}
+
+/// Who a budget belongs to: the authenticated principal, or the address of a client that
+/// failed to authenticate.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum RateLimitKey {
+    Principal(String),
+    Ip(IpAddr),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn tokens_at(&self, budget: RateBudget, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * budget.per_second).min(budget.burst)
+    }
+}
+
+/// Token buckets of every client, with separate budgets for reads and writes. Requests
+/// of a kind without a budget are never limited.
+///
+/// It has to be shared by every worker, as `Data<RateLimiter>`, for limits to apply to
+/// the whole server.
+pub struct RateLimiter {
+    read: Option<RateBudget>,
+    write: Option<RateBudget>,
+    buckets: Mutex<HashMap<(RateLimitKey, RequestKind), Bucket>>,
+}
+
+impl RateLimiter {
+    /// Number of buckets above which full ones are dropped, as they're the same as new
+    /// ones.
+    const MAX_BUCKETS: usize = 10_000;
+
+    pub fn new(read: Option<RateBudget>, write: Option<RateBudget>) -> Self {
+        Self {
+            read,
+            write,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Builds a limiter with the budgets of the settings, where each is only set if its
+    /// rate is.
+    pub fn from_settings(settings: &RateLimitSettings) -> Result<Self, SettingsError> {
+        Ok(Self::new(
+            RateBudget::from_settings(
+                settings.read_per_second,
+                settings.read_burst,
+                ("rate_limit.read_per_second", "rate_limit.read_burst"),
+            )?,
+            RateBudget::from_settings(
+                settings.write_per_second,
+                settings.write_burst,
+                ("rate_limit.write_per_second", "rate_limit.write_burst"),
+            )?,
+        ))
+    }
+
+    fn budget(&self, kind: RequestKind) -> Option<RateBudget> {
+        match kind {
+            RequestKind::Read => self.read,
+            RequestKind::Write => self.write,
+        }
+    }
+
+    /// How long until the client's bucket has a token, if it is empty, without taking
+    /// one.
+    pub fn wait_time(
+        &self,
+        key: &RateLimitKey,
+        kind: RequestKind,
+        now: Instant,
+    ) -> Option<Duration> {
+        let budget = self.budget(kind)?;
+        let tokens = self
+            .buckets
+            .lock()
+            .unwrap()
+            .get(&(key.clone(), kind))
+            .map_or(budget.burst, |bucket| bucket.tokens_at(budget, now));
+        (tokens < 1.0).then(|| Duration::from_secs_f64((1.0 - tokens) / budget.per_second))
+    }
+
+    /// Takes a token from the client's bucket, or returns how long until one is
+    /// available.
+    pub fn acquire(
+        &self,
+        key: RateLimitKey,
+        kind: RequestKind,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let budget = match self.budget(kind) {
+            Some(budget) => budget,
+            None => return Ok(()),
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= Self::MAX_BUCKETS {
+            buckets.retain(|(_, kind), bucket| {
+                self.budget(*kind)
+                    .is_some_and(|budget| bucket.tokens_at(budget, now) < budget.burst)
+            });
+        }
+
+        let bucket = buckets.entry((key, kind)).or_insert(Bucket {
+            tokens: budget.burst,
+            updated: now,
+        });
+        bucket.tokens = bucket.tokens_at(budget, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / budget.per_second,
+            ))
+        }
+    }
+}
+
+/// Rejects requests of clients that went over their budget, with a `Retry-After`
+/// header. It does nothing unless a [`RateLimiter`] is registered as app data.
+///
+/// Clients are limited twice: [`RateLimit::by_address`] wraps [`VerifyAuthorization`],
+/// and turns away addresses that failed to authenticate too often before their
+/// credentials are checked, while [`RateLimit::by_principal`] is wrapped after it and
+/// spends the budget of the authenticated principal.
+///
+/// [`VerifyAuthorization`]: super::VerifyAuthorization
+pub struct RateLimit {
+    by_principal: bool,
+}
+
+impl RateLimit {
+    /// Limits the requests of each authenticated principal.
+    pub fn by_principal() -> Self {
+        Self { by_principal: true }
+    }
+
+    /// Limits the failed authentications of each client address.
+    pub fn by_address() -> Self {
+        Self {
+            by_principal: false,
+        }
+    }
+
+    fn too_many_requests(retry_after: Duration) -> HttpResponse {
+        let mut response = ApiError::new(ApiAvroErrorCode::RateLimitExceeded).error_response();
+        // Retry-After is in whole seconds, rounding down would have clients retry too early
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            header::HeaderValue::from(retry_after.as_secs_f64().ceil() as u64),
+        );
+        response
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = S::Error;
+    type InitError = ();
+    type Transform = RateLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimitMiddleware {
+            service,
+            by_principal: self.by_principal,
+        })
+    }
+}
+
+type RateLimitFuture<E> =
+    Either<Ready<Result<ServiceResponse, E>>, LocalBoxFuture<'static, Result<ServiceResponse, E>>>;
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    by_principal: bool,
+}
+
+impl<S> RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse>,
+    S::Future: 'static,
+{
+    fn call_by_principal(
+        &self,
+        req: ServiceRequest,
+        limiter: &RateLimiter,
+    ) -> RateLimitFuture<S::Error> {
+        let key = req
+            .extensions()
+            .get::<Principal>()
+            .map(|principal| RateLimitKey::Principal(principal.name.to_owned()));
+        let retry_after = key.and_then(|key| {
+            limiter
+                .acquire(key, RequestKind::of(req.method()), Instant::now())
+                .err()
+        });
+        match retry_after {
+            Some(retry_after) => Either::Left(ok(
+                req.into_response(RateLimit::too_many_requests(retry_after))
+            )),
+            None => Either::Right(Box::pin(self.service.call(req))),
+        }
+    }
+
+    fn call_by_address(
+        &self,
+        req: ServiceRequest,
+        limiter: Data<RateLimiter>,
+    ) -> RateLimitFuture<S::Error> {
+        let Some(key) = req.peer_addr().map(|addr| RateLimitKey::Ip(addr.ip())) else {
+            return Either::Right(Box::pin(self.service.call(req)));
+        };
+        let kind = RequestKind::of(req.method());
+        if let Some(retry_after) = limiter.wait_time(&key, kind, Instant::now()) {
+            return Either::Left(ok(
+                req.into_response(RateLimit::too_many_requests(retry_after))
+            ));
+        }
+
+        let response = self.service.call(req);
+        Either::Right(Box::pin(async move {
+            let response = response.await?;
+            // Only failures spend the budget, clients sharing an address with others
+            // are limited by principal once authenticated
+            if response.status() == StatusCode::UNAUTHORIZED {
+                let _ = limiter.acquire(key, kind, Instant::now());
+            }
+            Ok(response)
+        }))
+    }
+}
+
+impl<S> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = S::Error;
+    type Future = RateLimitFuture<S::Error>;
+
+    fn poll_ready(&self, ct: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ct)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let Some(limiter) = req.app_data::<Data<RateLimiter>>().cloned() else {
+            return Either::Right(Box::pin(self.service.call(req)));
+        };
+        if self.by_principal {
+            self.call_by_principal(req, &limiter)
+        } else {
+            self.call_by_address(req, limiter)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use actix_web::http::Method;
+
+    use super::{RateBudget, RateLimitKey, RateLimiter, RequestKind};
+    use crate::settings::RateLimitSettings;
+
+    fn key(name: &str) -> RateLimitKey {
+        RateLimitKey::Principal(String::from(name))
+    }
+
+    #[test]
+    fn wait_time_takes_no_token() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        assert_eq!(limiter.wait_time(&key("a"), RequestKind::Write, now), None);
+        for _ in 0..3 {
+            assert!(limiter.acquire(key("a"), RequestKind::Write, now).is_ok());
+        }
+        assert_eq!(
+            limiter.wait_time(&key("a"), RequestKind::Write, now),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(limiter.wait_time(&key("a"), RequestKind::Read, now), None);
+    }
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(
+            None,
+            Some(RateBudget {
+                per_second: 2.0,
+                burst: 3.0,
+            }),
+        )
+    }
+
+    #[test]
+    fn request_kind_of_method() {
+        assert_eq!(RequestKind::of(&Method::GET), RequestKind::Read);
+        assert_eq!(RequestKind::of(&Method::POST), RequestKind::Write);
+        assert_eq!(RequestKind::of(&Method::DELETE), RequestKind::Write);
+    }
+
+    #[test]
+    fn acquire_up_to_burst() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.acquire(key("a"), RequestKind::Write, now).is_ok());
+        }
+        assert_eq!(
+            limiter.acquire(key("a"), RequestKind::Write, now),
+            Err(Duration::from_millis(500))
+        );
+        // other clients and reads have their own budget
+        assert!(limiter.acquire(key("b"), RequestKind::Write, now).is_ok());
+        assert!(limiter.acquire(key("a"), RequestKind::Read, now).is_ok());
+    }
+
+    #[test]
+    fn acquire_after_refill() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.acquire(key("a"), RequestKind::Write, now).is_ok());
+        }
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.acquire(key("a"), RequestKind::Write, later).is_ok());
+        assert!(limiter
+            .acquire(key("a"), RequestKind::Write, later)
+            .is_err());
+
+        // tokens don't accumulate past the burst
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter
+                .acquire(key("a"), RequestKind::Write, much_later)
+                .is_ok());
+        }
+        assert!(limiter
+            .acquire(key("a"), RequestKind::Write, much_later)
+            .is_err());
+    }
+
+    #[test]
+    fn budgets_from_settings() {
+        let settings = RateLimitSettings {
+            read_per_second: Some(0.5),
+            write_per_second: Some(5.0),
+            write_burst: Some(10.0),
+            ..Default::default()
+        };
+        let limiter = RateLimiter::from_settings(&settings).unwrap();
+        assert_eq!(
+            limiter.read,
+            Some(RateBudget {
+                per_second: 0.5,
+                burst: 1.0
+            })
+        );
+        assert_eq!(
+            limiter.write,
+            Some(RateBudget {
+                per_second: 5.0,
+                burst: 10.0
+            })
+        );
+
+        let settings = RateLimitSettings {
+            write_per_second: Some(5.0),
+            write_burst: Some(0.5),
+            ..Default::default()
+        };
+        assert_eq!(
+            RateLimiter::from_settings(&settings)
+                .err()
+                .map(|e| e.to_string()),
+            Some(String::from("rate_limit.write_burst (SCHEMA_REGISTRY_RATE_LIMIT_WRITE_BURST) must be at least 1"))
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::StatusCode;
+use actix_web::{Error, HttpMessage};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::task::{Context, Poll};
+use sentry::protocol::{Event, SpanStatus, Value};
+use sentry::{Hub, Level, SentryFutureExt, TransactionContext};
+
+use crate::api::errors::ApiError;
+use crate::middleware::RequestId;
+
+/// Reports the server errors of requests to Sentry, with the queries they ran as
+/// breadcrumbs, and samples requests as Sentry transactions.
+///
+/// Every request gets its own Sentry hub, tagged with its request ID, route and subject,
+/// so that panics are reported with them too. It has to be wrapped before
+/// [`super::AssignRequestId`], and after [`super::JsonErrors`] to see its responses.
+pub struct ReportErrors;
+
+impl ReportErrors {
+    /// Captures a server error, along with the code and cause of an `ApiError`.
+    fn report(error: &Error) {
+        if !error.as_response_error().status_code().is_server_error() {
+            return;
+        }
+
+        let mut event = Event {
+            level: Level::Error,
+            message: Some(error.to_string()),
+            ..Default::default()
+        };
+        if let Some(error) = error.as_error::<ApiError>() {
+            event.message = Some(error.response.message.clone());
+            event.tags.insert(
+                String::from("error_code"),
+                (error.response.error_code as u16).to_string(),
+            );
+            if let Some(cause) = &error.cause {
+                event
+                    .extra
+                    .insert(String::from("cause"), Value::from(cause.as_str()));
+            }
+        }
+        Hub::current().capture_event(event);
+    }
+
+    fn span_status(status: StatusCode) -> SpanStatus {
+        match status {
+            StatusCode::UNAUTHORIZED => SpanStatus::Unauthenticated,
+            StatusCode::FORBIDDEN => SpanStatus::PermissionDenied,
+            StatusCode::NOT_FOUND => SpanStatus::NotFound,
+            StatusCode::CONFLICT => SpanStatus::AlreadyExists,
+            StatusCode::TOO_MANY_REQUESTS => SpanStatus::ResourceExhausted,
+            status if status.is_client_error() => SpanStatus::InvalidArgument,
+            status if status.is_server_error() => SpanStatus::InternalError,
+            _ => SpanStatus::Ok,
+        }
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for ReportErrors
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ReportErrorsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ReportErrorsMiddleware { service })
+    }
+}
+
+pub struct ReportErrorsMiddleware<S> {
+    service: S,
+}
+
+impl<S> Service<ServiceRequest> for ReportErrorsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ct: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ct)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| req.path().to_string());
+        let hub = Arc::new(Hub::new_from_top(Hub::current()));
+        let transaction = hub.start_transaction(TransactionContext::new(
+            &format!("{} {}", req.method(), route),
+            "http.server",
+        ));
+        hub.configure_scope(|scope| {
+            scope.set_tag("route", &route);
+            if let Some(request_id) = req.extensions().get::<RequestId>() {
+                scope.set_tag("request_id", request_id);
+            }
+            scope.set_span(Some(transaction.clone().into()));
+        });
+
+        let response = Hub::run(hub.clone(), || self.service.call(req));
+        Box::pin(
+            async move {
+                let response = response.await;
+                let status = match &response {
+                    Ok(res) => {
+                        if let Some(subject) = res.request().match_info().get("subject") {
+                            Hub::current()
+                                .configure_scope(|scope| scope.set_tag("subject", subject));
+                            transaction.set_tag("subject", subject);
+                        }
+                        if let Some(error) = res.response().error() {
+                            ReportErrors::report(error);
+                        }
+                        res.status()
+                    }
+                    Err(e) => {
+                        ReportErrors::report(e);
+                        e.as_response_error().status_code()
+                    }
+                };
+                transaction.set_status(ReportErrors::span_status(status));
+                transaction.finish();
+                response
+            }
+            .bind_hub(hub),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReportErrors;
+    use crate::api::errors::{ApiAvroErrorCode, ApiError};
+    use actix_web::{error, Error};
+    use sentry::protocol::Value;
+
+    #[test]
+    fn report_errors_captures_internal_errors() {
+        let events = sentry::test::with_captured_events(|| {
+            ReportErrors::report(&Error::from(ApiError::internal(
+                ApiAvroErrorCode::BackendDatastoreError,
+                "no pool",
+            )));
+            ReportErrors::report(&Error::from(ApiError::new(
+                ApiAvroErrorCode::SubjectNotFound,
+            )));
+            ReportErrors::report(&error::ErrorServiceUnavailable("shutting down"));
+            ReportErrors::report(&error::ErrorBadRequest("bad payload"));
+        });
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0].message.as_deref(),
+            Some("Error in the backend datastore")
+        );
+        assert_eq!(events[0].tags["error_code"], "50001");
+        assert_eq!(events[0].extra["cause"], Value::from(r#""no pool""#));
+        assert_eq!(events[1].message.as_deref(), Some("shutting down"));
+        assert!(!events[1].tags.contains_key("error_code"));
+    }
+}
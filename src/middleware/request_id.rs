@@ -0,0 +1,150 @@
+use std::fmt;
+use std::sync::Arc;
+
+use actix_web::dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::task::{Context, Poll};
+use uuid::Uuid;
+
+use crate::api::errors::ApiError;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Longest request ID accepted from clients, longer ones are replaced.
+const MAX_LENGTH: usize = 128;
+
+/// Identifies a request in responses, logs, traces, audit events and Sentry events.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestId(Arc<str>);
+
+impl RequestId {
+    /// The ID given by the client, as long as it is printable ASCII of a sane length.
+    pub(crate) fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?.trim();
+        let valid = !value.is_empty()
+            && value.len() <= MAX_LENGTH
+            && value.bytes().all(|c| c.is_ascii_graphic());
+        valid.then(|| Self(value.into()))
+    }
+
+    fn generate() -> Self {
+        Self(Uuid::new_v4().to_string().into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    /// Falls back to a new ID outside of [`AssignRequestId`], so that it never fails.
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ok(req
+            .extensions()
+            .get::<Self>()
+            .cloned()
+            .unwrap_or_else(Self::generate))
+    }
+}
+
+/// Gives every request the ID of its `X-Request-Id` header, or a new one, and echoes it
+/// in the response.
+///
+/// It has to be wrapped last, so that the other middleware see the ID.
+pub struct AssignRequestId;
+
+impl<S> Transform<S, ServiceRequest> for AssignRequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AssignRequestIdMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AssignRequestIdMiddleware { service })
+    }
+}
+
+pub struct AssignRequestIdMiddleware<S> {
+    service: S,
+}
+
+impl<S> Service<ServiceRequest> for AssignRequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ct: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ct)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(RequestId::from_header)
+            .unwrap_or_else(RequestId::generate);
+        let header = HeaderValue::from_str(request_id.as_str()).ok();
+        // Requests forwarded to the leader keep the ID
+        if let Some(header) = &header {
+            req.headers_mut()
+                .insert(HeaderName::from_static("x-request-id"), header.clone());
+        }
+        req.extensions_mut().insert(request_id);
+
+        let response = self.service.call(req);
+        Box::pin(async move {
+            let mut response = response.await?;
+            if let Some(header) = header {
+                response
+                    .headers_mut()
+                    .insert(HeaderName::from_static("x-request-id"), header);
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestId;
+    use actix_web::http::header::HeaderValue;
+
+    #[test]
+    fn request_id_from_header() {
+        let request_id = RequestId::from_header(&HeaderValue::from_static(" ticket-42 ")).unwrap();
+        assert_eq!(request_id.as_str(), "ticket-42");
+
+        assert_eq!(RequestId::from_header(&HeaderValue::from_static("")), None);
+        assert_eq!(
+            RequestId::from_header(&HeaderValue::from_static("two words")),
+            None
+        );
+        let too_long = HeaderValue::from_str(&"a".repeat(129)).unwrap();
+        assert_eq!(RequestId::from_header(&too_long), None);
+    }
+
+    #[test]
+    fn request_id_generated() {
+        assert_ne!(RequestId::generate(), RequestId::generate());
+    }
+}
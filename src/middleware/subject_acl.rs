@@ -0,0 +1,97 @@
+use std::rc::Rc;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::web::Data;
+use actix_web::{HttpMessage, ResponseError};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::task::{Context, Poll};
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::auth::{Acl, DenyByDefault, Principal, Role};
+use crate::db::models::AclRule;
+use crate::db::{DbManage, DbPool};
+
+/// Rejects requests on a subject the principal's ACL rules don't give them access to.
+///
+/// It has to wrap routes with a `{subject}` path parameter, as the parameter is only
+/// known once the route matched. Like [`Authorize`], it relies on the principal
+/// identified by [`VerifyAuthorization`].
+///
+/// [`Authorize`]: super::Authorize
+/// [`VerifyAuthorization`]: super::VerifyAuthorization
+pub struct SubjectAcl;
+
+impl<S> Transform<S, ServiceRequest> for SubjectAcl
+where
+    S: Service<ServiceRequest, Response = ServiceResponse> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = S::Error;
+    type InitError = ();
+    type Transform = SubjectAclMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(SubjectAclMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+pub struct SubjectAclMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S> Service<ServiceRequest> for SubjectAclMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = S::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ct: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ct)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let subject = req.match_info().get("subject").map(str::to_string);
+        let principal = req.extensions().get::<Principal>().cloned();
+        let db = req.app_data::<Data<DbPool>>().cloned();
+        let deny_by_default = req.app_data::<Data<DenyByDefault>>().is_some();
+        let required = Role::required_for(req.method());
+
+        Box::pin(async move {
+            let subject = match subject {
+                Some(subject) => subject,
+                None => return service.call(req).await,
+            };
+
+            let allowed = match (principal, db) {
+                (Some(principal), Some(db)) => {
+                    let name = principal.name.to_owned();
+                    db.run(move |conn| AclRule::for_principal(conn, &name))
+                        .await
+                        .map(|rules| {
+                            Acl::new(&principal, &rules, deny_by_default)
+                                .permits(&subject, required)
+                        })
+                }
+                (None, _) => Ok(false),
+                (_, None) => Err(ApiError::new(ApiAvroErrorCode::BackendDatastoreError)),
+            };
+
+            match allowed {
+                Ok(true) => service.call(req).await,
+                Ok(false) => {
+                    let error = ApiError::new(ApiAvroErrorCode::UserDeniedOperation);
+                    Ok(req.into_response(error.error_response()))
+                }
+                Err(e) => Ok(req.error_response(e)),
+            }
+        })
+    }
+}
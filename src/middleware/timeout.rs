@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::rt::time::timeout;
+use actix_web::web::Data;
+use actix_web::Error;
+use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::task::{Context, Poll};
+
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::settings::{ServerSettings, SettingsError};
+
+/// How long a request can take before the registry gives up on it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RequestTimeout(pub Duration);
+
+impl RequestTimeout {
+    /// The timeout of the settings, if any. Requests are never cut short without one.
+    pub fn from_settings(settings: &ServerSettings) -> Result<Option<Self>, SettingsError> {
+        match settings.request_timeout_ms {
+            None => Ok(None),
+            Some(0) => Err(SettingsError::Invalid(
+                "server.request_timeout_ms",
+                "positive",
+            )),
+            Some(millis) => Ok(Some(Self(Duration::from_millis(millis)))),
+        }
+    }
+}
+
+/// Answers with `OperationTimedOut` when a request takes longer than the
+/// [`RequestTimeout`] in the app data, if any.
+///
+/// Work already handed to the blocking thread pool still runs to completion, but a
+/// statement timeout on the database bounds how long that can be.
+pub struct Timeout;
+
+impl<S> Transform<S, ServiceRequest> for Timeout
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = TimeoutMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(TimeoutMiddleware { service })
+    }
+}
+
+pub struct TimeoutMiddleware<S> {
+    service: S,
+}
+
+impl<S> Service<ServiceRequest> for TimeoutMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ct: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ct)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let limit = match req.app_data::<Data<RequestTimeout>>() {
+            Some(limit) => limit.0,
+            None => return Box::pin(self.service.call(req)),
+        };
+
+        let response = self.service.call(req);
+        Box::pin(async move {
+            match timeout(limit, response).await {
+                Ok(response) => response,
+                // The request went along with the abandoned call, see `JsonErrors`
+                Err(_) => Err(ApiError::new(ApiAvroErrorCode::OperationTimedOut).into()),
+            }
+        })
+    }
+}
@@ -0,0 +1,127 @@
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::HeaderMap;
+use actix_web::http::StatusCode;
+use actix_web::{Error, HttpMessage};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::task::{Context, Poll};
+use opentelemetry::global;
+use opentelemetry::propagation::Extractor;
+use std::time::Instant;
+use tracing::{field, info, info_span, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::middleware::RequestId;
+
+/// Runs every request in a span, child of the trace given in its `traceparent` header if
+/// any, and logs it once answered. The principal, subject and status are filled in as
+/// they become known, every log line of the request carries them.
+///
+/// Spans are only exported when tracing was set up with [`crate::telemetry::Telemetry`].
+pub struct Trace;
+
+impl<S> Transform<S, ServiceRequest> for Trace
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = TraceMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(TraceMiddleware { service })
+    }
+}
+
+pub struct TraceMiddleware<S> {
+    service: S,
+}
+
+impl<S> Service<ServiceRequest> for TraceMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, ct: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ct)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // Spans can't be renamed once started, so the route is looked up before routing
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| req.path().to_string());
+        let span = info_span!(
+            "HTTP request",
+            otel.name = format!("{} {}", req.method(), route),
+            otel.kind = "server",
+            otel.status_code = field::Empty,
+            http.request.method = %req.method(),
+            http.route = route,
+            http.response.status_code = field::Empty,
+            url.path = req.path(),
+            request_id = field::Empty,
+            principal = field::Empty,
+            subject = field::Empty,
+        );
+        if let Some(request_id) = req.extensions().get::<RequestId>() {
+            span.record("request_id", request_id.as_str());
+        }
+        let parent = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(req.headers()))
+        });
+        // Without a parent, the span starts a new trace
+        let _ = span.set_parent(parent);
+
+        let start = Instant::now();
+        let response = span.in_scope(|| self.service.call(req));
+        Box::pin(
+            async move {
+                let response = response.await;
+                let span = Span::current();
+                let status = match &response {
+                    Ok(res) => {
+                        if let Some(subject) = res.request().match_info().get("subject") {
+                            span.record("subject", subject);
+                        }
+                        res.status()
+                    }
+                    Err(e) => e.as_response_error().status_code(),
+                };
+                record_status(&span, status);
+                info!(
+                    status = status.as_u16(),
+                    latency_ms = start.elapsed().as_secs_f64() * 1000.0,
+                    "request answered"
+                );
+                response
+            }
+            .instrument(span),
+        )
+    }
+}
+
+fn record_status(span: &Span, status: StatusCode) {
+    span.record("http.response.status_code", status.as_u16());
+    if status.is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
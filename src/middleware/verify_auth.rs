@@ -1,66 +1,154 @@
 use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
-use actix_web::error::{Error, ErrorBadRequest, ErrorForbidden, ParseError};
-use actix_web::http::header::HeaderMap;
+use actix_web::http::header::{self, HeaderMap, HeaderValue};
+use actix_web::web::Data;
+use actix_web::{HttpMessage, HttpResponse, ResponseError};
 use base64::{engine::general_purpose::STANDARD as StandardEngine, Engine as _};
-use futures::future::{ok, Either, Ready};
+use futures::future::{ok, LocalBoxFuture, Ready};
 use futures::task::{Context, Poll};
+use std::rc::Rc;
+use std::sync::Arc;
+use tracing::Span;
 
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+use crate::auth::{
+    ForwardingKey, JwtVerifier, Principal, UserStore, API_KEY_PREFIX, FORWARDED_PRINCIPAL_HEADER,
+};
+use crate::db::models::ApiKey;
+use crate::db::{DbManage, DbPool};
+use crate::tls::ClientIdentity;
+
+/// Identifies the principal behind each request, from basic auth credentials (a user's,
+/// or an API key's `key_id:secret`) or, when a [`JwtVerifier`] is configured, from a
+/// bearer token.
+///
+/// Over mutual TLS, a client certificate issued for a user of the users file
+/// authenticates the request as that user, whatever its `Authorization` header.
+///
+/// On a leader sharing a [`ForwardingKey`] with its followers, requests they forward are
+/// authenticated as the principal they pass along.
 pub struct VerifyAuthorization {
-    password: String,
+    users: Arc<UserStore>,
+    jwt: Option<Arc<JwtVerifier>>,
+    forwarding: Option<Arc<ForwardingKey>>,
 }
 
 impl VerifyAuthorization {
-    pub fn new(password: &str) -> Self {
+    pub fn new(users: Arc<UserStore>) -> Self {
         Self {
-            password: password.to_string(),
+            users,
+            jwt: None,
+            forwarding: None,
         }
     }
 
-    fn validate(headers: &HeaderMap, password: &str) -> Result<(), Error> {
+    /// Also accepts `Authorization: Bearer <jwt>` headers, verified by `jwt`.
+    pub fn with_jwt(mut self, jwt: Arc<JwtVerifier>) -> Self {
+        self.jwt = Some(jwt);
+        self
+    }
+
+    /// Also trusts the principals of requests forwarded by followers sharing `key`.
+    pub fn with_forwarding(mut self, key: Arc<ForwardingKey>) -> Self {
+        self.forwarding = Some(key);
+        self
+    }
+
+    /// Principal a follower forwarded the request on behalf of, `Err` if it can't be
+    /// trusted.
+    fn forwarded_principal(
+        headers: &HeaderMap,
+        forwarding: Option<&ForwardingKey>,
+    ) -> Option<Result<Principal, ApiError>> {
+        let (token, key) = (headers.get(FORWARDED_PRINCIPAL_HEADER)?, forwarding?);
+        Some(
+            token
+                .to_str()
+                .ok()
+                .and_then(|token| key.verify(token))
+                .ok_or_else(|| ApiError::new(ApiAvroErrorCode::Unauthenticated)),
+        )
+    }
+
+    /// Checks the credentials and returns the principal they belong to, or the API key
+    /// that is left to check against the database.
+    fn validate(
+        headers: &HeaderMap,
+        users: &UserStore,
+        jwt: Option<&JwtVerifier>,
+    ) -> Result<Credentials, ApiError> {
+        let unauthenticated = || ApiError::new(ApiAvroErrorCode::Unauthenticated);
         let authorization = headers
             .get("Authorization")
-            .ok_or_else(|| ErrorBadRequest(ParseError::Header))?
+            .ok_or_else(unauthenticated)?
             .to_str()
-            .map_err(ErrorBadRequest)?;
+            .map_err(|_| unauthenticated())?;
+
+        if let Some(token) = authorization.strip_prefix("Bearer ") {
+            return jwt
+                .and_then(|jwt| jwt.verify(token.trim()))
+                .map(Credentials::Principal)
+                .ok_or_else(unauthenticated);
+        }
 
         if authorization.len() < 7 {
             // 'Basic ' is 6 chars long, so anything below 7 is invalid
-            return Err(ErrorBadRequest(ParseError::Header));
+            return Err(unauthenticated());
         }
 
         let (basic, base64_auth) = authorization.split_at(6);
         if basic.ne("Basic ") {
-            return Err(ErrorBadRequest(ParseError::Header));
+            return Err(unauthenticated());
         }
 
         // Decode the base64 auth which will contain the username and password in the
-        // format of username:password (we ignore the username)
+        // format of username:password
         match StandardEngine.decode(base64_auth) {
             Ok(bytes) => {
-                let mut basic_creds = std::str::from_utf8(&bytes)?
+                let mut basic_creds = std::str::from_utf8(&bytes)
+                    .map_err(|_| unauthenticated())?
                     .trim_end_matches('\n')
                     .splitn(2, ':');
-                let _username = basic_creds
-                    .next()
-                    .ok_or_else(|| ErrorBadRequest(ParseError::Header))?;
+                let username = basic_creds.next().ok_or_else(unauthenticated)?;
 
-                let header_password = basic_creds
-                    .next()
-                    .ok_or_else(|| ErrorBadRequest(ParseError::Header))?;
+                let header_password = basic_creds.next().ok_or_else(unauthenticated)?;
 
-                if *header_password != *password {
-                    return Err(ErrorForbidden(ParseError::Header));
+                if username.starts_with(API_KEY_PREFIX) {
+                    return Ok(Credentials::ApiKey {
+                        key_id: username.to_string(),
+                        secret: header_password.to_string(),
+                    });
                 }
-                Ok(())
+
+                users
+                    .authenticate(username, header_password)
+                    .map(Credentials::Principal)
+                    .ok_or_else(unauthenticated)
             }
-            Err(_) => Err(ErrorBadRequest(ParseError::Header)),
+            Err(_) => Err(unauthenticated()),
         }
     }
+
+    /// Asks clients to retry with credentials, for those that only send them when asked,
+    /// like browsers.
+    fn unauthenticated() -> HttpResponse {
+        let mut response = ApiError::new(ApiAvroErrorCode::Unauthenticated).error_response();
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static("Basic realm=\"schema-registry\""),
+        );
+        response
+    }
+}
+
+#[derive(Debug)]
+enum Credentials {
+    Principal(Principal),
+    ApiKey { key_id: String, secret: String },
 }
 
 impl<S> Transform<S, ServiceRequest> for VerifyAuthorization
 where
-    S: Service<ServiceRequest, Response = ServiceResponse>,
+    S: Service<ServiceRequest, Response = ServiceResponse> + 'static,
     S::Future: 'static,
 {
     type Response = ServiceResponse;
@@ -71,42 +159,94 @@ where
 
     fn new_transform(&self, service: S) -> Self::Future {
         ok(VerifyAuthorizationMiddleware {
-            service,
-            password: self.password.clone(),
+            service: Rc::new(service),
+            users: self.users.clone(),
+            jwt: self.jwt.clone(),
+            forwarding: self.forwarding.clone(),
         })
     }
 }
 
 pub struct VerifyAuthorizationMiddleware<S> {
-    service: S,
-    password: String,
+    service: Rc<S>,
+    users: Arc<UserStore>,
+    jwt: Option<Arc<JwtVerifier>>,
+    forwarding: Option<Arc<ForwardingKey>>,
 }
 
 impl<S> Service<ServiceRequest> for VerifyAuthorizationMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse>,
+    S: Service<ServiceRequest, Response = ServiceResponse> + 'static,
     S::Future: 'static,
 {
     type Response = ServiceResponse;
     type Error = S::Error;
-    type Future = Either<Ready<Result<Self::Response, Self::Error>>, S::Future>;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
 
     fn poll_ready(&self, ct: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.service.poll_ready(ct)
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        match VerifyAuthorization::validate(req.headers(), &self.password) {
-            Ok(_) => Either::Right(self.service.call(req)),
-            Err(_) => Either::Left(ok(req.error_response(ParseError::Header))),
-        }
+        let service = Rc::clone(&self.service);
+        let forwarded_principal =
+            VerifyAuthorization::forwarded_principal(req.headers(), self.forwarding.as_deref());
+        let certificate_principal = req
+            .conn_data::<ClientIdentity>()
+            .and_then(|identity| identity.names.iter().find_map(|name| self.users.user(name)));
+        let headers = req.headers().clone();
+        let users = self.users.clone();
+        let jwt = self.jwt.clone();
+        let db = req.app_data::<Data<DbPool>>().cloned();
+
+        Box::pin(async move {
+            let credentials = match (forwarded_principal, certificate_principal) {
+                (Some(forwarded), _) => forwarded.map(Credentials::Principal),
+                (None, Some(principal)) => Ok(Credentials::Principal(principal)),
+                // Passwords are hashed, which is slow on purpose, so they are checked on
+                // the blocking thread pool
+                (None, None) => actix_threadpool::run(move || {
+                    VerifyAuthorization::validate(&headers, &users, jwt.as_deref())
+                })
+                .await
+                .map_err(ApiError::from),
+            };
+            let principal = match (credentials, db) {
+                (Ok(Credentials::Principal(principal)), _) => Some(principal),
+                (Ok(Credentials::ApiKey { key_id, secret }), Some(db)) => {
+                    // Hashing the secret is slow on purpose, so it is checked on the
+                    // blocking thread pool along with the query
+                    match db
+                        .run(move |conn| ApiKey::authenticate(conn, &key_id, &secret))
+                        .await
+                    {
+                        Ok(principal) => principal,
+                        Err(e) => return Ok(req.error_response(e)),
+                    }
+                }
+                _ => None,
+            };
+
+            match principal {
+                Some(principal) => {
+                    Span::current().record("principal", principal.name.as_str());
+                    req.extensions_mut().insert(principal);
+                    service.call(req).await
+                }
+                None => Ok(req.into_response(VerifyAuthorization::unauthenticated())),
+            }
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::VerifyAuthorization;
+    use super::{Credentials, VerifyAuthorization};
+    use crate::auth::{
+        ForwardingKey, JwtVerifier, Principal, Role, UserStore, FORWARDED_PRINCIPAL_HEADER,
+    };
     use actix_web::http::header::{self, HeaderMap, HeaderValue};
+    use jsonwebtoken::{encode, EncodingKey, Header};
 
     const VALID_PASSWORD: &str = "some_password";
     const INVALID_PASSWORD: &str = "some_invalid_password";
@@ -119,7 +259,12 @@ mod tests {
             header::AUTHORIZATION,
             HeaderValue::from_static(CORRECT_AUTH),
         );
-        assert!(VerifyAuthorization::validate(&headers, VALID_PASSWORD).is_ok());
+        assert!(VerifyAuthorization::validate(
+            &headers,
+            &UserStore::with_password(VALID_PASSWORD),
+            None
+        )
+        .is_ok());
     }
 
     #[test]
@@ -129,33 +274,164 @@ mod tests {
             header::AUTHORIZATION,
             HeaderValue::from_static(CORRECT_AUTH),
         );
-        assert!(VerifyAuthorization::validate(&headers, INVALID_PASSWORD).is_err());
+        assert!(VerifyAuthorization::validate(
+            &headers,
+            &UserStore::with_password(INVALID_PASSWORD),
+            None
+        )
+        .is_err());
     }
 
     #[test]
     fn middleware_with_malformed_header() {
         let headers = HeaderMap::new();
-        assert!(VerifyAuthorization::validate(&headers, VALID_PASSWORD).is_err());
+        assert!(VerifyAuthorization::validate(
+            &headers,
+            &UserStore::with_password(VALID_PASSWORD),
+            None
+        )
+        .is_err());
     }
 
     #[test]
     fn middleware_with_malformed_header_content() {
         let mut headers = HeaderMap::new();
         headers.insert(header::AUTHORIZATION, HeaderValue::from_static("bad"));
-        assert!(VerifyAuthorization::validate(&headers, VALID_PASSWORD).is_err());
+        assert!(VerifyAuthorization::validate(
+            &headers,
+            &UserStore::with_password(VALID_PASSWORD),
+            None
+        )
+        .is_err());
     }
 
     #[test]
     fn middleware_with_wrong_content_length() {
         let mut headers = HeaderMap::new();
         headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Basic "));
-        assert!(VerifyAuthorization::validate(&headers, VALID_PASSWORD).is_err());
+        assert!(VerifyAuthorization::validate(
+            &headers,
+            &UserStore::with_password(VALID_PASSWORD),
+            None
+        )
+        .is_err());
     }
 
     #[test]
     fn middleware_with_bad_base64() {
         let mut headers = HeaderMap::new();
         headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Basic meh"));
-        assert!(VerifyAuthorization::validate(&headers, VALID_PASSWORD).is_err());
+        assert!(VerifyAuthorization::validate(
+            &headers,
+            &UserStore::with_password(VALID_PASSWORD),
+            None
+        )
+        .is_err());
+    }
+
+    fn bearer(secret: &[u8]) -> HeaderMap {
+        let claims = serde_json::json!({
+            "sub": "some_service",
+            "iss": "issuer",
+            "aud": "audience",
+            "exp": chrono::Utc::now().timestamp() + 300,
+            "roles": "reader",
+        });
+        let token = encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret),
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token.unwrap())).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn middleware_with_valid_bearer_token() {
+        let jwt = JwtVerifier::new("issuer", "audience").with_hs256_secret(b"secret");
+        let credentials = VerifyAuthorization::validate(
+            &bearer(b"secret"),
+            &UserStore::with_password(VALID_PASSWORD),
+            Some(&jwt),
+        );
+        match credentials.unwrap() {
+            Credentials::Principal(principal) => assert_eq!(principal.name, "some_service"),
+            credentials => panic!("unexpected credentials {:?}", credentials),
+        }
+    }
+
+    #[test]
+    fn middleware_with_invalid_bearer_token() {
+        let jwt = JwtVerifier::new("issuer", "audience").with_hs256_secret(b"secret");
+        assert!(VerifyAuthorization::validate(
+            &bearer(b"another_secret"),
+            &UserStore::with_password(VALID_PASSWORD),
+            Some(&jwt),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn middleware_with_bearer_token_without_jwt() {
+        assert!(VerifyAuthorization::validate(
+            &bearer(b"secret"),
+            &UserStore::with_password(VALID_PASSWORD),
+            None,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn middleware_with_api_key() {
+        // ak_0123456789abcdef:some_secret
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Basic YWtfMDEyMzQ1Njc4OWFiY2RlZjpzb21lX3NlY3JldA=="),
+        );
+        // the shared password never authenticates API keys, their secret is checked later
+        match VerifyAuthorization::validate(
+            &headers,
+            &UserStore::with_password("some_secret"),
+            None,
+        )
+        .unwrap()
+        {
+            Credentials::ApiKey { key_id, secret } => {
+                assert_eq!(key_id, "ak_0123456789abcdef");
+                assert_eq!(secret, "some_secret");
+            }
+            credentials => panic!("unexpected credentials {:?}", credentials),
+        }
+    }
+
+    #[test]
+    fn middleware_with_forwarded_principal() {
+        let key = ForwardingKey::new(b"shared");
+        let principal = Principal {
+            name: String::from("service.example.com"),
+            role: Role::Writer,
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::HeaderName::from_static(FORWARDED_PRINCIPAL_HEADER),
+            HeaderValue::from_str(&key.sign(&principal).unwrap()).unwrap(),
+        );
+
+        let forwarded = VerifyAuthorization::forwarded_principal(&headers, Some(&key));
+        assert_eq!(forwarded.unwrap().unwrap().name, "service.example.com");
+        let another = ForwardingKey::new(b"another");
+        assert!(
+            VerifyAuthorization::forwarded_principal(&headers, Some(&another))
+                .unwrap()
+                .is_err()
+        );
+        // without a key of its own, the registry isn't a leader and ignores the header
+        assert!(VerifyAuthorization::forwarded_principal(&headers, None).is_none());
     }
 }
@@ -1,34 +1,124 @@
 use actix_web::{
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    error::ParseError,
-    http,
+    http::header::{self, HeaderMap, HeaderValue},
+    ResponseError,
 };
-use futures::future::{ok, Either, Ready};
+use futures::future::{ok, LocalBoxFuture, Ready};
 use futures::task::{Context, Poll};
 
+use crate::api::errors::{ApiAvroErrorCode, ApiError};
+
+/// Negotiates the media type of responses from the `Accept` header, and rejects request
+/// bodies that aren't JSON.
+///
+/// JSON responses are sent with the negotiated media type, which is the registry's own
+/// when clients accept anything, or don't say what they accept.
 pub struct VerifyAcceptHeader;
 
-const VALID_ACCEPT_HEADERS: [&str; 3] = [
-    "application/vnd.schemaregistry+json",
+/// Media types responses can be sent as, from the most to the least preferred.
+const MEDIA_TYPES: [&str; 3] = [
     "application/vnd.schemaregistry.v1+json",
+    "application/vnd.schemaregistry+json",
     "application/json",
 ];
 
+/// A media range of an `Accept` header, such as `application/*;q=0.5`.
+struct MediaRange<'a> {
+    type_: &'a str,
+    subtype: &'a str,
+    quality: f32,
+}
+
+impl<'a> MediaRange<'a> {
+    fn parse(range: &'a str) -> Option<Self> {
+        let mut parts = range.split(';');
+        let (type_, subtype) = parts.next()?.trim().split_once('/')?;
+        let mut quality = 1.0;
+        for param in parts {
+            let (name, value) = param.split_once('=')?;
+            if name.trim().eq_ignore_ascii_case("q") {
+                quality = value
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|quality| (0.0..=1.0).contains(quality))?;
+            }
+        }
+
+        Some(Self {
+            type_: type_.trim(),
+            subtype: subtype.trim(),
+            quality,
+        })
+    }
+
+    /// How specifically the range matches `media_type`, if it does at all: `*/*` is
+    /// less specific than `application/*`, which is less specific than the media type.
+    fn specificity(&self, media_type: &str) -> Option<u8> {
+        let (type_, subtype) = media_type.split_once('/')?;
+        match (self.type_, self.subtype) {
+            ("*", "*") => Some(0),
+            (t, "*") if t.eq_ignore_ascii_case(type_) => Some(1),
+            (t, s) if t.eq_ignore_ascii_case(type_) && s.eq_ignore_ascii_case(subtype) => Some(2),
+            _ => None,
+        }
+    }
+}
+
 impl VerifyAcceptHeader {
-    fn is_valid(headers: &http::header::HeaderMap) -> bool {
-        match headers.get(http::header::ACCEPT) {
-            Some(v) => match v.to_str() {
-                Ok(s) => VALID_ACCEPT_HEADERS.iter().any(|h| *h == s),
-                _ => false,
-            },
-            None => false,
+    /// Picks the media type of the response, as described in RFC 7231: each media type
+    /// gets the quality of the most specific valid range matching it, and the best one
+    /// that is acceptable wins. `None` if no media type is.
+    fn negotiate(headers: &HeaderMap) -> Option<&'static str> {
+        let accept = match headers.get(header::ACCEPT) {
+            Some(accept) => accept.to_str().ok()?,
+            None => return Some(MEDIA_TYPES[0]),
+        };
+        // Invalid ranges are ignored, the others are enough to negotiate
+        let ranges = accept
+            .split(',')
+            .filter_map(MediaRange::parse)
+            .collect::<Vec<_>>();
+
+        let mut negotiated = None;
+        let mut best = 0.0;
+        for media_type in MEDIA_TYPES {
+            let quality = ranges
+                .iter()
+                .filter_map(|range| Some((range.specificity(media_type)?, range.quality)))
+                .max_by_key(|(specificity, _)| *specificity)
+                .map_or(0.0, |(_, quality)| quality);
+            if quality > best {
+                negotiated = Some(media_type);
+                best = quality;
+            }
+        }
+        negotiated
+    }
+
+    /// Whether the request body, if any, is JSON: either `application/json` or any
+    /// `application/*+json` media type.
+    fn has_json_body(headers: &HeaderMap) -> bool {
+        let content_type = match headers.get(header::CONTENT_TYPE) {
+            Some(content_type) => content_type.to_str().unwrap_or_default(),
+            None => return true,
+        };
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        match essence.split_once('/') {
+            Some(("application", subtype)) => subtype == "json" || subtype.ends_with("+json"),
+            _ => false,
         }
     }
 }
 
 impl<S> Transform<S, ServiceRequest> for VerifyAcceptHeader
 where
-    S: Service<ServiceRequest, Response = ServiceResponse>,
+    S: Service<ServiceRequest, Response = ServiceResponse> + 'static,
     S::Future: 'static,
 {
     type Response = ServiceResponse;
@@ -48,22 +138,44 @@ pub struct VerifyAcceptHeaderMiddleware<S> {
 
 impl<S> Service<ServiceRequest> for VerifyAcceptHeaderMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse>,
+    S: Service<ServiceRequest, Response = ServiceResponse> + 'static,
     S::Future: 'static,
 {
     type Response = ServiceResponse;
     type Error = S::Error;
-    type Future = Either<Ready<Result<Self::Response, Self::Error>>, S::Future>;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
 
     fn poll_ready(&self, ct: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.service.poll_ready(ct)
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        if VerifyAcceptHeader::is_valid(req.headers()) {
-            return Either::Right(self.service.call(req));
+        let media_type = match VerifyAcceptHeader::negotiate(req.headers()) {
+            Some(media_type) => media_type,
+            None => {
+                let error = ApiError::new(ApiAvroErrorCode::NotAcceptable);
+                return Box::pin(ok(req.into_response(error.error_response())));
+            }
+        };
+        if !VerifyAcceptHeader::has_json_body(req.headers()) {
+            let error = ApiError::new(ApiAvroErrorCode::UnsupportedMediaType);
+            return Box::pin(ok(req.into_response(error.error_response())));
         }
-        Either::Left(ok(req.error_response(ParseError::Header)))
+
+        let response = self.service.call(req);
+        Box::pin(async move {
+            let mut response = response.await?;
+            let is_json = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .is_some_and(|content_type| content_type == "application/json");
+            if is_json {
+                response
+                    .headers_mut()
+                    .insert(header::CONTENT_TYPE, HeaderValue::from_static(media_type));
+            }
+            Ok(response)
+        })
     }
 }
 
@@ -72,23 +184,119 @@ mod tests {
     use super::VerifyAcceptHeader;
     use actix_web::http::header::{self, HeaderMap, HeaderValue};
 
+    fn accept(value: &'static str) -> HeaderMap {
+        let mut hm = HeaderMap::new();
+        hm.insert(header::ACCEPT, HeaderValue::from_static(value));
+        hm
+    }
+
+    fn content_type(value: &'static str) -> HeaderMap {
+        let mut hm = HeaderMap::new();
+        hm.insert(header::CONTENT_TYPE, HeaderValue::from_static(value));
+        hm
+    }
+
     #[test]
     fn middleware_accept_header_is_invalid() {
-        let mut hm = HeaderMap::new();
-        hm.insert(header::ACCEPT, HeaderValue::from_static("invalid"));
-        assert!(!VerifyAcceptHeader::is_valid(&hm));
+        assert_eq!(VerifyAcceptHeader::negotiate(&accept("invalid")), None);
+        assert_eq!(VerifyAcceptHeader::negotiate(&accept("text/html")), None);
+        assert_eq!(
+            VerifyAcceptHeader::negotiate(&accept("application/json;q=2")),
+            None
+        );
+    }
+
+    #[test]
+    fn middleware_accept_header_with_invalid_ranges() {
+        assert_eq!(
+            VerifyAcceptHeader::negotiate(&accept("invalid, application/json")),
+            Some("application/json")
+        );
+        assert_eq!(
+            VerifyAcceptHeader::negotiate(&accept(
+                "application/json;q=2, application/vnd.schemaregistry+json;q=0.5"
+            )),
+            Some("application/vnd.schemaregistry+json")
+        );
+        assert_eq!(
+            VerifyAcceptHeader::negotiate(&accept("text/html;level, */*;q=0.1")),
+            Some("application/vnd.schemaregistry.v1+json")
+        );
     }
 
     #[test]
     fn middleware_accept_header_missing() {
-        let hm = HeaderMap::new();
-        assert!(!VerifyAcceptHeader::is_valid(&hm));
+        assert_eq!(
+            VerifyAcceptHeader::negotiate(&HeaderMap::new()),
+            Some("application/vnd.schemaregistry.v1+json")
+        );
     }
 
     #[test]
     fn middleware_accept_header_is_valid() {
-        let mut hm = HeaderMap::new();
-        hm.insert(header::ACCEPT, HeaderValue::from_static("application/json"));
-        assert!(VerifyAcceptHeader::is_valid(&hm));
+        assert_eq!(
+            VerifyAcceptHeader::negotiate(&accept("application/json")),
+            Some("application/json")
+        );
+        assert_eq!(
+            VerifyAcceptHeader::negotiate(&accept("application/vnd.schemaregistry+json")),
+            Some("application/vnd.schemaregistry+json")
+        );
+    }
+
+    #[test]
+    fn middleware_accept_header_with_wildcards() {
+        assert_eq!(
+            VerifyAcceptHeader::negotiate(&accept("*/*")),
+            Some("application/vnd.schemaregistry.v1+json")
+        );
+        assert_eq!(
+            VerifyAcceptHeader::negotiate(&accept("text/html, application/*;q=0.1")),
+            Some("application/vnd.schemaregistry.v1+json")
+        );
+    }
+
+    #[test]
+    fn middleware_accept_header_with_qualities() {
+        assert_eq!(
+            VerifyAcceptHeader::negotiate(&accept(
+                "application/vnd.schemaregistry.v1+json, application/json;q=0.9"
+            )),
+            Some("application/vnd.schemaregistry.v1+json")
+        );
+        assert_eq!(
+            VerifyAcceptHeader::negotiate(&accept(
+                "application/vnd.schemaregistry.v1+json;q=0.5, application/json"
+            )),
+            Some("application/json")
+        );
+        // the most specific range wins, even with a lower quality
+        assert_eq!(
+            VerifyAcceptHeader::negotiate(&accept(
+                "*/*, application/vnd.schemaregistry.v1+json;q=0, application/vnd.schemaregistry+json;q=0"
+            )),
+            Some("application/json")
+        );
+        assert_eq!(
+            VerifyAcceptHeader::negotiate(&accept("application/json;q=0, text/*")),
+            None
+        );
+    }
+
+    #[test]
+    fn middleware_content_type_is_json() {
+        assert!(VerifyAcceptHeader::has_json_body(&HeaderMap::new()));
+        assert!(VerifyAcceptHeader::has_json_body(&content_type(
+            "application/json; charset=utf-8"
+        )));
+        assert!(VerifyAcceptHeader::has_json_body(&content_type(
+            "application/vnd.schemaregistry.v1+json"
+        )));
+        assert!(!VerifyAcceptHeader::has_json_body(&content_type(
+            "text/plain"
+        )));
+        assert!(!VerifyAcceptHeader::has_json_body(&content_type(
+            "application/xml"
+        )));
     }
 }
@@ -0,0 +1,752 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use serde::Deserialize;
+use toml::{Table, Value};
+
+use crate::api::SchemaBody;
+use crate::auth::{JwtConfigError, Role, UserStoreError};
+use crate::telemetry::LogFormat;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SettingsError {
+    #[error("could not read {0}: {1}")]
+    Io(PathBuf, io::Error),
+    #[error("invalid settings file {0}: {1}")]
+    File(PathBuf, toml::de::Error),
+    #[error("invalid settings: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("{} must be set", named(.0))]
+    Missing(&'static str),
+    #[error("{} must be {}", named(.0), .1)]
+    Invalid(&'static str, &'static str),
+    #[error(transparent)]
+    Users(#[from] UserStoreError),
+    #[error(transparent)]
+    Jwt(#[from] JwtConfigError),
+}
+
+/// Every setting of the registry, read from a TOML file, then the environment, then
+/// command line flags, each overriding the previous one.
+///
+/// Settings are only parsed here. Each part of the registry checks its own when it is
+/// built from them, which main does before starting the server.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub server: ServerSettings,
+    pub database: DatabaseSettings,
+    pub auth: AuthSettings,
+    pub tls: TlsSettings,
+    pub rate_limit: RateLimitSettings,
+    pub leader: LeaderSettings,
+    pub logging: LoggingSettings,
+    pub telemetry: TelemetrySettings,
+    pub sentry: SentrySettings,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    /// Address to listen on
+    pub listen: String,
+    /// Number of workers, one per CPU core when unset
+    pub workers: Option<usize>,
+    /// Largest request body in bytes, larger ones get a `413`
+    pub max_schema_size: usize,
+    /// Milliseconds before a request is answered with a `50002`, never when unset
+    pub request_timeout_ms: Option<u64>,
+    /// Milliseconds before `/admin/export` is cut short, so that a slow client doesn't
+    /// hold a database connection for longer
+    pub export_timeout_ms: u64,
+    /// Whether Prometheus metrics are served on `/_/metrics`
+    pub metrics: bool,
+    /// Seconds between refreshes of the registry gauges
+    pub metrics_refresh_seconds: u64,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            listen: String::from("127.0.0.1:8080"),
+            workers: None,
+            max_schema_size: SchemaBody::DEFAULT_MAX_SIZE,
+            request_timeout_ms: None,
+            export_timeout_ms: 600_000,
+            metrics: true,
+            metrics_refresh_seconds: 30,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSettings {
+    /// URL of the primary, required
+    pub url: Option<String>,
+    /// URL of the read replica, if any
+    pub read_url: Option<String>,
+    /// Idle connections kept open, the max size when unset
+    pub pool_min_idle: Option<u32>,
+    pub pool_max_size: u32,
+    /// Seconds to wait for a connection
+    pub pool_connection_timeout: u64,
+    /// Seconds before an idle connection is closed, 0 never does
+    pub pool_idle_timeout: u64,
+    /// Seconds to wait for a replica connection before reading from the primary
+    pub read_connection_timeout: u64,
+    /// Attempts at reaching the database before giving up on startup
+    pub startup_attempts: u32,
+    /// Pause after the first failed attempt, doubled after each one
+    pub startup_backoff_ms: u64,
+    /// Milliseconds before Postgres cancels a statement, 0 never does
+    pub statement_timeout_ms: u64,
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        Self {
+            url: None,
+            read_url: None,
+            pool_min_idle: None,
+            pool_max_size: 10,
+            pool_connection_timeout: 30,
+            pool_idle_timeout: 600,
+            read_connection_timeout: 2,
+            startup_attempts: 10,
+            startup_backoff_ms: 500,
+            statement_timeout_ms: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthSettings {
+    /// Password of a single admin principal, for setups without a users file
+    pub password: Option<String>,
+    /// Name of the principal authenticating with the shared password, `registry` when
+    /// unset
+    pub password_principal: Option<String>,
+    pub users_file: Option<PathBuf>,
+    /// Deny principals other than admins access to subjects none of their ACL rules
+    /// match, rather than letting them use their role there
+    pub acl_deny_by_default: bool,
+    pub jwt: JwtSettings,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtSettings {
+    pub hs256_secret: Option<String>,
+    pub jwks_file: Option<PathBuf>,
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    /// Claim holding the role, `roles` when unset
+    pub role_claim: Option<String>,
+    /// Values of the role claim, and the role they stand for
+    pub role_mapping: BTreeMap<String, Role>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsSettings {
+    /// Server certificate chain, serving over TLS when set
+    pub cert_file: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+    pub client_ca_file: Option<PathBuf>,
+    pub require_client_cert: bool,
+    /// Seconds between checks of the certificate files
+    pub reload_interval: u64,
+}
+
+impl Default for TlsSettings {
+    fn default() -> Self {
+        Self {
+            cert_file: None,
+            key_file: None,
+            client_ca_file: None,
+            require_client_cert: false,
+            reload_interval: 30,
+        }
+    }
+}
+
+/// Budgets of each client, unlimited unless their rate is set. Bursts default to the
+/// rate.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitSettings {
+    pub read_per_second: Option<f64>,
+    pub read_burst: Option<f64>,
+    pub write_per_second: Option<f64>,
+    pub write_burst: Option<f64>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeaderSettings {
+    /// URL of the leader, left unset on the leader itself
+    pub url: Option<String>,
+    pub timeout_ms: u64,
+    /// Secret the leader and its followers share, for the leader to trust the principals
+    /// of forwarded requests
+    pub secret: Option<String>,
+}
+
+impl Default for LeaderSettings {
+    fn default() -> Self {
+        Self {
+            url: None,
+            timeout_ms: 5000,
+            secret: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingSettings {
+    pub format: LogFormat,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetrySettings {
+    /// Base URL of the OTLP/HTTP collector, nothing is traced when unset
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: String::from("avro-schema-registry"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SentrySettings {
+    /// DSN of the Sentry project, nothing is reported when unset
+    pub url: Option<String>,
+    pub sample_rate: f32,
+    pub traces_sample_rate: f32,
+}
+
+impl Default for SentrySettings {
+    fn default() -> Self {
+        Self {
+            url: None,
+            sample_rate: 1.0,
+            traces_sample_rate: 0.0,
+        }
+    }
+}
+
+/// How the value of an environment variable is read.
+#[derive(Clone, Copy)]
+enum Kind {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    /// Comma separated `claim_value=role` pairs
+    RoleMapping,
+}
+
+/// Environment variables overriding the settings file, and the setting they override.
+const ENV_VARS: &[(&str, &str, Kind)] = &[
+    ("DEFAULT_HOST", "server.listen", Kind::String),
+    ("SCHEMA_REGISTRY_WORKERS", "server.workers", Kind::Integer),
+    (
+        "SCHEMA_REGISTRY_MAX_SCHEMA_SIZE",
+        "server.max_schema_size",
+        Kind::Integer,
+    ),
+    (
+        "SCHEMA_REGISTRY_REQUEST_TIMEOUT_MS",
+        "server.request_timeout_ms",
+        Kind::Integer,
+    ),
+    (
+        "SCHEMA_REGISTRY_EXPORT_TIMEOUT_MS",
+        "server.export_timeout_ms",
+        Kind::Integer,
+    ),
+    ("SCHEMA_REGISTRY_METRICS", "server.metrics", Kind::Boolean),
+    (
+        "SCHEMA_REGISTRY_METRICS_REFRESH_SECONDS",
+        "server.metrics_refresh_seconds",
+        Kind::Integer,
+    ),
+    ("DATABASE_URL", "database.url", Kind::String),
+    ("DATABASE_READ_URL", "database.read_url", Kind::String),
+    (
+        "DATABASE_POOL_MIN_IDLE",
+        "database.pool_min_idle",
+        Kind::Integer,
+    ),
+    (
+        "DATABASE_POOL_MAX_SIZE",
+        "database.pool_max_size",
+        Kind::Integer,
+    ),
+    (
+        "DATABASE_POOL_CONNECTION_TIMEOUT",
+        "database.pool_connection_timeout",
+        Kind::Integer,
+    ),
+    (
+        "DATABASE_POOL_IDLE_TIMEOUT",
+        "database.pool_idle_timeout",
+        Kind::Integer,
+    ),
+    (
+        "DATABASE_READ_CONNECTION_TIMEOUT",
+        "database.read_connection_timeout",
+        Kind::Integer,
+    ),
+    (
+        "DATABASE_STARTUP_ATTEMPTS",
+        "database.startup_attempts",
+        Kind::Integer,
+    ),
+    (
+        "DATABASE_STARTUP_BACKOFF_MS",
+        "database.startup_backoff_ms",
+        Kind::Integer,
+    ),
+    (
+        "DATABASE_STATEMENT_TIMEOUT_MS",
+        "database.statement_timeout_ms",
+        Kind::Integer,
+    ),
+    ("SCHEMA_REGISTRY_PASSWORD", "auth.password", Kind::String),
+    (
+        "SCHEMA_REGISTRY_PASSWORD_PRINCIPAL",
+        "auth.password_principal",
+        Kind::String,
+    ),
+    (
+        "SCHEMA_REGISTRY_USERS_FILE",
+        "auth.users_file",
+        Kind::String,
+    ),
+    (
+        "SCHEMA_REGISTRY_ACL_DENY_BY_DEFAULT",
+        "auth.acl_deny_by_default",
+        Kind::Boolean,
+    ),
+    (
+        "SCHEMA_REGISTRY_JWT_HS256_SECRET",
+        "auth.jwt.hs256_secret",
+        Kind::String,
+    ),
+    (
+        "SCHEMA_REGISTRY_JWT_JWKS_FILE",
+        "auth.jwt.jwks_file",
+        Kind::String,
+    ),
+    (
+        "SCHEMA_REGISTRY_JWT_ISSUER",
+        "auth.jwt.issuer",
+        Kind::String,
+    ),
+    (
+        "SCHEMA_REGISTRY_JWT_AUDIENCE",
+        "auth.jwt.audience",
+        Kind::String,
+    ),
+    (
+        "SCHEMA_REGISTRY_JWT_ROLE_CLAIM",
+        "auth.jwt.role_claim",
+        Kind::String,
+    ),
+    (
+        "SCHEMA_REGISTRY_JWT_ROLE_MAPPING",
+        "auth.jwt.role_mapping",
+        Kind::RoleMapping,
+    ),
+    (
+        "SCHEMA_REGISTRY_TLS_CERT_FILE",
+        "tls.cert_file",
+        Kind::String,
+    ),
+    ("SCHEMA_REGISTRY_TLS_KEY_FILE", "tls.key_file", Kind::String),
+    (
+        "SCHEMA_REGISTRY_TLS_CLIENT_CA_FILE",
+        "tls.client_ca_file",
+        Kind::String,
+    ),
+    (
+        "SCHEMA_REGISTRY_TLS_REQUIRE_CLIENT_CERT",
+        "tls.require_client_cert",
+        Kind::Boolean,
+    ),
+    (
+        "SCHEMA_REGISTRY_TLS_RELOAD_INTERVAL",
+        "tls.reload_interval",
+        Kind::Integer,
+    ),
+    (
+        "SCHEMA_REGISTRY_RATE_LIMIT_READ_PER_SECOND",
+        "rate_limit.read_per_second",
+        Kind::Float,
+    ),
+    (
+        "SCHEMA_REGISTRY_RATE_LIMIT_READ_BURST",
+        "rate_limit.read_burst",
+        Kind::Float,
+    ),
+    (
+        "SCHEMA_REGISTRY_RATE_LIMIT_WRITE_PER_SECOND",
+        "rate_limit.write_per_second",
+        Kind::Float,
+    ),
+    (
+        "SCHEMA_REGISTRY_RATE_LIMIT_WRITE_BURST",
+        "rate_limit.write_burst",
+        Kind::Float,
+    ),
+    ("SCHEMA_REGISTRY_LEADER_URL", "leader.url", Kind::String),
+    (
+        "SCHEMA_REGISTRY_LEADER_TIMEOUT_MS",
+        "leader.timeout_ms",
+        Kind::Integer,
+    ),
+    (
+        "SCHEMA_REGISTRY_LEADER_SECRET",
+        "leader.secret",
+        Kind::String,
+    ),
+    ("SCHEMA_REGISTRY_LOG_FORMAT", "logging.format", Kind::String),
+    (
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        "telemetry.otlp_endpoint",
+        Kind::String,
+    ),
+    ("OTEL_SERVICE_NAME", "telemetry.service_name", Kind::String),
+    ("SENTRY_URL", "sentry.url", Kind::String),
+    ("SENTRY_SAMPLE_RATE", "sentry.sample_rate", Kind::Float),
+    (
+        "SENTRY_TRACES_SAMPLE_RATE",
+        "sentry.traces_sample_rate",
+        Kind::Float,
+    ),
+];
+
+/// A setting along with the environment variable overriding it, if any, as they are
+/// named in errors.
+pub(crate) fn named(key: &str) -> String {
+    match ENV_VARS.iter().find(|(_, setting, _)| *setting == key) {
+        Some((var, _, _)) => format!("{} ({})", key, var),
+        None => key.to_string(),
+    }
+}
+
+impl Kind {
+    fn parse(self, var: &'static str, value: &str) -> Result<Value, SettingsError> {
+        let invalid = |expected| SettingsError::Invalid(var, expected);
+        match self {
+            Self::String => Ok(Value::String(value.to_string())),
+            Self::Integer => value
+                .parse::<u64>()
+                .ok()
+                .and_then(|value| i64::try_from(value).ok())
+                .map(Value::Integer)
+                .ok_or_else(|| invalid("a non-negative integer")),
+            Self::Float => value
+                .parse::<f64>()
+                .map(Value::Float)
+                .map_err(|_| invalid("a number")),
+            Self::Boolean => match value {
+                "true" => Ok(Value::Boolean(true)),
+                "false" => Ok(Value::Boolean(false)),
+                _ => Err(invalid("either true or false")),
+            },
+            Self::RoleMapping => value
+                .split(',')
+                .filter(|pair| !pair.trim().is_empty())
+                .map(|pair| {
+                    let (claim_value, role) = pair
+                        .split_once('=')
+                        .ok_or_else(|| invalid("comma separated `claim_value=role` pairs"))?;
+                    Ok((
+                        claim_value.trim().to_string(),
+                        Value::String(role.trim().to_string()),
+                    ))
+                })
+                .collect::<Result<Table, _>>()
+                .map(Value::Table),
+        }
+    }
+}
+
+/// Command line flags, overriding both the settings file and the environment.
+#[derive(Args, Clone, Debug, Default)]
+pub struct SettingsArgs {
+    /// Settings file, in TOML
+    #[arg(short, long, env = "SCHEMA_REGISTRY_CONFIG", value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Address to listen on [default: 127.0.0.1:8080]
+    #[arg(long, value_name = "HOST:PORT")]
+    pub listen: Option<String>,
+    /// Number of workers [default: one per CPU core]
+    #[arg(long)]
+    pub workers: Option<usize>,
+    /// URL of the primary database
+    #[arg(long, value_name = "URL")]
+    pub database_url: Option<String>,
+    /// URL of the read replica
+    #[arg(long, value_name = "URL")]
+    pub database_read_url: Option<String>,
+    /// Maximum number of database connections [default: 10]
+    #[arg(long, value_name = "SIZE")]
+    pub pool_max_size: Option<u32>,
+    /// JSON file listing the users and their roles
+    #[arg(long, value_name = "FILE")]
+    pub users_file: Option<PathBuf>,
+    /// Server certificate chain, to serve over TLS
+    #[arg(long, value_name = "FILE")]
+    pub tls_cert_file: Option<PathBuf>,
+    /// Private key of the server certificate
+    #[arg(long, value_name = "FILE")]
+    pub tls_key_file: Option<PathBuf>,
+    /// URL of the leader, which writes are forwarded to
+    #[arg(long, value_name = "URL")]
+    pub leader_url: Option<String>,
+    /// Either text or json [default: text]
+    #[arg(long, value_name = "FORMAT")]
+    pub log_format: Option<LogFormat>,
+    /// Don't serve Prometheus metrics
+    #[arg(long)]
+    pub no_metrics: bool,
+}
+
+impl SettingsArgs {
+    fn apply(&self, settings: &mut Settings) {
+        fn set<T: Clone>(setting: &mut T, flag: &Option<T>) {
+            if let Some(value) = flag {
+                *setting = value.clone();
+            }
+        }
+        fn set_some<T: Clone>(setting: &mut Option<T>, flag: &Option<T>) {
+            if flag.is_some() {
+                setting.clone_from(flag);
+            }
+        }
+
+        set(&mut settings.server.listen, &self.listen);
+        set_some(&mut settings.server.workers, &self.workers);
+        set_some(&mut settings.database.url, &self.database_url);
+        set_some(&mut settings.database.read_url, &self.database_read_url);
+        set(&mut settings.database.pool_max_size, &self.pool_max_size);
+        set_some(&mut settings.auth.users_file, &self.users_file);
+        set_some(&mut settings.tls.cert_file, &self.tls_cert_file);
+        set_some(&mut settings.tls.key_file, &self.tls_key_file);
+        set_some(&mut settings.leader.url, &self.leader_url);
+        set(&mut settings.logging.format, &self.log_format);
+        if self.no_metrics {
+            settings.server.metrics = false;
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings file given with `--config`, if any, overridden by the
+    /// environment and then by `args`.
+    pub fn load(args: &SettingsArgs) -> Result<Self, SettingsError> {
+        let file = args.config.as_deref().map(read_file).transpose()?;
+        Self::layered(file, |var| env::var(var).ok(), args)
+    }
+
+    /// Settings of the environment alone, such as when the registry is embedded or
+    /// tested.
+    pub fn from_env() -> Result<Self, SettingsError> {
+        Self::layered(None, |var| env::var(var).ok(), &SettingsArgs::default())
+    }
+
+    fn layered(
+        file: Option<Table>,
+        env: impl Fn(&str) -> Option<String>,
+        args: &SettingsArgs,
+    ) -> Result<Self, SettingsError> {
+        let mut table = file.unwrap_or_default();
+        // Empty variables are the same as unset ones
+        for (var, key, kind) in ENV_VARS {
+            if let Some(value) = env(var).filter(|value| !value.is_empty()) {
+                insert(&mut table, key, kind.parse(var, &value)?);
+            }
+        }
+
+        let mut settings: Self = Value::Table(table).try_into()?;
+        args.apply(&mut settings);
+        Ok(settings)
+    }
+}
+
+/// Reads a settings file, checking it on its own first so that errors point at its
+/// lines.
+fn read_file(path: &Path) -> Result<Table, SettingsError> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| SettingsError::Io(path.to_path_buf(), e))?;
+    toml::from_str::<Settings>(&contents)
+        .and_then(|_| toml::from_str::<Table>(&contents))
+        .map_err(|e| SettingsError::File(path.to_path_buf(), e))
+}
+
+/// Sets the value at the dotted `key`, merging it with the tables already there.
+fn insert(table: &mut Table, key: &str, value: Value) {
+    let Some((section, rest)) = key.split_once('.') else {
+        table.insert(key.to_string(), value);
+        return;
+    };
+    let section = table
+        .entry(section)
+        .or_insert_with(|| Value::Table(Table::new()));
+    match section {
+        Value::Table(section) => insert(section, rest, value),
+        // Not a table in the file, which fails deserialization anyway
+        section => *section = Value::Table(Table::from_iter([(rest.to_string(), value)])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Settings, SettingsArgs, SettingsError};
+    use crate::auth::Role;
+    use crate::db::DbPoolConfig;
+    use crate::telemetry::LogFormat;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn layered(
+        file: &str,
+        env: &[(&str, &str)],
+        args: &SettingsArgs,
+    ) -> Result<Settings, SettingsError> {
+        let env: HashMap<_, _> = env.iter().copied().collect();
+        Settings::layered(
+            Some(toml::from_str(file).unwrap()),
+            |var| env.get(var).map(|value| value.to_string()),
+            args,
+        )
+    }
+
+    #[test]
+    fn settings_precedence() {
+        let file = r#"
+            [server]
+            listen = "0.0.0.0:8080"
+            workers = 2
+
+            [database]
+            url = "postgres://file"
+            pool_max_size = 5
+
+            [auth.jwt]
+            issuer = "https://idp.example.com"
+            role_mapping = { registry-admins = "admin" }
+        "#;
+        let env = [
+            ("DATABASE_URL", "postgres://env"),
+            ("DATABASE_POOL_MAX_SIZE", "20"),
+            ("SCHEMA_REGISTRY_JWT_ROLE_MAPPING", "ci=writer, "),
+            ("SCHEMA_REGISTRY_PASSWORD", ""),
+        ];
+        let args = SettingsArgs {
+            database_url: Some(String::from("postgres://flag")),
+            log_format: Some(LogFormat::Json),
+            ..Default::default()
+        };
+        let settings = layered(file, &env, &args).unwrap();
+
+        assert_eq!(settings.server.listen, "0.0.0.0:8080");
+        assert_eq!(settings.server.workers, Some(2));
+        assert_eq!(settings.database.url.as_deref(), Some("postgres://flag"));
+        assert_eq!(settings.database.pool_max_size, 20);
+        assert_eq!(settings.database.pool_idle_timeout, 600);
+        assert_eq!(
+            settings.auth.jwt.issuer.as_deref(),
+            Some("https://idp.example.com")
+        );
+        assert_eq!(
+            settings.auth.jwt.role_mapping,
+            [(String::from("ci"), Role::Writer)].into()
+        );
+        assert_eq!(settings.auth.password, None);
+        assert_eq!(settings.logging.format, LogFormat::Json);
+        assert!(settings.server.metrics);
+    }
+
+    #[test]
+    fn settings_errors() {
+        let error = |file, env: &[(&str, &str)]| {
+            layered(file, env, &SettingsArgs::default())
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            error("", &[("DATABASE_POOL_MAX_SIZE", "ten")]),
+            "DATABASE_POOL_MAX_SIZE must be a non-negative integer"
+        );
+        assert_eq!(
+            error("", &[("SCHEMA_REGISTRY_TLS_REQUIRE_CLIENT_CERT", "yes")]),
+            "SCHEMA_REGISTRY_TLS_REQUIRE_CLIENT_CERT must be either true or false"
+        );
+        assert!(error("[server]\nlisten_on = \"x\"", &[]).contains("unknown field `listen_on`"));
+        assert!(error("", &[("SCHEMA_REGISTRY_LOG_FORMAT", "xml")]).contains("unknown variant"));
+        assert_eq!(
+            SettingsError::Missing("database.url").to_string(),
+            "database.url (DATABASE_URL) must be set"
+        );
+    }
+
+    #[test]
+    fn settings_pool_min_idle_within_max_size() {
+        let pool = |env: &[(&str, &str)]| {
+            let settings = layered("", env, &SettingsArgs::default()).unwrap();
+            DbPoolConfig::from_settings(&settings.database)
+        };
+        let url = ("DATABASE_URL", "postgres://localhost/registry");
+
+        assert!(pool(&[url, ("DATABASE_POOL_MIN_IDLE", "10")]).is_ok());
+        let error = pool(&[url, ("DATABASE_POOL_MIN_IDLE", "11")]).unwrap_err();
+        assert!(matches!(
+            error,
+            SettingsError::Invalid("database.pool_min_idle", _)
+        ));
+    }
+
+    #[test]
+    fn settings_file_errors_point_at_lines() {
+        let path = std::env::temp_dir().join("avro-schema-registry-settings-test.toml");
+        std::fs::write(&path, "[database]\npool_max_size = \"ten\"\n").unwrap();
+        let error = Settings::load(&SettingsArgs {
+            config: Some(path.clone()),
+            ..Default::default()
+        })
+        .unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(&error, SettingsError::File(file, _) if *file == path));
+        assert!(error.to_string().contains("line 2"));
+
+        let missing = PathBuf::from("/nonexistent/settings.toml");
+        assert!(matches!(
+            Settings::load(&SettingsArgs {
+                config: Some(missing),
+                ..Default::default()
+            }),
+            Err(SettingsError::Io(..))
+        ));
+    }
+}
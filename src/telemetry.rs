@@ -0,0 +1,189 @@
+use std::str::FromStr;
+
+use diesel::connection::{
+    set_default_instrumentation, DebugQuery, Instrumentation, InstrumentationEvent,
+};
+use opentelemetry::global;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use serde::Deserialize;
+use tracing::{field, info_span, Span};
+use tracing_subscriber::layer::{Layer, SubscriberExt};
+use tracing_subscriber::util::{SubscriberInitExt, TryInitError};
+use tracing_subscriber::{fmt, EnvFilter};
+
+use crate::settings::TelemetrySettings;
+
+/// What is logged when `RUST_LOG` isn't set.
+const DEFAULT_LOG_FILTER: &str = "actix_web=debug,avro_schema_registry=debug";
+
+#[derive(Debug, thiserror::Error)]
+pub enum TelemetryError {
+    #[error("invalid OTLP exporter: {0}")]
+    Exporter(#[from] ExporterBuildError),
+    #[error("a tracing subscriber is already set: {0}")]
+    Subscriber(#[from] TryInitError),
+    #[error("could not instrument database connections: {0}")]
+    Database(#[from] diesel::result::Error),
+}
+
+/// How log lines are written to stderr.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines, prefixed with the fields of the spans they are logged in.
+    #[default]
+    Text,
+    /// A JSON object per line, with the fields of its spans under `spans`.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(String::from("must be either text or json")),
+        }
+    }
+}
+
+/// Logs to stderr in `format`, filtered by `RUST_LOG`, and exports spans with
+/// `tracer_provider` if any. Records of the `log` crate are logged as well.
+///
+/// Only log lines are filtered, spans are exported whatever their level.
+pub fn init_subscriber(
+    format: LogFormat,
+    tracer_provider: Option<&SdkTracerProvider>,
+) -> Result<(), TelemetryError> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    let logs = match format {
+        LogFormat::Text => fmt::layer().with_writer(std::io::stderr).boxed(),
+        LogFormat::Json => fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_writer(std::io::stderr)
+            .boxed(),
+    };
+    let traces = tracer_provider.map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer("avro-schema-registry"))
+    });
+
+    tracing_subscriber::registry()
+        .with(logs.with_filter(filter))
+        .with(traces)
+        .try_init()?;
+    Ok(())
+}
+
+/// Where spans are exported to, over OTLP/HTTP.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Telemetry {
+    /// Base URL of the collector, spans are sent to its `/v1/traces`.
+    pub endpoint: String,
+    pub service_name: String,
+}
+
+impl Telemetry {
+    /// Where spans are exported to, if anywhere.
+    pub fn from_settings(settings: &TelemetrySettings) -> Option<Self> {
+        Some(Self {
+            endpoint: settings.otlp_endpoint.clone()?,
+            service_name: settings.service_name.clone(),
+        })
+    }
+
+    /// Exports spans to the collector and picks up the W3C trace context of requests.
+    /// Spans are only recorded once the provider is given to [`init_subscriber`].
+    ///
+    /// Spans are exported in batches from a background thread. Flush or shut down the
+    /// returned provider so that none are lost on exit.
+    pub fn init(&self) -> Result<SdkTracerProvider, TelemetryError> {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", self.endpoint.trim_end_matches('/')))
+            .build()?;
+        let provider = SdkTracerProvider::builder()
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(self.service_name.clone())
+                    .build(),
+            )
+            .with_batch_exporter(exporter)
+            .build();
+
+        global::set_tracer_provider(provider.clone());
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        Ok(provider)
+    }
+}
+
+/// Instruments every database connection established from then on, so that queries are
+/// traced, and leave Sentry breadcrumbs when it is enabled.
+pub fn instrument_queries() -> Result<(), TelemetryError> {
+    set_default_instrumentation(|| Some(Box::new(QueryTracing::default())))?;
+    Ok(())
+}
+
+/// Traces the queries of a connection, each in a span child of whatever span is current
+/// when it starts.
+#[derive(Debug, Default)]
+struct QueryTracing {
+    query: Option<Span>,
+}
+
+impl Instrumentation for QueryTracing {
+    fn on_connection_event(&mut self, event: InstrumentationEvent<'_>) {
+        match event {
+            InstrumentationEvent::StartQuery { query, .. } => {
+                let statement = statement(query);
+                let operation = statement.split_whitespace().next().unwrap_or("query");
+                self.query = Some(info_span!(
+                    "db.query",
+                    otel.name = operation,
+                    otel.kind = "client",
+                    otel.status_code = field::Empty,
+                    db.system = "postgresql",
+                    db.statement = statement,
+                    error.message = field::Empty,
+                ));
+            }
+            InstrumentationEvent::FinishQuery { query, error, .. } => {
+                #[cfg(feature = "sentry")]
+                sentry::add_breadcrumb(|| sentry::Breadcrumb {
+                    ty: String::from("query"),
+                    category: Some(String::from("db.query")),
+                    message: Some(statement(query)),
+                    level: match error {
+                        Some(_) => sentry::Level::Error,
+                        None => sentry::Level::Info,
+                    },
+                    ..Default::default()
+                });
+                #[cfg(not(feature = "sentry"))]
+                let _ = query;
+                if let (Some(span), Some(error)) = (self.query.take(), error) {
+                    span.record("otel.status_code", "ERROR");
+                    span.record("error.message", field::display(error));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The SQL of `query`, without its bind values, which can be whole schemas.
+fn statement(query: &dyn DebugQuery) -> String {
+    let query = query.to_string();
+    match query.split_once(" -- binds: ") {
+        Some((statement, _)) => statement.to_string(),
+        None => query,
+    }
+}
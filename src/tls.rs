@@ -0,0 +1,378 @@
+use std::any::Any;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use log::{info, warn};
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::CertificateDer;
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{InconsistentKeys, RootCertStore, ServerConfig};
+use x509_parser::extensions::GeneralName;
+
+use crate::settings::{SettingsError, TlsSettings};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("could not read {0}: {1}")]
+    Io(PathBuf, io::Error),
+    #[error("no certificate found in {0}")]
+    NoCertificate(PathBuf),
+    #[error("no private key found in {0}")]
+    NoPrivateKey(PathBuf),
+    #[error("the private key in {1} doesn't match the certificate in {0}")]
+    KeyMismatch(PathBuf, PathBuf),
+    #[error("invalid TLS settings: {0}")]
+    Rustls(#[from] rustls::Error),
+    #[error("invalid client CA: {0}")]
+    ClientVerifier(#[from] rustls::server::VerifierBuilderError),
+}
+
+/// Settings for serving the registry over TLS.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+    /// CA that client certificates must be issued by, enables mutual TLS
+    pub client_ca_file: Option<PathBuf>,
+    /// Whether clients without a certificate are turned away, rather than left to
+    /// authenticate with their `Authorization` header
+    pub require_client_cert: bool,
+    /// How often the certificate and key files are checked for changes
+    pub reload_interval: Duration,
+}
+
+impl TlsConfig {
+    /// Serves over TLS if a certificate is set, in which case its key is required.
+    pub fn from_settings(settings: &TlsSettings) -> Result<Option<Self>, SettingsError> {
+        let Some(cert_file) = settings.cert_file.clone() else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            cert_file,
+            key_file: settings
+                .key_file
+                .clone()
+                .ok_or(SettingsError::Missing("tls.key_file"))?,
+            client_ca_file: settings.client_ca_file.clone(),
+            require_client_cert: settings.require_client_cert,
+            reload_interval: Duration::from_secs(settings.reload_interval),
+        }))
+    }
+
+    /// Builds the rustls configuration, and starts watching the certificate and key
+    /// files so that renewed certificates are served without a restart.
+    pub fn server_config(&self) -> Result<ServerConfig, TlsError> {
+        let provider = Arc::new(ring::default_provider());
+        let resolver = Arc::new(ReloadingCertResolver::new(
+            &self.cert_file,
+            &self.key_file,
+            provider.clone(),
+        )?);
+        resolver.clone().watch(self.reload_interval);
+
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+        let builder = match &self.client_ca_file {
+            Some(ca_file) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(ca_file)? {
+                    roots.add(cert)?;
+                }
+                let verifier = WebPkiClientVerifier::builder_with_provider(roots.into(), provider);
+                let verifier = if self.require_client_cert {
+                    verifier.build()?
+                } else {
+                    verifier.allow_unauthenticated().build()?
+                };
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        Ok(builder.with_cert_resolver(resolver))
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, TlsError> {
+    fs::read(path).map_err(|e| TlsError::Io(path.to_owned(), e))
+}
+
+fn parse_certs(pem: &[u8], path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let certs = rustls_pemfile::certs(&mut &pem[..])
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| TlsError::Io(path.to_owned(), e))?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificate(path.to_owned()));
+    }
+    Ok(certs)
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    parse_certs(&read(path)?, path)
+}
+
+/// Serves the certificate and key found in a pair of files, reloaded whenever their
+/// content changes.
+#[derive(Debug)]
+pub struct ReloadingCertResolver {
+    cert_file: PathBuf,
+    key_file: PathBuf,
+    provider: Arc<CryptoProvider>,
+    current: RwLock<LoadedCert>,
+}
+
+#[derive(Debug)]
+struct LoadedCert {
+    pem: (Vec<u8>, Vec<u8>),
+    key: Arc<CertifiedKey>,
+}
+
+impl ReloadingCertResolver {
+    pub fn new(
+        cert_file: &Path,
+        key_file: &Path,
+        provider: Arc<CryptoProvider>,
+    ) -> Result<Self, TlsError> {
+        let pem = (read(cert_file)?, read(key_file)?);
+        let key = Self::certified_key(cert_file, key_file, &pem, &provider)?;
+        Ok(Self {
+            cert_file: cert_file.to_owned(),
+            key_file: key_file.to_owned(),
+            provider,
+            current: RwLock::new(LoadedCert { pem, key }),
+        })
+    }
+
+    fn certified_key(
+        cert_file: &Path,
+        key_file: &Path,
+        (cert_pem, key_pem): &(Vec<u8>, Vec<u8>),
+        provider: &CryptoProvider,
+    ) -> Result<Arc<CertifiedKey>, TlsError> {
+        let certs = parse_certs(cert_pem, cert_file)?;
+        let key = rustls_pemfile::private_key(&mut &key_pem[..])
+            .map_err(|e| TlsError::Io(key_file.to_owned(), e))?
+            .ok_or_else(|| TlsError::NoPrivateKey(key_file.to_owned()))?;
+        let key = provider.key_provider.load_private_key(key)?;
+        let certified = CertifiedKey::new(certs, key);
+        match certified.keys_match() {
+            // Keys whose public half can't be told are left to the handshake
+            Ok(()) | Err(rustls::Error::InconsistentKeys(InconsistentKeys::Unknown)) => {
+                Ok(Arc::new(certified))
+            }
+            Err(rustls::Error::InconsistentKeys(_)) => Err(TlsError::KeyMismatch(
+                cert_file.to_owned(),
+                key_file.to_owned(),
+            )),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Reloads the certificate if either file changed. Returns whether it did.
+    ///
+    /// A certificate that fails to load, or doesn't match the private key, is logged and
+    /// ignored, the previous one is kept until the files are fixed: renewals often write
+    /// the two files one after the other, so they can be briefly out of sync.
+    pub fn reload_if_changed(&self) -> bool {
+        let pem = match (read(&self.cert_file), read(&self.key_file)) {
+            (Ok(cert), Ok(key)) => (cert, key),
+            (Err(e), _) | (_, Err(e)) => {
+                warn!("keeping the current TLS certificate: {}", e);
+                return false;
+            }
+        };
+        if self.current.read().expect("poisoned lock").pem == pem {
+            return false;
+        }
+
+        match Self::certified_key(&self.cert_file, &self.key_file, &pem, &self.provider) {
+            Ok(key) => {
+                *self.current.write().expect("poisoned lock") = LoadedCert { pem, key };
+                info!("reloaded TLS certificate from {:?}", self.cert_file);
+                true
+            }
+            Err(e) => {
+                warn!("keeping the current TLS certificate: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Checks the files for changes every `interval`, on a background thread.
+    pub fn watch(self: Arc<Self>, interval: Duration) {
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            self.reload_if_changed();
+        });
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().expect("poisoned lock").key.clone())
+    }
+}
+
+/// Names a verified client certificate was issued for: its DNS, email and URI subject
+/// alternative names, then its subject common name. Available from the request's
+/// connection data, see [`HttpRequest::conn_data`].
+///
+/// [`HttpRequest::conn_data`]: actix_web::HttpRequest::conn_data
+#[derive(Debug, Clone)]
+pub struct ClientIdentity {
+    pub names: Vec<String>,
+}
+
+impl ClientIdentity {
+    pub fn from_certificate(cert: &CertificateDer) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(cert).ok()?;
+
+        let mut names: Vec<String> = cert
+            .subject_alternative_name()
+            .ok()
+            .flatten()
+            .map(|san| {
+                san.value
+                    .general_names
+                    .iter()
+                    .filter_map(|name| match name {
+                        GeneralName::DNSName(name)
+                        | GeneralName::RFC822Name(name)
+                        | GeneralName::URI(name) => Some(name.to_string()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        names.extend(
+            cert.subject()
+                .iter_common_name()
+                .filter_map(|cn| cn.as_str().ok())
+                .map(str::to_string),
+        );
+
+        (!names.is_empty()).then_some(Self { names })
+    }
+}
+
+/// Makes the identity of the client certificate of TLS connections available to the
+/// requests they carry. Pass it to [`actix_web::HttpServer::on_connect`].
+pub fn on_connect(connection: &dyn Any, data: &mut Extensions) {
+    let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+    let (_, session) = stream.get_ref();
+    // Only certificates that the client verifier accepted make it this far
+    if let Some(identity) = session
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .and_then(ClientIdentity::from_certificate)
+    {
+        data.insert(identity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientIdentity, ReloadingCertResolver, TlsError};
+    use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair, SanType};
+    use rustls::crypto::ring;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn self_signed(name: &str) -> (String, String) {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec![name.to_string()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn reload_changed_certificate() {
+        let dir = temp_dir("reload_changed_certificate");
+        let (cert_file, key_file) = (dir.join("cert.pem"), dir.join("key.pem"));
+        let (cert, key) = self_signed("first.example.com");
+        std::fs::write(&cert_file, cert).unwrap();
+        std::fs::write(&key_file, key).unwrap();
+
+        let resolver =
+            ReloadingCertResolver::new(&cert_file, &key_file, Arc::new(ring::default_provider()))
+                .unwrap();
+        let first = resolver.current.read().unwrap().key.clone();
+        assert!(!resolver.reload_if_changed());
+
+        // a half written renewal is ignored
+        let (cert, key) = self_signed("second.example.com");
+        std::fs::write(&cert_file, &cert).unwrap();
+        std::fs::write(&key_file, "").unwrap();
+        assert!(!resolver.reload_if_changed());
+        assert_eq!(resolver.current.read().unwrap().key.cert, first.cert);
+
+        std::fs::write(&key_file, key).unwrap();
+        assert!(resolver.reload_if_changed());
+        assert_ne!(resolver.current.read().unwrap().key.cert, first.cert);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reload_certificate_with_mismatched_key() {
+        let dir = temp_dir("reload_certificate_with_mismatched_key");
+        let (cert_file, key_file) = (dir.join("cert.pem"), dir.join("key.pem"));
+        let (cert, key) = self_signed("first.example.com");
+        std::fs::write(&cert_file, cert).unwrap();
+        std::fs::write(&key_file, &key).unwrap();
+
+        let resolver =
+            ReloadingCertResolver::new(&cert_file, &key_file, Arc::new(ring::default_provider()))
+                .unwrap();
+        let first = resolver.current.read().unwrap().key.clone();
+
+        // the certificate was renewed with a new key, but the key file was not written yet
+        let (cert, _) = self_signed("second.example.com");
+        std::fs::write(&cert_file, &cert).unwrap();
+        assert!(!resolver.reload_if_changed());
+        assert_eq!(resolver.current.read().unwrap().key.cert, first.cert);
+        assert!(matches!(
+            ReloadingCertResolver::new(&cert_file, &key_file, Arc::new(ring::default_provider())),
+            Err(TlsError::KeyMismatch(_, _))
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn client_identity_from_certificate() {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec!["service.example.com".to_string()]).unwrap();
+        params
+            .subject_alt_names
+            .push(SanType::Rfc822Name("team@example.com".try_into().unwrap()));
+        params.distinguished_name = DistinguishedName::new();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "payments");
+        let cert = params.self_signed(&key).unwrap();
+
+        let identity = ClientIdentity::from_certificate(cert.der()).unwrap();
+        assert_eq!(
+            identity.names,
+            vec!["service.example.com", "team@example.com", "payments"]
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use actix_web::http;
+
+use crate::common::server::{setup, ApiTesterServer};
+use crate::db::DbAuxOperations;
+use avro_schema_registry::api::SchemaBody;
+use avro_schema_registry::auth::{PatternType, Role};
+use avro_schema_registry::db::models::{AclRule, AclRuleBody, ApiKey, ApiKeyBody};
+use avro_schema_registry::db::DbConnection;
+
+const WRITER: (&str, &str) = ("writer", "writer_password");
+
+const DENIED: &str = r#"^\{"error_code":40301,"message":"User is denied operation"\}$"#;
+
+fn schema() -> serde_json::Value {
+    let schema_s = std::fs::read_to_string("tests/fixtures/schema.json").unwrap();
+    json!(SchemaBody { schema: schema_s })
+}
+
+/// The writer may register under `payments.*`, but only read `orders.*`.
+fn restrict_writer(conn: &mut DbConnection) -> AclRule {
+    let payments = AclRule::insert(
+        conn,
+        AclRuleBody {
+            principal: String::from("writer"),
+            pattern: String::from("payments.*"),
+            pattern_type: PatternType::Glob,
+            role: Role::Writer,
+        },
+    )
+    .unwrap();
+    AclRule::insert(
+        conn,
+        AclRuleBody {
+            principal: String::from("writer"),
+            pattern: String::from("orders."),
+            pattern_type: PatternType::Prefix,
+            role: Role::Reader,
+        },
+    )
+    .unwrap();
+    payments
+}
+
+#[actix_rt::test]
+async fn test_acl_rules_restrict_subjects() {
+    let (server, mut conn) = setup();
+    restrict_writer(&mut conn);
+
+    server
+        .test_as(
+            WRITER,
+            http::Method::POST,
+            "/subjects/payments.refunds/versions",
+            Some(schema()),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::POST,
+            "/subjects/orders.created/versions",
+            Some(schema()),
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+    // reading is allowed, the subject just doesn't exist
+    server
+        .test_as(
+            WRITER,
+            http::Method::GET,
+            "/subjects/orders.created/versions",
+            None,
+            http::StatusCode::NOT_FOUND,
+            r#"\{"error_code":40401,"message":"Subject not found"\}"#,
+        )
+        .await;
+    // no rule matches other subjects, where the writer keeps its role
+    server
+        .test_as(
+            WRITER,
+            http::Method::POST,
+            "/subjects/users.created/versions",
+            Some(schema()),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+    // admins are not restricted
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/orders.created/versions",
+            Some(schema()),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_acl_rules_deny_other_subjects_by_default() {
+    let (_, mut conn) = setup();
+    restrict_writer(&mut conn);
+    let server = ApiTesterServer::with_acl_deny_by_default();
+
+    server
+        .test_as(
+            WRITER,
+            http::Method::POST,
+            "/subjects/payments.refunds/versions",
+            Some(schema()),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::GET,
+            "/subjects/users.created/versions",
+            None,
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::POST,
+            "/compatibility/subjects/users.created/versions/latest",
+            Some(schema()),
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_principal_without_rules_keeps_its_role() {
+    let (server, _) = setup();
+
+    server
+        .test_as(
+            WRITER,
+            http::Method::POST,
+            "/subjects/payments.refunds/versions",
+            Some(schema()),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+    ApiTesterServer::with_acl_deny_by_default()
+        .test_as(
+            WRITER,
+            http::Method::POST,
+            "/subjects/payments.refunds/versions",
+            Some(schema()),
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_acl_rules_restrict_listing_and_schema_lookups() {
+    let (_, mut conn) = setup();
+    restrict_writer(&mut conn);
+    let server = ApiTesterServer::with_acl_deny_by_default();
+    let payments = conn.register_schema(
+        String::from("payments.refunds"),
+        std::fs::read_to_string("tests/fixtures/schema.json").unwrap(),
+    );
+    let users = conn.register_schema(
+        String::from("users.created"),
+        std::fs::read_to_string("tests/fixtures/schema2.json").unwrap(),
+    );
+
+    server
+        .test_as(
+            WRITER,
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::OK,
+            r#"^\["payments.refunds"\]$"#,
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::GET,
+            &format!("/schemas/ids/{}", payments.id),
+            None,
+            http::StatusCode::OK,
+            r#"^\{"schema":"#,
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::GET,
+            &format!("/schemas/ids/{}", users.id),
+            None,
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_acl_rules_management() {
+    let (server, mut conn) = setup();
+
+    server
+        .test(
+            http::Method::GET,
+            "/acl",
+            None,
+            http::StatusCode::OK,
+            r"^\[\]$",
+        )
+        .await;
+    server
+        .test(
+            http::Method::POST,
+            "/acl",
+            Some(json!({
+                "principal": "writer",
+                "pattern": "payments.refunds",
+                "pattern_type": "exact",
+                "role": "writer"
+            })),
+            http::StatusCode::OK,
+            r#"^\{"id":\d+,"principal":"writer","pattern":"payments.refunds","pattern_type":"exact","role":"writer","created_at":"[^"]+","updated_at":"[^"]+"\}$"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::POST,
+            "/acl",
+            Some(json!({
+                "principal": "writer",
+                "pattern": "payments.*",
+                "pattern_type": "regex",
+                "role": "writer"
+            })),
+            http::StatusCode::BAD_REQUEST,
+            r#"^\{"error_code":400,"message":"Malformed request: unknown variant `regex`.*"\}$"#,
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::GET,
+            "/acl",
+            None,
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+
+    let rule = restrict_writer(&mut conn);
+    let path = format!("/acl/{}", rule.id);
+    server
+        .test(
+            http::Method::DELETE,
+            &path,
+            None,
+            http::StatusCode::OK,
+            r#""pattern":"payments\.\*""#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::DELETE,
+            &path,
+            None,
+            http::StatusCode::NOT_FOUND,
+            r#"\{"error_code":40490,"message":"ACL rule not found"\}"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::GET,
+            "/audit",
+            None,
+            http::StatusCode::OK,
+            r#"^\[\{"id":\d+,"action":"DELETE_ACL_RULE".*"action":"CREATE_ACL_RULE""#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_acl_check() {
+    let (server, mut conn) = setup();
+    restrict_writer(&mut conn);
+
+    // principals check their own access
+    server
+        .test_as(
+            WRITER,
+            http::Method::POST,
+            "/acl/check",
+            Some(json!({"subject": "payments.refunds", "role": "writer"})),
+            http::StatusCode::OK,
+            r#"^\{"allowed":true,"effective_role":"writer"\}$"#,
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::POST,
+            "/acl/check",
+            Some(json!({"subject": "orders.created", "role": "writer"})),
+            http::StatusCode::OK,
+            r#"^\{"allowed":false,"effective_role":"reader"\}$"#,
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::POST,
+            "/acl/check",
+            Some(json!({"subject": "users.created", "role": "reader"})),
+            http::StatusCode::OK,
+            r#"^\{"allowed":true,"effective_role":"writer"\}$"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::POST,
+            "/acl/check",
+            Some(json!({"subject": "users.created", "role": "admin"})),
+            http::StatusCode::OK,
+            r#"^\{"allowed":true,"effective_role":"admin"\}$"#,
+        )
+        .await;
+
+    // admins check the access of others, with their role
+    server
+        .test(
+            http::Method::POST,
+            "/acl/check",
+            Some(json!({"principal": "writer", "subject": "orders.created", "role": "writer"})),
+            http::StatusCode::OK,
+            r#"^\{"allowed":false,"effective_role":"reader"\}$"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::POST,
+            "/acl/check",
+            Some(json!({"principal": "reader", "subject": "payments.refunds", "role": "writer"})),
+            http::StatusCode::OK,
+            r#"^\{"allowed":false,"effective_role":"reader"\}$"#,
+        )
+        .await;
+    ApiKey::create(
+        &mut conn,
+        ApiKeyBody {
+            owner: String::from("ci"),
+            role: Role::Reader,
+            expires_at: None,
+        },
+    )
+    .unwrap();
+    server
+        .test(
+            http::Method::POST,
+            "/acl/check",
+            Some(json!({"principal": "ci", "subject": "payments.refunds", "role": "writer"})),
+            http::StatusCode::OK,
+            r#"^\{"allowed":false,"effective_role":"reader"\}$"#,
+        )
+        .await;
+    // only their rules tell about principals known from neither
+    server
+        .test(
+            http::Method::POST,
+            "/acl/check",
+            Some(json!({"principal": "payments-service", "subject": "orders.created", "role": "writer"})),
+            http::StatusCode::OK,
+            r#"^\{"allowed":true,"effective_role":"writer"\}$"#,
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::POST,
+            "/acl/check",
+            Some(json!({"principal": "reader", "subject": "payments.refunds", "role": "reader"})),
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+}
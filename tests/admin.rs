@@ -0,0 +1,485 @@
+use actix_web::http;
+use diesel::prelude::*;
+use serde_json::Value as JsonValue;
+
+use avro_schema_registry::admin::{self, Drift, ImportSummary, Problem, ReplaySummary};
+use avro_schema_registry::db::models::{
+    CompatibilityLevel, Config, Mode, Schema, SchemaVersion, Subject,
+};
+use avro_schema_registry::db::{DbConnection, DbManage, DbPool};
+
+use crate::common::server::setup;
+use crate::db::DbAuxOperations;
+
+fn connection() -> DbConnection {
+    let mut conn = DbPool::new_pool(Some(1)).connection().unwrap();
+    conn.reset();
+    conn
+}
+
+fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!("tests/fixtures/{}", name)).unwrap()
+}
+
+fn export(conn: &mut DbConnection) -> String {
+    let mut output = vec![];
+    admin::export(conn, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+/// An export without when things were created and updated, to compare what it holds
+/// with another's.
+fn without_timestamps(export: &str) -> String {
+    regex::Regex::new(r#","created_at":"[^"]+","updated_at":"[^"]+""#)
+        .unwrap()
+        .replace_all(export, "")
+        .into_owned()
+}
+
+/// Imports an export into the empty registry, and describes why it failed.
+fn import_error(conn: &mut DbConnection, export: &str) -> String {
+    conn.reset();
+    admin::import(conn, export.as_bytes(), &admin::audit_context())
+        .unwrap_err()
+        .to_string()
+}
+
+#[test]
+fn test_export_and_import_round_trip() {
+    let mut conn = connection();
+    let audit = admin::audit_context();
+    conn.register_schema("test.subject".to_string(), fixture("schema.json"));
+    admin::set_compatibility(
+        &mut conn,
+        Some("test.subject"),
+        CompatibilityLevel::CompatNone,
+        &audit,
+    )
+    .unwrap();
+    let second = conn.register_schema("test.subject".to_string(), fixture("schema2.json"));
+    conn.register_schema("deleted.subject".to_string(), fixture("schema.json"));
+    Subject::delete_by_name(&mut conn, "deleted.subject".to_string()).unwrap();
+
+    let exported = export(&mut conn);
+    let records = exported
+        .lines()
+        .map(|line| serde_json::from_str::<JsonValue>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(records.len(), 10);
+    assert_eq!(records[0]["type"], "header");
+    assert_eq!(records[0]["format"], admin::EXPORT_FORMAT);
+    let mut subjects = records[2..4].to_vec();
+    for subject in &mut subjects {
+        let subject = subject.as_object_mut().unwrap();
+        assert!(subject.remove("created_at").unwrap().is_string());
+        assert!(subject.remove("updated_at").unwrap().is_string());
+    }
+    assert_eq!(
+        subjects,
+        [
+            json!({ "type": "subject", "name": "test.subject", "deleted": false, "compatibility": "NONE", "mode": null }),
+            json!({ "type": "subject", "name": "deleted.subject", "deleted": true, "compatibility": null, "mode": null }),
+        ]
+    );
+    assert_eq!(records[9], json!({ "type": "end", "records": 9 }));
+
+    conn.reset();
+    let summary = admin::import(&mut conn, exported.as_bytes(), &audit).unwrap();
+    assert_eq!(
+        summary,
+        ImportSummary {
+            subjects: 2,
+            schemas: 2,
+            versions: 3,
+        }
+    );
+    // timestamps included
+    assert_eq!(export(&mut conn), exported);
+
+    // New schemas are given IDs past the imported ones
+    let third = conn.register_schema(
+        "test.subject".to_string(),
+        fixture("schema_backward_compatible.json"),
+    );
+    assert!(third.id > second.id);
+}
+
+#[actix_rt::test]
+async fn test_export_endpoint_streams_the_export() {
+    let (server, mut conn) = setup();
+    conn.register_schema("test.subject".to_string(), fixture("schema.json"));
+
+    server
+        .test(
+            http::Method::GET,
+            "/admin/export",
+            None,
+            http::StatusCode::OK,
+            r#"(?s)^\{"type":"header","format":1,.*\{"type":"subject","name":"test.subject",.*\{"type":"end","records":5\}\n$"#,
+        )
+        .await;
+}
+
+#[test]
+fn test_import_requires_an_empty_registry() {
+    let mut conn = connection();
+    conn.register_schema("test.subject".to_string(), fixture("schema.json"));
+    let exported = export(&mut conn);
+
+    let error = admin::import(&mut conn, exported.as_bytes(), &admin::audit_context())
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("empty registry"), "{}", error);
+}
+
+#[test]
+fn test_import_rejects_invalid_exports() {
+    let mut conn = connection();
+    conn.register_schema("test.subject".to_string(), fixture("schema.json"));
+    let exported = export(&mut conn);
+    let lines = exported.lines().collect::<Vec<_>>();
+
+    let truncated = lines[..lines.len() - 1].join("\n");
+    assert_eq!(
+        import_error(&mut conn, &truncated),
+        "invalid export: the export is truncated"
+    );
+    let headless = lines[1..].join("\n");
+    assert_eq!(
+        import_error(&mut conn, &headless),
+        "line 1: invalid export: the header is missing"
+    );
+    let future = exported.replacen(
+        &format!(r#""format":{}"#, admin::EXPORT_FORMAT),
+        r#""format":99"#,
+        1,
+    );
+    assert_eq!(
+        import_error(&mut conn, &future),
+        "line 1: invalid export: format 99 isn't supported"
+    );
+    let malformed = format!("{}\n{{\"type\":\"table\"}}\n", lines[0]);
+    assert!(import_error(&mut conn, &malformed).starts_with("line 2: "));
+
+    // Nothing is kept from a failed import
+    assert_eq!(Subject::count(&mut conn).unwrap(), 0);
+    assert!(Config::all(&mut conn).unwrap().is_empty());
+}
+
+#[test]
+fn test_import_verifies_fingerprints() {
+    let mut conn = connection();
+    let schema = conn.register_schema("test.subject".to_string(), fixture("schema.json"));
+    let exported = export(&mut conn);
+
+    let tampered = exported.replace(&schema.fingerprint2.clone().unwrap(), "tampered");
+    let error = import_error(&mut conn, &tampered);
+    assert!(
+        error.starts_with("1 schemas don't match their exported fingerprints"),
+        "{}",
+        error
+    );
+    assert_eq!(Schema::count(&mut conn).unwrap(), 0);
+}
+
+/// A record of the `_schemas` topic, as `kafka-console-consumer` prints it.
+fn topic_record(key: JsonValue, value: Option<JsonValue>) -> String {
+    let value = value.map_or("null".to_string(), |value| value.to_string());
+    format!("{}\t{}\n", key, value)
+}
+
+fn schema_record(subject: &str, version: i32, id: i64, schema: &str, deleted: bool) -> String {
+    topic_record(
+        json!({ "keytype": "SCHEMA", "subject": subject, "version": version, "magic": 1 }),
+        Some(json!({
+            "subject": subject,
+            "version": version,
+            "id": id,
+            "schema": schema,
+            "deleted": deleted,
+        })),
+    )
+}
+
+fn confluent_dump() -> String {
+    let (schema, schema2) = (fixture("schema.json"), fixture("schema2.json"));
+    let old = fixture("schema_backward_compatible.json");
+    [
+        topic_record(
+            json!({ "keytype": "CONFIG", "subject": null, "magic": 0 }),
+            Some(json!({ "compatibilityLevel": "NONE" })),
+        ),
+        schema_record("payments", 1, 21, &schema, false),
+        schema_record("payments", 2, 22, &schema2, false),
+        topic_record(
+            json!({ "keytype": "CONFIG", "subject": "payments", "magic": 0 }),
+            Some(json!({ "compatibilityLevel": "FULL" })),
+        ),
+        schema_record("refunds", 1, 21, &schema, false),
+        schema_record("refunds", 1, 21, &schema, true),
+        topic_record(
+            json!({ "keytype": "DELETE_SUBJECT", "subject": "refunds", "magic": 0 }),
+            Some(json!({ "subject": "refunds", "version": 1 })),
+        ),
+        schema_record("old", 1, 23, &old, false),
+        schema_record("old", 1, 23, &old, true),
+        topic_record(
+            json!({ "keytype": "SCHEMA", "subject": "old", "version": 1, "magic": 1 }),
+            None,
+        ),
+        topic_record(
+            json!({ "keytype": "MODE", "subject": "payments", "magic": 0 }),
+            Some(json!({ "mode": "READONLY" })),
+        ),
+        topic_record(json!({ "keytype": "NOOP", "magic": 0 }), None),
+    ]
+    .concat()
+}
+
+#[test]
+fn test_replay_confluent_dump() {
+    let mut conn = connection();
+    let audit = admin::audit_context();
+    let dump = confluent_dump();
+
+    let summary = admin::replay_confluent(&mut conn, dump.as_bytes(), &audit).unwrap();
+    assert_eq!(
+        summary,
+        ReplaySummary {
+            records: 12,
+            schemas: 3,
+            versions: 4,
+        }
+    );
+    assert_eq!(
+        Subject::distinct_names(&mut conn).unwrap(),
+        vec!["payments".to_string()]
+    );
+    assert_eq!(
+        SchemaVersion::get_schema_id(&mut conn, "payments".to_string(), 2)
+            .unwrap()
+            .0,
+        22
+    );
+    assert!(SchemaVersion::get_schema_id(&mut conn, "refunds".to_string(), 1).is_err());
+    assert_eq!(
+        Config::get_with_subject_name(&mut conn, "payments".to_string()).unwrap(),
+        "FULL"
+    );
+    assert_eq!(Config::get_global_compatibility(&mut conn).unwrap(), "NONE");
+    assert_eq!(
+        Config::get_mode(&mut conn, "payments").unwrap(),
+        Mode::ReadOnly
+    );
+
+    // Replaying it again leaves everything as it was, even though the version deleted
+    // for good is added before its tombstone deletes it again
+    let replayed = without_timestamps(&export(&mut conn));
+    let summary = admin::replay_confluent(&mut conn, dump.as_bytes(), &audit).unwrap();
+    assert_eq!((summary.schemas, summary.versions), (0, 1));
+    assert_eq!(without_timestamps(&export(&mut conn)), replayed);
+
+    // New schemas are given IDs past the replayed ones
+    let registered = Schema::new(&mut conn, r#""string""#.to_string(), "new".to_string()).unwrap();
+    assert!(registered.id > 23);
+}
+
+#[test]
+fn test_replay_confluent_dump_is_extended() {
+    let mut conn = connection();
+    let audit = admin::audit_context();
+    let dump = confluent_dump();
+    admin::replay_confluent(&mut conn, dump.as_bytes(), &audit).unwrap();
+    let replayed = without_timestamps(&export(&mut conn));
+
+    conn.reset();
+    let half = dump.lines().take(5).collect::<Vec<_>>().join("\n");
+    let summary = admin::replay_confluent(&mut conn, half.as_bytes(), &audit).unwrap();
+    assert_eq!((summary.schemas, summary.versions), (2, 3));
+    let summary = admin::replay_confluent(&mut conn, dump.as_bytes(), &audit).unwrap();
+    assert_eq!((summary.schemas, summary.versions), (1, 1));
+    assert_eq!(without_timestamps(&export(&mut conn)), replayed);
+}
+
+#[test]
+fn test_replay_confluent_dump_keeps_ids() {
+    let mut conn = connection();
+    let dump = [
+        schema_record("payments", 1, 21, &fixture("schema.json"), false),
+        schema_record("refunds", 1, 21, &fixture("schema2.json"), false),
+    ]
+    .concat();
+
+    let error = admin::replay_confluent(&mut conn, dump.as_bytes(), &admin::audit_context())
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        error,
+        "line 2: invalid dump: schema 21 isn't the one already imported with that ID"
+    );
+    // Records before the failing one are rolled back along with it
+    assert!(Subject::distinct_names(&mut conn).unwrap().is_empty());
+}
+
+#[test]
+fn test_adopt_salsify_rewrites_fingerprints() {
+    use avro_schema_registry::db::models::schema::schemas::dsl::{
+        fingerprint, fingerprint2, schemas,
+    };
+
+    let mut conn = connection();
+    let audit = admin::audit_context();
+    // Salsify's registry stores its own fingerprint2, or none at all
+    let replaced = conn.register_schema("test.subject".to_string(), fixture("schema.json"));
+    diesel::update(schemas.find(replaced.id))
+        .set(fingerprint2.eq("salsify-v2"))
+        .execute(&mut conn)
+        .unwrap();
+    let adopted = conn.register_schema("users.subject".to_string(), fixture("users.json"));
+    diesel::update(schemas.find(adopted.id))
+        .set(fingerprint2.eq(None::<String>))
+        .execute(&mut conn)
+        .unwrap();
+    let bogus = conn.register_schema("other.subject".to_string(), fixture("schema2.json"));
+    diesel::update(schemas.find(bogus.id))
+        .set((fingerprint.eq("bogus"), fingerprint2.eq(None::<String>)))
+        .execute(&mut conn)
+        .unwrap();
+
+    let adoption = admin::adopt_salsify(&mut conn, true, &audit).unwrap();
+    assert_eq!((adoption.schemas, adoption.updated), (3, 2));
+    assert_eq!(admin::verify(&mut conn).unwrap().len(), 3);
+
+    let adoption = admin::adopt_salsify(&mut conn, false, &audit).unwrap();
+    assert!(adoption.baseline.is_empty());
+    assert_eq!(adoption.updated, 2);
+    assert_eq!(adoption.problems.len(), 1);
+    assert!(matches!(
+        adoption.problems[0],
+        Problem::Fingerprint { id, .. } if id == bogus.id
+    ));
+    // Salsify's fingerprint2 is replaced, and only the schema that can't be used is left
+    // as it was
+    let stored = schemas
+        .find(replaced.id)
+        .select(fingerprint2)
+        .first::<Option<String>>(&mut conn)
+        .unwrap();
+    assert_ne!(stored.as_deref(), Some("salsify-v2"));
+    let drifts = admin::verify(&mut conn).unwrap();
+    assert_eq!(drifts.len(), 1);
+    assert!(matches!(drifts[0], Drift::Fingerprint { id, .. } if id == bogus.id));
+}
+
+#[test]
+fn test_schemas_registered_by_salsify_are_found() {
+    use avro_schema_registry::db::models::schema::schemas::dsl::{fingerprint2, schemas};
+
+    let mut conn = connection();
+    let registered = conn.register_schema("test.subject".to_string(), fixture("schema.json"));
+    diesel::update(schemas.find(registered.id))
+        .set(fingerprint2.eq(None::<String>))
+        .execute(&mut conn)
+        .unwrap();
+
+    let found = conn.register_schema("other.subject".to_string(), fixture("schema.json"));
+    assert_eq!(found.id, registered.id);
+}
+
+#[test]
+fn test_check_compatibility() {
+    let mut conn = connection();
+    conn.register_schema("test.subject".to_string(), fixture("schema.json"));
+
+    let compatible = fixture("schema_backward_compatible.json");
+    assert!(admin::check(&mut conn, "test.subject", &compatible, &[]).unwrap());
+    let incompatible = fixture("schema2.json");
+    assert!(!admin::check(&mut conn, "test.subject", &incompatible, &[]).unwrap());
+    assert!(admin::check(&mut conn, "test.subject", "nope", &[]).is_err());
+}
+
+#[test]
+fn test_gc_deletes_orphan_schemas() {
+    let mut conn = connection();
+    let registered = conn.register_schema("test.subject".to_string(), fixture("schema.json"));
+    let orphan = Schema::new(
+        &mut conn,
+        r#""string""#.to_string(),
+        "orphan-fingerprint".to_string(),
+    )
+    .unwrap();
+
+    let orphans = Schema::orphans(&mut conn).unwrap();
+    assert_eq!(
+        orphans.iter().map(|schema| schema.id).collect::<Vec<_>>(),
+        vec![orphan.id]
+    );
+    assert_eq!(
+        admin::collect_garbage(&mut conn, &admin::audit_context()).unwrap(),
+        vec![orphan.id]
+    );
+    assert!(Schema::get_by_id(&mut conn, orphan.id).is_err());
+    assert!(Schema::get_by_id(&mut conn, registered.id).is_ok());
+    assert!(admin::collect_garbage(&mut conn, &admin::audit_context())
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_verify_accepts_fingerprints_stored_in_full() {
+    use avro_schema_registry::db::models::schema::schemas::dsl::{fingerprint, schemas};
+
+    let mut conn = connection();
+    // Before using salsify's format, this registry stored fingerprints in full, leading
+    // zeros included, which about one schema in sixteen has
+    let schema = (0..1000)
+        .map(|i| {
+            let record = format!(r#"{{"type":"record","name":"R{}","fields":[]}}"#, i);
+            conn.register_schema(format!("subject.{}", i), record)
+        })
+        .find(|schema| schema.fingerprint2.as_deref().unwrap().starts_with('0'))
+        .unwrap();
+    diesel::update(schemas.find(schema.id))
+        .set(fingerprint.eq(schema.fingerprint2.clone().unwrap()))
+        .execute(&mut conn)
+        .unwrap();
+    assert!(admin::verify(&mut conn).unwrap().is_empty());
+
+    let exported = export(&mut conn);
+    conn.reset();
+    admin::import(&mut conn, exported.as_bytes(), &admin::audit_context()).unwrap();
+}
+
+#[test]
+fn test_verify_reports_drift() {
+    use avro_schema_registry::db::models::schema::schemas::dsl::{fingerprint2, schemas};
+
+    let mut conn = connection();
+    conn.register_schema("test.subject".to_string(), fixture("schema.json"));
+    let drifted = conn.register_schema("other.subject".to_string(), fixture("schema2.json"));
+    assert!(admin::verify(&mut conn).unwrap().is_empty());
+
+    diesel::update(schemas.find(drifted.id))
+        .set(fingerprint2.eq("stale"))
+        .execute(&mut conn)
+        .unwrap();
+    let unparseable =
+        Schema::new(&mut conn, "nope".to_string(), "unparseable".to_string()).unwrap();
+
+    let drifts = admin::verify(&mut conn).unwrap();
+    assert_eq!(drifts.len(), 2);
+    match &drifts[0] {
+        Drift::Fingerprint {
+            id,
+            fingerprint,
+            fingerprint2: stored,
+            computed,
+        } => {
+            assert_eq!(*id, drifted.id);
+            assert_eq!(*fingerprint, Schema::salsify_fingerprint(computed));
+            assert_eq!(stored.as_deref(), Some("stale"));
+        }
+        drift => panic!("unexpected drift {:?}", drift),
+    }
+    assert!(matches!(drifts[1], Drift::Unparseable { id, .. } if id == unparseable.id));
+}
@@ -0,0 +1,212 @@
+use actix_web::http;
+use chrono::{Duration, Utc};
+
+use crate::common::server::{setup, UNAUTHENTICATED};
+use avro_schema_registry::auth::Role;
+use avro_schema_registry::db::models::{ApiKey, ApiKeyBody, ApiKeyWithSecret};
+use avro_schema_registry::db::DbConnection;
+
+fn create_key(conn: &mut DbConnection, role: Role) -> ApiKeyWithSecret {
+    ApiKey::create(
+        conn,
+        ApiKeyBody {
+            owner: String::from("payments"),
+            role,
+            expires_at: None,
+        },
+    )
+    .unwrap()
+}
+
+#[actix_rt::test]
+async fn test_create_api_key() {
+    let (server, _) = setup();
+
+    server
+        .test(
+            http::Method::POST,
+            "/keys",
+            Some(json!({"owner": "payments", "role": "writer"})),
+            http::StatusCode::OK,
+            r#"^\{"id":\d+,"key_id":"ak_[0-9a-f]{16}","owner":"payments","role":"writer","expires_at":null,"last_used_at":null,"revoked_at":null,"created_at":"[^"]+","updated_at":"[^"]+","secret":"[\w-]{43}"\}$"#,
+        )
+        .await;
+    // the secret is never listed
+    server
+        .test(
+            http::Method::GET,
+            "/keys",
+            None,
+            http::StatusCode::OK,
+            r#"^\[\{"id":\d+,"key_id":"ak_[0-9a-f]{16}","owner":"payments","role":"writer","expires_at":null,"last_used_at":null,"revoked_at":null,"created_at":"[^"]+","updated_at":"[^"]+"\}\]$"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_authenticate_with_api_key() {
+    let (server, mut conn) = setup();
+    let created = create_key(&mut conn, Role::Reader);
+    let credentials = (created.key.key_id.as_str(), created.secret.as_str());
+
+    server
+        .test_as(
+            credentials,
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::OK,
+            r"\[\]",
+        )
+        .await;
+    // the key has the role it was created with
+    server
+        .test_as(
+            credentials,
+            http::Method::PUT,
+            "/config",
+            Some(json!({"compatibility": "FULL"})),
+            http::StatusCode::FORBIDDEN,
+            r#"\{"error_code":40301,"message":"User is denied operation"\}"#,
+        )
+        .await;
+    server
+        .test_as(
+            (credentials.0, "not_the_secret"),
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::UNAUTHORIZED,
+            UNAUTHENTICATED,
+        )
+        .await;
+    server
+        .test(
+            http::Method::GET,
+            "/keys",
+            None,
+            http::StatusCode::OK,
+            r#""last_used_at":"[^"]+""#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_rotate_and_revoke_api_key() {
+    let (server, mut conn) = setup();
+    let created = create_key(&mut conn, Role::Reader);
+    let key_id = created.key.key_id.as_str();
+    // verified secrets are cached, which must not outlive a rotation or a revocation
+    server
+        .test_as(
+            (key_id, created.secret.as_str()),
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::OK,
+            r"\[\]",
+        )
+        .await;
+
+    server
+        .test(
+            http::Method::POST,
+            &format!("/keys/{}/rotate", key_id),
+            None,
+            http::StatusCode::OK,
+            r#""secret":"[\w-]{43}""#,
+        )
+        .await;
+    // the previous secret stopped working
+    server
+        .test_as(
+            (key_id, created.secret.as_str()),
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::UNAUTHORIZED,
+            UNAUTHENTICATED,
+        )
+        .await;
+
+    let rotated = ApiKey::rotate(&mut conn, key_id).unwrap();
+    server
+        .test_as(
+            (key_id, rotated.secret.as_str()),
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::OK,
+            r"\[\]",
+        )
+        .await;
+    server
+        .test(
+            http::Method::DELETE,
+            &format!("/keys/{}", key_id),
+            None,
+            http::StatusCode::OK,
+            r#""revoked_at":"[^"]+""#,
+        )
+        .await;
+    server
+        .test_as(
+            (key_id, rotated.secret.as_str()),
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::UNAUTHORIZED,
+            UNAUTHENTICATED,
+        )
+        .await;
+    server
+        .test(
+            http::Method::DELETE,
+            &format!("/keys/{}", key_id),
+            None,
+            http::StatusCode::NOT_FOUND,
+            r#"\{"error_code":40491,"message":"API key not found"\}"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_expired_api_key() {
+    let (server, mut conn) = setup();
+    let created = ApiKey::create(
+        &mut conn,
+        ApiKeyBody {
+            owner: String::from("payments"),
+            role: Role::Admin,
+            expires_at: Some(Utc::now() - Duration::minutes(1)),
+        },
+    )
+    .unwrap();
+
+    server
+        .test_as(
+            (created.key.key_id.as_str(), created.secret.as_str()),
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::UNAUTHORIZED,
+            UNAUTHENTICATED,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_api_keys_require_admin() {
+    let (server, _) = setup();
+
+    server
+        .test_as(
+            ("writer", "writer_password"),
+            http::Method::POST,
+            "/keys",
+            Some(json!({"owner": "payments", "role": "admin"})),
+            http::StatusCode::FORBIDDEN,
+            r#"\{"error_code":40301,"message":"User is denied operation"\}"#,
+        )
+        .await;
+}
@@ -0,0 +1,138 @@
+use actix_web::http;
+
+use crate::common::server::setup;
+use avro_schema_registry::api::SchemaBody;
+
+#[actix_rt::test]
+async fn test_audit_without_events() {
+    let (server, _) = setup();
+
+    // returns empty list
+    server
+        .test(
+            http::Method::GET,
+            "/audit",
+            None,
+            http::StatusCode::OK,
+            r"\[\]",
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_audit_records_every_mutation_under_subject() {
+    let (server, _) = setup();
+    let schema_s = std::fs::read_to_string("tests/fixtures/schema.json").unwrap();
+    let schema = SchemaBody { schema: schema_s };
+
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(json!(schema)),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+    // registering the same schema again changes nothing, so it isn't recorded
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(json!(schema)),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::PUT,
+            "/config/test.subject",
+            Some(json!({"compatibility": "FULL"})),
+            http::StatusCode::OK,
+            r#"\{"compatibility":"FULL"\}"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/test.subject",
+            None,
+            http::StatusCode::OK,
+            r"\[1\]",
+        )
+        .await;
+
+    // it returns the events newest first
+    server
+        .test(
+            http::Method::GET,
+            "/audit?subject=test.subject",
+            None,
+            http::StatusCode::OK,
+            &[
+                r#"^\[\{"id":\d+,"action":"DELETE_SUBJECT","subject":"test.subject","actor":"test_user","request_id":"[^"]+","old_value":\{"versions":\[1\]\},"new_value":null,"created_at":"[^"]+"\},"#,
+                r#"\{"id":\d+,"action":"SET_SUBJECT_CONFIG","subject":"test.subject","actor":"test_user","request_id":"[^"]+","old_value":null,"new_value":\{"compatibility":"FULL"\},"created_at":"[^"]+"\},"#,
+                r#"\{"id":\d+,"action":"REGISTER_SCHEMA","subject":"test.subject","actor":"test_user","request_id":"[^"]+","old_value":null,"new_value":\{"id":\d+,"version":1\},"created_at":"[^"]+"\}\]$"#,
+            ]
+            .concat(),
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_audit_records_global_config_change() {
+    let (server, _) = setup();
+
+    server
+        .test(
+            http::Method::PUT,
+            "/config",
+            Some(json!({"compatibility": "FULL"})),
+            http::StatusCode::OK,
+            r#"\{"compatibility":"FULL"\}"#,
+        )
+        .await;
+
+    server
+        .test(
+            http::Method::GET,
+            "/audit?actor=test_user&from=2000-01-01T00:00:00Z",
+            None,
+            http::StatusCode::OK,
+            r#"^\[\{"id":\d+,"action":"SET_GLOBAL_CONFIG","subject":null,"actor":"test_user","request_id":"[^"]+","old_value":\{"compatibility":"BACKWARD"\},"new_value":\{"compatibility":"FULL"\},"created_at":"[^"]+"\}\]$"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_audit_with_filters_matching_nothing() {
+    let (server, _) = setup();
+
+    server
+        .test(
+            http::Method::PUT,
+            "/config",
+            Some(json!({"compatibility": "FULL"})),
+            http::StatusCode::OK,
+            r#"\{"compatibility":"FULL"\}"#,
+        )
+        .await;
+
+    for path in [
+        "/audit?actor=someone_else",
+        "/audit?subject=test.subject",
+        "/audit?to=2000-01-01T00:00:00Z",
+        "/audit?offset=1",
+    ] {
+        server
+            .test(
+                http::Method::GET,
+                path,
+                None,
+                http::StatusCode::OK,
+                r"^\[\]$",
+            )
+            .await;
+    }
+}
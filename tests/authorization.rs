@@ -0,0 +1,179 @@
+use actix_web::http;
+
+use crate::common::server::{setup, UNAUTHENTICATED};
+use avro_schema_registry::api::SchemaBody;
+
+const READER: (&str, &str) = ("reader", "reader_password");
+const WRITER: (&str, &str) = ("writer", "writer_password");
+const ADMIN: (&str, &str) = ("admin", "admin_password");
+
+const DENIED: &str = r#"^\{"error_code":40301,"message":"User is denied operation"\}$"#;
+
+fn schema() -> serde_json::Value {
+    let schema_s = std::fs::read_to_string("tests/fixtures/schema.json").unwrap();
+    json!(SchemaBody { schema: schema_s })
+}
+
+#[actix_rt::test]
+async fn test_reader_can_only_read() {
+    let (server, _) = setup();
+
+    server
+        .test_as(
+            READER,
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::OK,
+            r"\[\]",
+        )
+        .await;
+    server
+        .test_as(
+            READER,
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(schema()),
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+    server
+        .test_as(
+            READER,
+            http::Method::PUT,
+            "/config",
+            Some(json!({"compatibility": "FULL"})),
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_writer_can_register_but_not_delete() {
+    let (server, _) = setup();
+
+    server
+        .test_as(
+            WRITER,
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(schema()),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::GET,
+            "/subjects/test.subject/versions",
+            None,
+            http::StatusCode::OK,
+            r"\[1\]",
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::DELETE,
+            "/subjects/test.subject",
+            None,
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::PUT,
+            "/config/test.subject",
+            Some(json!({"compatibility": "FULL"})),
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::GET,
+            "/audit",
+            None,
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+    server
+        .test_as(
+            WRITER,
+            http::Method::GET,
+            "/admin/export",
+            None,
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_admin_can_delete_and_change_config() {
+    let (server, _) = setup();
+
+    server
+        .test_as(
+            ADMIN,
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(schema()),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+    server
+        .test_as(
+            ADMIN,
+            http::Method::PUT,
+            "/config/test.subject",
+            Some(json!({"compatibility": "FULL"})),
+            http::StatusCode::OK,
+            r#"\{"compatibility":"FULL"\}"#,
+        )
+        .await;
+    server
+        .test_as(
+            ADMIN,
+            http::Method::DELETE,
+            "/subjects/test.subject",
+            None,
+            http::StatusCode::OK,
+            r"\[1\]",
+        )
+        .await;
+    server
+        .test_as(
+            ADMIN,
+            http::Method::GET,
+            "/audit?actor=admin",
+            None,
+            http::StatusCode::OK,
+            r#""action":"DELETE_SUBJECT""#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_user_with_wrong_password_is_rejected() {
+    let (server, _) = setup();
+
+    server
+        .test_as(
+            ("reader", "admin_password"),
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::UNAUTHORIZED,
+            UNAUTHENTICATED,
+        )
+        .await;
+}
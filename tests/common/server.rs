@@ -1,18 +1,29 @@
+use std::time::Duration;
+
 use actix_test as test;
 use actix_web::{
     error::PayloadError,
     http,
-    web::{Bytes, Data},
+    web::{self, Bytes, Data},
     App,
 };
+use actix_web_prom::PrometheusMetricsBuilder;
 use awc::{ClientRequest, ClientResponse};
 use futures::{executor::block_on, stream::Stream};
+use prometheus::Registry;
 use serde_json::Value as JsonValue;
 
-use super::settings::get_schema_registry_password;
+use super::settings::{configure_auth, get_schema_registry_password};
 use crate::db::DbAuxOperations;
 use avro_schema_registry::app;
-use avro_schema_registry::db::{DbConnection, DbManage, DbPool};
+use avro_schema_registry::auth::DenyByDefault;
+use avro_schema_registry::db::{DbConnection, DbManage, DbPool, DbPoolConfig, DbReadPool};
+use avro_schema_registry::metrics::Metrics;
+use avro_schema_registry::middleware::{Leader, RateLimiter, RequestTimeout};
+
+pub const TEST_USER: &str = "test_user";
+pub const UNAUTHENTICATED: &str =
+    r#"^\{"error_code":40101,"message":"Missing or invalid credentials"\}$"#;
 
 pub struct ApiTesterServer(test::TestServer);
 
@@ -24,16 +35,148 @@ pub fn setup() -> (ApiTesterServer, DbConnection) {
     (server, conn)
 }
 
+pub fn configure_db(cfg: &mut web::ServiceConfig) {
+    configure_pool(cfg, DbPool::new_pool(Some(1)));
+}
+
+fn configure_pool(cfg: &mut web::ServiceConfig, db_pool: DbPool) {
+    // Metrics that no endpoint serves, for servers that don't look at them
+    let metrics = Metrics::new(&Registry::new()).unwrap();
+    configure_pool_with_metrics(cfg, db_pool, Data::new(metrics));
+}
+
+fn configure_pool_with_metrics(
+    cfg: &mut web::ServiceConfig,
+    db_pool: DbPool,
+    metrics: Data<Metrics>,
+) {
+    cfg.app_data(Data::new(DbReadPool::new(db_pool.clone(), None)))
+        .app_data(Data::new(db_pool))
+        .app_data(metrics);
+}
+
 impl ApiTesterServer {
     pub fn new() -> Self {
+        configure_auth();
         Self(test::start(|| {
             App::new()
                 .configure(app::monitoring_routing)
-                .app_data(Data::new(DbPool::new_pool(Some(1))))
+                .configure(configure_db)
+                .configure(app::api_routing)
+        }))
+    }
+
+    /// Starts a server with a single worker thread, so that anything blocking that
+    /// worker is visible to every other request.
+    pub fn with_single_worker() -> Self {
+        configure_auth();
+        Self(test::start_with(test::config().workers(1), || {
+            App::new()
+                .configure(app::monitoring_routing)
+                .configure(configure_db)
+                .configure(app::api_routing)
+        }))
+    }
+
+    /// Starts a server whose workers share `rate_limiter`.
+    pub fn with_rate_limiter(rate_limiter: RateLimiter) -> Self {
+        configure_auth();
+        let rate_limiter = Data::new(rate_limiter);
+        Self(test::start(move || {
+            App::new()
+                .configure(configure_db)
+                .app_data(rate_limiter.clone())
                 .configure(app::api_routing)
         }))
     }
 
+    /// Starts a server whose requests time out after `request_timeout`.
+    pub fn with_request_timeout(request_timeout: RequestTimeout) -> Self {
+        configure_auth();
+        let request_timeout = Data::new(request_timeout);
+        Self(test::start(move || {
+            App::new()
+                .configure(configure_db)
+                .app_data(request_timeout.clone())
+                .configure(app::api_routing)
+        }))
+    }
+
+    /// Starts a server denying principals access to subjects none of their ACL rules
+    /// match.
+    pub fn with_acl_deny_by_default() -> Self {
+        configure_auth();
+        Self(test::start(|| {
+            App::new()
+                .configure(configure_db)
+                .app_data(Data::new(DenyByDefault))
+                .configure(app::api_routing)
+        }))
+    }
+
+    /// Starts a server whose workers share `db_pool`.
+    pub fn with_pool(db_pool: DbPool) -> Self {
+        configure_auth();
+        Self(test::start(move || {
+            App::new()
+                .configure(|cfg| configure_pool(cfg, db_pool.clone()))
+                .configure(app::monitoring_routing)
+                .configure(app::api_routing)
+        }))
+    }
+
+    /// Starts a server serving the registry's metrics on `/_/metrics`, refreshing them
+    /// every `refresh_interval`.
+    pub fn with_metrics(refresh_interval: Duration) -> Self {
+        configure_auth();
+        let registry = Registry::new();
+        let metrics = Data::new(Metrics::new(&registry).unwrap());
+        let prometheus = PrometheusMetricsBuilder::new("avro_schema_registry")
+            .endpoint("/_/metrics")
+            .registry(registry)
+            .build()
+            .unwrap();
+        let db_pool = DbPool::from_config_with_events(
+            &DbPoolConfig {
+                min_idle: Some(0),
+                max_size: 2,
+                ..DbPoolConfig::from_env()
+            },
+            metrics.pool_events(),
+        );
+        Metrics::refresh_periodically(metrics.clone(), db_pool.clone(), refresh_interval);
+        Self(test::start(move || {
+            App::new()
+                .wrap(prometheus.clone())
+                .configure(|cfg| configure_pool_with_metrics(cfg, db_pool.clone(), metrics.clone()))
+                .configure(app::monitoring_routing)
+                .configure(app::api_routing)
+        }))
+    }
+
+    /// Starts a server forwarding every write to `leader`.
+    pub fn with_leader(leader: Leader) -> Self {
+        configure_auth();
+        let leader = Data::new(leader);
+        Self(test::start(move || {
+            App::new()
+                .configure(configure_db)
+                .app_data(leader.clone())
+                .configure(app::api_routing)
+        }))
+    }
+
+    /// URL of the server, without a trailing slash.
+    pub fn url(&self) -> String {
+        let Self(server) = self;
+        server.url("").trim_end_matches('/').to_string()
+    }
+
+    pub fn request(&self, method: http::Method, path: &str) -> ClientRequest {
+        let Self(server) = self;
+        server.request(method, server.url(path)).avro_headers()
+    }
+
     pub async fn test(
         &self,
         method: http::Method,
@@ -42,9 +185,45 @@ impl ApiTesterServer {
         expected_status: http::StatusCode,
         expected_body: &str,
     ) {
-        let Self(server) = self;
-        let req = server.request(method, server.url(path)).avro_headers();
+        let req = self.request(method, path);
+        Self::send(req, request_body, expected_status, expected_body).await;
+    }
+
+    /// Same as [`ApiTesterServer::test`], authenticating with the given username and
+    /// password instead of the shared registry password.
+    pub async fn test_as(
+        &self,
+        (username, password): (&str, &str),
+        method: http::Method,
+        path: &str,
+        request_body: Option<JsonValue>,
+        expected_status: http::StatusCode,
+        expected_body: &str,
+    ) {
+        let req = self.request(method, path).basic_auth(username, password);
+        Self::send(req, request_body, expected_status, expected_body).await;
+    }
 
+    /// Same as [`ApiTesterServer::test`], authenticating with a bearer token.
+    pub async fn test_with_token(
+        &self,
+        token: &str,
+        method: http::Method,
+        path: &str,
+        request_body: Option<JsonValue>,
+        expected_status: http::StatusCode,
+        expected_body: &str,
+    ) {
+        let req = self.request(method, path).bearer_auth(token);
+        Self::send(req, request_body, expected_status, expected_body).await;
+    }
+
+    async fn send(
+        req: ClientRequest,
+        request_body: Option<JsonValue>,
+        expected_status: http::StatusCode,
+        expected_body: &str,
+    ) {
         match request_body {
             Some(b) => req
                 .send_json(&b)
@@ -69,11 +248,11 @@ impl AvroRequest for ClientRequest {
     fn avro_headers(self) -> Self {
         self.insert_header((http::header::CONTENT_TYPE, "application/json"))
             .insert_header((http::header::ACCEPT, "application/vnd.schemaregistry+json"))
-            .basic_auth("", get_schema_registry_password())
+            .basic_auth(TEST_USER, get_schema_registry_password())
     }
 }
 
-trait ValidateResponse {
+pub trait ValidateResponse {
     fn validate(self, expected_status: http::StatusCode, expected_body: &str);
 }
 
@@ -90,6 +269,10 @@ where
             .collect::<String>()
             .replace("\\r\\n", "")
             .replace("\\n", "");
+        // Error bodies end with the ID of the request, which differs every time
+        let s = regex::Regex::new(r#","request_id":"[^"]+"\}$"#)
+            .unwrap()
+            .replace(&s, "}");
 
         match regex::Regex::new(expected_body_regex) {
             Ok(re) => {
@@ -1,5 +1,28 @@
 use std::env;
 
+pub const JWT_SECRET: &str = "test_jwt_secret";
+pub const JWT_ISSUER: &str = "https://idp.example.com";
+pub const JWT_AUDIENCE: &str = "schema-registry";
+pub const LEADER_SECRET: &str = "test_leader_secret";
+
 pub fn get_schema_registry_password() -> String {
     env::var("SCHEMA_REGISTRY_PASSWORD").unwrap_or_else(|_| "test_password".to_string())
 }
+
+/// Points the server at the test users (see `tests/fixtures/users.json`), unless another
+/// users file was given, makes the shared password authenticate `TEST_USER` and lets the
+/// server accept tokens signed with [`JWT_SECRET`], and the principals followers sharing
+/// [`LEADER_SECRET`] forward requests on behalf of.
+pub fn configure_auth() {
+    if env::var("SCHEMA_REGISTRY_USERS_FILE").is_err() {
+        env::set_var("SCHEMA_REGISTRY_USERS_FILE", "tests/fixtures/users.json");
+    }
+    env::set_var(
+        "SCHEMA_REGISTRY_PASSWORD_PRINCIPAL",
+        super::server::TEST_USER,
+    );
+    env::set_var("SCHEMA_REGISTRY_JWT_HS256_SECRET", JWT_SECRET);
+    env::set_var("SCHEMA_REGISTRY_JWT_ISSUER", JWT_ISSUER);
+    env::set_var("SCHEMA_REGISTRY_JWT_AUDIENCE", JWT_AUDIENCE);
+    env::set_var("SCHEMA_REGISTRY_LEADER_SECRET", LEADER_SECRET);
+}
@@ -184,3 +184,49 @@ async fn test_schema_for_full_compatibility_with_subject_and_with_version() {
         )
         .await;
 }
+
+#[actix_rt::test]
+async fn test_register_incompatible_schema() {
+    let (server, mut conn) = setup();
+    conn.create_test_subject_with_config("BACKWARD");
+
+    let schema_s = std::fs::read_to_string("tests/fixtures/schema.json").unwrap();
+    let _ = conn.register_schema(String::from("test.subject"), schema_s.to_string());
+
+    let schema_forward_compatible_s =
+        std::fs::read_to_string("tests/fixtures/schema_forward_compatible.json").unwrap();
+    let schema_forward_compatible = SchemaBody {
+        schema: schema_forward_compatible_s.to_string(),
+    };
+
+    // returns 409 with 'Schema being registered is incompatible with an earlier schema'
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(json!(schema_forward_compatible)),
+            http::StatusCode::CONFLICT,
+            r#"\{"error_code":40901,"message":"Schema being registered is incompatible with an earlier schema"\}"#,
+        )
+        .await;
+
+    // without compatibility checks it is registered as the next version
+    server
+        .test(
+            http::Method::PUT,
+            "/config/test.subject",
+            Some(json!({"compatibility": "NONE"})),
+            http::StatusCode::OK,
+            r#"\{"compatibility":"NONE"\}"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(json!(schema_forward_compatible)),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+}
@@ -0,0 +1,84 @@
+use std::sync::Barrier;
+use std::thread;
+
+use avro_schema_registry::db::models::{RegisterSchema, Schema, SchemaVersion};
+use avro_schema_registry::db::{DbManage, DbPool};
+
+use crate::db::DbAuxOperations;
+
+const WRITERS: usize = 8;
+
+fn schema_with_field(field: &str) -> String {
+    format!(
+        r#"{{"type": "record", "name": "test", "fields": [{{"type": "string", "name": "{}", "default": ""}}]}}"#,
+        field
+    )
+}
+
+/// Registers every schema from its own thread and connection, all released at once.
+fn register_concurrently(subject: &str, schemas: Vec<String>) -> Vec<Schema> {
+    let pool = DbPool::new_pool(Some(schemas.len() as u32));
+    let barrier = Barrier::new(schemas.len());
+
+    thread::scope(|scope| {
+        schemas
+            .into_iter()
+            .map(|schema| {
+                let (pool, barrier) = (&pool, &barrier);
+                scope.spawn(move || {
+                    let mut conn = pool.connection().unwrap();
+                    barrier.wait();
+                    Schema::register_new_version(
+                        &mut conn,
+                        RegisterSchema {
+                            subject: subject.to_string(),
+                            schema,
+                            references: vec![],
+                        },
+                    )
+                    .unwrap()
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+#[test]
+fn test_concurrent_registrations_get_distinct_versions() {
+    let mut conn = DbPool::new_pool(Some(1)).connection().unwrap();
+    conn.reset();
+
+    let schemas = (0..WRITERS)
+        .map(|i| schema_with_field(&format!("field{}", i)))
+        .collect();
+    let mut ids = register_concurrently("test.subject", schemas)
+        .into_iter()
+        .map(|schema| schema.id)
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), WRITERS);
+
+    let versions =
+        SchemaVersion::versions_with_subject_name(&mut conn, String::from("test.subject")).unwrap();
+    assert_eq!(versions, (1..=WRITERS as i32).map(Some).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_concurrent_registrations_of_the_same_schema_are_idempotent() {
+    let mut conn = DbPool::new_pool(Some(1)).connection().unwrap();
+    conn.reset();
+
+    let schemas = vec![schema_with_field("field1"); WRITERS];
+    let registered = register_concurrently("test.subject", schemas);
+    assert!(registered
+        .iter()
+        .all(|schema| schema.id == registered[0].id));
+
+    let versions =
+        SchemaVersion::versions_with_subject_name(&mut conn, String::from("test.subject")).unwrap();
+    assert_eq!(versions, vec![Some(1)]);
+}
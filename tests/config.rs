@@ -129,3 +129,31 @@ async fn test_update_compatibility_level_with_non_existent_subject() {
         )
         .await;
 }
+
+#[actix_rt::test]
+async fn test_get_compatibility_level_with_subject_without_config() {
+    let (server, mut conn) = setup();
+    conn.add_subjects(vec![String::from("test.subject")]);
+
+    // returns 404 with 'Subject does not have subject-level compatibility configured'
+    server
+        .test(
+            http::Method::GET,
+            "/config/test.subject",
+            None,
+            http::StatusCode::NOT_FOUND,
+            r#"\{"error_code":40408,"message":"Subject does not have subject-level compatibility configured"\}"#,
+        )
+        .await;
+
+    // with defaultToGlobal it returns the global compatibility
+    server
+        .test(
+            http::Method::GET,
+            "/config/test.subject?defaultToGlobal=true",
+            None,
+            http::StatusCode::OK,
+            r#"\{"compatibility":"BACKWARD"\}"#,
+        )
+        .await;
+}
@@ -0,0 +1,95 @@
+use actix_web::http;
+
+use crate::common::server::{setup, ApiTesterServer};
+
+async fn get_subjects(
+    server: &ApiTesterServer,
+    accept: &str,
+) -> (http::StatusCode, Option<String>) {
+    let response = server
+        .request(http::Method::GET, "/subjects")
+        .insert_header((http::header::ACCEPT, accept))
+        .send()
+        .await
+        .unwrap();
+    let content_type = response
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .map(|value| value.to_str().unwrap().to_string());
+    (response.status(), content_type)
+}
+
+#[actix_rt::test]
+async fn test_accept_header_negotiation() {
+    let (server, _) = setup();
+
+    for (accept, media_type) in [
+        (
+            "application/vnd.schemaregistry.v1+json, application/json;q=0.9",
+            "application/vnd.schemaregistry.v1+json",
+        ),
+        ("*/*", "application/vnd.schemaregistry.v1+json"),
+        ("application/json", "application/json"),
+        (
+            "application/vnd.schemaregistry+json",
+            "application/vnd.schemaregistry+json",
+        ),
+    ] {
+        assert_eq!(
+            get_subjects(&server, accept).await,
+            (http::StatusCode::OK, Some(media_type.to_string())),
+            "{}",
+            accept
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn test_accept_header_missing() {
+    let (server, _) = setup();
+
+    let mut request = server.request(http::Method::GET, "/subjects");
+    request.headers_mut().remove(http::header::ACCEPT);
+    let response = request.send().await.unwrap();
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert_eq!(
+        response.headers().get(http::header::CONTENT_TYPE).unwrap(),
+        "application/vnd.schemaregistry.v1+json"
+    );
+}
+
+#[actix_rt::test]
+async fn test_not_acceptable() {
+    let (server, _) = setup();
+
+    let mut response = server
+        .request(http::Method::GET, "/subjects")
+        .insert_header((http::header::ACCEPT, "text/html"))
+        .insert_header(("X-Request-Id", "test-request"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), http::StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(
+        response.body().await.unwrap(),
+        r#"{"error_code":406,"message":"None of the accepted media types can be produced","request_id":"test-request"}"#
+    );
+}
+
+#[actix_rt::test]
+async fn test_unsupported_content_type() {
+    let (server, _) = setup();
+
+    let mut response = server
+        .request(http::Method::POST, "/subjects/test.subject/versions")
+        .insert_header((http::header::CONTENT_TYPE, "text/plain"))
+        .insert_header(("X-Request-Id", "test-request"))
+        .send_body("{\"type\": \"string\"}")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(
+        response.body().await.unwrap(),
+        r#"{"error_code":415,"message":"Unsupported media type","request_id":"test-request"}"#
+    );
+}
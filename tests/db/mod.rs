@@ -21,13 +21,21 @@ pub trait DbAuxOperations {
 
 impl DbAuxOperations for DbConnection {
     fn reset(&mut self) {
+        use avro_schema_registry::db::models::schema::acl_rules::dsl::acl_rules;
+        use avro_schema_registry::db::models::schema::api_keys::dsl::api_keys;
+        use avro_schema_registry::db::models::schema::audit_events::dsl::audit_events;
         use avro_schema_registry::db::models::schema::configs::dsl::configs;
+        use avro_schema_registry::db::models::schema::schema_references::dsl::schema_references;
         use avro_schema_registry::db::models::schema::schema_versions::dsl::schema_versions;
         use avro_schema_registry::db::models::schema::schemas::dsl::schemas;
         use avro_schema_registry::db::models::schema::subjects::dsl::subjects;
 
         self.transaction::<_, diesel::result::Error, _>(|conn| {
+            diesel::delete(acl_rules).execute(conn)?;
+            diesel::delete(api_keys).execute(conn)?;
+            diesel::delete(audit_events).execute(conn)?;
             diesel::delete(configs).execute(conn)?;
+            diesel::delete(schema_references).execute(conn)?;
             diesel::delete(schemas).execute(conn)?;
             diesel::delete(subjects).execute(conn)?;
             diesel::delete(schema_versions).execute(conn)
@@ -108,6 +116,14 @@ impl DbAuxOperations for DbConnection {
 
     fn register_schema(&mut self, subject: String, schema: String) -> Schema {
         use avro_schema_registry::db::models::RegisterSchema;
-        Schema::register_new_version(self, RegisterSchema { subject, schema }).unwrap()
+        Schema::register_new_version(
+            self,
+            RegisterSchema {
+                subject,
+                schema,
+                references: vec![],
+            },
+        )
+        .unwrap()
     }
 }
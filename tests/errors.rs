@@ -0,0 +1,81 @@
+use actix_web::http;
+
+use crate::common::server::{setup, ValidateResponse, UNAUTHENTICATED};
+
+#[actix_rt::test]
+async fn test_malformed_json_body() {
+    let (server, _) = setup();
+
+    let mut response = server
+        .request(http::Method::POST, "/subjects/test.subject/versions")
+        .send_body("{\"schema\": ")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+    let body = response.body().await.unwrap();
+    assert!(std::str::from_utf8(&body)
+        .unwrap()
+        .starts_with(r#"{"error_code":400,"message":"Malformed request: "#));
+
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(json!({"not_schema": "{\"type\": \"string\"}"})),
+            http::StatusCode::BAD_REQUEST,
+            r#"^\{"error_code":400,"message":"Malformed request: missing field `schema`.*"\}$"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_invalid_version_in_path() {
+    let (server, _) = setup();
+
+    server
+        .test(
+            http::Method::GET,
+            "/subjects/test.subject/versions/first",
+            None,
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+            r#"^\{"error_code":42202,"message":"Invalid version: .*first.*"\}$"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_unmatched_route_and_method() {
+    let (server, _) = setup();
+
+    server
+        .test(
+            http::Method::GET,
+            "/not/a/route",
+            None,
+            http::StatusCode::NOT_FOUND,
+            r#"^\{"error_code":404,"message":"Not found"\}$"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::PATCH,
+            "/config",
+            None,
+            http::StatusCode::METHOD_NOT_ALLOWED,
+            r#"^\{"error_code":405,"message":"Method not allowed"\}$"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_unauthenticated() {
+    let (server, _) = setup();
+
+    let mut request = server.request(http::Method::GET, "/subjects");
+    request.headers_mut().remove(http::header::AUTHORIZATION);
+    let response = request.send().await.unwrap();
+    assert!(response
+        .headers()
+        .contains_key(http::header::WWW_AUTHENTICATE));
+    response.validate(http::StatusCode::UNAUTHORIZED, UNAUTHENTICATED);
+}
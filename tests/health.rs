@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use actix_web::http;
+
+use crate::common::server::{setup, ApiTesterServer};
+use avro_schema_registry::db::{DbManage, DbPool, DbPoolConfig};
+
+#[actix_rt::test]
+async fn test_live() {
+    let (server, _) = setup();
+
+    server
+        .test(
+            http::Method::GET,
+            "/_/live",
+            None,
+            http::StatusCode::OK,
+            r#"^\{"status":"alive"\}$"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_ready() {
+    let (server, _) = setup();
+
+    server
+        .test(
+            http::Method::GET,
+            "/_/ready",
+            None,
+            http::StatusCode::OK,
+            &[
+                r#"^\{"status":"ready","checks":\{"#,
+                r#""pool":\{"status":"up","latency_ms":\d+\},"#,
+                r#""database":\{"status":"up","latency_ms":\d+\},"#,
+                r#""migrations":\{"status":"up","latency_ms":\d+\},"#,
+                r#""mode":\{"status":"up","latency_ms":\d+,"detail":"READWRITE"\}\}\}$"#,
+            ]
+            .concat(),
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_ready_while_importing() {
+    let (server, _) = setup();
+
+    server
+        .test(
+            http::Method::PUT,
+            "/mode",
+            Some(json!({"mode": "IMPORT"})),
+            http::StatusCode::OK,
+            r#"\{"mode":"IMPORT"\}"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::GET,
+            "/_/ready",
+            None,
+            http::StatusCode::OK,
+            r#"^\{"status":"ready",.*"mode":\{"status":"up","latency_ms":\d+,"detail":"IMPORT"\}\}\}$"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_not_ready_with_unreachable_database() {
+    let config = DbPoolConfig {
+        database_url: String::from("postgres://postgres@127.0.0.1:1/unreachable"),
+        min_idle: Some(0),
+        max_size: 1,
+        connection_timeout: Duration::from_millis(100),
+        ..DbPoolConfig::from_env()
+    };
+    let server = ApiTesterServer::with_pool(DbPool::from_config(&config));
+
+    server
+        .test(
+            http::Method::GET,
+            "/_/ready",
+            None,
+            http::StatusCode::SERVICE_UNAVAILABLE,
+            r#"^\{"status":"unavailable","checks":\{"pool":\{"status":"down",.*"database":\{"status":"down","latency_ms":0,"detail":"no database connection"\}"#,
+        )
+        .await;
+    // the process itself is still alive
+    server
+        .test(
+            http::Method::GET,
+            "/_/live",
+            None,
+            http::StatusCode::OK,
+            r#"^\{"status":"alive"\}$"#,
+        )
+        .await;
+}
@@ -0,0 +1,106 @@
+use actix_web::http;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::Value;
+
+use crate::common::server::{setup, UNAUTHENTICATED};
+use crate::common::settings::{JWT_AUDIENCE, JWT_ISSUER, JWT_SECRET};
+use avro_schema_registry::api::SchemaBody;
+
+const DENIED: &str = r#"^\{"error_code":40301,"message":"User is denied operation"\}$"#;
+
+fn token(claims: Value) -> String {
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+    )
+    .unwrap()
+}
+
+fn claims(role: &str) -> Value {
+    json!({
+        "sub": "payments-service",
+        "iss": JWT_ISSUER,
+        "aud": JWT_AUDIENCE,
+        "exp": chrono::Utc::now().timestamp() + 300,
+        "roles": [role],
+    })
+}
+
+#[actix_rt::test]
+async fn test_bearer_token_roles() {
+    let (server, _) = setup();
+    let schema_s = std::fs::read_to_string("tests/fixtures/schema.json").unwrap();
+    let schema = json!(SchemaBody { schema: schema_s });
+
+    server
+        .test_with_token(
+            &token(claims("writer")),
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(schema.clone()),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+    server
+        .test_with_token(
+            &token(claims("reader")),
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(schema),
+            http::StatusCode::FORBIDDEN,
+            DENIED,
+        )
+        .await;
+    server
+        .test_with_token(
+            &token(claims("reader")),
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::OK,
+            r#"^\["test.subject"\]$"#,
+        )
+        .await;
+
+    // the token's subject is the actor of the audit events
+    server
+        .test(
+            http::Method::GET,
+            "/audit",
+            None,
+            http::StatusCode::OK,
+            r#""actor":"payments-service""#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_invalid_bearer_tokens() {
+    let (server, _) = setup();
+
+    let mut expired = claims("admin");
+    expired["exp"] = json!(chrono::Utc::now().timestamp() - 300);
+    let mut other_audience = claims("admin");
+    other_audience["aud"] = json!("another-service");
+    let forged = encode(
+        &Header::default(),
+        &claims("admin"),
+        &EncodingKey::from_secret(b"not_the_secret"),
+    )
+    .unwrap();
+
+    for token in [token(expired), token(other_audience), forged] {
+        server
+            .test_with_token(
+                &token,
+                http::Method::GET,
+                "/subjects",
+                None,
+                http::StatusCode::UNAUTHORIZED,
+                UNAUTHENTICATED,
+            )
+            .await;
+    }
+}
@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use actix_web::http;
+
+use crate::common::server::{setup, ApiTesterServer, ValidateResponse, UNAUTHENTICATED};
+use crate::common::settings::LEADER_SECRET;
+use avro_schema_registry::api::SchemaBody;
+use avro_schema_registry::auth::{
+    ForwardingKey, PatternType, Principal, Role, FORWARDED_PRINCIPAL_HEADER,
+};
+use avro_schema_registry::db::models::{AclRule, AclRuleBody};
+use avro_schema_registry::middleware::Leader;
+
+fn schema() -> SchemaBody {
+    let schema_s = std::fs::read_to_string("tests/fixtures/schema.json").unwrap();
+    SchemaBody { schema: schema_s }
+}
+
+fn leader_at(url: String, timeout: Duration) -> Leader {
+    Leader {
+        url,
+        timeout,
+        key: ForwardingKey::new(LEADER_SECRET.as_bytes()),
+    }
+}
+
+#[actix_rt::test]
+async fn test_follower_forwards_writes_to_leader() {
+    let (leader, _) = setup();
+    let follower = ApiTesterServer::with_leader(leader_at(leader.url(), Duration::from_secs(5)));
+
+    follower
+        .test(
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(json!(schema())),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+
+    // both share the database, so the follower serves reads on its own
+    follower
+        .test(
+            http::Method::GET,
+            "/subjects/test.subject/versions",
+            None,
+            http::StatusCode::OK,
+            r"^\[1\]$",
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_follower_with_unreachable_leader() {
+    setup();
+    let follower = ApiTesterServer::with_leader(leader_at(
+        String::from("http://127.0.0.1:1"),
+        Duration::from_millis(500),
+    ));
+
+    // returns 500 with 'Error while forwarding the request to the leader'
+    follower
+        .test(
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(json!(schema())),
+            http::StatusCode::INTERNAL_SERVER_ERROR,
+            r#"^\{"error_code":50003,"message":"Error while forwarding the request to the leader.*"\}$"#,
+        )
+        .await;
+
+    follower
+        .test(
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::OK,
+            r"^\[\]$",
+        )
+        .await;
+
+    // lookups and checks sent as POST change nothing, so the follower serves them too
+    follower
+        .test(
+            http::Method::POST,
+            "/subjects/test.subject",
+            Some(json!(schema())),
+            http::StatusCode::NOT_FOUND,
+            r#"^\{"error_code":40401,"message":"Subject not found"\}$"#,
+        )
+        .await;
+    follower
+        .test(
+            http::Method::POST,
+            "/acl/check",
+            Some(json!({"subject": "test.subject", "role": "writer"})),
+            http::StatusCode::OK,
+            r#"^\{"allowed":true,"effective_role":"admin"\}$"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_follower_checks_acls_before_forwarding() {
+    let (_, mut conn) = setup();
+    AclRule::insert(
+        &mut conn,
+        AclRuleBody {
+            principal: String::from("writer"),
+            pattern: String::from("test.subject"),
+            pattern_type: PatternType::Exact,
+            role: Role::Reader,
+        },
+    )
+    .unwrap();
+    let follower = ApiTesterServer::with_leader(leader_at(
+        String::from("http://127.0.0.1:1"),
+        Duration::from_millis(500),
+    ));
+
+    // the writer may only read the subject, so the unreachable leader is never asked
+    follower
+        .test_as(
+            ("writer", "writer_password"),
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(json!(schema())),
+            http::StatusCode::FORBIDDEN,
+            r#"^\{"error_code":40301,"message":"User is denied operation"\}$"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_leader_only_trusts_principals_signed_with_its_secret() {
+    let (leader, _) = setup();
+    let principal = Principal {
+        name: String::from("admin"),
+        role: Role::Admin,
+    };
+    let forged = ForwardingKey::new(b"another_secret")
+        .sign(&principal)
+        .unwrap();
+
+    // a valid password doesn't make up for a forged principal
+    leader
+        .request(http::Method::GET, "/subjects")
+        .insert_header((FORWARDED_PRINCIPAL_HEADER, forged))
+        .send()
+        .await
+        .unwrap()
+        .validate(http::StatusCode::UNAUTHORIZED, UNAUTHENTICATED);
+
+    let signed = ForwardingKey::new(LEADER_SECRET.as_bytes())
+        .sign(&principal)
+        .unwrap();
+    leader
+        .request(http::Method::GET, "/subjects")
+        .insert_header((FORWARDED_PRINCIPAL_HEADER, signed))
+        .send()
+        .await
+        .unwrap()
+        .validate(http::StatusCode::OK, r"^\[\]$");
+}
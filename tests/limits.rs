@@ -0,0 +1,136 @@
+use actix_web::http;
+
+use crate::common::server::{setup, ApiTesterServer, UNAUTHENTICATED};
+use avro_schema_registry::api::SchemaBody;
+use avro_schema_registry::middleware::{RateBudget, RateLimiter};
+
+const RATE_LIMITED: &str = r#"^\{"error_code":429,"message":"Rate limit exceeded"\}$"#;
+
+fn schema() -> serde_json::Value {
+    let schema_s = std::fs::read_to_string("tests/fixtures/schema.json").unwrap();
+    json!(SchemaBody { schema: schema_s })
+}
+
+#[actix_rt::test]
+async fn test_writes_over_budget_are_rate_limited() {
+    let _ = setup();
+    let server = ApiTesterServer::with_rate_limiter(RateLimiter::new(
+        None,
+        Some(RateBudget {
+            per_second: 0.01,
+            burst: 2.0,
+        }),
+    ));
+
+    for _ in 0..2 {
+        server
+            .test(
+                http::Method::POST,
+                "/subjects/test.subject/versions",
+                Some(schema()),
+                http::StatusCode::OK,
+                r#"\{"id":"\d+"\}"#,
+            )
+            .await;
+    }
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(schema()),
+            http::StatusCode::TOO_MANY_REQUESTS,
+            RATE_LIMITED,
+        )
+        .await;
+
+    let response = server
+        .request(http::Method::POST, "/subjects/test.subject/versions")
+        .send_json(&schema())
+        .await
+        .unwrap();
+    let retry_after = response
+        .headers()
+        .get(http::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap();
+    assert!((1..=100).contains(&retry_after));
+
+    // reads have their own budget, and other principals their own buckets
+    server
+        .test(
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::OK,
+            r#"\["test.subject"\]"#,
+        )
+        .await;
+    server
+        .test_as(
+            ("writer", "writer_password"),
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(schema()),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_schema_over_max_size_is_rejected() {
+    let (server, _) = setup();
+    let schema = json!(SchemaBody {
+        schema: format!(
+            r#"{{"type": "string", "doc": "{}"}}"#,
+            "a".repeat(SchemaBody::DEFAULT_MAX_SIZE)
+        )
+    });
+
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(schema),
+            http::StatusCode::PAYLOAD_TOO_LARGE,
+            r#"^\{"error_code":413,"message":"Request body is too large"\}$"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_failed_authentications_are_rate_limited_by_address() {
+    let _ = setup();
+    let server = ApiTesterServer::with_rate_limiter(RateLimiter::new(
+        Some(RateBudget {
+            per_second: 0.01,
+            burst: 2.0,
+        }),
+        None,
+    ));
+
+    for _ in 0..2 {
+        server
+            .test_as(
+                ("reader", "not_the_password"),
+                http::Method::GET,
+                "/subjects",
+                None,
+                http::StatusCode::UNAUTHORIZED,
+                UNAUTHENTICATED,
+            )
+            .await;
+    }
+    // the address is out of budget, valid credentials or not
+    server
+        .test_as(
+            ("reader", "reader_password"),
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::TOO_MANY_REQUESTS,
+            RATE_LIMITED,
+        )
+        .await;
+}
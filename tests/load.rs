@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant};
+
+use actix_web::http;
+use diesel::prelude::*;
+
+use crate::common::server::ApiTesterServer;
+use crate::db::DbAuxOperations;
+use avro_schema_registry::db::{DbManage, DbPool};
+
+const SLOW_REQUESTS: usize = 5;
+const HEALTH_CHECKS: usize = 10;
+
+#[actix_rt::test]
+async fn test_slow_queries_do_not_block_health_check() {
+    let server = ApiTesterServer::with_single_worker();
+    let mut conn = DbPool::new_pool(Some(1)).connection().unwrap();
+    conn.reset();
+    conn.add_subjects(vec![String::from("subject1")]);
+
+    // Hold an exclusive lock on `subjects` from a separate session so that every query
+    // listing the subjects stalls inside Postgres until we release it.
+    diesel::sql_query("BEGIN").execute(&mut conn).unwrap();
+    diesel::sql_query("LOCK TABLE subjects IN ACCESS EXCLUSIVE MODE")
+        .execute(&mut conn)
+        .unwrap();
+
+    let slow_requests = (0..SLOW_REQUESTS)
+        .map(|_| actix_rt::spawn(server.request(http::Method::GET, "/subjects").send()))
+        .collect::<Vec<_>>();
+
+    // Give the slow requests time to reach the database
+    actix_rt::time::sleep(Duration::from_millis(250)).await;
+
+    for _ in 0..HEALTH_CHECKS {
+        let started = Instant::now();
+        server
+            .test(
+                http::Method::GET,
+                "/_/health_check",
+                None,
+                http::StatusCode::OK,
+                r#"\{"status": "healthy"\}"#,
+            )
+            .await;
+        assert!(
+            started.elapsed() < Duration::from_secs(1),
+            "health check took {:?} while queries were stalled",
+            started.elapsed()
+        );
+    }
+    assert!(slow_requests.iter().all(|request| !request.is_finished()));
+
+    diesel::sql_query("ROLLBACK").execute(&mut conn).unwrap();
+    for request in slow_requests {
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use actix_web::http;
+
+use crate::common::server::{setup, ApiTesterServer};
+use avro_schema_registry::api::SchemaBody;
+
+fn schema() -> SchemaBody {
+    let schema_s = std::fs::read_to_string("tests/fixtures/schema.json").unwrap();
+    SchemaBody { schema: schema_s }
+}
+
+async fn scrape(server: &ApiTesterServer) -> String {
+    let mut response = server
+        .request(http::Method::GET, "/_/metrics")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), http::StatusCode::OK);
+    String::from_utf8(response.body().await.unwrap().to_vec()).unwrap()
+}
+
+#[actix_rt::test]
+async fn test_metrics() {
+    setup();
+    let server = ApiTesterServer::with_metrics(Duration::from_millis(100));
+
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(json!(schema())),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::POST,
+            "/compatibility/subjects/test.subject/versions/1",
+            Some(json!(schema())),
+            http::StatusCode::OK,
+            r#"\{"is_compatible":true\}"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::GET,
+            "/schemas/ids/0",
+            None,
+            http::StatusCode::NOT_FOUND,
+            r#"\{"error_code":40403,"message":"Schema not found"\}"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/test.subject",
+            None,
+            http::StatusCode::OK,
+            r"\[1\]",
+        )
+        .await;
+    // leaves time for the gauges to be refreshed at least once
+    actix_rt::time::sleep(Duration::from_millis(300)).await;
+
+    let metrics = scrape(&server).await;
+    for series in [
+        r#"avro_schema_registry_schemas_registered_total{result="created",subject="test.subject"} 1"#,
+        r#"avro_schema_registry_compatibility_checks_total{level="BACKWARD",outcome="compatible"} 1"#,
+        r#"avro_schema_registry_schema_lookups_total{result="miss"} 1"#,
+        r#"avro_schema_registry_deletes_total{kind="subject",permanent="false"} 1"#,
+        r#"avro_schema_registry_db_pool_connections{state="idle"}"#,
+        "avro_schema_registry_db_pool_max_size 2",
+        "avro_schema_registry_db_pool_wait_seconds_count",
+        "avro_schema_registry_subjects ",
+        "avro_schema_registry_schemas ",
+        "avro_schema_registry_schema_versions ",
+    ] {
+        assert!(
+            metrics.contains(series),
+            "missing {} in:\n{}",
+            series,
+            metrics
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use actix_web::http;
+
+use crate::common::server::setup;
+use crate::db::DbAuxOperations;
+use avro_schema_registry::api::SchemaBody;
+
+#[actix_rt::test]
+async fn test_get_global_mode() {
+    let (server, _) = setup();
+
+    server
+        .test(
+            http::Method::GET,
+            "/mode",
+            None,
+            http::StatusCode::OK,
+            r#"^\{"mode":"READWRITE"\}$"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_set_invalid_mode() {
+    let (server, mut conn) = setup();
+    conn.add_subjects(vec![String::from("test.subject")]);
+
+    // returns 422 with 'Invalid mode'
+    server
+        .test(
+            http::Method::PUT,
+            "/mode",
+            Some(json!({"mode": "NOT_VALID"})),
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+            r#"\{"error_code":42204,"message":"Invalid mode"\}"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::PUT,
+            "/mode/test.subject",
+            Some(json!({"mode": "NOT_VALID"})),
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+            r#"\{"error_code":42204,"message":"Invalid mode"\}"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_read_only_subject_rejects_changes() {
+    let (server, mut conn) = setup();
+    let schema_s = std::fs::read_to_string("tests/fixtures/schema.json").unwrap();
+    let _ = conn.register_schema(String::from("test.subject"), schema_s.to_string());
+
+    server
+        .test(
+            http::Method::PUT,
+            "/mode/test.subject",
+            Some(json!({"mode": "READONLY"})),
+            http::StatusCode::OK,
+            r#"^\{"mode":"READONLY"\}$"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::GET,
+            "/mode/test.subject",
+            None,
+            http::StatusCode::OK,
+            r#"^\{"mode":"READONLY"\}$"#,
+        )
+        .await;
+
+    // returns 422 with 'Operation not permitted'
+    let schema_s =
+        std::fs::read_to_string("tests/fixtures/schema_backward_compatible.json").unwrap();
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(json!(SchemaBody { schema: schema_s })),
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+            r#"\{"error_code":42205,"message":"Operation not permitted"\}"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/test.subject",
+            None,
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+            r#"\{"error_code":42205,"message":"Operation not permitted"\}"#,
+        )
+        .await;
+    // reads are still allowed
+    server
+        .test(
+            http::Method::GET,
+            "/subjects/test.subject/versions",
+            None,
+            http::StatusCode::OK,
+            r"^\[1\]$",
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_import_mode_requires_empty_registry() {
+    let (server, mut conn) = setup();
+    conn.add_subjects(vec![String::from("test.subject")]);
+
+    // returns 422 with 'Operation not permitted'
+    server
+        .test(
+            http::Method::PUT,
+            "/mode",
+            Some(json!({"mode": "IMPORT"})),
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+            r#"\{"error_code":42205,"message":"Operation not permitted"\}"#,
+        )
+        .await;
+
+    server
+        .test(
+            http::Method::PUT,
+            "/mode?force=true",
+            Some(json!({"mode": "IMPORT"})),
+            http::StatusCode::OK,
+            r#"^\{"mode":"IMPORT"\}$"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::GET,
+            "/mode",
+            None,
+            http::StatusCode::OK,
+            r#"^\{"mode":"IMPORT"\}$"#,
+        )
+        .await;
+}
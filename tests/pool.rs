@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use avro_schema_registry::api::errors::{ApiAvroErrorCode, ApiError};
+use avro_schema_registry::db::{DbConnection, DbManage, DbPool, DbPoolConfig, DbReadPool};
+
+#[test]
+fn test_pool_is_ready_with_reachable_database() {
+    let config = DbPoolConfig {
+        min_idle: Some(0),
+        max_size: 1,
+        ..DbPoolConfig::from_env()
+    };
+    let pool = DbPool::from_config(&config);
+
+    assert!(pool.wait_until_ready(&config).is_ok());
+}
+
+#[test]
+fn test_pool_gives_up_on_unreachable_database() {
+    let config = DbPoolConfig {
+        database_url: String::from("postgres://postgres@127.0.0.1:1/unreachable"),
+        min_idle: Some(0),
+        max_size: 1,
+        connection_timeout: Duration::from_millis(100),
+        startup_attempts: 3,
+        startup_backoff: Duration::from_millis(10),
+        ..DbPoolConfig::from_env()
+    };
+    let pool = DbPool::from_config(&config);
+
+    assert!(pool.wait_until_ready(&config).is_err());
+}
+
+#[test]
+fn test_read_pool_uses_healthy_replica() {
+    let primary = DbPool::new_pool(Some(1));
+    let replica = DbPool::new_pool(Some(1));
+    let pool = DbReadPool::new(primary.clone(), Some(replica.clone()));
+
+    // Each pool has a single connection, so the one handing it out has none left idle
+    let _conn = pool.connection().unwrap();
+    assert_eq!(replica.state().idle_connections, 0);
+    assert_eq!(primary.state().idle_connections, 1);
+}
+
+#[test]
+fn test_read_pool_falls_back_to_primary_with_unhealthy_replica() {
+    let config = DbPoolConfig {
+        database_url: String::from("postgres://postgres@127.0.0.1:1/unreachable"),
+        min_idle: Some(0),
+        max_size: 1,
+        connection_timeout: Duration::from_millis(100),
+        ..DbPoolConfig::from_env()
+    };
+    let primary = DbPool::new_pool(Some(1));
+    let pool = DbReadPool::new(primary.clone(), Some(DbPool::from_config(&config)));
+
+    // The first read notices the replica is down and the next ones skip it altogether
+    let conn = pool.connection().unwrap();
+    assert_eq!(primary.state().idle_connections, 0);
+    drop(conn);
+    let _conn = pool.connection().unwrap();
+    assert_eq!(primary.state().idle_connections, 0);
+}
+
+#[actix_rt::test]
+async fn test_read_pool_looks_up_missing_rows_on_primary() {
+    let primary = DbPool::new_pool(Some(1));
+    let replica = DbPool::new_pool(Some(1));
+    let pool = DbReadPool::new(primary, Some(replica.clone()));
+    // The replica has a single connection, which it has no longer idle while running `f`
+    let lookup = |code| {
+        let replica = replica.clone();
+        move |_: &mut DbConnection| match replica.state().idle_connections {
+            0 => Err(ApiError::new(code)),
+            _ => Ok("primary"),
+        }
+    };
+
+    let found = pool
+        .run_or_primary(lookup(ApiAvroErrorCode::SchemaNotFound))
+        .await;
+    assert_eq!(found.unwrap(), "primary");
+    // Only rows that are missing are looked up again
+    let denied = pool
+        .run_or_primary(lookup(ApiAvroErrorCode::UserDeniedOperation))
+        .await;
+    assert_eq!(
+        denied.unwrap_err().response.error_code,
+        ApiAvroErrorCode::UserDeniedOperation
+    );
+}
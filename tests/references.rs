@@ -0,0 +1,150 @@
+use actix_web::http;
+
+use crate::common::server::{setup, ApiTesterServer};
+
+const INNER: &str =
+    r#"{"type": "record", "name": "Inner", "fields": [{"type": "string", "name": "field1"}]}"#;
+const OUTER: &str =
+    r#"{"type": "record", "name": "Outer", "fields": [{"type": "Inner", "name": "inner"}]}"#;
+
+async fn register_with_reference(server: &ApiTesterServer) {
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/inner/versions",
+            Some(json!({ "schema": INNER })),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/outer/versions",
+            Some(json!({
+                "schema": OUTER,
+                "references": [{"name": "Inner", "subject": "inner", "version": 1}]
+            })),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_register_schema_with_references() {
+    let (server, _) = setup();
+    register_with_reference(&server).await;
+
+    server
+        .test(
+            http::Method::GET,
+            "/subjects/outer/versions/1",
+            None,
+            http::StatusCode::OK,
+            r#""references":\[\{"name":"Inner","subject":"inner","version":1\}\]"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_register_schema_with_missing_reference() {
+    let (server, _) = setup();
+
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/outer/versions",
+            Some(json!({
+                "schema": OUTER,
+                "references": [{"name": "Inner", "subject": "inner", "version": 1}]
+            })),
+            http::StatusCode::NOT_FOUND,
+            r#"\{"error_code":40401,"message":"Subject not found"\}"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_delete_referenced_schema() {
+    let (server, _) = setup();
+    register_with_reference(&server).await;
+
+    // returns 422 with 'One or more references exist to the schema'
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/inner/versions/1",
+            None,
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+            r#"\{"error_code":42206,"message":"One or more references exist to the schema"\}"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/inner",
+            None,
+            http::StatusCode::UNPROCESSABLE_ENTITY,
+            r#"\{"error_code":42206,"message":"One or more references exist to the schema"\}"#,
+        )
+        .await;
+
+    // once the referencing schema is deleted, so can the referenced one
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/outer",
+            None,
+            http::StatusCode::OK,
+            r"\[1\]",
+        )
+        .await;
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/inner",
+            None,
+            http::StatusCode::OK,
+            r"\[1\]",
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_register_same_schema_with_other_references() {
+    let (server, _) = setup();
+    register_with_reference(&server).await;
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/inner.copy/versions",
+            Some(json!({ "schema": INNER })),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+
+    // the referenced types are the same, but not where they come from
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/outer.copy/versions",
+            Some(json!({
+                "schema": OUTER,
+                "references": [{"name": "Inner", "subject": "inner.copy", "version": 1}]
+            })),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+    server
+        .test(
+            http::Method::GET,
+            "/subjects/outer.copy/versions/1",
+            None,
+            http::StatusCode::OK,
+            r#""references":\[\{"name":"Inner","subject":"inner.copy","version":1\}\]"#,
+        )
+        .await;
+}
@@ -0,0 +1,76 @@
+use actix_web::http;
+
+use crate::common::server::setup;
+
+#[actix_rt::test]
+async fn test_request_id_is_echoed_in_errors_and_audit() {
+    let (server, _) = setup();
+
+    let mut response = server
+        .request(http::Method::GET, "/subjects/test.subject/versions")
+        .insert_header(("X-Request-Id", "ticket-42"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+    assert_eq!(response.headers().get("X-Request-Id").unwrap(), "ticket-42");
+    assert_eq!(
+        response.body().await.unwrap(),
+        r#"{"error_code":40401,"message":"Subject not found","request_id":"ticket-42"}"#
+    );
+
+    let response = server
+        .request(http::Method::PUT, "/config")
+        .insert_header(("X-Request-Id", "ticket-43"))
+        .send_json(&json!({"compatibility": "FULL"}))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert_eq!(response.headers().get("X-Request-Id").unwrap(), "ticket-43");
+
+    let mut response = server
+        .request(http::Method::GET, "/audit")
+        .send()
+        .await
+        .unwrap();
+    let body = response.body().await.unwrap();
+    assert!(std::str::from_utf8(&body)
+        .unwrap()
+        .contains(r#""action":"SET_GLOBAL_CONFIG","subject":null,"actor":"test_user","request_id":"ticket-43""#));
+}
+
+#[actix_rt::test]
+async fn test_request_id_is_generated() {
+    let (server, _) = setup();
+
+    // IDs that can't be logged as is are replaced
+    let mut response = server
+        .request(http::Method::GET, "/subjects/test.subject/versions")
+        .insert_header(("X-Request-Id", "two words"))
+        .send()
+        .await
+        .unwrap();
+    let request_id = response
+        .headers()
+        .get("X-Request-Id")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(request_id.len(), 36);
+    let body = response.body().await.unwrap();
+    assert!(std::str::from_utf8(&body)
+        .unwrap()
+        .ends_with(&format!(r#","request_id":"{request_id}"}}"#)));
+
+    let response = server
+        .request(http::Method::GET, "/subjects")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert_ne!(
+        response.headers().get("X-Request-Id").unwrap(),
+        request_id.as_str()
+    );
+}
@@ -0,0 +1,181 @@
+use actix_web::http;
+
+use crate::common::server::{setup, ApiTesterServer};
+use avro_schema_registry::api::SchemaBody;
+
+async fn register_schema(server: &ApiTesterServer) {
+    let schema_s = std::fs::read_to_string("tests/fixtures/schema.json").unwrap();
+    server
+        .test(
+            http::Method::POST,
+            "/subjects/test.subject/versions",
+            Some(json!(SchemaBody { schema: schema_s })),
+            http::StatusCode::OK,
+            r#"\{"id":"\d+"\}"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_delete_soft_deleted_subject() {
+    let (server, _) = setup();
+    register_schema(&server).await;
+
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/test.subject",
+            None,
+            http::StatusCode::OK,
+            r"\[1\]",
+        )
+        .await;
+    // a soft deleted subject is hidden
+    server
+        .test(
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::OK,
+            r"^\[\]$",
+        )
+        .await;
+
+    // returns 404 with 'Subject was soft deleted'
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/test.subject",
+            None,
+            http::StatusCode::NOT_FOUND,
+            r#"\{"error_code":40404,"message":"Subject was soft deleted. Set permanent=true to delete permanently"\}"#,
+        )
+        .await;
+
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/test.subject?permanent=true",
+            None,
+            http::StatusCode::OK,
+            r"\[1\]",
+        )
+        .await;
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/test.subject?permanent=true",
+            None,
+            http::StatusCode::NOT_FOUND,
+            r#"\{"error_code":40401,"message":"Subject not found"\}"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_delete_subject_permanently_without_soft_delete() {
+    let (server, _) = setup();
+    register_schema(&server).await;
+
+    // returns 404 with 'Subject was not deleted first'
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/test.subject?permanent=true",
+            None,
+            http::StatusCode::NOT_FOUND,
+            r#"\{"error_code":40405,"message":"Subject was not deleted first before being permanently deleted"\}"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_delete_soft_deleted_schema_version() {
+    let (server, _) = setup();
+    register_schema(&server).await;
+
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/test.subject/versions/1",
+            None,
+            http::StatusCode::OK,
+            "1",
+        )
+        .await;
+    // a soft deleted version is hidden
+    server
+        .test(
+            http::Method::GET,
+            "/subjects/test.subject/versions/1",
+            None,
+            http::StatusCode::NOT_FOUND,
+            r#"\{"error_code":40402,"message":"Version not found"\}"#,
+        )
+        .await;
+
+    // returns 404 with 'Version was soft deleted'
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/test.subject/versions/1",
+            None,
+            http::StatusCode::NOT_FOUND,
+            r#"\{"error_code":40406,"message":"Version was soft deleted. Set permanent=true to delete permanently"\}"#,
+        )
+        .await;
+
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/test.subject/versions/1?permanent=true",
+            None,
+            http::StatusCode::OK,
+            "1",
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_delete_schema_version_permanently_without_soft_delete() {
+    let (server, _) = setup();
+    register_schema(&server).await;
+
+    // returns 404 with 'Version was not deleted first'
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/test.subject/versions/1?permanent=true",
+            None,
+            http::StatusCode::NOT_FOUND,
+            r#"\{"error_code":40407,"message":"Version was not deleted first before being permanently deleted"\}"#,
+        )
+        .await;
+}
+
+#[actix_rt::test]
+async fn test_register_soft_deleted_schema_restores_version() {
+    let (server, _) = setup();
+    register_schema(&server).await;
+
+    server
+        .test(
+            http::Method::DELETE,
+            "/subjects/test.subject",
+            None,
+            http::StatusCode::OK,
+            r"\[1\]",
+        )
+        .await;
+    register_schema(&server).await;
+
+    server
+        .test(
+            http::Method::GET,
+            "/subjects/test.subject/versions",
+            None,
+            http::StatusCode::OK,
+            r"^\[1\]$",
+        )
+        .await;
+}
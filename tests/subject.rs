@@ -102,7 +102,8 @@ async fn test_get_versions_under_subject_with_versions() {
         .await;
 
     // it returns list of many
-    let schema2_s = std::fs::read_to_string("tests/fixtures/schema2.json").unwrap();
+    let schema2_s =
+        std::fs::read_to_string("tests/fixtures/schema_backward_compatible.json").unwrap();
     let schema2 = SchemaBody { schema: schema2_s };
 
     // This modifies the database state in preparation for the next request
@@ -279,14 +280,14 @@ async fn test_register_schema_under_subject_with_invalid_schema() {
         schema: "{}".to_string(),
     };
 
-    // it returns 422 with 'Invalid Avro schema'
+    // it returns 422 with 'Invalid Avro schema' and the reason it can't be parsed
     server
         .test(
             http::Method::POST,
             "/subjects/test.subject/versions",
             Some(json!(schema)),
             http::StatusCode::UNPROCESSABLE_ENTITY,
-            r#"\{"error_code":42201,"message":"Invalid Avro schema"\}"#,
+            r#"\{"error_code":42201,"message":"Invalid Avro schema: .+"\}"#,
         )
         .await;
 }
@@ -433,7 +434,8 @@ async fn test_delete_schema_version_under_subject_with_subject() {
             r#"\{"id":"\d+"\}"#,
         )
         .await;
-    let schema_s = std::fs::read_to_string("tests/fixtures/schema2.json").unwrap();
+    let schema_s =
+        std::fs::read_to_string("tests/fixtures/schema_backward_compatible.json").unwrap();
     let schema = SchemaBody { schema: schema_s };
     server
         .test(
@@ -0,0 +1,72 @@
+use std::sync::{Arc, Mutex};
+
+use actix_test as test;
+use actix_web::{http, web, App, HttpResponse};
+
+use crate::common::server::setup;
+use avro_schema_registry::telemetry::{self, LogFormat, Telemetry};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+type Exports = Arc<Mutex<Vec<u8>>>;
+
+/// Stands in for an OpenTelemetry collector, keeping the body of every OTLP export.
+fn start_collector(exports: Exports) -> test::TestServer {
+    test::start(move || {
+        let exports = exports.clone();
+        App::new().route(
+            "/v1/traces",
+            web::post().to(move |body: web::Bytes| {
+                exports.lock().unwrap().extend_from_slice(&body);
+                async { HttpResponse::Ok().finish() }
+            }),
+        )
+    })
+}
+
+fn contains(exports: &[u8], expected: &[u8]) -> bool {
+    exports
+        .windows(expected.len())
+        .any(|window| window == expected)
+}
+
+#[actix_rt::test]
+async fn test_spans_are_exported_with_the_inbound_trace() {
+    let exports = Exports::default();
+    let collector = start_collector(exports.clone());
+    let tracer_provider = Telemetry {
+        endpoint: collector.url("").trim_end_matches('/').to_string(),
+        service_name: String::from("avro-schema-registry-test"),
+    }
+    .init()
+    .unwrap();
+    telemetry::init_subscriber(LogFormat::Text, Some(&tracer_provider)).unwrap();
+    // Connections are only instrumented when established afterwards
+    telemetry::instrument_queries().unwrap();
+    let (server, _) = setup();
+
+    let response = server
+        .request(http::Method::GET, "/subjects/test.subject/versions")
+        .insert_header(("traceparent", TRACEPARENT))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+
+    actix_web::rt::task::spawn_blocking(move || tracer_provider.force_flush())
+        .await
+        .unwrap()
+        .unwrap();
+
+    // OTLP/HTTP exports are protobuf, where IDs are raw bytes and strings are UTF-8
+    let exports = exports.lock().unwrap();
+    let trace_id = (0..TRACE_ID.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&TRACE_ID[i..i + 2], 16).unwrap())
+        .collect::<Vec<_>>();
+    assert!(contains(&exports, &trace_id));
+    assert!(contains(&exports, b"GET /subjects/{subject}/versions"));
+    assert!(contains(&exports, b"test.subject"));
+    assert!(contains(&exports, b"db.statement"));
+}
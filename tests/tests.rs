@@ -1,9 +1,31 @@
 #[macro_use]
 extern crate serde_json;
 
+mod acl;
+mod admin;
+mod api_keys;
+mod audit;
+mod authorization;
 mod common;
 mod compatibility;
+mod concurrency;
 mod config;
+mod content_negotiation;
 mod db;
+mod errors;
+mod health;
+mod jwt;
+mod leader;
+mod limits;
+mod load;
+mod metrics;
+mod mode;
+mod pool;
+mod references;
+mod request_id;
 mod schemas;
+mod soft_delete;
 mod subject;
+mod telemetry;
+mod timeout;
+mod tls;
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use actix_web::http;
+use diesel::connection::SimpleConnection;
+
+use crate::common::server::{setup, ApiTesterServer};
+use avro_schema_registry::db::{DbConnection, DbManage, DbPool, DbPoolConfig};
+use avro_schema_registry::middleware::RequestTimeout;
+
+const TIMED_OUT: &str = r#"^\{"error_code":50002,"message":"Operation timed out.*"\}$"#;
+
+/// Blocks every query on subjects until the returned connection rolls back.
+fn lock_subjects(conn: &mut DbConnection) {
+    conn.batch_execute("BEGIN; LOCK TABLE subjects IN ACCESS EXCLUSIVE MODE")
+        .unwrap();
+}
+
+#[actix_rt::test]
+async fn test_request_timeout() {
+    let (_, mut conn) = setup();
+    let server = ApiTesterServer::with_request_timeout(RequestTimeout(Duration::from_millis(200)));
+
+    lock_subjects(&mut conn);
+    server
+        .test(
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::INTERNAL_SERVER_ERROR,
+            TIMED_OUT,
+        )
+        .await;
+    conn.batch_execute("ROLLBACK").unwrap();
+}
+
+#[actix_rt::test]
+async fn test_statement_timeout() {
+    let (_, mut conn) = setup();
+    let config = DbPoolConfig {
+        min_idle: Some(0),
+        max_size: 1,
+        statement_timeout: Some(Duration::from_millis(200)),
+        ..DbPoolConfig::from_env()
+    };
+    let server = ApiTesterServer::with_pool(DbPool::from_config(&config));
+
+    lock_subjects(&mut conn);
+    server
+        .test(
+            http::Method::GET,
+            "/subjects",
+            None,
+            http::StatusCode::INTERNAL_SERVER_ERROR,
+            TIMED_OUT,
+        )
+        .await;
+    conn.batch_execute("ROLLBACK").unwrap();
+}
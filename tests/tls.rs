@@ -0,0 +1,173 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use actix_web::{http, App, HttpServer};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType, IsCa, KeyPair,
+};
+use rustls::crypto::ring;
+use rustls::pki_types::PrivateKeyDer;
+use rustls::{ClientConfig, RootCertStore};
+
+use crate::common::server::{configure_db, TEST_USER};
+use crate::common::settings::{configure_auth, get_schema_registry_password};
+use avro_schema_registry::app;
+use avro_schema_registry::tls::{self, TlsConfig};
+
+/// A CA, and certificates it issued, generated for each test.
+struct Pki {
+    dir: PathBuf,
+    ca: Certificate,
+    ca_key: KeyPair,
+}
+
+impl Pki {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = params.self_signed(&ca_key).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+
+        Self { dir, ca, ca_key }
+    }
+
+    fn issue(&self, params: CertificateParams) -> (Certificate, KeyPair) {
+        let key = KeyPair::generate().unwrap();
+        let cert = params.signed_by(&key, &self.ca, &self.ca_key).unwrap();
+        (cert, key)
+    }
+
+    fn server_config(&self) -> rustls::ServerConfig {
+        let (cert, key) =
+            self.issue(CertificateParams::new(vec!["localhost".to_string()]).unwrap());
+        std::fs::write(self.dir.join("server.pem"), cert.pem()).unwrap();
+        std::fs::write(self.dir.join("server.key"), key.serialize_pem()).unwrap();
+
+        TlsConfig {
+            cert_file: self.dir.join("server.pem"),
+            key_file: self.dir.join("server.key"),
+            client_ca_file: Some(self.dir.join("ca.pem")),
+            require_client_cert: false,
+            reload_interval: std::time::Duration::from_secs(30),
+        }
+        .server_config()
+        .unwrap()
+    }
+
+    /// A client trusting the CA, presenting a certificate for `common_name` if given.
+    fn client(&self, common_name: Option<&str>) -> awc::Client {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.ca.der().clone()).unwrap();
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+
+        let config = match common_name {
+            Some(common_name) => {
+                let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+                params.distinguished_name = DistinguishedName::new();
+                params
+                    .distinguished_name
+                    .push(DnType::CommonName, common_name);
+                let (cert, key) = self.issue(params);
+                builder
+                    .with_client_auth_cert(
+                        vec![cert.der().clone()],
+                        PrivateKeyDer::Pkcs8(key.serialize_der().into()),
+                    )
+                    .unwrap()
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        awc::Client::builder()
+            .connector(awc::Connector::new().rustls_0_23(Arc::new(config)))
+            .finish()
+    }
+}
+
+impl Drop for Pki {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn start_server(pki: &Pki) -> SocketAddr {
+    configure_auth();
+    let server = HttpServer::new(|| {
+        App::new()
+            .configure(configure_db)
+            .configure(app::api_routing)
+    })
+    .on_connect(tls::on_connect)
+    .workers(1)
+    .bind_rustls_0_23(("127.0.0.1", 0), pki.server_config())
+    .unwrap();
+
+    let addr = server.addrs()[0];
+    actix_rt::spawn(server.run());
+    addr
+}
+
+async fn status(request: awc::ClientRequest) -> http::StatusCode {
+    request
+        .insert_header((http::header::CONTENT_TYPE, "application/json"))
+        .insert_header((http::header::ACCEPT, "application/vnd.schemaregistry+json"))
+        .send_json(&json!({"schema": "{\"type\": \"string\"}"}))
+        .await
+        .unwrap()
+        .status()
+}
+
+#[actix_rt::test]
+async fn test_client_certificate_authenticates_user() {
+    let pki = Pki::new("test_client_certificate_authenticates_user");
+    let addr = start_server(&pki);
+    let url = |path: &str| format!("https://localhost:{}{}", addr.port(), path);
+
+    // the certificate is issued for the reader of tests/fixtures/users.json
+    let client = pki.client(Some("reader"));
+    assert_eq!(
+        status(client.get(url("/subjects"))).await,
+        http::StatusCode::OK
+    );
+    assert_eq!(
+        status(client.post(url("/subjects/test.subject/versions"))).await,
+        http::StatusCode::FORBIDDEN
+    );
+
+    // certificates of unknown users fall back to the Authorization header
+    let client = pki.client(Some("someone"));
+    assert_eq!(
+        status(client.get(url("/subjects"))).await,
+        http::StatusCode::UNAUTHORIZED
+    );
+}
+
+#[actix_rt::test]
+async fn test_client_certificate_is_optional() {
+    let pki = Pki::new("test_client_certificate_is_optional");
+    let addr = start_server(&pki);
+    let url = format!("https://localhost:{}/subjects", addr.port());
+
+    let client = pki.client(None);
+    assert_eq!(
+        status(client.get(&url)).await,
+        http::StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status(
+            client
+                .get(&url)
+                .basic_auth(TEST_USER, get_schema_registry_password())
+        )
+        .await,
+        http::StatusCode::OK
+    );
+}